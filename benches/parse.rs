@@ -0,0 +1,103 @@
+//! Parsing throughput for typical messages, run with `cargo bench`.
+
+use std::hint::black_box;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use scopa::{
+    parse_message, Class, DomainName, Message, Query, RecordType, ResourceData, ResourceRecord,
+};
+
+fn name(name: &str) -> DomainName {
+    name.parse().unwrap()
+}
+
+fn small_query() -> Vec<u8> {
+    Query::new("www.example.com", RecordType::A)
+        .build()
+        .unwrap()
+}
+
+/// A response with a hundred addresses for the queried name.
+fn large_response() -> Vec<u8> {
+    let query = parse_message(&small_query()).unwrap();
+    let mut response = Message::reply_to(&query);
+    for i in 0..50 {
+        let owner = name("www.example.com");
+        response.push_answer(ResourceRecord::new(
+            owner.clone(),
+            Class::In,
+            300,
+            ResourceData::A(Ipv4Addr::new(192, 0, 2, i)),
+        ));
+        let address = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, i.into());
+        response.push_answer(ResourceRecord::new(
+            owner,
+            Class::In,
+            300,
+            ResourceData::Aaaa(address),
+        ));
+    }
+    response.to_bytes().unwrap()
+}
+
+/// A response where nearly every name is made of pointers into others, as
+/// with long CNAME chains and referrals.
+fn compressed_response() -> Vec<u8> {
+    let query = Query::new("a.deep.chain.of.labels.example.com", RecordType::Mx)
+        .build()
+        .unwrap();
+    let mut response = Message::reply_to(&parse_message(&query).unwrap());
+    let alias = |i: usize| name(&format!("alias{i}.deep.chain.of.labels.example.com"));
+    response.push_answer(ResourceRecord::new(
+        name("a.deep.chain.of.labels.example.com"),
+        Class::In,
+        300,
+        ResourceData::Cname(alias(0)),
+    ));
+    for i in 0..40 {
+        response.push_answer(ResourceRecord::new(
+            alias(i),
+            Class::In,
+            300,
+            ResourceData::Cname(alias(i + 1)),
+        ));
+    }
+    for i in 0..20 {
+        let exchange = name(&format!("mx{i}.labels.example.com"));
+        response.push_answer(ResourceRecord::new(
+            alias(40),
+            Class::In,
+            300,
+            ResourceData::Mx {
+                preference: i,
+                exchange: exchange.clone(),
+            },
+        ));
+        response.push_additional(ResourceRecord::new(
+            exchange,
+            Class::In,
+            300,
+            ResourceData::A(Ipv4Addr::new(192, 0, 2, i as u8)),
+        ));
+    }
+    response.to_bytes().unwrap()
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_message");
+    for (id, message) in [
+        ("small_query", small_query()),
+        ("large_response", large_response()),
+        ("compressed_response", compressed_response()),
+    ] {
+        group.throughput(Throughput::Bytes(message.len() as u64));
+        group.bench_function(id, |b| {
+            b.iter(|| parse_message(black_box(&message)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
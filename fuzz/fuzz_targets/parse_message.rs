@@ -0,0 +1,16 @@
+//! Feeds raw bytes to the parsers, which must never panic on them. Run with
+//! `cargo fuzz run parse_message`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(message) = scopa::parse_message(data) {
+        // whatever parses has to encode again
+        let _ = message.to_bytes();
+        let _ = message.to_string();
+    }
+    let _ = scopa::parse_message_lenient(data);
+    let _ = scopa::MessageRef::parse(data);
+});
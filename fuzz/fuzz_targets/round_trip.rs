@@ -0,0 +1,16 @@
+//! Encodes arbitrary messages and checks that they parse back the same. Run
+//! with `cargo fuzz run round_trip`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use scopa::{parse_message, Message};
+
+fuzz_target!(|message: Message| {
+    let bytes = message.to_bytes().unwrap();
+    let parsed = parse_message(&bytes).unwrap();
+
+    assert_eq!(message.header(), parsed.header());
+    assert_eq!(message.questions(), parsed.questions());
+    assert_eq!(bytes, parsed.to_bytes().unwrap());
+});
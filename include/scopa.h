@@ -0,0 +1,49 @@
+/* The C interface to scopa, built with `cargo build-ffi`
+ * into target/release/libscopa.so (or .dylib, .dll). */
+
+#ifndef SCOPA_H
+#define SCOPA_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A parsed message. */
+typedef struct ScopaMessage ScopaMessage;
+
+#define SCOPA_SECTION_QUESTION 0
+#define SCOPA_SECTION_ANSWER 1
+#define SCOPA_SECTION_AUTHORITY 2
+#define SCOPA_SECTION_ADDITIONAL 3
+
+/* Returns NULL if the bytes don't hold a valid message. */
+ScopaMessage *scopa_parse(const uint8_t *ptr, size_t len);
+void scopa_free(ScopaMessage *message);
+
+uint16_t scopa_id(const ScopaMessage *message);
+bool scopa_is_response(const ScopaMessage *message);
+uint8_t scopa_opcode(const ScopaMessage *message);
+uint16_t scopa_rcode(const ScopaMessage *message);
+
+/* Accessors for the entries of a section. Out of range, they return 0 or
+ * NULL. Strings have to be freed with scopa_string_free. */
+size_t scopa_count(const ScopaMessage *message, uint32_t section);
+char *scopa_name(const ScopaMessage *message, uint32_t section, size_t index);
+uint16_t scopa_type(const ScopaMessage *message, uint32_t section, size_t index);
+uint16_t scopa_class(const ScopaMessage *message, uint32_t section, size_t index);
+char *scopa_to_string(const ScopaMessage *message, uint32_t section, size_t index);
+
+/* The message as JSON in the format of RFC 8427. */
+char *scopa_to_json(const ScopaMessage *message);
+
+void scopa_string_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,219 @@
+//! [`Arbitrary`] implementations for fuzzing and property tests.
+//!
+//! They only produce values that survive encoding and parsing unchanged:
+//! names are valid, header counts match the sections, and types without
+//! a variant of their own can't take the number of one that has.
+
+use alloc::vec::Vec;
+use core::net::{Ipv4Addr, Ipv6Addr};
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{
+    Class, DomainName, Ds, GenericData, Header, Message, Opcode, Question, Rcode, RecordType,
+    ResourceData, ResourceRecord, Soa,
+};
+
+/// Bytes mostly used in labels, a few of them needing escapes in text. Any
+/// other byte, even one above ASCII, shows up now and then.
+const LABEL_BYTES: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-_*. \\";
+
+/// Up to this many entries per section, to keep messages small.
+const MAX_ENTRIES: usize = 4;
+
+fn entries<'a, T: Arbitrary<'a>>(u: &mut Unstructured<'a>) -> Result<Vec<T>> {
+    let count = u.int_in_range(0..=MAX_ENTRIES)?;
+    (0..count).map(|_| T::arbitrary(u)).collect()
+}
+
+fn bytes(u: &mut Unstructured<'_>, max: usize) -> Result<Vec<u8>> {
+    let length = u.int_in_range(0..=max)?.min(u.len());
+    Ok(u.bytes(length)?.to_vec())
+}
+
+impl<'a> Arbitrary<'a> for DomainName {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut name = DomainName::root();
+        for _ in 0..u.int_in_range(0..=5)? {
+            let length = u.int_in_range(1..=20)?;
+            let label: Vec<u8> = (0..length)
+                .map(|_| {
+                    if u.ratio(1, 8)? {
+                        u8::arbitrary(u)
+                    } else {
+                        u.choose(LABEL_BYTES).copied()
+                    }
+                })
+                .collect::<Result<_>>()?;
+            name.push_label(&label);
+        }
+        Ok(name)
+    }
+}
+
+impl<'a> Arbitrary<'a> for RecordType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(RecordType::from(u16::arbitrary(u)?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for Class {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Class::from(u16::arbitrary(u)?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for Question {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Question {
+            domain_name: u.arbitrary()?,
+            q_type: u.arbitrary()?,
+            q_class: u.arbitrary()?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for ResourceData {
+    /// One of the common types, or data of a type from the private use
+    /// range.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let data = match u.int_in_range(0..=11)? {
+            0 => ResourceData::A(Ipv4Addr::from(u32::arbitrary(u)?)),
+            1 => ResourceData::Aaaa(Ipv6Addr::from(u128::arbitrary(u)?)),
+            2 => ResourceData::Ns(u.arbitrary()?),
+            3 => ResourceData::Cname(u.arbitrary()?),
+            4 => ResourceData::Ptr(u.arbitrary()?),
+            5 => ResourceData::Dname(u.arbitrary()?),
+            6 => ResourceData::Mx {
+                preference: u.arbitrary()?,
+                exchange: u.arbitrary()?,
+            },
+            7 => {
+                let strings = u.int_in_range(1..=3)?;
+                ResourceData::Txt((0..strings).map(|_| bytes(u, 255)).collect::<Result<_>>()?)
+            }
+            8 => ResourceData::Srv {
+                priority: u.arbitrary()?,
+                weight: u.arbitrary()?,
+                port: u.arbitrary()?,
+                target: u.arbitrary()?,
+            },
+            9 => ResourceData::Soa(Soa {
+                mname: u.arbitrary()?,
+                rname: u.arbitrary()?,
+                serial: u.arbitrary()?,
+                refresh: u.arbitrary()?,
+                retry: u.arbitrary()?,
+                expire: u.arbitrary()?,
+                minimum: u.arbitrary()?,
+            }),
+            10 => ResourceData::Ds(Ds {
+                key_tag: u.arbitrary()?,
+                algorithm: u.arbitrary()?,
+                digest_type: u.arbitrary()?,
+                digest: bytes(u, 64)?,
+            }),
+            _ => ResourceData::Unknown {
+                r#type: RecordType::Unknown(u.int_in_range(65280..=65534)?),
+                data: GenericData(bytes(u, 64)?),
+            },
+        };
+        Ok(data)
+    }
+}
+
+impl<'a> Arbitrary<'a> for ResourceRecord {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(ResourceRecord::new(
+            u.arbitrary()?,
+            u.arbitrary()?,
+            u.arbitrary()?,
+            u.arbitrary()?,
+        ))
+    }
+}
+
+impl<'a> Arbitrary<'a> for Message {
+    /// A message without EDNS, so the RCODE is at most 15.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let questions: Vec<Question> = entries(u)?;
+        let answers: Vec<ResourceRecord> = entries(u)?;
+        let authorities: Vec<ResourceRecord> = entries(u)?;
+        let additionals: Vec<ResourceRecord> = entries(u)?;
+
+        let header = Header {
+            id: u.arbitrary()?,
+            response: u.arbitrary()?,
+            opcode: Opcode::from(u.int_in_range(0..=15)?),
+            authoritative: u.arbitrary()?,
+            truncated: u.arbitrary()?,
+            recursion_desired: u.arbitrary()?,
+            recursion_available: u.arbitrary()?,
+            z: u.arbitrary()?,
+            authentic_data: u.arbitrary()?,
+            checking_disabled: u.arbitrary()?,
+            rcode: Rcode::from(u.int_in_range(0..=15)?),
+            question_count: questions.len() as u16,
+            answer_count: answers.len() as u16,
+            authority_count: authorities.len() as u16,
+            additional_count: additionals.len() as u16,
+        };
+
+        Ok(Message {
+            header,
+            questions,
+            answers,
+            authorities,
+            additionals,
+            edns: None,
+            parse_violations: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_message;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn test_round_trip(seed in proptest::collection::vec(any::<u8>(), 0..2048)) {
+            let message = Message::arbitrary(&mut Unstructured::new(&seed)).unwrap();
+            let bytes = message.to_bytes().unwrap();
+            let parsed = parse_message(&bytes).unwrap();
+
+            prop_assert_eq!(message.header(), parsed.header());
+            prop_assert_eq!(message.questions(), parsed.questions());
+            for question in message.questions() {
+                prop_assert_eq!(Ok(question.name().clone()), question.name().to_string().parse());
+            }
+            let sections = |message: &Message| [message.answers().to_vec(), message.authorities().to_vec(), message.additionals().to_vec()].concat();
+            for (record, parsed) in sections(&message).iter().zip(&sections(&parsed)) {
+                // the lengths of RDATA with compressed names differ
+                prop_assert!(record.eq_ignore_ttl(parsed) && record.ttl() == parsed.ttl(), "{record:?} != {parsed:?}");
+            }
+            prop_assert_eq!(bytes, parsed.to_bytes().unwrap());
+        }
+
+        #[test]
+        fn test_parse_never_panics(seed in proptest::collection::vec(any::<u8>(), 0..2048), flips in proptest::collection::vec((any::<usize>(), any::<u8>()), 1..8)) {
+            // valid messages with a few bytes changed get much further than
+            // random bytes
+            let mut bytes = Message::arbitrary(&mut Unstructured::new(&seed)).unwrap().to_bytes().unwrap();
+            for (index, byte) in flips {
+                let length = bytes.len();
+                bytes[index % length] = byte;
+            }
+
+            if let Ok(message) = parse_message(&bytes) {
+                let _ = (message.to_bytes(), message.to_string());
+            }
+            let _ = crate::parse_message_lenient(&bytes);
+            if let Ok(message) = crate::MessageRef::parse(&bytes) {
+                let _ = message.to_message();
+            }
+        }
+    }
+}
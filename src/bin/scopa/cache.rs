@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use scopa::{Class, DomainName, Edns, EdnsOption, Message, Rcode, RecordType, ResourceData};
+
+use crate::config::CacheConfig;
+
+/// Cached responses by question, which get their TTLs lowered by the time
+/// they spent in the cache when they are handed out.
+pub struct Cache {
+    config: CacheConfig,
+    entries: HashMap<Key, Entry>,
+}
+
+/// The question of a query along with its DO and CD bits, as responses with
+/// DNSSEC records or without validation are only for queries asking for them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    name: DomainName,
+    r#type: RecordType,
+    class: Class,
+    dnssec_ok: bool,
+    checking_disabled: bool,
+}
+
+struct Entry {
+    response: Message,
+    stored: Instant,
+    expires: Instant,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Self {
+        Cache {
+            config,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Changes the limits, dropping responses if there are now too many. The
+    /// TTL limits only apply to the responses cached from now on.
+    pub fn set_config(&mut self, config: CacheConfig, now: Instant) {
+        self.config = config;
+        while self.entries.len() > self.config.size {
+            self.evict(now);
+        }
+    }
+
+    /// The cached response to `query`, with its ID and question as it was
+    /// asked, and EDNS only if the query has it, with its payload size and
+    /// DO bit.
+    pub fn get(&mut self, query: &Message, now: Instant) -> Option<Message> {
+        let key = key(query)?;
+        let entry = self.entries.get(&key)?;
+        if entry.expires <= now {
+            self.entries.remove(&key);
+            return None;
+        }
+
+        let mut response = entry.response.clone();
+        response.set_id(query.id());
+        response.set_questions(query.questions().to_vec());
+        let options = response
+            .edns()
+            .map(|edns| edns.options.clone())
+            .unwrap_or_default();
+        response.set_edns(query.edns().map(|edns| Edns {
+            udp_payload: edns.udp_payload,
+            dnssec_ok: edns.dnssec_ok,
+            options,
+            ..Edns::default()
+        }));
+        let age = (now - entry.stored).as_secs() as u32;
+        map_ttls(&mut response, |ttl| ttl.saturating_sub(age));
+        Some(response)
+    }
+
+    /// Keeps the response to `query` for as long as the lowest TTL of its
+    /// records, within the configured limits. Negative responses are kept for
+    /// the TTL of the SOA record as RFC 2308 says. Other errors and truncated
+    /// responses aren't kept at all.
+    pub fn insert(&mut self, query: &Message, response: &Message, now: Instant) {
+        if self.config.size == 0 || response.header().truncated() {
+            return;
+        }
+        let Some(key) = key(query) else {
+            return;
+        };
+
+        let ttl = match response.header().rcode() {
+            Rcode::NoError if !response.answers().is_empty() => {
+                response.answers().iter().map(|record| record.ttl()).min()
+            }
+            Rcode::NoError | Rcode::NxDomain => negative_ttl(response),
+            _ => None,
+        };
+        let Some(ttl) = ttl else {
+            return;
+        };
+
+        // the TTLs in the response are clamped as well, so that they don't
+        // count down from beyond the maximum
+        let ttl = ttl.clamp(self.config.min_ttl(), self.config.max_ttl());
+        let mut response = response.clone();
+        // cookies, subnets and the like were for the client that asked first
+        if let Some(mut edns) = response.edns().cloned() {
+            edns.options.retain(|option| !is_per_client(option));
+            response.set_edns(Some(edns));
+        }
+        let CacheConfig {
+            min_ttl, max_ttl, ..
+        } = self.config;
+        map_ttls(&mut response, |ttl| ttl.clamp(min_ttl, max_ttl));
+
+        if self.entries.len() >= self.config.size && !self.entries.contains_key(&key) {
+            self.evict(now);
+        }
+        self.entries.insert(
+            key,
+            Entry {
+                response,
+                stored: now,
+                expires: now + ttl,
+            },
+        );
+    }
+
+    /// Makes room for one more entry, dropping the expired ones or, if there
+    /// are none, the one expiring first.
+    fn evict(&mut self, now: Instant) {
+        self.entries.retain(|_, entry| entry.expires > now);
+        if self.entries.len() < self.config.size {
+            return;
+        }
+        let first = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.expires)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = first {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+fn key(query: &Message) -> Option<Key> {
+    match query.questions() {
+        [question] => Some(Key {
+            name: question.name().clone(),
+            r#type: question.record_type(),
+            class: question.class(),
+            dnssec_ok: query.edns().is_some_and(|edns| edns.dnssec_ok),
+            checking_disabled: query.header().checking_disabled(),
+        }),
+        _ => None,
+    }
+}
+
+/// Whether an EDNS option is only meant for the client the response went
+/// to.
+fn is_per_client(option: &EdnsOption) -> bool {
+    matches!(
+        option,
+        EdnsOption::Cookie(_)
+            | EdnsOption::ClientSubnet(_)
+            | EdnsOption::Padding(_)
+            | EdnsOption::TcpKeepalive(_)
+    )
+}
+
+/// Changes the TTLs of all records, the OPT record aside.
+fn map_ttls(message: &mut Message, f: impl Fn(u32) -> u32) {
+    for record in message.answers_mut().iter_mut() {
+        record.set_ttl(f(record.ttl().as_secs() as u32));
+    }
+    for record in message.authorities_mut().iter_mut() {
+        record.set_ttl(f(record.ttl().as_secs() as u32));
+    }
+    for record in message.additionals_mut().iter_mut() {
+        record.set_ttl(f(record.ttl().as_secs() as u32));
+    }
+}
+
+/// The lower of the TTL and the minimum field of the SOA record in the
+/// authority section, if there is one.
+fn negative_ttl(response: &Message) -> Option<Duration> {
+    response
+        .authorities()
+        .iter()
+        .find_map(|record| match record.rdata() {
+            ResourceData::Soa(soa) => {
+                Some(record.ttl().min(Duration::from_secs(soa.minimum.into())))
+            }
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scopa::testutil::{fake_negative_response, fake_query, fake_response};
+    use scopa::Cookie;
+
+    #[test]
+    fn test_get() {
+        let mut cache = Cache::new(CacheConfig::default());
+        let now = Instant::now();
+        cache.insert(&fake_query("example.com", RecordType::A), &fake_response("example.com", RecordType::A, "192.0.2.1"), now);
+
+        let mut query = fake_query("EXAMPLE.com", RecordType::A);
+        query.set_id(7);
+        let response = cache.get(&query, now + Duration::from_secs(100)).unwrap();
+        assert!(response.is_response_to(&query));
+        assert_eq!(Duration::from_secs(200), response.answers()[0].ttl());
+
+        assert!(cache.get(&fake_query("example.com", RecordType::Aaaa), now).is_none());
+        assert!(cache.get(&query, now + Duration::from_secs(300)).is_none());
+
+        // the question is answered as it was asked
+        assert_eq!("EXAMPLE.com.", response.questions()[0].name().to_string());
+    }
+
+    #[test]
+    fn test_dnssec_bits() {
+        let mut cache = Cache::new(CacheConfig::default());
+        let now = Instant::now();
+        let mut query = fake_query("example.com", RecordType::A);
+        query.set_edns(Some(Edns { dnssec_ok: true, ..Edns::default() }));
+        cache.insert(&query, &fake_response("example.com", RecordType::A, "192.0.2.1"), now);
+
+        // only queries with DO set get responses to queries with it
+        assert!(cache.get(&query, now).is_some());
+        query.set_edns(Some(Edns::default()));
+        assert!(cache.get(&query, now).is_none());
+        assert!(cache.get(&fake_query("example.com", RecordType::A), now).is_none());
+    }
+
+    #[test]
+    fn test_edns() {
+        let mut cache = Cache::new(CacheConfig::default());
+        let now = Instant::now();
+        let with_cookie = |client: u8| {
+            let mut query = fake_query("example.com", RecordType::A);
+            query.set_edns(Some(Edns { udp_payload: 4096 - u16::from(client), options: vec![EdnsOption::Cookie(Cookie::new([client; 8]))], ..Edns::default() }));
+            query
+        };
+        let mut response = fake_response("example.com", RecordType::A, "192.0.2.1");
+        response.set_edns(Some(Edns { options: vec![EdnsOption::Cookie(Cookie::new([1; 8])), EdnsOption::Nsid(b"ns1".to_vec()), EdnsOption::Padding(8)], ..Edns::default() }));
+        cache.insert(&with_cookie(1), &response, now);
+
+        // the cookie isn't handed to others, and the payload size is theirs
+        for client in [1, 2] {
+            let edns = cache.get(&with_cookie(client), now).unwrap().edns().cloned().unwrap();
+            assert_eq!((4096 - u16::from(client), vec![EdnsOption::Nsid(b"ns1".to_vec())]), (edns.udp_payload, edns.options));
+        }
+        // queries without EDNS get the same response, but without it
+        assert_eq!(None, cache.get(&fake_query("example.com", RecordType::A), now).unwrap().edns());
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut cache = Cache::new(CacheConfig { size: 2, min_ttl: 0, max_ttl: 60 });
+        let now = Instant::now();
+        cache.insert(&fake_query("example.com", RecordType::A), &fake_response("example.com", RecordType::A, "192.0.2.1"), now);
+        assert_eq!(Duration::from_secs(60), cache.get(&fake_query("example.com", RecordType::A), now).unwrap().answers()[0].ttl());
+
+        // negative responses are cached for the SOA minimum, other errors not
+        cache.insert(&fake_query("nx.example.com", RecordType::A), &fake_negative_response("nx.example.com", RecordType::A, Rcode::NxDomain, "example.com"), now + Duration::from_secs(1));
+        assert!(cache.get(&fake_query("nx.example.com", RecordType::A), now + Duration::from_secs(60)).is_some());
+        cache.insert(&fake_query("servfail.example.com", RecordType::A), &fake_negative_response("servfail.example.com", RecordType::A, Rcode::ServFail, "example.com"), now);
+        assert!(cache.get(&fake_query("servfail.example.com", RecordType::A), now).is_none());
+
+        // a full cache drops the entry expiring first
+        cache.insert(&fake_query("www.example.com", RecordType::A), &fake_response("www.example.com", RecordType::A, "192.0.2.2"), now + Duration::from_secs(2));
+        assert_eq!(2, cache.entries.len());
+        assert!(cache.get(&fake_query("example.com", RecordType::A), now).is_none());
+
+        cache.set_config(CacheConfig { size: 1, ..cache.config }, now);
+        assert_eq!(1, cache.entries.len());
+        assert!(cache.get(&fake_query("www.example.com", RecordType::A), now + Duration::from_secs(2)).is_some());
+    }
+}
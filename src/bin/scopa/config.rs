@@ -0,0 +1,937 @@
+//! The configuration file, in TOML:
+//!
+//! ```toml
+//! listen = ["127.0.0.1:53", "[::1]:53"]
+//! upstreams = [
+//!     "1.1.1.1",
+//!     "9.9.9.9:53",
+//!     "tls://[2620:fe::fe]#dns.quad9.net",
+//!     { address = "tls://1.1.1.1", pins = ["<base64 hash>"] },
+//!     "https://dns.quad9.net/dns-query#9.9.9.9",
+//!     "quic://94.140.14.14#dns.adguard-dns.com",
+//!     { address = "https://dns.google/resolve", format = "json" },
+//! ]
+//! timeout_ms = 2000
+//! tcp_idle_timeout_ms = 10000
+//! selection = "fastest"
+//! probe_interval_secs = 60
+//! race = 1
+//! blocklists = ["/etc/scopa/ads.txt"]
+//!
+//! [failover]
+//! max_failures = 3
+//! down_secs = 30
+//!
+//! [cache]
+//! size = 4096
+//! min_ttl = 0
+//! max_ttl = 86400
+//!
+//! [[local]]
+//! name = "router.lan"
+//! type = "A"
+//! data = "192.168.1.1"
+//!
+//! [log]
+//! level = "info"
+//! ```
+//!
+//! Addresses are on port 53 unless they have one. Every setting can be left
+//! out, see [`Config::default`]. Upstreams are described at [`Upstream`].
+
+use std::collections::HashSet;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use std::{fs, io};
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use http::Uri;
+use rustls::pki_types::ServerName;
+use scopa::{Class, DomainName, GenericData, RecordType, ResourceData, ResourceRecord};
+use serde::{Deserialize, Deserializer};
+
+/// The configuration file as written, before the files it names are read.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(deserialize_with = "addresses")]
+    listen: Vec<SocketAddr>,
+    #[serde(deserialize_with = "upstreams")]
+    upstreams: Vec<Upstream>,
+    timeout_ms: u64,
+    tcp_idle_timeout_ms: u64,
+    selection: Selection,
+    probe_interval_secs: u64,
+    race: usize,
+    failover: FailoverConfig,
+    cache: CacheConfig,
+    blocklists: Vec<PathBuf>,
+    local: Vec<LocalRecord>,
+    log: LogConfig,
+}
+
+impl Default for ConfigFile {
+    fn default() -> Self {
+        let Config {
+            listen,
+            upstreams,
+            timeout,
+            tcp_idle_timeout,
+            selection,
+            probe_interval,
+            race,
+            failover,
+            cache,
+            level,
+            ..
+        } = Config::default();
+        ConfigFile {
+            listen,
+            upstreams,
+            timeout_ms: timeout.as_millis() as u64,
+            tcp_idle_timeout_ms: tcp_idle_timeout.as_millis() as u64,
+            selection,
+            probe_interval_secs: probe_interval.as_secs(),
+            race,
+            failover,
+            cache,
+            blocklists: Vec::new(),
+            local: Vec::new(),
+            log: LogConfig { level },
+        }
+    }
+}
+
+/// Which upstream queries go to first.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Selection {
+    /// The first one in the configuration.
+    #[default]
+    Ordered,
+    /// The one with the lowest average response time.
+    Fastest,
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selection::Ordered => write!(f, "ordered"),
+            Selection::Fastest => write!(f, "fastest"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FailoverConfig {
+    /// Upstreams that time out or answer SERVFAIL this many times in a row
+    /// are skipped while there are others.
+    pub max_failures: u32,
+    /// For this many seconds, after which they get another try.
+    pub down_secs: u64,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        FailoverConfig {
+            max_failures: 3,
+            down_secs: 30,
+        }
+    }
+}
+
+impl FailoverConfig {
+    pub fn down_time(&self) -> Duration {
+        Duration::from_secs(self.down_secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// The most responses kept, 0 to disable the cache.
+    pub size: usize,
+    /// The TTLs of cached records are raised to at least this many seconds.
+    pub min_ttl: u32,
+    /// And lowered to at most this many.
+    pub max_ttl: u32,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            size: 4096,
+            min_ttl: 0,
+            max_ttl: 86400,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn min_ttl(&self) -> Duration {
+        Duration::from_secs(self.min_ttl.into())
+    }
+
+    pub fn max_ttl(&self) -> Duration {
+        Duration::from_secs(self.max_ttl.into())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LocalRecord {
+    name: DomainName,
+    #[serde(rename = "type")]
+    record_type: String,
+    #[serde(default = "local_ttl")]
+    ttl: u32,
+    data: String,
+}
+
+fn local_ttl() -> u32 {
+    300
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LogConfig {
+    level: Level,
+}
+
+/// How much is logged, each level including the ones before it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    /// Only what goes wrong.
+    Error,
+    /// And what the proxy is doing.
+    #[default]
+    Info,
+    /// And every query and response.
+    Debug,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Error => write!(f, "error"),
+            Level::Info => write!(f, "info"),
+            Level::Debug => write!(f, "debug"),
+        }
+    }
+}
+
+/// The validated configuration, with the blocklists read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub listen: Vec<SocketAddr>,
+    /// Queries go to the first one that is up, or the fastest, and to the
+    /// next ones in turn if it fails.
+    pub upstreams: Vec<Upstream>,
+    /// How long to wait for a response from each upstream before trying the
+    /// next, or answering with SERVFAIL after the last.
+    pub timeout: Duration,
+    /// How long TCP connections from clients are kept open without a query.
+    pub tcp_idle_timeout: Duration,
+    pub selection: Selection,
+    /// How often the upstreams queries don't go to are sent one anyway to
+    /// measure them, when selecting the fastest.
+    pub probe_interval: Duration,
+    /// How many upstreams are sent each query at once, the first to give a
+    /// response other than SERVFAIL winning. The others are tried in turn
+    /// if none does.
+    pub race: usize,
+    pub failover: FailoverConfig,
+    pub cache: CacheConfig,
+    /// Names answered with NXDOMAIN, together with all names below them.
+    pub blocked: HashSet<DomainName>,
+    /// Records answered without asking upstream.
+    pub local: Vec<ResourceRecord>,
+    pub level: Level,
+}
+
+impl Default for Config {
+    /// Listens on `127.0.0.1:2000` and forwards to `1.1.1.1`, waiting two
+    /// seconds for responses and caching up to 4096 of them for at most a
+    /// day. Idle TCP connections are closed after ten seconds, as RFC 7766
+    /// suggests.
+    fn default() -> Self {
+        Config {
+            listen: vec![SocketAddr::from(([127, 0, 0, 1], 2000))],
+            upstreams: vec![Upstream::Plain(SocketAddr::from(([1, 1, 1, 1], 53)))],
+            timeout: Duration::from_secs(2),
+            tcp_idle_timeout: Duration::from_secs(10),
+            selection: Selection::default(),
+            probe_interval: Duration::from_secs(60),
+            race: 1,
+            failover: FailoverConfig::default(),
+            cache: CacheConfig::default(),
+            blocked: HashSet::new(),
+            local: Vec::new(),
+            level: Level::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|error| ConfigError::Read {
+            path: path.to_owned(),
+            error,
+        })?;
+        Config::parse(&text).map_err(|error| match error {
+            ConfigError::Syntax(message) => {
+                ConfigError::Syntax(format!("{}: {message}", path.display()))
+            }
+            error => error,
+        })
+    }
+
+    /// Parses and validates a configuration, reading the blocklists it
+    /// names.
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        let file: ConfigFile =
+            toml::from_str(text).map_err(|error| ConfigError::Syntax(error.to_string()))?;
+
+        if file.listen.is_empty() {
+            return Err(ConfigError::Invalid("no addresses to listen on".to_owned()));
+        }
+        if file.upstreams.is_empty() {
+            return Err(ConfigError::Invalid("no upstreams".to_owned()));
+        }
+        if file.timeout_ms == 0 || file.tcp_idle_timeout_ms == 0 {
+            return Err(ConfigError::Invalid("a timeout of 0".to_owned()));
+        }
+        if file.probe_interval_secs == 0 {
+            return Err(ConfigError::Invalid("a probe interval of 0".to_owned()));
+        }
+        if file.race == 0 {
+            return Err(ConfigError::Invalid("a race of 0 upstreams".to_owned()));
+        }
+        if file.failover.max_failures == 0 {
+            return Err(ConfigError::Invalid("a max_failures of 0".to_owned()));
+        }
+        if file.cache.min_ttl > file.cache.max_ttl {
+            return Err(ConfigError::Invalid(format!(
+                "the cache's min_ttl {} is above its max_ttl {}",
+                file.cache.min_ttl, file.cache.max_ttl
+            )));
+        }
+
+        let mut blocked = HashSet::new();
+        for path in &file.blocklists {
+            read_blocklist(path, &mut blocked)?;
+        }
+        let local = file
+            .local
+            .into_iter()
+            .map(LocalRecord::into_record)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Config {
+            listen: file.listen,
+            upstreams: file.upstreams,
+            timeout: Duration::from_millis(file.timeout_ms),
+            tcp_idle_timeout: Duration::from_millis(file.tcp_idle_timeout_ms),
+            selection: file.selection,
+            probe_interval: Duration::from_secs(file.probe_interval_secs),
+            race: file.race,
+            failover: file.failover,
+            cache: file.cache,
+            blocked,
+            local,
+            level: file.log.level,
+        })
+    }
+
+    /// What is different in `new`, a line for each setting, to report after
+    /// reloading.
+    pub fn changes(&self, new: &Config) -> Vec<String> {
+        fn list<T: fmt::Display>(items: &[T]) -> String {
+            items
+                .iter()
+                .map(T::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+        let failover = |failover: &FailoverConfig| {
+            format!(
+                "max_failures {}, down_secs {}",
+                failover.max_failures, failover.down_secs
+            )
+        };
+        let cache = |cache: &CacheConfig| {
+            format!(
+                "size {}, min_ttl {}, max_ttl {}",
+                cache.size, cache.min_ttl, cache.max_ttl
+            )
+        };
+
+        let mut changes = Vec::new();
+        if self.listen != new.listen {
+            changes.push(format!(
+                "listen: {} -> {} (takes a restart)",
+                list(&self.listen),
+                list(&new.listen)
+            ));
+        }
+        if self.upstreams != new.upstreams {
+            changes.push(format!(
+                "upstreams: {} -> {}",
+                list(&self.upstreams),
+                list(&new.upstreams)
+            ));
+        }
+        if self.timeout != new.timeout {
+            changes.push(format!("timeout: {:?} -> {:?}", self.timeout, new.timeout));
+        }
+        if self.tcp_idle_timeout != new.tcp_idle_timeout {
+            changes.push(format!(
+                "tcp idle timeout: {:?} -> {:?}",
+                self.tcp_idle_timeout, new.tcp_idle_timeout
+            ));
+        }
+        if self.selection != new.selection {
+            changes.push(format!(
+                "selection: {} -> {}",
+                self.selection, new.selection
+            ));
+        }
+        if self.probe_interval != new.probe_interval {
+            changes.push(format!(
+                "probe interval: {:?} -> {:?}",
+                self.probe_interval, new.probe_interval
+            ));
+        }
+        if self.race != new.race {
+            changes.push(format!("race: {} -> {} upstreams", self.race, new.race));
+        }
+        if self.failover != new.failover {
+            changes.push(format!(
+                "failover: {} -> {}",
+                failover(&self.failover),
+                failover(&new.failover)
+            ));
+        }
+        if self.cache != new.cache {
+            changes.push(format!(
+                "cache: {} -> {}",
+                cache(&self.cache),
+                cache(&new.cache)
+            ));
+        }
+        let blocked = new.blocked.difference(&self.blocked).count();
+        let unblocked = self.blocked.difference(&new.blocked).count();
+        if blocked + unblocked > 0 {
+            changes.push(format!(
+                "blocklists: {blocked} names added, {unblocked} removed"
+            ));
+        }
+        let added = new
+            .local
+            .iter()
+            .filter(|record| !self.local.contains(record))
+            .count();
+        let removed = self
+            .local
+            .iter()
+            .filter(|record| !new.local.contains(record))
+            .count();
+        if added + removed > 0 {
+            changes.push(format!("local records: {added} added, {removed} removed"));
+        }
+        if self.level != new.level {
+            changes.push(format!("log level: {} -> {}", self.level, new.level));
+        }
+        changes
+    }
+
+    /// Whether `name` or one of the names above it is blocked.
+    pub fn is_blocked(&self, name: &DomainName) -> bool {
+        !self.blocked.is_empty() && name.ancestors().any(|name| self.blocked.contains(&name))
+    }
+}
+
+impl LocalRecord {
+    fn into_record(self) -> Result<ResourceRecord, ConfigError> {
+        let invalid = |reason: &str| {
+            ConfigError::Invalid(format!(
+                "local record {} {}: {reason}",
+                self.name, self.record_type
+            ))
+        };
+        let record_type: RecordType = self
+            .record_type
+            .parse()
+            .map_err(|_| invalid("unknown type"))?;
+        let address = || {
+            self.data
+                .parse::<IpAddr>()
+                .map_err(|_| invalid("invalid address"))
+        };
+        let name = || {
+            self.data
+                .parse::<DomainName>()
+                .map_err(|error| invalid(&error.to_string()))
+        };
+
+        let data = match record_type {
+            RecordType::A => match address()? {
+                IpAddr::V4(address) => ResourceData::A(address),
+                IpAddr::V6(_) => return Err(invalid("an IPv6 address")),
+            },
+            RecordType::Aaaa => match address()? {
+                IpAddr::V6(address) => ResourceData::Aaaa(address),
+                IpAddr::V4(_) => return Err(invalid("an IPv4 address")),
+            },
+            RecordType::Cname => ResourceData::Cname(name()?),
+            RecordType::Ptr => ResourceData::Ptr(name()?),
+            RecordType::Txt if self.data.len() <= 255 => {
+                ResourceData::Txt(vec![self.data.clone().into_bytes()])
+            }
+            RecordType::Txt => return Err(invalid("longer than 255 bytes")),
+            _ => ResourceData::Unknown {
+                r#type: record_type,
+                data: self
+                    .data
+                    .parse::<GenericData>()
+                    .map_err(|error| invalid(&error.to_string()))?,
+            },
+        };
+
+        Ok(ResourceRecord::new(
+            self.name.clone(),
+            Class::In,
+            self.ttl,
+            data,
+        ))
+    }
+}
+
+/// Adds the names in a blocklist to `blocked`. The lists have a name per
+/// line, or an address followed by a name as in hosts files, and comments
+/// starting with `#`.
+fn read_blocklist(path: &Path, blocked: &mut HashSet<DomainName>) -> Result<(), ConfigError> {
+    let text = fs::read_to_string(path).map_err(|error| ConfigError::Read {
+        path: path.to_owned(),
+        error,
+    })?;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let Some(name) = line.split_whitespace().last() else {
+            continue;
+        };
+        let name = name.parse().map_err(|error| {
+            ConfigError::Invalid(format!(
+                "{}:{}: {name:?}: {error}",
+                path.display(),
+                number + 1
+            ))
+        })?;
+        blocked.insert(name);
+    }
+
+    Ok(())
+}
+
+/// Parses an IP address with an optional port, 53 if it has none.
+pub fn parse_address(address: &str) -> Result<SocketAddr, String> {
+    parse_address_on(address, 53)
+}
+
+fn parse_address_on(address: &str, port: u16) -> Result<SocketAddr, String> {
+    // IPv6 addresses may be in brackets without a port, as in URLs
+    let ip = address
+        .strip_prefix('[')
+        .and_then(|ip| ip.strip_suffix(']'))
+        .unwrap_or(address);
+    address
+        .parse()
+        .or_else(|_| ip.parse().map(|ip: IpAddr| SocketAddr::new(ip, port)))
+        .map_err(|_| format!("{address:?} is neither an IP address nor one with a port"))
+}
+
+fn addresses<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<SocketAddr>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|address| parse_address(address).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// An upstream resolver and how queries get to it, written as an address
+/// like `1.1.1.1` or `[2620:fe::fe]:53` for plain DNS, or with `tls://` in
+/// front for DNS over TLS, optionally with the name to check the server's
+/// certificate for after a `#`: `tls://9.9.9.9#dns.quad9.net`, and the same
+/// with `quic://` for DNS over QUIC. DNS over HTTPS upstreams are written
+/// as the URL of their endpoint, optionally with the address to connect to
+/// after a `#`: `https://dns.quad9.net/dns-query#9.9.9.9`.
+///
+/// In the configuration file, an upstream can also be a table with the
+/// `address` and `pins`, the base64 SHA-256 hashes of the public keys
+/// (SPKI) the certificate of an upstream over TLS, HTTPS or QUIC may have.
+/// HTTPS upstreams can have the `format = "json"` of the JSON APIs of
+/// Google and Cloudflare, like `https://dns.google/resolve`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Upstream {
+    /// Over UDP, and TCP for truncated responses.
+    Plain(SocketAddr),
+    /// Over TLS (RFC 7858).
+    Tls(TlsUpstream),
+    /// Over HTTPS (RFC 8484).
+    Https(HttpsUpstream),
+    /// Over QUIC (RFC 9250).
+    Quic(TlsUpstream),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TlsUpstream {
+    /// On port 853 unless another is given, which is TCP for TLS and UDP for
+    /// QUIC.
+    pub address: SocketAddr,
+    /// The name sent with SNI and checked against the certificate, the IP
+    /// address unless another is given.
+    pub name: String,
+    /// When there are any, the certificate also needs to have one of these
+    /// public keys.
+    pub pins: Vec<[u8; 32]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HttpsUpstream {
+    /// The URL queries are posted to.
+    pub uri: Uri,
+    /// The address to connect to, instead of the ones the system resolves
+    /// the host name to. Without it, resolving the name mustn't need this
+    /// proxy.
+    pub address: Option<SocketAddr>,
+    /// When there are any, the certificate also needs to have one of these
+    /// public keys.
+    pub pins: Vec<[u8; 32]>,
+    pub format: HttpsFormat,
+}
+
+/// How queries and responses go over HTTPS.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpsFormat {
+    /// Posted in the wire format, `application/dns-message` (RFC 8484).
+    #[default]
+    Message,
+    /// Asked for with the name and type in the URL, and answered in the
+    /// JSON format of the APIs of Google and Cloudflare,
+    /// `application/dns-json`.
+    Json,
+}
+
+impl HttpsUpstream {
+    /// The host name of the URL, or its IP address without brackets.
+    pub fn host(&self) -> &str {
+        let host = self.uri.host().unwrap_or_default();
+        host.strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host)
+    }
+
+    pub fn port(&self) -> u16 {
+        self.uri.port_u16().unwrap_or(443)
+    }
+}
+
+impl FromStr for Upstream {
+    type Err = String;
+
+    fn from_str(upstream: &str) -> Result<Upstream, String> {
+        let Some((scheme, rest)) = upstream.split_once("://") else {
+            return parse_address(upstream).map(Upstream::Plain);
+        };
+        match scheme {
+            "tls" | "quic" => {
+                let (address, name) = match rest.split_once('#') {
+                    Some((address, name)) => (address, Some(name)),
+                    None => (rest, None),
+                };
+                let address = parse_address_on(address, 853)?;
+                let name = name.map_or_else(|| address.ip().to_string(), str::to_owned);
+                if ServerName::try_from(name.as_str()).is_err() {
+                    return Err(format!("{name:?} is not a valid server name"));
+                }
+                let tls = TlsUpstream {
+                    address,
+                    name,
+                    pins: Vec::new(),
+                };
+                Ok(match scheme {
+                    "tls" => Upstream::Tls(tls),
+                    _ => Upstream::Quic(tls),
+                })
+            }
+            "https" => {
+                let (url, address) = match upstream.split_once('#') {
+                    Some((url, address)) => (url, Some(address)),
+                    None => (upstream, None),
+                };
+                let uri: Uri = url
+                    .parse()
+                    .map_err(|_| format!("{url:?} is not a valid URL"))?;
+                let mut https = HttpsUpstream {
+                    uri,
+                    address: None,
+                    pins: Vec::new(),
+                    format: HttpsFormat::default(),
+                };
+                if ServerName::try_from(https.host()).is_err() {
+                    return Err(format!("{:?} is not a valid server name", https.host()));
+                }
+                https.address = address
+                    .map(|address| parse_address_on(address, https.port()))
+                    .transpose()?;
+                Ok(Upstream::Https(https))
+            }
+            _ => Err(format!("{upstream:?} has an unknown scheme {scheme:?}")),
+        }
+    }
+}
+
+/// The address, and the name if it isn't the IP address.
+impl fmt::Display for TlsUpstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name == self.address.ip().to_string() {
+            write!(f, "{}", self.address)
+        } else {
+            write!(f, "{}#{}", self.address, self.name)
+        }
+    }
+}
+
+impl fmt::Display for Upstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Upstream::Plain(address) => write!(f, "{address}"),
+            Upstream::Tls(tls) => write!(f, "tls://{tls}"),
+            Upstream::Quic(quic) => write!(f, "quic://{quic}"),
+            Upstream::Https(HttpsUpstream {
+                uri,
+                address: Some(address),
+                ..
+            }) => write!(f, "{uri}#{address}"),
+            Upstream::Https(https) => write!(f, "{}", https.uri),
+        }
+    }
+}
+
+fn upstreams<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Upstream>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Entry {
+        Address(String),
+        Table {
+            address: String,
+            #[serde(default)]
+            pins: Vec<String>,
+            format: Option<HttpsFormat>,
+        },
+    }
+
+    let parse = |entry: Entry| {
+        let (address, pins, format) = match entry {
+            Entry::Address(address) => (address, Vec::new(), None),
+            Entry::Table {
+                address,
+                pins,
+                format,
+            } => (address, pins, format),
+        };
+        let mut upstream = address.parse()?;
+        match (&mut upstream, format) {
+            (Upstream::Https(https), Some(format)) => https.format = format,
+            (_, Some(_)) => {
+                return Err(format!("{address:?} has a format, but isn't over HTTPS"));
+            }
+            (_, None) => {}
+        }
+        match &mut upstream {
+            Upstream::Tls(TlsUpstream {
+                pins: upstream_pins,
+                ..
+            })
+            | Upstream::Https(HttpsUpstream {
+                pins: upstream_pins,
+                ..
+            })
+            | Upstream::Quic(TlsUpstream {
+                pins: upstream_pins,
+                ..
+            }) => {
+                *upstream_pins = pins
+                    .iter()
+                    .map(|pin| parse_pin(pin))
+                    .collect::<Result<_, _>>()?
+            }
+            Upstream::Plain(_) if !pins.is_empty() => {
+                return Err(format!("{address:?} has pins, but is plain DNS"));
+            }
+            Upstream::Plain(_) => {}
+        }
+        Ok(upstream)
+    };
+    Vec::<Entry>::deserialize(deserializer)?
+        .into_iter()
+        .map(|entry| parse(entry).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+fn parse_pin(pin: &str) -> Result<[u8; 32], String> {
+    BASE64_STANDARD
+        .decode(pin)
+        .ok()
+        .and_then(|hash| hash.try_into().ok())
+        .ok_or_else(|| format!("the pin {pin:?} is not a base64 SHA-256 hash"))
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration or a blocklist couldn't be read.
+    Read { path: PathBuf, error: io::Error },
+    /// The configuration isn't valid TOML or has unknown settings or
+    /// values of the wrong type.
+    Syntax(String),
+    /// A setting has a value that can't be used.
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, error } => {
+                write!(f, "can't read {}: {error}", path.display())
+            }
+            ConfigError::Syntax(message) => write!(f, "{message}"),
+            ConfigError::Invalid(message) => write!(f, "invalid configuration: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_parse() {
+        let blocklist = std::env::temp_dir().join(format!("scopa-test-blocklist-{}", std::process::id()));
+        fs::write(&blocklist, "# ads\nads.example\n0.0.0.0 tracker.example # hosts format\n\n").unwrap();
+        let text = format!(
+            r#"
+            listen = ["[::1]:5353"]
+            upstreams = ["9.9.9.9", "149.112.112.112:53"]
+            blocklists = [{blocklist:?}]
+
+            [cache]
+            max_ttl = 3600
+
+            [[local]]
+            name = "router.lan"
+            type = "a"
+            data = "192.168.1.1"
+
+            [log]
+            level = "debug"
+            "#
+        );
+        let config = Config::parse(&text).unwrap();
+        fs::remove_file(&blocklist).unwrap();
+
+        assert_eq!(vec!["[::1]:5353".parse::<SocketAddr>().unwrap()], config.listen);
+        assert_eq!(vec![Upstream::Plain(SocketAddr::from(([9, 9, 9, 9], 53))), Upstream::Plain(SocketAddr::from(([149, 112, 112, 112], 53)))], config.upstreams);
+        assert_eq!(CacheConfig { size: 4096, min_ttl: 0, max_ttl: 3600 }, config.cache);
+        assert!(config.is_blocked(&"www.ADS.example".parse().unwrap()) && config.is_blocked(&"tracker.example".parse().unwrap()));
+        assert!(!config.is_blocked(&"example".parse().unwrap()));
+        assert_eq!(vec![ResourceRecord::new("router.lan".parse().unwrap(), Class::In, 300, ResourceData::A(Ipv4Addr::new(192, 168, 1, 1)))], config.local);
+        assert_eq!(Level::Debug, config.level);
+
+        assert_eq!(Config::default(), Config::parse("").unwrap());
+    }
+
+    #[test]
+    fn test_upstreams() {
+        let tls = |address: &str, name: &str, pins: Vec<[u8; 32]>| Upstream::Tls(TlsUpstream { address: address.parse().unwrap(), name: name.to_owned(), pins });
+        let config = Config::parse(r#"upstreams = ["tls://9.9.9.9", "tls://[2620:fe::fe]:8853#dns.quad9.net", { address = "tls://1.1.1.1#one.one.one.one", pins = ["AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8="] }]"#).unwrap();
+        assert_eq!(vec![tls("9.9.9.9:853", "9.9.9.9", vec![]), tls("[2620:fe::fe]:8853", "dns.quad9.net", vec![]), tls("1.1.1.1:853", "one.one.one.one", vec![std::array::from_fn(|i| i as u8)])], config.upstreams);
+        assert_eq!(vec!["tls://9.9.9.9:853", "tls://[2620:fe::fe]:8853#dns.quad9.net"], config.upstreams[..2].iter().map(Upstream::to_string).collect::<Vec<_>>());
+        assert_eq!(Ok(Upstream::Plain("1.1.1.1:53".parse().unwrap())), "1.1.1.1".parse());
+        assert_eq!(Ok(tls("[2620:fe::fe]:853", "dns.quad9.net", vec![])), "tls://[2620:fe::fe]#dns.quad9.net".parse());
+        let quic = "quic://94.140.14.14#dns.adguard-dns.com".parse::<Upstream>().unwrap();
+        assert_eq!((Upstream::Quic(TlsUpstream { address: "94.140.14.14:853".parse().unwrap(), name: "dns.adguard-dns.com".to_owned(), pins: vec![] }), "quic://94.140.14.14:853#dns.adguard-dns.com"), (quic.clone(), quic.to_string().as_str()));
+
+        let config = Config::parse(r#"upstreams = ["https://dns.quad9.net/dns-query", { address = "https://[2620:fe::fe]:8443/dns-query#[2620:fe::9]", pins = ["AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8="] }]"#).unwrap();
+        let [Upstream::Https(quad9), Upstream::Https(ipv6)] = &config.upstreams[..] else { panic!("{:?}", config.upstreams) };
+        assert_eq!(("dns.quad9.net", 443, "/dns-query", None), (quad9.host(), quad9.port(), quad9.uri.path(), quad9.address));
+        assert_eq!(("2620:fe::fe", 8443, Some("[2620:fe::9]:8443".parse().unwrap()), 1), (ipv6.host(), ipv6.port(), ipv6.address, ipv6.pins.len()));
+        assert_eq!(vec!["https://dns.quad9.net/dns-query", "https://[2620:fe::fe]:8443/dns-query#[2620:fe::9]:8443"], config.upstreams.iter().map(Upstream::to_string).collect::<Vec<_>>());
+        assert_eq!(HttpsFormat::Message, quad9.format);
+        let config = Config::parse(r#"upstreams = [{ address = "https://dns.google/resolve", format = "json" }]"#).unwrap();
+        assert!(matches!(&config.upstreams[..], [Upstream::Https(HttpsUpstream { format: HttpsFormat::Json, .. })]));
+
+        let error = |text: &str| Config::parse(text).unwrap_err().to_string();
+        assert!(error(r#"upstreams = ["h3://9.9.9.9"]"#).contains("\"h3://9.9.9.9\" has an unknown scheme \"h3\""));
+        assert!(error(r#"upstreams = ["tls://9.9.9.9#not a name"]"#).contains("\"not a name\" is not a valid server name"));
+        assert!(error(r#"upstreams = ["https://dns quad9/dns-query"]"#).contains("is not a valid URL"));
+        assert!(error(r#"upstreams = ["https://dns.quad9.net/dns-query#dns.quad9.net"]"#).contains("\"dns.quad9.net\" is neither an IP address nor one with a port"));
+        assert!(error(r#"upstreams = [{ address = "1.1.1.1", pins = [] }, { address = "9.9.9.9", pins = ["AAEC"] }]"#).contains("\"9.9.9.9\" has pins, but is plain DNS"));
+        assert!(error(r#"upstreams = [{ address = "tls://9.9.9.9", format = "json" }]"#).contains("\"tls://9.9.9.9\" has a format, but isn't over HTTPS"));
+        assert!(error(r#"upstreams = [{ address = "tls://9.9.9.9", pins = ["AAEC"] }]"#).contains("the pin \"AAEC\" is not a base64 SHA-256 hash"));
+    }
+
+    #[test]
+    fn test_changes() {
+        let old = Config::parse(r#"local = [{ name = "router.lan", type = "A", data = "192.168.1.1" }]"#).unwrap();
+        assert!(old.changes(&old).is_empty());
+
+        let mut new = Config::parse(r#"
+            listen = ["127.0.0.1:53"]
+            upstreams = ["9.9.9.9", "tls://9.9.9.9"]
+            timeout_ms = 500
+            tcp_idle_timeout_ms = 30000
+            selection = "fastest"
+            race = 2
+            failover = { down_secs = 60 }
+            cache = { size = 100 }
+            local = [{ name = "nas.lan", type = "A", data = "192.168.1.2" }]
+            log = { level = "error" }
+        "#).unwrap();
+        new.blocked = ["ads.example".parse().unwrap()].into();
+        assert_eq!(vec![
+            "listen: 127.0.0.1:2000 -> 127.0.0.1:53 (takes a restart)",
+            "upstreams: 1.1.1.1:53 -> 9.9.9.9:53, tls://9.9.9.9:853",
+            "timeout: 2s -> 500ms",
+            "tcp idle timeout: 10s -> 30s",
+            "selection: ordered -> fastest",
+            "race: 1 -> 2 upstreams",
+            "failover: max_failures 3, down_secs 30 -> max_failures 3, down_secs 60",
+            "cache: size 4096, min_ttl 0, max_ttl 86400 -> size 100, min_ttl 0, max_ttl 86400",
+            "blocklists: 1 names added, 0 removed",
+            "local records: 1 added, 1 removed",
+            "log level: info -> error",
+        ], old.changes(&new));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let error = |text: &str| Config::parse(text).unwrap_err().to_string();
+        assert!(error("listen = 53").contains("invalid type"));
+        assert!(error("upstream = []").contains("unknown field `upstream`"));
+        assert!(error("upstreams = [\"dns.quad9.net\"]").contains("\"dns.quad9.net\" is neither an IP address nor one with a port"));
+        assert_eq!("invalid configuration: no upstreams", error("upstreams = []"));
+        assert_eq!("invalid configuration: a max_failures of 0", error("failover = { max_failures = 0 }"));
+        assert_eq!("invalid configuration: the cache's min_ttl 60 is above its max_ttl 30", error("cache = { min_ttl = 60, max_ttl = 30 }"));
+        assert_eq!("invalid configuration: local record router.lan. A: an IPv6 address", error("local = [{ name = \"router.lan\", type = \"A\", data = \"::1\" }]"));
+        assert!(error("blocklists = [\"/nonexistent/scopa\"]").starts_with("can't read /nonexistent/scopa: "));
+    }
+}
@@ -0,0 +1,392 @@
+//! Forwarding to upstreams over HTTPS (RFC 8484), posting the queries on
+//! one HTTP/2 connection per upstream that they share, or asking for them
+//! in the JSON format of the APIs of Google and Cloudflare.
+
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use h2::client::SendRequest;
+use http::header::{ACCEPT, CONTENT_TYPE};
+use http::{Request, StatusCode};
+use rustls::pki_types::ServerName;
+use rustls::RootCertStore;
+use scopa::{parse_message_detailed, Class, EdnsOption, Message, QUERY_BLOCK_SIZE};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+use crate::config::{HttpsFormat, HttpsUpstream};
+use crate::tls;
+
+const DNS_MESSAGE: &str = "application/dns-message";
+const DNS_JSON: &str = "application/dns-json";
+
+/// Forwards queries to upstreams over HTTPS, on a connection per upstream
+/// that is opened on first use and kept open until the server closes it.
+pub struct HttpsForwarder {
+    roots: Arc<RootCertStore>,
+    connections: Mutex<HashMap<HttpsUpstream, Slot>>,
+}
+
+/// The connection to an upstream, which the first query needing a new one
+/// opens while the others wait.
+type Slot = Arc<tokio::sync::Mutex<Option<SendRequest<Bytes>>>>;
+
+impl Default for HttpsForwarder {
+    fn default() -> Self {
+        HttpsForwarder::new(tls::default_roots())
+    }
+}
+
+impl HttpsForwarder {
+    pub fn new(roots: RootCertStore) -> Self {
+        HttpsForwarder {
+            roots: Arc::new(roots),
+            connections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sends a query to `upstream` in its format and waits for the response,
+    /// which comes back with the ID of the query.
+    pub async fn forward(&self, upstream: &HttpsUpstream, query: &Message) -> io::Result<Message> {
+        match upstream.format {
+            HttpsFormat::Message => self.post(upstream, query).await,
+            HttpsFormat::Json => self.get_json(upstream, query).await,
+        }
+    }
+
+    async fn post(&self, upstream: &HttpsUpstream, query: &Message) -> io::Result<Message> {
+        // an ID of 0 lets HTTP caches answer equal queries alike, and the
+        // query is padded as RFC 8467 recommends if it has EDNS
+        let mut sent = query.clone();
+        sent.set_id(0);
+        sent.pad_to_block(QUERY_BLOCK_SIZE)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+        let body = sent
+            .to_bytes()
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+        let request = Request::post(upstream.uri.clone())
+            .header(CONTENT_TYPE, DNS_MESSAGE)
+            .header(ACCEPT, DNS_MESSAGE)
+            .body(())
+            .expect("the request is valid");
+
+        let (response, message) = self
+            .send(upstream, request, Some(Bytes::from(body)))
+            .await?;
+        if response
+            .headers
+            .get(CONTENT_TYPE)
+            .is_none_or(|content_type| content_type.as_bytes() != DNS_MESSAGE.as_bytes())
+        {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{} didn't answer with {DNS_MESSAGE}", upstream.uri),
+            ));
+        }
+        let mut response = match parse_message_detailed(&message) {
+            Ok(response) if response.is_response_to(&sent) => response,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("the response from {} doesn't match the query", upstream.uri),
+                ))
+            }
+            Err(err) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("could not parse response: {err}\n{}", err.hexdump(&message)),
+                ))
+            }
+        };
+
+        // the padding was for the way here, not for the client
+        if let Some(mut edns) = response.edns().cloned() {
+            edns.options
+                .retain(|option| !matches!(option, EdnsOption::Padding(_)));
+            response.set_edns(Some(edns));
+        }
+        response.set_id(query.id());
+        Ok(response)
+    }
+
+    /// Asks for the records of the question of `query` with the name and
+    /// type in the URL, and the CD and DO bits if they are set. The JSON
+    /// answer comes with another content type from some servers, so any is
+    /// taken.
+    async fn get_json(&self, upstream: &HttpsUpstream, query: &Message) -> io::Result<Message> {
+        let question = match query.questions() {
+            [question] if question.class() == Class::In => question,
+            _ => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "the JSON format only has a single question of class IN",
+                ))
+            }
+        };
+        let separator = if upstream.uri.query().is_some() {
+            '&'
+        } else {
+            '?'
+        };
+        let mut uri = format!(
+            "{}{separator}name={}&type={}",
+            upstream.uri,
+            percent_encode(&question.name().to_string()),
+            u16::from(question.record_type())
+        );
+        if query.header().checking_disabled() {
+            uri.push_str("&cd=1");
+        }
+        if query.edns().is_some_and(|edns| edns.dnssec_ok) {
+            uri.push_str("&do=1");
+        }
+        let request = Request::get(uri)
+            .header(ACCEPT, DNS_JSON)
+            .body(())
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+
+        let (_, json) = self.send(upstream, request, None).await?;
+        let json = std::str::from_utf8(&json)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+        Message::from_dns_json(json, query).map_err(|err| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("could not read the answer from {}: {err}", upstream.uri),
+            )
+        })
+    }
+
+    /// Sends a request with `body`, returning the response if its status is
+    /// 200, with its body.
+    async fn send(
+        &self,
+        upstream: &HttpsUpstream,
+        request: Request<()>,
+        body: Option<Bytes>,
+    ) -> io::Result<(http::response::Parts, Vec<u8>)> {
+        let mut sender = self.connection(upstream).await?;
+        let (response, mut stream) = sender
+            .send_request(request, body.is_none())
+            .map_err(io::Error::other)?;
+        if let Some(body) = body {
+            stream.send_data(body, true).map_err(io::Error::other)?;
+        }
+        let response = response.await.map_err(io::Error::other)?;
+        if response.status() != StatusCode::OK {
+            return Err(io::Error::other(format!(
+                "{} answered with {}",
+                upstream.uri,
+                response.status()
+            )));
+        }
+
+        let (response, mut body) = response.into_parts();
+        let mut data = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(io::Error::other)?;
+            let _ = body.flow_control().release_capacity(chunk.len());
+            data.extend_from_slice(&chunk);
+            if data.len() > usize::from(u16::MAX) {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("the response from {} is too long", upstream.uri),
+                ));
+            }
+        }
+        Ok((response, data))
+    }
+
+    /// A handle to the open connection to `upstream`, ready for a request.
+    async fn connection(&self, upstream: &HttpsUpstream) -> io::Result<SendRequest<Bytes>> {
+        let slot = Arc::clone(
+            self.connections
+                .lock()
+                .unwrap()
+                .entry(upstream.clone())
+                .or_default(),
+        );
+        let mut slot = slot.lock().await;
+        if let Some(sender) = slot.clone() {
+            // which fails once the connection is closed
+            if let Ok(sender) = sender.ready().await {
+                return Ok(sender);
+            }
+        }
+        let sender = self.open(upstream).await?;
+        *slot = Some(sender.clone());
+        Ok(sender)
+    }
+
+    async fn open(&self, upstream: &HttpsUpstream) -> io::Result<SendRequest<Bytes>> {
+        let name = ServerName::try_from(upstream.host().to_owned())
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+        let stream = match upstream.address {
+            Some(address) => TcpStream::connect(address).await?,
+            // trying the addresses the system resolves the name to in turn
+            None => TcpStream::connect((upstream.host(), upstream.port())).await?,
+        };
+        let mut config = tls::client_config(&self.roots, &upstream.pins);
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        let stream = TlsConnector::from(Arc::new(config))
+            .connect(name, stream)
+            .await?;
+
+        let (sender, connection) = h2::client::handshake(stream)
+            .await
+            .map_err(io::Error::other)?;
+        let uri = upstream.uri.clone();
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                println!("The connection to {uri} failed: {err}");
+            }
+        });
+        Ok(sender)
+    }
+}
+
+/// Escapes everything but the unreserved characters of RFC 3986, for a
+/// value in the query of a URL.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::tests::{certificate, roots, server_config};
+    use http::Response;
+    use scopa::testutil::{fake_query, fake_response};
+    use scopa::{Edns, RecordType};
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+
+    struct Server {
+        address: SocketAddr,
+        roots: RootCertStore,
+        connections: Arc<AtomicUsize>,
+    }
+
+    impl Server {
+        /// Answers the queries posted to /dns-query and asked for at /resolve
+        /// in JSON with an A record, and everything else with 404.
+        async fn start() -> Server {
+            let certificate = certificate();
+            let mut config = server_config(&certificate);
+            config.alpn_protocols = vec![b"h2".to_vec()];
+            let acceptor = TlsAcceptor::from(Arc::new(config));
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let connections = Arc::new(AtomicUsize::new(0));
+            let server = Server { address: listener.local_addr().unwrap(), roots: roots(&certificate), connections: Arc::clone(&connections) };
+
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let (acceptor, connections) = (acceptor.clone(), Arc::clone(&connections));
+                    tokio::spawn(async move {
+                        let Ok(stream) = acceptor.accept(stream).await else { return };
+                        let Ok(mut connection) = h2::server::handshake(stream).await else { return };
+                        connections.fetch_add(1, Ordering::SeqCst);
+                        while let Some(Ok((request, mut respond))) = connection.accept().await {
+                            if request.uri().path() == "/resolve" {
+                                assert_eq!((&http::Method::GET, Some(DNS_JSON.as_bytes())), (request.method(), request.headers().get(ACCEPT).map(|value| value.as_bytes())));
+                                let pairs: Vec<_> = request.uri().query().unwrap().split('&').collect();
+                                assert!(pairs.contains(&"type=1"));
+                                let name = pairs.iter().find_map(|pair| pair.strip_prefix("name=")).unwrap();
+                                let response = fake_response(name, RecordType::A, "192.0.2.1");
+                                let mut stream = respond.send_response(Response::builder().header(CONTENT_TYPE, "application/x-javascript").body(()).unwrap(), false).unwrap();
+                                stream.send_data(Bytes::from(response.to_dns_json()), true).unwrap();
+                                continue;
+                            }
+                            if request.uri().path() != "/dns-query" {
+                                respond.send_response(Response::builder().status(StatusCode::NOT_FOUND).body(()).unwrap(), true).unwrap();
+                                continue;
+                            }
+                            assert_eq!((&http::Method::POST, Some(DNS_MESSAGE.as_bytes())), (request.method(), request.headers().get(CONTENT_TYPE).map(|value| value.as_bytes())));
+                            let mut body = request.into_body();
+                            let mut query = Vec::new();
+                            while let Some(data) = body.data().await {
+                                query.extend_from_slice(&data.unwrap());
+                            }
+                            let query = parse_message_detailed(&query).unwrap();
+                            assert_eq!(0, query.id());
+                            let mut response = fake_response(&query.questions()[0].name().to_string(), RecordType::A, "192.0.2.1");
+                            response.set_id(query.id());
+                            response.set_edns(query.edns().cloned());
+                            let mut stream = respond.send_response(Response::builder().header(CONTENT_TYPE, DNS_MESSAGE).body(()).unwrap(), false).unwrap();
+                            stream.send_data(Bytes::from(response.to_bytes().unwrap()), true).unwrap();
+                        }
+                    });
+                }
+            });
+            server
+        }
+
+        fn upstream(&self, url: &str, address: Option<SocketAddr>) -> HttpsUpstream {
+            let url = url.replace("PORT", &self.address.port().to_string());
+            HttpsUpstream { uri: url.parse().unwrap(), address, pins: Vec::new(), format: HttpsFormat::Message }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forward() {
+        let server = Server::start().await;
+        let forwarder = HttpsForwarder::new(server.roots.clone());
+        let mut query = fake_query("www.example.com", RecordType::A);
+        query.set_id(7);
+        query.set_edns(Some(Edns::default()));
+
+        // both queries go over the same connection, and the padding isn't
+        // passed on
+        let upstream = server.upstream("https://dns.test:PORT/dns-query", Some(server.address));
+        for _ in 0..2 {
+            let response = forwarder.forward(&upstream, &query).await.unwrap();
+            assert!(response.is_response_to(&query));
+            assert_eq!(Some(&Vec::new()), response.edns().map(|edns| &edns.options));
+        }
+        assert_eq!(1, server.connections.load(Ordering::SeqCst));
+
+        // without an address, the host name is resolved
+        let upstream = server.upstream("https://localhost:PORT/dns-query", None);
+        assert!(forwarder.forward(&upstream, &query).await.unwrap().is_response_to(&query));
+
+        let upstream = server.upstream("https://dns.test:PORT/other", Some(server.address));
+        assert!(forwarder.forward(&upstream, &query).await.unwrap_err().to_string().contains("404"));
+        let upstream = server.upstream("https://other.test:PORT/dns-query", Some(server.address));
+        assert!(forwarder.forward(&upstream, &query).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_forward_json() {
+        let server = Server::start().await;
+        let forwarder = HttpsForwarder::new(server.roots.clone());
+        let mut query = fake_query("www.example.com", RecordType::A);
+        query.set_id(7);
+        let upstream = HttpsUpstream { format: HttpsFormat::Json, ..server.upstream("https://dns.test:PORT/resolve", Some(server.address)) };
+
+        let response = forwarder.forward(&upstream, &query).await.unwrap();
+        assert!(response.is_response_to(&query));
+        assert_eq!(7, response.id());
+        assert_eq!("192.0.2.1", response.answers()[0].rdata().to_string());
+
+        let upstream = HttpsUpstream { format: HttpsFormat::Json, ..server.upstream("https://dns.test:PORT/other", Some(server.address)) };
+        assert!(forwarder.forward(&upstream, &query).await.unwrap_err().to_string().contains("404"));
+    }
+
+    #[test]
+    fn test_percent_encode() {
+        assert_eq!("www.example-1_~.com.", percent_encode("www.example-1_~.com."));
+        assert_eq!("a%20b%2Fc%C3%A9", percent_encode("a b/cé"));
+    }
+}
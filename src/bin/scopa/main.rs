@@ -0,0 +1,647 @@
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+use clap::Parser;
+use scopa::{parse_message_detailed, Class, Message, Rcode};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::task::JoinSet;
+use tokio::time;
+
+use crate::cache::Cache;
+use crate::config::{parse_address, Config, ConfigError, Level, Upstream};
+use crate::upstream::Upstreams;
+
+mod cache;
+mod config;
+mod https;
+mod quic;
+mod tcp;
+mod tls;
+mod upstream;
+
+/// A DNS forwarder, passing queries on to an upstream resolver.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// The TOML configuration file to load.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// The address and port to accept queries on, instead of the ones
+    /// configured [default: 127.0.0.1:2000].
+    #[arg(short, long, value_parser = parse_address)]
+    listen: Vec<SocketAddr>,
+    /// The resolver to forward queries to, on port 53 unless one is given,
+    /// over TLS like tls://9.9.9.9#dns.quad9.net, over HTTPS like
+    /// https://dns.quad9.net/dns-query or over QUIC like
+    /// quic://94.140.14.14#dns.adguard-dns.com, instead of the ones
+    /// configured [default: 1.1.1.1].
+    #[arg(short, long)]
+    upstream: Vec<Upstream>,
+    /// Prints every query and response.
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+impl Args {
+    fn config(&self) -> Result<Config, ConfigError> {
+        let mut config = match &self.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+        if !self.listen.is_empty() {
+            config.listen.clone_from(&self.listen);
+        }
+        if !self.upstream.is_empty() {
+            config.upstreams.clone_from(&self.upstream);
+        }
+        if self.verbose {
+            config.level = Level::Debug;
+        }
+        Ok(config)
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    let config = match args.config() {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("scopa: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match serve(args, config).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("scopa: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The configuration in use. Reloads replace it, while the queries being
+/// answered keep the one they started with.
+type SharedConfig = Arc<RwLock<Arc<Config>>>;
+
+/// Answers queries over UDP and TCP on all the configured addresses until
+/// one of them fails.
+async fn serve(args: Args, config: Config) -> io::Result<()> {
+    let cache = Arc::new(Mutex::new(Cache::new(config.cache)));
+    let config: SharedConfig = Arc::new(RwLock::new(Arc::new(config)));
+    reload_on_hangup(args, Arc::clone(&config), Arc::clone(&cache))?;
+
+    let proxy = Arc::new(Proxy::new(Arc::clone(&config), cache));
+    let current = proxy.config();
+    let mut listeners = JoinSet::new();
+    for &listen in &current.listen {
+        let client = UdpSocket::bind(listen).await?;
+        let tcp = TcpListener::bind(listen).await?;
+        if current.level >= Level::Info {
+            let upstreams: Vec<_> = current.upstreams.iter().map(Upstream::to_string).collect();
+            println!("Forwarding queries on {listen} to {}", upstreams.join(", "));
+        }
+        listeners.spawn(Arc::clone(&proxy).run(client));
+        listeners.spawn(Arc::clone(&proxy).run_tcp(tcp));
+    }
+
+    match listeners.join_next().await {
+        Some(result) => result.expect("a listener panicked"),
+        None => Ok(()),
+    }
+}
+
+/// Reloads the configuration on SIGHUP, reporting what changed.
+#[cfg(unix)]
+fn reload_on_hangup(args: Args, config: SharedConfig, cache: Arc<Mutex<Cache>>) -> io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            match reload(&args, &config, &cache) {
+                Ok(changes) if changes.is_empty() => {
+                    println!("Reloaded the configuration, nothing changed");
+                }
+                Ok(changes) => {
+                    println!("Reloaded the configuration:");
+                    for change in changes {
+                        println!("  {change}");
+                    }
+                }
+                Err(error) => {
+                    eprintln!("Could not reload the configuration, keeping the old one: {error}");
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn reload_on_hangup(_: Args, _: SharedConfig, _: Arc<Mutex<Cache>>) -> io::Result<()> {
+    Ok(())
+}
+
+/// Replaces the configuration with the one `args` give now, keeping the
+/// cached responses. Returns what changed.
+fn reload(
+    args: &Args,
+    config: &SharedConfig,
+    cache: &Mutex<Cache>,
+) -> Result<Vec<String>, ConfigError> {
+    let new = args.config()?;
+    let old = Arc::clone(&config.read().unwrap());
+    cache.lock().unwrap().set_config(new.cache, Instant::now());
+    let changes = old.changes(&new);
+    *config.write().unwrap() = Arc::new(new);
+    Ok(changes)
+}
+
+/// How a query reached the proxy, which limits the size of the response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transport {
+    Udp,
+    Tcp,
+}
+
+struct Proxy {
+    config: SharedConfig,
+    cache: Arc<Mutex<Cache>>,
+    upstreams: Arc<Upstreams>,
+}
+
+impl Proxy {
+    fn new(config: SharedConfig, cache: Arc<Mutex<Cache>>) -> Proxy {
+        Proxy {
+            config,
+            cache,
+            upstreams: Arc::default(),
+        }
+    }
+
+    fn config(&self) -> Arc<Config> {
+        Arc::clone(&self.config.read().unwrap())
+    }
+
+    fn verbose(&self) -> bool {
+        self.config().level >= Level::Debug
+    }
+
+    /// Answers the queries arriving at `client`, each in a task of its own.
+    async fn run(self: Arc<Self>, client: UdpSocket) -> io::Result<()> {
+        let client = Arc::new(client);
+        let mut request = [0; 512];
+        loop {
+            let (request_amt, src) = client.recv_from(&mut request).await?;
+            if self.verbose() {
+                println!("Received {} bytes from: {}", request_amt, src);
+            }
+
+            let request = request[..request_amt].to_vec();
+            let (proxy, client) = (Arc::clone(&self), Arc::clone(&client));
+            tokio::spawn(async move {
+                let Some(response) = proxy.handle(&request, src, Transport::Udp).await else {
+                    return;
+                };
+                match client.send_to(&response, src).await {
+                    Ok(_) if proxy.verbose() => {
+                        println!("Sent back a response of {} bytes", response.len());
+                    }
+                    Ok(_) => {}
+                    Err(err) => println!("Could not send a response to {src}: {err}"),
+                }
+            });
+        }
+    }
+
+    /// Answers the queries on the connections accepted by `listener`.
+    async fn run_tcp(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, src) = listener.accept().await?;
+            if self.verbose() {
+                println!("Accepted a connection from {src}");
+            }
+            tokio::spawn(Arc::clone(&self).serve_connection(stream, src));
+        }
+    }
+
+    /// Answers the queries on a connection until the client closes it or
+    /// sends none for the idle timeout. Pipelined queries are answered
+    /// concurrently, and so maybe out of order, as RFC 7766 allows.
+    async fn serve_connection(self: Arc<Self>, stream: TcpStream, src: SocketAddr) {
+        let (mut reader, writer) = stream.into_split();
+        let writer = Arc::new(tokio::sync::Mutex::new(writer));
+        loop {
+            let idle_timeout = self.config().tcp_idle_timeout;
+            let request = match time::timeout(idle_timeout, tcp::read_message(&mut reader)).await {
+                Ok(Ok(Some(request))) => request,
+                Ok(Ok(None)) => break,
+                Ok(Err(err)) => {
+                    println!("Could not read a query from {src}: {err}");
+                    break;
+                }
+                Err(_) => {
+                    if self.verbose() {
+                        println!("Closing the idle connection from {src}");
+                    }
+                    break;
+                }
+            };
+            if self.verbose() {
+                println!("Received {} bytes from: {}", request.len(), src);
+            }
+
+            let (proxy, writer) = (Arc::clone(&self), Arc::clone(&writer));
+            tokio::spawn(async move {
+                let Some(response) = proxy.handle(&request, src, Transport::Tcp).await else {
+                    return;
+                };
+                match tcp::write_message(&mut *writer.lock().await, &response).await {
+                    Ok(()) if proxy.verbose() => {
+                        println!("Sent back a response of {} bytes", response.len());
+                    }
+                    Ok(()) => {}
+                    Err(err) => println!("Could not send a response to {src}: {err}"),
+                }
+            });
+        }
+    }
+
+    /// The response to a request, from the local records, the blocklists,
+    /// the cache or upstream, in that order, or SERVFAIL if no upstream
+    /// answers. Returns `None` for requests that can't be parsed.
+    async fn handle(
+        &self,
+        request: &[u8],
+        client: SocketAddr,
+        transport: Transport,
+    ) -> Option<Vec<u8>> {
+        let config = self.config();
+        let query = match parse_message_detailed(request) {
+            Ok(parsed_request) => parsed_request,
+            Err(err) => {
+                println!("Could not parse request: {err}\n{}", err.hexdump(request));
+                return None;
+            }
+        };
+        if config.level >= Level::Debug {
+            println!("{query}");
+        }
+
+        let response = match answer_locally(&config, &query) {
+            Some(response) => response,
+            None => {
+                let cached = self.cache.lock().unwrap().get(&query, Instant::now());
+                match cached {
+                    Some(response) => response,
+                    None => match self.forward(&config, request, &query, client).await {
+                        Some(response) => {
+                            self.cache
+                                .lock()
+                                .unwrap()
+                                .insert(&query, &response, Instant::now());
+                            response
+                        }
+                        None => {
+                            let mut response = Message::reply_to(&query);
+                            response.set_rcode(Rcode::ServFail);
+                            response
+                        }
+                    },
+                }
+            }
+        };
+        if config.level >= Level::Debug {
+            println!("{response}");
+        }
+
+        // responses over UDP have to fit what the client can take, which is
+        // 512 bytes unless it says otherwise with EDNS
+        let encoded = match transport {
+            Transport::Udp => {
+                let max_size = query.edns().map_or(512, |edns| edns.udp_payload.max(512));
+                response
+                    .truncated_to(max_size.into())
+                    .and_then(|response| response.to_bytes())
+            }
+            Transport::Tcp => response.to_bytes(),
+        };
+        match encoded {
+            Ok(bytes) => Some(bytes),
+            Err(err) => {
+                println!("Could not encode the response: {err}");
+                None
+            }
+        }
+    }
+
+    /// Tries the upstreams in turn until one answers in time with anything
+    /// but SERVFAIL, or returns the last SERVFAIL if none does. The
+    /// upstreams due for a probe are sent the query as well, in the
+    /// background.
+    async fn forward(
+        &self,
+        config: &Arc<Config>,
+        request: &[u8],
+        query: &Message,
+        client: SocketAddr,
+    ) -> Option<Message> {
+        let now = Instant::now();
+        let mut order = self.upstreams.health.order(config, now);
+        for upstream in self.upstreams.health.due_for_probe(config, now) {
+            let upstreams = Arc::clone(&self.upstreams);
+            let (config, request, query) = (Arc::clone(config), request.to_vec(), query.clone());
+            tokio::spawn(async move {
+                upstreams
+                    .exchange(&config, &upstream, &request, &query, client)
+                    .await
+            });
+        }
+
+        let mut failed = None;
+        if config.race > 1 {
+            let racing = order.drain(..config.race.min(order.len())).collect();
+            match self.race(config, racing, request, query, client).await {
+                Ok(response) => return Some(response),
+                Err(response) => failed = response,
+            }
+        }
+        for upstream in order {
+            match self
+                .upstreams
+                .exchange(config, &upstream, request, query, client)
+                .await
+            {
+                Some(response) if response.header().rcode() != Rcode::ServFail => {
+                    return Some(response);
+                }
+                response => failed = response.or(failed),
+            }
+        }
+        failed
+    }
+
+    /// Sends the query to all of `upstreams` at once, returning the first
+    /// response other than SERVFAIL and dropping the queries still waiting.
+    /// Fails with the last SERVFAIL if there is no such response.
+    async fn race(
+        &self,
+        config: &Arc<Config>,
+        upstreams: Vec<Upstream>,
+        request: &[u8],
+        query: &Message,
+        client: SocketAddr,
+    ) -> Result<Message, Option<Message>> {
+        let mut racing = JoinSet::new();
+        for upstream in upstreams {
+            let upstreams = Arc::clone(&self.upstreams);
+            let (config, request, query) = (Arc::clone(config), request.to_vec(), query.clone());
+            racing.spawn(async move {
+                upstreams
+                    .exchange(&config, &upstream, &request, &query, client)
+                    .await
+            });
+        }
+
+        let mut failed = None;
+        while let Some(response) = racing.join_next().await {
+            match response.expect("a query upstream panicked") {
+                Some(response) if response.header().rcode() != Rcode::ServFail => {
+                    // dropping the set aborts the others
+                    return Ok(response);
+                }
+                response => failed = response.or(failed),
+            }
+        }
+        Err(failed)
+    }
+}
+
+/// The answer from the local records, or NXDOMAIN for blocked names.
+fn answer_locally(config: &Config, query: &Message) -> Option<Message> {
+    let question = query.questions().first()?;
+    let records: Vec<_> = config
+        .local
+        .iter()
+        .filter(|record| record.name() == question.name() && question.class() == Class::In)
+        .collect();
+
+    let mut response = Message::reply_to(query);
+    if !records.is_empty() {
+        // a name with records of other types only has no data
+        for record in records {
+            if record.record_type() == question.record_type() {
+                response.push_answer(record.clone());
+            }
+        }
+    } else if config.is_blocked(question.name()) {
+        response.set_rcode(Rcode::NxDomain);
+    } else {
+        return None;
+    }
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr};
+    use crate::config::{CacheConfig, FailoverConfig, Selection};
+    use scopa::testutil::{fake_negative_response, fake_query, fake_response, MockServer, Reply, QUERY_A, RESPONSE_A};
+    use scopa::{parse_message, RecordType};
+    use std::time::Duration;
+
+    const CLIENT: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 5353);
+
+    fn proxy(config: Config) -> Proxy {
+        let cache = Arc::new(Mutex::new(Cache::new(config.cache)));
+        Proxy::new(Arc::new(RwLock::new(Arc::new(config))), cache)
+    }
+
+    #[test]
+    fn test_args() {
+        let args = Args::try_parse_from(["scopa", "--listen", "[::1]:53", "-u", "9.9.9.9", "-u", "1.1.1.1", "-v"]).unwrap();
+        let config = args.config().unwrap();
+        assert_eq!((vec!["[::1]:53".parse().unwrap()], vec!["9.9.9.9:53".parse().unwrap(), "1.1.1.1:53".parse().unwrap()], Level::Debug), (config.listen, config.upstreams, config.level));
+        assert!(Args::try_parse_from(["scopa", "-u", "dns.quad9.net"]).is_err());
+        assert!(Args::try_parse_from(["scopa", "--config", "/nonexistent/scopa.toml"]).unwrap().config().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_forward() {
+        let upstream = MockServer::start().unwrap();
+        upstream.script("www.example.com", RecordType::A, [Reply::Answer(fake_response("www.example.com", RecordType::A, "192.0.2.1"))]);
+        let proxy = proxy(Config { upstreams: vec![Upstream::Plain(upstream.udp_addr())], ..Config::default() });
+
+        assert_eq!(Some(RESPONSE_A.to_vec()), proxy.handle(QUERY_A, CLIENT, Transport::Udp).await);
+        assert_eq!(None, proxy.handle(&QUERY_A[..20], CLIENT, Transport::Udp).await);
+        // from the cache
+        assert_eq!(Some(RESPONSE_A.to_vec()), proxy.handle(QUERY_A, CLIENT, Transport::Udp).await);
+
+        let queries = upstream.queries();
+        assert_eq!(1, queries.len());
+        assert_eq!(fake_query("www.example.com", RecordType::A).questions(), queries[0].1.questions());
+    }
+
+    #[tokio::test]
+    async fn test_truncation() {
+        let upstream = MockServer::start().unwrap();
+        let mut large = fake_response("www.example.com", RecordType::A, "192.0.2.0");
+        for i in 1..40 {
+            large.push_answer(fake_response("www.example.com", RecordType::A, &format!("192.0.2.{i}")).answers()[0].clone());
+        }
+        upstream.script("www.example.com", RecordType::A, [Reply::Answer(large)]);
+        let proxy = proxy(Config { upstreams: vec![Upstream::Plain(upstream.udp_addr())], ..Config::default() });
+
+        // a client over UDP gets what fits and asks again over TCP
+        let response = proxy.handle(QUERY_A, CLIENT, Transport::Udp).await.unwrap();
+        assert!(response.len() <= 512);
+        assert!(parse_message(&response).unwrap().header().truncated());
+        let response = parse_message(&proxy.handle(QUERY_A, CLIENT, Transport::Tcp).await.unwrap()).unwrap();
+        assert_eq!(40, response.answers().len());
+    }
+
+    #[test]
+    fn test_answer_locally() {
+        let config = Config::parse(r#"
+            local = [{ name = "router.lan", type = "A", data = "192.168.1.1" }]
+        "#).unwrap();
+        let mut config = config;
+        config.blocked.insert("ads.example".parse().unwrap());
+
+        let response = answer_locally(&config, &fake_query("router.lan", RecordType::A)).unwrap();
+        assert_eq!(vec![Ipv4Addr::new(192, 168, 1, 1)], response.a_records().collect::<Vec<_>>());
+        assert!(answer_locally(&config, &fake_query("router.lan", RecordType::Aaaa)).unwrap().answers().is_empty());
+        assert_eq!(Rcode::NxDomain, answer_locally(&config, &fake_query("www.ads.example", RecordType::A)).unwrap().header().rcode());
+        assert!(answer_locally(&config, &fake_query("example.com", RecordType::A)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let upstream = MockServer::start().unwrap();
+        upstream.script("www.example.com", RecordType::A, [Reply::Drop]);
+        let proxy = proxy(Config { upstreams: vec![Upstream::Plain(upstream.udp_addr())], timeout: Duration::from_millis(50), ..Config::default() });
+
+        let response = parse_message(&proxy.handle(QUERY_A, CLIENT, Transport::Udp).await.unwrap()).unwrap();
+        assert_eq!(Rcode::ServFail, response.header().rcode());
+        assert!(response.is_response_to(&parse_message(QUERY_A).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_failover() {
+        let (lost, failing, working) = (MockServer::start().unwrap(), MockServer::start().unwrap(), MockServer::start().unwrap());
+        lost.script("www.example.com", RecordType::A, [Reply::Drop]);
+        failing.script("www.example.com", RecordType::A, [Reply::Answer(fake_negative_response("www.example.com", RecordType::A, Rcode::ServFail, "example.com"))]);
+        working.script("www.example.com", RecordType::A, [Reply::Answer(fake_response("www.example.com", RecordType::A, "192.0.2.1"))]);
+        let upstreams = vec![Upstream::Plain(lost.udp_addr()), Upstream::Plain(failing.udp_addr()), Upstream::Plain(working.udp_addr())];
+        let failover = FailoverConfig { max_failures: 1, down_secs: 30 };
+        let proxy = proxy(Config { upstreams, timeout: Duration::from_millis(50), failover, cache: CacheConfig { size: 0, ..CacheConfig::default() }, ..Config::default() });
+
+        // the failed upstreams are skipped the next time
+        for _ in 0..2 {
+            assert_eq!(Some(RESPONSE_A.to_vec()), proxy.handle(QUERY_A, CLIENT, Transport::Udp).await);
+        }
+        assert_eq!((1, 1, 2), (lost.queries().len(), failing.queries().len(), working.queries().len()));
+    }
+
+    #[tokio::test]
+    async fn test_probes() {
+        let (first, second) = (MockServer::start().unwrap(), MockServer::start().unwrap());
+        for upstream in [&first, &second] {
+            upstream.script("www.example.com", RecordType::A, [Reply::Answer(fake_response("www.example.com", RecordType::A, "192.0.2.1"))]);
+        }
+        let cache = CacheConfig { size: 0, ..CacheConfig::default() };
+        let proxy = proxy(Config { upstreams: vec![Upstream::Plain(first.udp_addr()), Upstream::Plain(second.udp_addr())], selection: Selection::Fastest, cache, ..Config::default() });
+
+        // the second upstream isn't measured yet and gets a copy of the query
+        assert_eq!(Some(RESPONSE_A.to_vec()), proxy.handle(QUERY_A, CLIENT, Transport::Udp).await);
+        time::timeout(Duration::from_secs(1), async {
+            while second.queries().is_empty() {
+                time::sleep(Duration::from_millis(5)).await;
+            }
+        }).await.unwrap();
+        assert_eq!(1, first.queries().len());
+    }
+
+    #[tokio::test]
+    async fn test_race() {
+        let (lost, working) = (MockServer::start().unwrap(), MockServer::start().unwrap());
+        lost.script("www.example.com", RecordType::A, [Reply::Drop]);
+        working.script("www.example.com", RecordType::A, [Reply::Answer(fake_response("www.example.com", RecordType::A, "192.0.2.1"))]);
+        let proxy = proxy(Config { upstreams: vec![Upstream::Plain(lost.udp_addr()), Upstream::Plain(working.udp_addr())], timeout: Duration::from_secs(5), race: 2, ..Config::default() });
+
+        // the lost query isn't waited for
+        let response = time::timeout(Duration::from_secs(1), proxy.handle(QUERY_A, CLIENT, Transport::Udp)).await.unwrap();
+        assert_eq!(Some(RESPONSE_A.to_vec()), response);
+        assert_eq!(1, working.queries().len());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_queries() {
+        let upstream = MockServer::start().unwrap();
+        upstream.script("www.example.com", RecordType::A, [Reply::Drop]);
+        upstream.script("example.com", RecordType::A, [Reply::Answer(fake_response("example.com", RecordType::A, "192.0.2.1"))]);
+        let proxy = Arc::new(proxy(Config { upstreams: vec![Upstream::Plain(upstream.udp_addr())], ..Config::default() }));
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(Arc::clone(&proxy).run(listener));
+
+        // the unanswered query doesn't hold up the second one
+        client.send_to(QUERY_A, address).await.unwrap();
+        client.send_to(&fake_query("example.com", RecordType::A).to_bytes().unwrap(), address).await.unwrap();
+        let mut response = [0; 512];
+        let length = time::timeout(Duration::from_secs(1), client.recv(&mut response)).await.unwrap().unwrap();
+        assert_eq!(Rcode::NoError, parse_message(&response[..length]).unwrap().header().rcode());
+    }
+
+    #[tokio::test]
+    async fn test_tcp() {
+        let upstream = MockServer::start().unwrap();
+        upstream.script("www.example.com", RecordType::A, [Reply::Answer(fake_response("www.example.com", RecordType::A, "192.0.2.1"))]);
+        let proxy = Arc::new(proxy(Config { upstreams: vec![Upstream::Plain(upstream.udp_addr())], tcp_idle_timeout: Duration::from_millis(100), ..Config::default() }));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(Arc::clone(&proxy).run_tcp(listener));
+
+        // pipelined queries on one connection, which is closed once idle
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        tcp::write_message(&mut stream, QUERY_A).await.unwrap();
+        tcp::write_message(&mut stream, QUERY_A).await.unwrap();
+        for _ in 0..2 {
+            assert_eq!(Some(RESPONSE_A.to_vec()), tcp::read_message(&mut stream).await.unwrap());
+        }
+        assert_eq!(None, time::timeout(Duration::from_secs(1), tcp::read_message(&mut stream)).await.unwrap().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        let path = std::env::temp_dir().join(format!("scopa-test-reload-{}.toml", std::process::id()));
+        std::fs::write(&path, "upstreams = [\"127.0.0.1\"]\n").unwrap();
+        let args = Args::try_parse_from(["scopa", "--config", path.to_str().unwrap()]).unwrap();
+        let config = args.config().unwrap();
+        let cache = Arc::new(Mutex::new(Cache::new(config.cache)));
+        let proxy = Proxy::new(Arc::new(RwLock::new(Arc::new(config))), Arc::clone(&cache));
+        cache.lock().unwrap().insert(&fake_query("www.example.com", RecordType::A), &fake_response("www.example.com", RecordType::A, "192.0.2.1"), Instant::now());
+
+        // queries being answered keep the configuration they started with
+        let before = proxy.config();
+        std::fs::write(&path, "upstreams = [\"127.0.0.2\"]\n").unwrap();
+        assert_eq!(vec!["upstreams: 127.0.0.1:53 -> 127.0.0.2:53"], reload(&args, &proxy.config, &cache).unwrap());
+        assert_eq!(vec![Upstream::Plain(SocketAddr::from(([127, 0, 0, 1], 53)))], before.upstreams);
+        assert_eq!(vec![Upstream::Plain(SocketAddr::from(([127, 0, 0, 2], 53)))], proxy.config().upstreams);
+        assert_eq!(Some(RESPONSE_A.to_vec()), proxy.handle(QUERY_A, CLIENT, Transport::Udp).await);
+
+        // an invalid configuration is not used
+        std::fs::write(&path, "upstreams = []\n").unwrap();
+        assert!(reload(&args, &proxy.config, &cache).is_err());
+        assert_eq!(vec![Upstream::Plain(SocketAddr::from(([127, 0, 0, 2], 53)))], proxy.config().upstreams);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+//! Forwarding to upstreams over QUIC (RFC 9250), with a stream per query on
+//! one connection per upstream, resumed with 0-RTT when the server allows.
+
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use quinn::crypto::rustls::QuicClientConfig;
+use quinn::{ClientConfig, Connection, Endpoint, RecvStream, SendStream, VarInt, ZeroRttAccepted};
+use rustls::RootCertStore;
+use scopa::{parse_message_detailed, EdnsOption, Message, QUERY_BLOCK_SIZE};
+use tokio::sync::OnceCell;
+
+use crate::config::TlsUpstream;
+use crate::{tcp, tls};
+
+/// The error code for streams of queries that are given up on.
+const DOQ_REQUEST_CANCELLED: VarInt = VarInt::from_u32(3);
+
+/// Forwards queries to upstreams over QUIC, from an endpoint per address
+/// family, on a connection per upstream that is opened on first use.
+pub struct QuicForwarder {
+    roots: Arc<RootCertStore>,
+    v4: OnceCell<Endpoint>,
+    v6: OnceCell<Endpoint>,
+    upstreams: Mutex<HashMap<TlsUpstream, Arc<tokio::sync::Mutex<State>>>>,
+}
+
+/// The connection to an upstream, which the first query needing a new one
+/// opens while the others wait, and the configuration keeping the session
+/// tickets to resume the next one with.
+#[derive(Default)]
+struct State {
+    config: Option<ClientConfig>,
+    connection: Option<Connection>,
+}
+
+impl Default for QuicForwarder {
+    fn default() -> Self {
+        QuicForwarder::new(tls::default_roots())
+    }
+}
+
+impl QuicForwarder {
+    pub fn new(roots: RootCertStore) -> Self {
+        QuicForwarder {
+            roots: Arc::new(roots),
+            v4: OnceCell::new(),
+            v6: OnceCell::new(),
+            upstreams: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sends a query to `upstream` on a stream of its own and waits for the
+    /// response, which comes back with the ID of the query. A query on a
+    /// connection that turns out to be closed, or in early data the server
+    /// turned down, is sent again.
+    pub async fn forward(&self, upstream: &TlsUpstream, query: &Message) -> io::Result<Message> {
+        // the ID has to be 0, and the query is padded as RFC 8467
+        // recommends if it has EDNS
+        let mut sent = query.clone();
+        sent.set_id(0);
+        sent.pad_to_block(QUERY_BLOCK_SIZE)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+        let request = sent
+            .to_bytes()
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+
+        let (connection, early) = self.connection(upstream).await?;
+        let response = match exchange(&connection, &request).await {
+            Ok(response) => response,
+            Err(err) => {
+                let retry = match early {
+                    Some(accepted) => !accepted.await,
+                    None => connection.close_reason().is_some(),
+                };
+                if !retry {
+                    return Err(err);
+                }
+                let (connection, _) = self.connection(upstream).await?;
+                exchange(&connection, &request).await?
+            }
+        };
+
+        let mut response = match parse_message_detailed(&response) {
+            Ok(response) if response.is_response_to(&sent) => response,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "the response from {} doesn't match the query",
+                        upstream.address
+                    ),
+                ))
+            }
+            Err(err) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "could not parse response: {err}\n{}",
+                        err.hexdump(&response)
+                    ),
+                ))
+            }
+        };
+        // the padding was for the way here, not for the client
+        if let Some(mut edns) = response.edns().cloned() {
+            edns.options
+                .retain(|option| !matches!(option, EdnsOption::Padding(_)));
+            response.set_edns(Some(edns));
+        }
+        response.set_id(query.id());
+        Ok(response)
+    }
+
+    /// The open connection to `upstream`, and whether 0-RTT was accepted
+    /// if it was just opened with it.
+    async fn connection(
+        &self,
+        upstream: &TlsUpstream,
+    ) -> io::Result<(Connection, Option<ZeroRttAccepted>)> {
+        let state = Arc::clone(
+            self.upstreams
+                .lock()
+                .unwrap()
+                .entry(upstream.clone())
+                .or_default(),
+        );
+        let mut state = state.lock().await;
+        if let Some(connection) = state
+            .connection
+            .as_ref()
+            .filter(|connection| connection.close_reason().is_none())
+        {
+            return Ok((connection.clone(), None));
+        }
+
+        let config = match &state.config {
+            Some(config) => config.clone(),
+            None => {
+                let mut config = tls::client_config(&self.roots, &upstream.pins);
+                config.alpn_protocols = vec![b"doq".to_vec()];
+                config.enable_early_data = true;
+                let config = QuicClientConfig::try_from(config)
+                    .expect("ring has the initial cipher suite of QUIC");
+                state
+                    .config
+                    .insert(ClientConfig::new(Arc::new(config)))
+                    .clone()
+            }
+        };
+        let connecting = self
+            .endpoint(upstream.address)
+            .await?
+            .connect_with(config, upstream.address, &upstream.name)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+        // with a session ticket from an earlier connection, queries can go
+        // right away as RFC 9250 allows, as they don't change anything
+        let (connection, early) = match connecting.into_0rtt() {
+            Ok((connection, accepted)) => (connection, Some(accepted)),
+            Err(connecting) => (connecting.await?, None),
+        };
+        state.connection = Some(connection.clone());
+        Ok((connection, early))
+    }
+
+    /// The endpoint for servers of the family of `server`, bound on first
+    /// use.
+    async fn endpoint(&self, server: SocketAddr) -> io::Result<&Endpoint> {
+        let (cell, unspecified) = match server {
+            SocketAddr::V4(_) => (&self.v4, IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            SocketAddr::V6(_) => (&self.v6, IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        };
+        cell.get_or_try_init(|| async { Endpoint::client(SocketAddr::new(unspecified, 0)) })
+            .await
+    }
+}
+
+/// Sends a request on a new stream, which is finished after it, and reads
+/// the response from it.
+async fn exchange(connection: &Connection, request: &[u8]) -> io::Result<Vec<u8>> {
+    let (send, recv) = connection.open_bi().await?;
+    let mut stream = Stream {
+        send,
+        recv,
+        done: false,
+    };
+    tcp::write_message(&mut stream.send, request).await?;
+    stream.send.finish()?;
+    let response = tcp::read_message(&mut stream.recv)
+        .await?
+        .ok_or_else(|| io::Error::new(ErrorKind::UnexpectedEof, "upstream closed the stream"))?;
+    stream.done = true;
+    Ok(response)
+}
+
+/// The stream of a query, which is cancelled if dropped before the
+/// response came in.
+struct Stream {
+    send: SendStream,
+    recv: RecvStream,
+    done: bool,
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = self.send.reset(DOQ_REQUEST_CANCELLED);
+            let _ = self.recv.stop(DOQ_REQUEST_CANCELLED);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::tests::{certificate, roots, server_config};
+    use quinn::crypto::rustls::QuicServerConfig;
+    use quinn::ServerConfig;
+    use scopa::testutil::{fake_query, fake_response};
+    use scopa::{Edns, RecordType};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Server {
+        address: SocketAddr,
+        roots: RootCertStore,
+        connections: Arc<AtomicUsize>,
+    }
+
+    impl Server {
+        /// Answers each query with an A record, accepting 0-RTT.
+        fn start() -> Server {
+            let certificate = certificate();
+            let mut config = server_config(&certificate);
+            config.alpn_protocols = vec![b"doq".to_vec()];
+            config.max_early_data_size = u32::MAX;
+            let config = ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(config).unwrap()));
+            let endpoint = Endpoint::server(config, (Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+            let connections = Arc::new(AtomicUsize::new(0));
+            let server = Server { address: endpoint.local_addr().unwrap(), roots: roots(&certificate), connections: Arc::clone(&connections) };
+
+            tokio::spawn(async move {
+                while let Some(incoming) = endpoint.accept().await {
+                    let connections = Arc::clone(&connections);
+                    tokio::spawn(async move {
+                        let Ok((connection, _)) = incoming.accept().unwrap().into_0rtt() else { return };
+                        connections.fetch_add(1, Ordering::SeqCst);
+                        while let Ok((mut send, mut recv)) = connection.accept_bi().await {
+                            tokio::spawn(async move {
+                                let query = recv.read_to_end(65537).await.unwrap();
+                                let query = parse_message_detailed(&query[2..]).unwrap();
+                                assert_eq!(0, query.id());
+                                let mut response = fake_response(&query.questions()[0].name().to_string(), RecordType::A, "192.0.2.1");
+                                response.set_id(query.id());
+                                response.set_edns(query.edns().cloned());
+                                tcp::write_message(&mut send, &response.to_bytes().unwrap()).await.unwrap();
+                                send.finish().unwrap();
+                            });
+                        }
+                    });
+                }
+            });
+            server
+        }
+
+        fn upstream(&self, name: &str) -> TlsUpstream {
+            TlsUpstream { address: self.address, name: name.to_owned(), pins: Vec::new() }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forward() {
+        let server = Server::start();
+        let forwarder = QuicForwarder::new(server.roots.clone());
+        let mut query = fake_query("www.example.com", RecordType::A);
+        query.set_id(7);
+        query.set_edns(Some(Edns::default()));
+        let upstream = server.upstream("dns.test");
+
+        // the queries share the connection, with a stream each
+        let (first, second) = tokio::join!(forwarder.forward(&upstream, &query), forwarder.forward(&upstream, &query));
+        for response in [first.unwrap(), second.unwrap()] {
+            assert!(response.is_response_to(&query));
+            assert_eq!(Some(&Vec::new()), response.edns().map(|edns| &edns.options));
+        }
+        assert_eq!(1, server.connections.load(Ordering::SeqCst));
+
+        // the next connection is resumed with 0-RTT
+        let (connection, early) = forwarder.connection(&upstream).await.unwrap();
+        assert!(early.is_none());
+        connection.close(VarInt::from_u32(0), b"");
+        let (_, early) = forwarder.connection(&upstream).await.unwrap();
+        assert!(early.unwrap().await);
+        assert!(forwarder.forward(&upstream, &query).await.unwrap().is_response_to(&query));
+        assert_eq!(2, server.connections.load(Ordering::SeqCst));
+
+        assert!(forwarder.forward(&server.upstream("other.test"), &query).await.is_err());
+    }
+}
@@ -0,0 +1,59 @@
+//! Framing of messages on TCP connections, like [`scopa::read_tcp_message`]
+//! and [`scopa::write_tcp_message`] but without blocking.
+
+use std::io::{self, ErrorKind};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Reads the next message from a connection. Returns `None` if the
+/// connection was closed before another message started.
+pub async fn read_message(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0; 2];
+    let mut read = 0;
+    while read < length.len() {
+        match reader.read(&mut length[read..]).await? {
+            0 if read == 0 => return Ok(None),
+            0 => return Err(ErrorKind::UnexpectedEof.into()),
+            n => read += n,
+        }
+    }
+
+    let mut message = vec![0; u16::from_be_bytes(length) as usize];
+    reader.read_exact(&mut message).await?;
+    Ok(Some(message))
+}
+
+/// Writes a message with its length in front, in a single write.
+pub async fn write_message(
+    writer: &mut (impl AsyncWrite + Unpin),
+    message: &[u8],
+) -> io::Result<()> {
+    let length = u16::try_from(message.len())
+        .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "message longer than 65535 bytes"))?;
+
+    let mut framed = Vec::with_capacity(2 + message.len());
+    framed.extend_from_slice(&length.to_be_bytes());
+    framed.extend_from_slice(message);
+    writer.write_all(&framed).await?;
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_framing() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        write_message(&mut client, b"first").await.unwrap();
+        write_message(&mut client, b"").await.unwrap();
+        client.write_all(&[0, 5, b'x']).await.unwrap();
+        drop(client);
+
+        assert_eq!(Some(b"first".to_vec()), read_message(&mut server).await.unwrap());
+        assert_eq!(Some(Vec::new()), read_message(&mut server).await.unwrap());
+        assert_eq!(ErrorKind::UnexpectedEof, read_message(&mut server).await.unwrap_err().kind());
+        assert_eq!(None, read_message(&mut server).await.unwrap());
+        assert!(write_message(&mut tokio::io::sink(), &[0; 65536]).await.is_err());
+    }
+}
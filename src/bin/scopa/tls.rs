@@ -0,0 +1,530 @@
+//! Forwarding to upstreams over TLS (RFC 7858), sharing one connection per
+//! upstream between the queries to it.
+
+use std::collections::HashMap;
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use scopa::{parse_message_detailed, Edns, EdnsOption, Message, QUERY_BLOCK_SIZE};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+use tokio::time;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+use crate::config::TlsUpstream;
+use crate::tcp;
+use crate::upstream::Transactions;
+
+/// How long an idle connection is kept open if the server doesn't say with
+/// the edns-tcp-keepalive option (RFC 7828).
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Forwards queries to upstreams over TLS, on a connection per upstream that
+/// is opened on first use and kept open while it is used.
+pub struct TlsForwarder {
+    roots: Arc<RootCertStore>,
+    connections: Mutex<HashMap<TlsUpstream, Slot>>,
+}
+
+/// The connection to an upstream, which the first query needing a new one
+/// opens while the others wait.
+type Slot = Arc<tokio::sync::Mutex<Option<Arc<Connection>>>>;
+
+impl Default for TlsForwarder {
+    fn default() -> Self {
+        TlsForwarder::new(default_roots())
+    }
+}
+
+/// The certificate authorities Mozilla trusts.
+pub fn default_roots() -> RootCertStore {
+    RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    }
+}
+
+/// The configuration for connections to a server with a certificate issued
+/// by one of `roots` and, if there are any pins, with one of their keys.
+pub fn client_config(roots: &Arc<RootCertStore>, pins: &[[u8; 32]]) -> ClientConfig {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier =
+        WebPkiServerVerifier::builder_with_provider(Arc::clone(roots), Arc::clone(&provider))
+            .build()
+            .expect("the root store is not empty");
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default protocol versions");
+    let config = if pins.is_empty() {
+        builder.with_webpki_verifier(verifier)
+    } else {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedVerifier {
+                verifier,
+                pins: pins.to_vec(),
+            }))
+    };
+    config.with_no_client_auth()
+}
+
+impl TlsForwarder {
+    pub fn new(roots: RootCertStore) -> Self {
+        TlsForwarder {
+            roots: Arc::new(roots),
+            connections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sends a query for `client` to `upstream` and waits for the response,
+    /// which comes back with the ID of the query. A query on a connection
+    /// that turns out to be closed is sent again on a new one.
+    pub async fn forward(
+        &self,
+        upstream: &TlsUpstream,
+        query: &Message,
+        client: SocketAddr,
+    ) -> io::Result<Message> {
+        let (connection, reused) = self.connection(upstream).await?;
+        let mut response = match connection.exchange(query, client).await {
+            // the server may have closed the connection while it was idle
+            Err(_) if reused => {
+                connection.close();
+                let (connection, _) = self.connection(upstream).await?;
+                connection.exchange(query, client).await?
+            }
+            response => response?,
+        };
+
+        // the options were for this connection, not for the client
+        if let Some(mut edns) = response.edns().cloned() {
+            edns.options.retain(|option| {
+                !matches!(option, EdnsOption::TcpKeepalive(_) | EdnsOption::Padding(_))
+            });
+            response.set_edns(Some(edns));
+        }
+        response.set_id(query.id());
+        Ok(response)
+    }
+
+    /// The open connection to `upstream`, and whether it was open already.
+    async fn connection(&self, upstream: &TlsUpstream) -> io::Result<(Arc<Connection>, bool)> {
+        let slot = Arc::clone(
+            self.connections
+                .lock()
+                .unwrap()
+                .entry(upstream.clone())
+                .or_default(),
+        );
+        let mut slot = slot.lock().await;
+        if let Some(connection) = slot.as_ref().filter(|connection| connection.is_open()) {
+            return Ok((Arc::clone(connection), true));
+        }
+        let connection =
+            Connection::open(upstream, client_config(&self.roots, &upstream.pins)).await?;
+        *slot = Some(Arc::clone(&connection));
+        Ok((connection, false))
+    }
+}
+
+/// A connection to an upstream, with the queries sent on it that are still
+/// waiting for a response.
+struct Connection {
+    address: SocketAddr,
+    writer: tokio::sync::Mutex<WriteHalf<TlsStream<TcpStream>>>,
+    transactions: Transactions,
+    open: AtomicBool,
+    /// In milliseconds, the last one the server advertised if it did.
+    idle_timeout: AtomicU64,
+}
+
+impl Connection {
+    async fn open(upstream: &TlsUpstream, config: ClientConfig) -> io::Result<Arc<Self>> {
+        let name = ServerName::try_from(upstream.name.clone())
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+        let stream = TcpStream::connect(upstream.address).await?;
+        let stream = TlsConnector::from(Arc::new(config))
+            .connect(name, stream)
+            .await?;
+        let (reader, writer) = tokio::io::split(stream);
+        let connection = Arc::new(Connection {
+            address: upstream.address,
+            writer: tokio::sync::Mutex::new(writer),
+            transactions: Transactions::default(),
+            open: AtomicBool::new(true),
+            idle_timeout: AtomicU64::new(IDLE_TIMEOUT.as_millis() as u64),
+        });
+        tokio::spawn(Arc::clone(&connection).read(reader));
+        Ok(connection)
+    }
+
+    fn is_open(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    fn close(&self) {
+        self.open.store(false, Ordering::Relaxed);
+    }
+
+    /// Sends a query with an ID of its own, asking for the idle timeout and
+    /// padded as RFC 8467 recommends when it has EDNS to carry the options.
+    async fn exchange(&self, query: &Message, client: SocketAddr) -> io::Result<Message> {
+        let mut pending = self.transactions.register(self.address, query, client)?;
+        // the reader fails the queries it knows of when it stops
+        if !self.is_open() {
+            return Err(io::Error::new(
+                ErrorKind::NotConnected,
+                "the connection is closed",
+            ));
+        }
+        let mut query = query.clone();
+        query.set_id(pending.id);
+        if let Some(mut edns) = query.edns().cloned() {
+            edns.options
+                .retain(|option| !matches!(option, EdnsOption::TcpKeepalive(_)));
+            edns.options.push(EdnsOption::TcpKeepalive(None));
+            query.set_edns(Some(edns));
+        }
+        query
+            .pad_to_block(QUERY_BLOCK_SIZE)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+        let request = query
+            .to_bytes()
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+
+        if let Err(err) = tcp::write_message(&mut *self.writer.lock().await, &request).await {
+            self.close();
+            return Err(err);
+        }
+        pending.response().await
+    }
+
+    /// Hands the responses to the queries they answer, until the server
+    /// closes the connection or it has been idle for the idle timeout.
+    async fn read(self: Arc<Self>, reader: ReadHalf<TlsStream<TcpStream>>) {
+        let mut reader = BufReader::new(reader);
+        loop {
+            // waiting for data rather than for a whole message, which could
+            // be cut off by the timeout; the responses are always waited for,
+            // checking again at least every 100ms
+            let idle_timeout = Duration::from_millis(self.idle_timeout.load(Ordering::Relaxed))
+                .max(Duration::from_millis(100));
+            match time::timeout(idle_timeout, reader.fill_buf()).await {
+                Ok(Ok([])) => break,
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => {
+                    println!("Could not read from {}: {err}", self.address);
+                    break;
+                }
+                Err(_) if self.transactions.is_empty() => break,
+                Err(_) => continue,
+            }
+
+            let response = match tcp::read_message(&mut reader).await {
+                Ok(Some(response)) => response,
+                Ok(None) => break,
+                Err(err) => {
+                    println!("Could not read from {}: {err}", self.address);
+                    break;
+                }
+            };
+            match parse_message_detailed(&response) {
+                Ok(response) => {
+                    if let Some(timeout) = response.edns().and_then(Edns::tcp_keepalive) {
+                        self.idle_timeout
+                            .store(timeout.as_millis() as u64, Ordering::Relaxed);
+                    }
+                    self.transactions.answer(self.address, response);
+                }
+                Err(err) => println!(
+                    "Could not parse response: {err}\n{}",
+                    err.hexdump(&response)
+                ),
+            }
+        }
+
+        self.close();
+        self.transactions.clear();
+        let _ = self.writer.lock().await.shutdown().await;
+    }
+}
+
+/// Verifies certificates as usual, and that they have one of the pinned
+/// public keys, by the SHA-256 hash of their SubjectPublicKeyInfo.
+#[derive(Debug)]
+struct PinnedVerifier {
+    verifier: Arc<WebPkiServerVerifier>,
+    pins: Vec<[u8; 32]>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.verifier.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        let certificate = webpki::EndEntityCert::try_from(end_entity)
+            .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        let hash = Sha256::digest(certificate.subject_public_key_info().as_ref());
+        if self.pins.iter().any(|pin| pin[..] == hash[..]) {
+            Ok(verified)
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.verifier.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.verifier.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.verifier.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rcgen::{CertifiedKey, KeyPair, PublicKeyData};
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+    use rustls::ServerConfig;
+    use scopa::testutil::{fake_query, fake_response};
+    use scopa::RecordType;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::atomic::AtomicUsize;
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+
+    const CLIENT: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 5353);
+
+    /// A self-signed certificate for dns.test and localhost.
+    pub fn certificate() -> CertifiedKey<KeyPair> {
+        rcgen::generate_simple_self_signed(["dns.test".to_string(), "localhost".to_string()])
+            .unwrap()
+    }
+
+    pub fn server_config(certificate: &CertifiedKey<KeyPair>) -> ServerConfig {
+        let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(
+            certificate.signing_key.serialize_der(),
+        ));
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![certificate.cert.der().clone()], key)
+            .unwrap()
+    }
+
+    /// Roots trusting only `certificate`.
+    pub fn roots(certificate: &CertifiedKey<KeyPair>) -> RootCertStore {
+        let mut roots = RootCertStore::empty();
+        roots.add(certificate.cert.der().clone()).unwrap();
+        roots
+    }
+
+    struct Server {
+        address: SocketAddr,
+        certificate: CertifiedKey<KeyPair>,
+        connections: Arc<AtomicUsize>,
+        queries: Arc<Mutex<Vec<Message>>>,
+    }
+
+    impl Server {
+        /// Answers each query over TLS with an A record and a keepalive of
+        /// 60s, closing every connection after its first response if asked to.
+        async fn start(close: bool) -> Server {
+            let certificate = certificate();
+            let acceptor = TlsAcceptor::from(Arc::new(server_config(&certificate)));
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let (connections, queries) = (
+                Arc::new(AtomicUsize::new(0)),
+                Arc::new(Mutex::new(Vec::new())),
+            );
+            let server = Server {
+                address: listener.local_addr().unwrap(),
+                certificate,
+                connections: Arc::clone(&connections),
+                queries: Arc::clone(&queries),
+            };
+
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let (acceptor, connections, queries) = (
+                        acceptor.clone(),
+                        Arc::clone(&connections),
+                        Arc::clone(&queries),
+                    );
+                    tokio::spawn(async move {
+                        let Ok(mut stream) = acceptor.accept(stream).await else {
+                            return;
+                        };
+                        connections.fetch_add(1, Ordering::SeqCst);
+                        while let Ok(Some(query)) = tcp::read_message(&mut stream).await {
+                            let query = parse_message_detailed(&query).unwrap();
+                            let mut response = fake_response(
+                                &query.questions()[0].name().to_string(),
+                                RecordType::A,
+                                "192.0.2.1",
+                            );
+                            response.set_id(query.id());
+                            if query.edns().is_some() {
+                                response.set_edns(Some(Edns {
+                                    options: vec![EdnsOption::TcpKeepalive(Some(600))],
+                                    ..Edns::default()
+                                }));
+                            }
+                            queries.lock().unwrap().push(query);
+                            tcp::write_message(&mut stream, &response.to_bytes().unwrap())
+                                .await
+                                .unwrap();
+                            if close {
+                                let _ = stream.shutdown().await;
+                                return;
+                            }
+                        }
+                    });
+                }
+            });
+            server
+        }
+
+        fn forwarder(&self) -> TlsForwarder {
+            TlsForwarder::new(roots(&self.certificate))
+        }
+
+        fn upstream(&self, pins: Vec<[u8; 32]>) -> TlsUpstream {
+            TlsUpstream {
+                address: self.address,
+                name: "dns.test".to_string(),
+                pins,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forward() {
+        let server = Server::start(false).await;
+        let forwarder = server.forwarder();
+        let mut query = fake_query("www.example.com", RecordType::A);
+        query.set_id(7);
+        query.set_edns(Some(Edns::default()));
+
+        // both queries go over the same connection, padded and asking for
+        // the keepalive, which is honored but not passed on
+        for _ in 0..2 {
+            let response = forwarder
+                .forward(&server.upstream(Vec::new()), &query, CLIENT)
+                .await
+                .unwrap();
+            assert!(response.is_response_to(&query));
+            assert_eq!(Some(&Vec::new()), response.edns().map(|edns| &edns.options));
+        }
+        assert_eq!(1, server.connections.load(Ordering::SeqCst));
+        let sent = server.queries.lock().unwrap()[0].clone();
+        assert_eq!(0, sent.to_bytes().unwrap().len() % QUERY_BLOCK_SIZE);
+        assert!(sent
+            .edns()
+            .unwrap()
+            .options
+            .contains(&EdnsOption::TcpKeepalive(None)));
+        let connection = forwarder
+            .connection(&server.upstream(Vec::new()))
+            .await
+            .unwrap()
+            .0;
+        assert_eq!(60_000, connection.idle_timeout.load(Ordering::Relaxed));
+
+        // queries without EDNS go as they are
+        let query = fake_query("example.com", RecordType::A);
+        assert!(forwarder
+            .forward(&server.upstream(Vec::new()), &query, CLIENT)
+            .await
+            .unwrap()
+            .is_response_to(&query));
+        assert_eq!(None, server.queries.lock().unwrap()[2].edns());
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() {
+        let server = Server::start(true).await;
+        let forwarder = server.forwarder();
+        let query = fake_query("www.example.com", RecordType::A);
+        for _ in 0..2 {
+            assert!(forwarder
+                .forward(&server.upstream(Vec::new()), &query, CLIENT)
+                .await
+                .unwrap()
+                .is_response_to(&query));
+        }
+        assert_eq!(2, server.connections.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_certificates() {
+        let server = Server::start(false).await;
+        let query = fake_query("www.example.com", RecordType::A);
+        let pin: [u8; 32] =
+            Sha256::digest(server.certificate.signing_key.subject_public_key_info()).into();
+        assert!(server
+            .forwarder()
+            .forward(&server.upstream(vec![[0; 32], pin]), &query, CLIENT)
+            .await
+            .is_ok());
+        assert!(server
+            .forwarder()
+            .forward(&server.upstream(vec![[0; 32]]), &query, CLIENT)
+            .await
+            .is_err());
+
+        let other = TlsUpstream {
+            name: "other.test".to_string(),
+            ..server.upstream(Vec::new())
+        };
+        assert!(server
+            .forwarder()
+            .forward(&other, &query, CLIENT)
+            .await
+            .is_err());
+        assert!(TlsForwarder::default()
+            .forward(&server.upstream(Vec::new()), &query, CLIENT)
+            .await
+            .is_err());
+    }
+}
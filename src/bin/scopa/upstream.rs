@@ -0,0 +1,532 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use scopa::{parse_message_detailed, Message, Question, Rcode};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::{oneshot, OnceCell};
+use tokio::time;
+
+use crate::config::{Config, Selection, Upstream};
+use crate::https::HttpsForwarder;
+use crate::quic::QuicForwarder;
+use crate::tcp;
+use crate::tls::TlsForwarder;
+
+/// The upstreams, and how they have been doing.
+#[derive(Default)]
+pub struct Upstreams {
+    forwarder: Forwarder,
+    tls: TlsForwarder,
+    https: HttpsForwarder,
+    quic: QuicForwarder,
+    pub health: Health,
+}
+
+impl Upstreams {
+    /// Sends a query to `upstream`, counting a timeout, an error or SERVFAIL
+    /// as a failure of it and measuring how long other responses took.
+    /// Returns the response, SERVFAIL included.
+    pub async fn exchange(
+        &self,
+        config: &Config,
+        upstream: &Upstream,
+        request: &[u8],
+        query: &Message,
+        client: SocketAddr,
+    ) -> Option<Message> {
+        let started = Instant::now();
+        let forwarded = async {
+            match upstream {
+                Upstream::Plain(server) => {
+                    self.forwarder
+                        .forward(*server, request, query, client)
+                        .await
+                }
+                Upstream::Tls(tls) => self.tls.forward(tls, query, client).await,
+                Upstream::Https(https) => self.https.forward(https, query).await,
+                Upstream::Quic(quic) => self.quic.forward(quic, query).await,
+            }
+        };
+        let response = match time::timeout(config.timeout, forwarded).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(err)) => {
+                println!("Could not forward a query to {upstream}: {err}");
+                self.health.failed(upstream, config, Instant::now());
+                return None;
+            }
+            Err(_) => {
+                println!("No response from {upstream} in time");
+                self.health.failed(upstream, config, Instant::now());
+                return None;
+            }
+        };
+
+        if response.header().rcode() == Rcode::ServFail {
+            println!("{upstream} answered SERVFAIL");
+            self.health.failed(upstream, config, Instant::now());
+        } else {
+            self.health.succeeded(upstream, started.elapsed());
+        }
+        Some(response)
+    }
+}
+
+/// Forwards queries upstream over one socket per address family, passing
+/// each response on to the query it answers as soon as it arrives.
+#[derive(Default)]
+pub struct Forwarder {
+    v4: OnceCell<Arc<Socket>>,
+    v6: OnceCell<Arc<Socket>>,
+}
+
+struct Socket {
+    socket: UdpSocket,
+    transactions: Transactions,
+}
+
+impl Forwarder {
+    /// Sends a request for `client` to `server` and waits for the response,
+    /// which comes back with the ID of the request. Truncated responses are
+    /// asked for again over TCP. The query stops waiting when the future is
+    /// dropped, so a timeout can be put around it.
+    pub async fn forward(
+        &self,
+        server: SocketAddr,
+        request: &[u8],
+        query: &Message,
+        client: SocketAddr,
+    ) -> io::Result<Message> {
+        let socket = self.socket(server).await?;
+        let mut pending = socket.transactions.register(server, query, client)?;
+
+        // the ID sent upstream is our own, so that clients can't pick it,
+        // and only a response matching it is forwarded
+        let mut udp_request = request.to_vec();
+        udp_request[..2].copy_from_slice(&pending.id.to_be_bytes());
+        socket.socket.send_to(&udp_request, server).await?;
+
+        let mut response = pending.response().await?;
+        drop(pending);
+        if response.header().truncated() {
+            response = forward_tcp(server, request, query).await?;
+        }
+        response.set_id(query.id());
+        Ok(response)
+    }
+
+    /// The socket for servers of the family of `server`, bound on first use.
+    async fn socket(&self, server: SocketAddr) -> io::Result<&Arc<Socket>> {
+        let (cell, unspecified) = match server {
+            SocketAddr::V4(_) => (&self.v4, IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            SocketAddr::V6(_) => (&self.v6, IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        };
+        cell.get_or_try_init(|| async {
+            let socket = Arc::new(Socket {
+                socket: UdpSocket::bind((unspecified, 0)).await?,
+                transactions: Transactions::default(),
+            });
+            tokio::spawn(Arc::clone(&socket).dispatch());
+            Ok(socket)
+        })
+        .await
+    }
+}
+
+impl Socket {
+    /// Hands the responses to the transactions they answer.
+    async fn dispatch(self: Arc<Self>) {
+        let mut buffer = vec![0; 65535];
+        loop {
+            let (length, from) = match self.socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(err) => {
+                    println!("Could not receive from upstream: {err}");
+                    continue;
+                }
+            };
+            match parse_message_detailed(&buffer[..length]) {
+                Ok(response) => self.transactions.answer(from, response),
+                Err(err) => println!(
+                    "Could not parse response: {err}\n{}",
+                    err.hexdump(&buffer[..length])
+                ),
+            }
+        }
+    }
+}
+
+/// The queries sent on a socket or connection that are still waiting for
+/// a response, by the ID they were sent with.
+#[derive(Default)]
+pub struct Transactions {
+    in_flight: Mutex<HashMap<u16, Transaction>>,
+}
+
+struct Transaction {
+    server: SocketAddr,
+    questions: Vec<Question>,
+    client: SocketAddr,
+    response: oneshot::Sender<Message>,
+}
+
+impl Transactions {
+    /// Picks an unused random ID to send a query for `client` to `server`
+    /// with.
+    pub fn register(
+        &self,
+        server: SocketAddr,
+        query: &Message,
+        client: SocketAddr,
+    ) -> io::Result<Pending<'_>> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if in_flight.len() > usize::from(u16::MAX) {
+            return Err(io::Error::other("too many queries in flight"));
+        }
+        let id = loop {
+            let id = Message::random_id();
+            if !in_flight.contains_key(&id) {
+                break id;
+            }
+        };
+        let (sender, response) = oneshot::channel();
+        in_flight.insert(
+            id,
+            Transaction {
+                server,
+                questions: query.questions().to_vec(),
+                client,
+                response: sender,
+            },
+        );
+        Ok(Pending {
+            transactions: self,
+            id,
+            response,
+        })
+    }
+
+    /// Hands a response from `from` to the query it answers, or drops it if
+    /// it answers none.
+    pub fn answer(&self, from: SocketAddr, response: Message) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let id = response.id();
+        match in_flight.get(&id) {
+            Some(transaction)
+                if transaction.server == from
+                    && response.header().is_response()
+                    && transaction.questions == response.questions() => {}
+            Some(transaction) => {
+                println!(
+                    "Dropped a response from {from} that doesn't match the query from {}",
+                    transaction.client
+                );
+                return;
+            }
+            None => {
+                println!("Dropped a response from {from} that doesn't match a query in flight");
+                return;
+            }
+        }
+        let transaction = in_flight.remove(&id).unwrap();
+        // the query may have stopped waiting in the meantime
+        let _ = transaction.response.send(response);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.in_flight.lock().unwrap().is_empty()
+    }
+
+    /// Fails all the queries waiting, for when no more responses can come.
+    pub fn clear(&self) {
+        self.in_flight.lock().unwrap().clear();
+    }
+}
+
+/// A query waiting for its response, which is forgotten when dropped.
+pub struct Pending<'a> {
+    transactions: &'a Transactions,
+    pub id: u16,
+    response: oneshot::Receiver<Message>,
+}
+
+impl Pending<'_> {
+    pub async fn response(&mut self) -> io::Result<Message> {
+        (&mut self.response)
+            .await
+            .map_err(|_| io::Error::other("stopped receiving responses from upstream"))
+    }
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        // the ID belongs to another query if the response came in and the
+        // ID was picked again, which then is still waiting
+        self.response.close();
+        let mut in_flight = self.transactions.in_flight.lock().unwrap();
+        if in_flight
+            .get(&self.id)
+            .is_some_and(|transaction| transaction.response.is_closed())
+        {
+            in_flight.remove(&self.id);
+        }
+    }
+}
+
+/// How each upstream has been doing: its failures in a row, to skip the
+/// ones that seem to be down for a while, and how fast it responds.
+#[derive(Default)]
+pub struct Health {
+    upstreams: Mutex<HashMap<Upstream, Status>>,
+}
+
+#[derive(Default)]
+struct Status {
+    failures: u32,
+    down_until: Option<Instant>,
+    /// The moving average of the response times, with timeouts counted
+    /// as taking the whole timeout.
+    latency: Option<Duration>,
+    /// When a query was last sent to it.
+    last_sent: Option<Instant>,
+}
+
+impl Health {
+    /// The upstreams to try in turn, those that aren't down, or all of them
+    /// if they are all down. They come in the order configured or, when
+    /// selecting the fastest, by latency, the ones not measured yet last.
+    pub fn order(&self, config: &Config, now: Instant) -> Vec<Upstream> {
+        let mut statuses = self.upstreams.lock().unwrap();
+        let mut up: Vec<_> = config
+            .upstreams
+            .iter()
+            .filter(|upstream| {
+                statuses
+                    .get(upstream)
+                    .is_none_or(|status| status.is_up(now))
+            })
+            .collect();
+        if up.is_empty() {
+            up = config.upstreams.iter().collect();
+        }
+        if config.selection == Selection::Fastest {
+            // a stable sort keeps the configured order among equals
+            up.sort_by_key(|upstream| {
+                let latency = statuses.get(upstream).and_then(|status| status.latency);
+                (latency.is_none(), latency)
+            });
+        }
+        if let Some(&first) = up.first() {
+            statuses.entry(first.clone()).or_default().last_sent = Some(now);
+        }
+        up.into_iter().cloned().collect()
+    }
+
+    /// The upstreams that are up but haven't been sent a query for the probe
+    /// interval, to send one anyway when selecting the fastest, so that they
+    /// get measured and chosen once they are faster.
+    pub fn due_for_probe(&self, config: &Config, now: Instant) -> Vec<Upstream> {
+        if config.selection != Selection::Fastest {
+            return Vec::new();
+        }
+        let mut statuses = self.upstreams.lock().unwrap();
+        config
+            .upstreams
+            .iter()
+            .filter(|&upstream| {
+                let status = statuses.entry(upstream.clone()).or_default();
+                let due = status.is_up(now)
+                    && status.last_sent.is_none_or(|last_sent| {
+                        now.saturating_duration_since(last_sent) >= config.probe_interval
+                    });
+                if due {
+                    status.last_sent = Some(now);
+                }
+                due
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn succeeded(&self, upstream: &Upstream, elapsed: Duration) {
+        let mut statuses = self.upstreams.lock().unwrap();
+        let status = statuses.entry(upstream.clone()).or_default();
+        status.failures = 0;
+        status.down_until = None;
+        status.measure(elapsed);
+    }
+
+    /// Counts a failure, taking the upstream down once it has failed too
+    /// often in a row. Once back up, a single failure takes it down again.
+    pub fn failed(&self, upstream: &Upstream, config: &Config, now: Instant) {
+        let mut statuses = self.upstreams.lock().unwrap();
+        let status = statuses.entry(upstream.clone()).or_default();
+        status.measure(config.timeout);
+        status.failures += 1;
+        let failover = &config.failover;
+        if status.failures >= failover.max_failures {
+            status.down_until = Some(now + failover.down_time());
+            println!(
+                "Skipping {upstream} for {:?} after {} failures in a row",
+                failover.down_time(),
+                status.failures
+            );
+        }
+    }
+}
+
+impl Status {
+    fn is_up(&self, now: Instant) -> bool {
+        self.down_until.is_none_or(|down_until| down_until <= now)
+    }
+
+    /// Adds a response time to the average, giving it the weight of an
+    /// eighth like the smoothed round-trip time of TCP (RFC 6298).
+    fn measure(&mut self, elapsed: Duration) {
+        self.latency = Some(match self.latency {
+            Some(latency) => latency * 7 / 8 + elapsed / 8,
+            None => elapsed,
+        });
+    }
+}
+
+/// Sends a request to `server` on a connection of its own and reads the
+/// response, which has to answer it.
+async fn forward_tcp(server: SocketAddr, request: &[u8], query: &Message) -> io::Result<Message> {
+    let mut stream = TcpStream::connect(server).await?;
+    let mut query = query.clone();
+    query.set_id(Message::random_id());
+    let mut request = request.to_vec();
+    request[..2].copy_from_slice(&query.id().to_be_bytes());
+    tcp::write_message(&mut stream, &request).await?;
+
+    let response = tcp::read_message(&mut stream).await?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "upstream closed the connection",
+        )
+    })?;
+    match parse_message_detailed(&response) {
+        Ok(parsed) if parsed.is_response_to(&query) => Ok(parsed),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the response from {server} over TCP doesn't match the request"),
+        )),
+        Err(err) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "could not parse response: {err}\n{}",
+                err.hexdump(&response)
+            ),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scopa::testutil::{fake_query, fake_response, MockServer, Reply, Transport};
+    use crate::config::FailoverConfig;
+    use scopa::{Rcode, RecordType};
+    use std::time::Duration;
+    use tokio::time;
+
+    const CLIENT: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 5353);
+
+    #[tokio::test]
+    async fn test_in_flight() {
+        let upstream = MockServer::start().unwrap();
+        upstream.script("www.example.com", RecordType::A, [Reply::Drop]);
+        upstream.script("example.com", RecordType::A, [Reply::Answer(fake_response("example.com", RecordType::A, "192.0.2.1"))]);
+        let forwarder = Forwarder::default();
+        let (mut lost, mut answered) = (fake_query("www.example.com", RecordType::A), fake_query("example.com", RecordType::A));
+        lost.set_id(7);
+        answered.set_id(7);
+        let (lost_request, answered_request) = (lost.to_bytes().unwrap(), answered.to_bytes().unwrap());
+
+        // a lost response only holds up its own query, which is forgotten
+        // once it is given up on
+        let lost = time::timeout(Duration::from_millis(50), forwarder.forward(upstream.udp_addr(), &lost_request, &lost, CLIENT));
+        let answered_response = time::timeout(Duration::from_secs(1), forwarder.forward(upstream.udp_addr(), &answered_request, &answered, CLIENT));
+        let (lost, answered_response) = tokio::join!(lost, answered_response);
+        assert!(lost.is_err());
+        let answered_response = answered_response.unwrap().unwrap();
+        assert!(answered_response.is_response_to(&answered));
+        assert_eq!(Rcode::NoError, answered_response.header().rcode());
+        assert!(forwarder.v4.get().unwrap().transactions.is_empty());
+
+        assert_eq!(2, upstream.queries().len());
+    }
+
+    #[test]
+    fn test_health() {
+        let (first, second) = (Upstream::Plain(SocketAddr::from(([192, 0, 2, 1], 53))), Upstream::Plain(SocketAddr::from(([192, 0, 2, 2], 53))));
+        let config = Config { upstreams: vec![first.clone(), second.clone()], failover: FailoverConfig { max_failures: 2, down_secs: 30 }, ..Config::default() };
+        let health = Health::default();
+        let now = Instant::now();
+
+        health.failed(&first, &config, now);
+        assert_eq!(vec![first.clone(), second.clone()], health.order(&config, now));
+        health.failed(&first, &config, now);
+        assert_eq!(vec![second.clone()], health.order(&config, now));
+        health.failed(&second, &config, now);
+        health.failed(&second, &config, now);
+        assert_eq!(vec![first.clone(), second.clone()], health.order(&config, now));
+
+        // back after the down time, until the next failure
+        let later = now + Duration::from_secs(30);
+        health.failed(&second, &config, later - Duration::from_secs(1));
+        assert_eq!(vec![first.clone()], health.order(&config, later));
+        health.failed(&first, &config, later);
+        assert_eq!(vec![second.clone()], health.order(&config, later + Duration::from_secs(29)));
+        health.succeeded(&first, Duration::from_millis(10));
+        assert_eq!(vec![first.clone(), second.clone()], health.order(&config, later + Duration::from_secs(29)));
+    }
+
+    #[test]
+    fn test_fastest() {
+        let upstreams: Vec<_> = (1..=3).map(|i| Upstream::Plain(SocketAddr::from(([192, 0, 2, i], 53)))).collect();
+        let config = Config { upstreams: upstreams.clone(), selection: Selection::Fastest, probe_interval: Duration::from_secs(60), ..Config::default() };
+        let health = Health::default();
+        let now = Instant::now();
+
+        // unmeasured upstreams are probed, and come last until they are
+        assert_eq!(upstreams, health.order(&config, now));
+        assert_eq!(upstreams[1..], health.due_for_probe(&config, now));
+        health.succeeded(&upstreams[0], Duration::from_millis(40));
+        health.succeeded(&upstreams[1], Duration::from_millis(20));
+        assert_eq!([1, 0, 2].map(|i| upstreams[i].clone()).to_vec(), health.order(&config, now));
+        assert!(health.due_for_probe(&config, now + Duration::from_secs(59)).is_empty());
+
+        // the average follows slowly, from 20 to 30, 38.75 and 46.4ms
+        for _ in 0..2 {
+            health.succeeded(&upstreams[1], Duration::from_millis(100));
+            assert_eq!([1, 0, 2].map(|i| upstreams[i].clone()).to_vec(), health.order(&config, now));
+        }
+        health.succeeded(&upstreams[1], Duration::from_millis(100));
+        assert_eq!(upstreams, health.order(&config, now + Duration::from_secs(30)));
+
+        // the first one was sent a query since, the others are due again
+        assert_eq!(upstreams[1..], health.due_for_probe(&config, now + Duration::from_secs(60)));
+    }
+
+    #[tokio::test]
+    async fn test_truncated() {
+        let upstream = MockServer::start().unwrap();
+        let mut full = fake_response("www.example.com", RecordType::A, "192.0.2.1");
+        for address in ["192.0.2.2", "192.0.2.3"] {
+            full.push_answer(fake_response("www.example.com", RecordType::A, address).answers()[0].clone());
+        }
+        let truncated = full.truncated_to(60).unwrap();
+        assert!(truncated.header().truncated());
+        upstream.script("www.example.com", RecordType::A, [Reply::Answer(truncated), Reply::Answer(full)]);
+
+        let query = fake_query("www.example.com", RecordType::A);
+        let response = Forwarder::default().forward(upstream.udp_addr(), &query.to_bytes().unwrap(), &query, CLIENT).await.unwrap();
+        assert!(response.is_response_to(&query));
+        assert_eq!(3, response.answers().len());
+        assert_eq!(vec![Transport::Udp, Transport::Tcp], upstream.queries().iter().map(|(transport, _)| *transport).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,284 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use crate::{parse_name, DomainName, EncodeError, ParseError};
+
+/// Bounds-checked reader over a received message, also usable for parsing
+/// RDATA of types scopa doesn't know.
+///
+/// Positions are always absolute offsets into the whole message, also for
+/// buffers split off from another one, so compression pointers stay valid.
+#[derive(Debug, Clone, Copy)]
+pub struct Buffer<'a> {
+    buf: &'a [u8],
+    index: usize,
+    end: usize,
+}
+
+impl<'a> Buffer<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Buffer {
+            buf,
+            index: 0,
+            end: buf.len(),
+        }
+    }
+
+    /// The absolute offset of the next byte.
+    pub fn position(&self) -> usize {
+        self.index
+    }
+
+    /// The number of bytes left before the end of the buffer.
+    pub fn remaining(&self) -> usize {
+        self.end - self.index
+    }
+
+    /// Moves to an absolute offset, which may lie before the start of a split
+    /// off buffer, but not past its end.
+    pub fn seek(&mut self, offset: usize) -> Result<(), ParseError> {
+        if offset > self.end {
+            return Err(ParseError::UnexpectedEof);
+        }
+
+        self.index = offset;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index == self.end
+    }
+
+    /// The next byte, without moving past it.
+    pub fn peek(&self) -> Result<u8, ParseError> {
+        self.buf[self.index..self.end]
+            .first()
+            .copied()
+            .ok_or(ParseError::UnexpectedEof)
+    }
+
+    pub fn next_u8(&mut self) -> Result<u8, ParseError> {
+        Ok(self.next_n(1)?[0])
+    }
+
+    pub fn next_n(&mut self, n: usize) -> Result<&'a [u8], ParseError> {
+        if n > self.remaining() {
+            return Err(ParseError::UnexpectedEof);
+        }
+
+        let bytes = &self.buf[self.index..self.index + n];
+        self.index += n;
+        Ok(bytes)
+    }
+
+    pub fn next_u16(&mut self) -> Result<u16, ParseError> {
+        let bytes = self.next_n(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn next_u32(&mut self) -> Result<u32, ParseError> {
+        let bytes = self.next_n(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// A possibly compressed name. Pointers are followed into the rest of the
+    /// message, also from a split off buffer.
+    pub fn next_name(&mut self) -> Result<DomainName, ParseError> {
+        parse_name(self)
+    }
+
+    /// Everything up to the end of the buffer.
+    pub fn rest(&mut self) -> &'a [u8] {
+        let bytes = &self.buf[self.index..self.end];
+        self.index = self.end;
+        bytes
+    }
+
+    /// Splits off the next `n` bytes into a buffer of their own, for example
+    /// to keep an RDATA parser from reading into the next record.
+    pub fn split(&mut self, n: usize) -> Result<Buffer<'a>, ParseError> {
+        let start = self.index;
+        self.next_n(n)?;
+
+        Ok(Buffer {
+            buf: self.buf,
+            index: start,
+            end: self.index,
+        })
+    }
+}
+
+/// Writer for encoding a message, the counterpart to [`Buffer`].
+pub(crate) struct BufferMut {
+    buf: Vec<u8>,
+    /// Offsets of the names written so far, by their wire format, or `None` if
+    /// compression is disabled. Matching is case sensitive so that a name
+    /// keeps the case it was given, which matters for example for 0x20
+    /// randomized question names.
+    names: Option<BTreeMap<Vec<u8>, u16>>,
+}
+
+impl BufferMut {
+    pub(crate) fn new() -> Self {
+        BufferMut {
+            buf: Vec::new(),
+            names: Some(BTreeMap::new()),
+        }
+    }
+
+    /// A writer that never compresses names.
+    pub(crate) fn uncompressed() -> Self {
+        BufferMut {
+            buf: Vec::new(),
+            names: None,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub(crate) fn into_vec(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub(crate) fn write(&mut self, byte: u8) {
+        self.buf.push(byte);
+    }
+
+    pub(crate) fn write_n(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn write_u16(&mut self, value: u16) {
+        self.write_n(&value.to_be_bytes());
+    }
+
+    pub(crate) fn write_u32(&mut self, value: u32) {
+        self.write_n(&value.to_be_bytes());
+    }
+
+    pub(crate) fn write_character_string(&mut self, string: &[u8]) -> Result<(), EncodeError> {
+        let length = u8::try_from(string.len()).map_err(|_| EncodeError::CharacterStringTooLong)?;
+        self.write(length);
+        self.write_n(string);
+
+        Ok(())
+    }
+
+    /// Writes a name, replacing the longest suffix that was written with
+    /// compression before by a pointer if `compress` is set.
+    pub(crate) fn write_name(
+        &mut self,
+        name: &DomainName,
+        compress: bool,
+    ) -> Result<(), EncodeError> {
+        name.check().map_err(EncodeError::InvalidName)?;
+
+        let mut suffix = name.as_wire();
+        while let Some(&length) = suffix.first() {
+            if let Some(names) = self.names.as_mut().filter(|_| compress) {
+                if let Some(&offset) = names.get(suffix) {
+                    self.write_u16(0b11000000_00000000 | offset);
+                    return Ok(());
+                }
+                // pointers only have 14 bits for the offset
+                if self.buf.len() < 0b01000000_00000000 {
+                    names.insert(suffix.to_vec(), self.buf.len() as u16);
+                }
+            }
+
+            let (label, rest) = suffix.split_at(1 + length as usize);
+            self.write_n(label);
+            suffix = rest;
+        }
+        self.write(0);
+
+        Ok(())
+    }
+
+    /// Writes a 16 bit length followed by whatever `write` writes, patching
+    /// the length in afterwards, the way RDATA and SvcParam values are framed.
+    pub(crate) fn with_length(
+        &mut self,
+        write: impl FnOnce(&mut Self) -> Result<(), EncodeError>,
+    ) -> Result<(), EncodeError> {
+        let start = self.buf.len();
+        self.write_u16(0);
+        write(self)?;
+
+        let length =
+            u16::try_from(self.buf.len() - start - 2).map_err(|_| EncodeError::DataTooLong)?;
+        self.buf[start..start + 2].copy_from_slice(&length.to_be_bytes());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NameError;
+
+    #[test]
+    fn test_reads_stop_at_end() {
+        let mut buf = Buffer::new(&[1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(Ok(0x0102), buf.next_u16());
+        assert_eq!((2, 5), (buf.position(), buf.remaining()));
+        assert_eq!(Ok(3), buf.peek());
+
+        let mut split = buf.split(3).unwrap();
+        assert_eq!(Err(ParseError::UnexpectedEof), split.next_u32());
+        assert_eq!(Ok(&[3, 4][..]), split.next_n(2));
+        assert_eq!(4, split.position());
+        assert_eq!(&[5], split.rest());
+        assert!(split.is_empty());
+        assert_eq!(Err(ParseError::UnexpectedEof), split.peek());
+        assert_eq!(Err(ParseError::UnexpectedEof), split.next_u8());
+
+        assert_eq!(5, buf.position());
+        assert_eq!(Err(ParseError::UnexpectedEof), buf.split(3).map(|_| ()));
+        assert_eq!(Ok(6), buf.next_u8());
+
+        split.seek(1).unwrap();
+        assert_eq!(Ok(2), split.next_u8());
+        assert_eq!(Err(ParseError::UnexpectedEof), split.seek(6));
+    }
+
+    #[test]
+    fn test_writes() {
+        let mut buf = BufferMut::new();
+        buf.write_u16(0x0102);
+        buf.with_length(|buf| {
+            buf.write_u32(0x03040506);
+            buf.write_character_string(b"ab")
+        })
+        .unwrap();
+        assert_eq!(vec![1, 2, 0, 7, 3, 4, 5, 6, 2, 97, 98], buf.into_vec());
+
+        let mut buf = BufferMut::new();
+        assert_eq!(Err(EncodeError::CharacterStringTooLong), buf.write_character_string(&[0; 256]));
+        assert_eq!(Err(EncodeError::DataTooLong), buf.with_length(|buf| {
+            buf.write_n(&[0; 65536]);
+            Ok(())
+        }));
+    }
+
+    #[test]
+    fn test_write_name() {
+        let name: DomainName = "www.example.com".parse().unwrap();
+        let mut buf = BufferMut::new();
+        buf.write_name(&"example.com".parse().unwrap(), true).unwrap();
+        buf.write_name(&name, false).unwrap();
+        buf.write_name(&name, true).unwrap();
+        buf.write_name(&name, true).unwrap();
+        assert_eq!(vec![7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 3, 119, 119, 119, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 3, 119, 119, 119, 192, 0, 192, 30], buf.into_vec());
+
+        let mut buf = BufferMut::uncompressed();
+        buf.write_name(&name, true).unwrap();
+        buf.write_name(&name, true).unwrap();
+        assert_eq!(34, buf.len());
+
+        let long = DomainName::from_labels(vec!["a".repeat(64)]);
+        assert_eq!(Err(EncodeError::InvalidName(NameError::LabelTooLong)), buf.write_name(&long, true));
+    }
+}
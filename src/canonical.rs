@@ -0,0 +1,110 @@
+//! The canonical form and order of records (RFC 4034 section 6), which
+//! DNSSEC signatures are computed over and which makes messages comparable
+//! regardless of name case and record order.
+
+use alloc::vec::Vec;
+
+use crate::{buffer::BufferMut, EncodeError, ResourceData, ResourceRecord};
+
+impl ResourceData {
+    /// The RDATA uncompressed and with the names lowercased in the types
+    /// listed in RFC 4034 6.2. The next name of NSEC records keeps its case,
+    /// as corrected by RFC 6840.
+    pub fn to_canonical_wire(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut data = self.clone();
+        match &mut data {
+            ResourceData::Ns(name)
+            | ResourceData::Cname(name)
+            | ResourceData::Ptr(name)
+            | ResourceData::Dname(name)
+            | ResourceData::Mx { exchange: name, .. }
+            | ResourceData::Srv { target: name, .. }
+            | ResourceData::Naptr {
+                replacement: name, ..
+            } => *name = name.to_lowercase(),
+            ResourceData::Soa(soa) => {
+                soa.mname = soa.mname.to_lowercase();
+                soa.rname = soa.rname.to_lowercase();
+            }
+            ResourceData::Rrsig(rrsig) | ResourceData::Sig(rrsig) => {
+                rrsig.signer_name = rrsig.signer_name.to_lowercase();
+            }
+            _ => {}
+        }
+
+        data.to_wire()
+    }
+}
+
+impl ResourceRecord {
+    /// The record with a lowercased owner name and canonical RDATA.
+    pub fn to_canonical_wire(&self) -> Result<Vec<u8>, EncodeError> {
+        let data = self.data.to_canonical_wire()?;
+
+        let mut buf = BufferMut::uncompressed();
+        buf.write_name(&self.name.to_lowercase(), false)?;
+        buf.write_u16(self.r#type.into());
+        buf.write_u16(self.class.into());
+        buf.write_u32(self.ttl);
+        buf.write_u16(u16::try_from(data.len()).map_err(|_| EncodeError::DataTooLong)?);
+        buf.write_n(&data);
+
+        Ok(buf.into_vec())
+    }
+}
+
+/// Sorts records canonically, by owner name, type and class, and within
+/// each RRset by their canonical RDATA, dropping duplicates, which an RRset
+/// can't contain.
+///
+/// Records whose RDATA can't be encoded sort first in their RRset.
+pub fn sort_canonical(records: &mut Vec<ResourceRecord>) {
+    let mut keyed: Vec<_> = records
+        .drain(..)
+        .map(|record| (record.data.to_canonical_wire().unwrap_or_default(), record))
+        .collect();
+    keyed.sort_by(|(a_data, a), (b_data, b)| {
+        a.name
+            .cmp(&b.name)
+            .then_with(|| u16::from(a.r#type).cmp(&b.r#type.into()))
+            .then_with(|| u16::from(a.class).cmp(&b.class.into()))
+            .then_with(|| a_data.cmp(b_data))
+    });
+    keyed.dedup_by(|(a_data, a), (b_data, b)| {
+        a.name == b.name && a.r#type == b.r#type && a.class == b.class && a_data == b_data
+    });
+
+    records.extend(keyed.into_iter().map(|(_, record)| record));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Class, DomainName, Soa};
+    use alloc::vec;
+
+    fn name(name: &str) -> DomainName {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn test_canonical_wire() {
+        let mx = ResourceData::Mx { preference: 10, exchange: name("Mail.Example") };
+        assert_eq!(vec![0, 10, 4, 109, 97, 105, 108, 7, 101, 120, 97, 109, 112, 108, 101, 0], mx.to_canonical_wire().unwrap());
+        let soa = ResourceData::Soa(Soa { mname: name("NS"), rname: name("A.b"), serial: 1, refresh: 2, retry: 3, expire: 4, minimum: 5 });
+        assert_eq!(vec![2, 110, 115, 0, 1, 97, 1, 98, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0, 5], soa.to_canonical_wire().unwrap());
+        let nsec = ResourceData::Nsec(crate::Nsec { next_domain_name: name("B"), types: vec![] });
+        assert_eq!(vec![1, 66, 0], nsec.to_canonical_wire().unwrap());
+
+        let record = ResourceRecord::new(name("WWW.example"), Class::In, 300, ResourceData::Cname(name("Example")));
+        assert_eq!(vec![3, 119, 119, 119, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 5, 0, 1, 0, 0, 1, 44, 0, 9, 7, 101, 120, 97, 109, 112, 108, 101, 0], record.to_canonical_wire().unwrap());
+    }
+
+    #[test]
+    fn test_sort_canonical() {
+        let a = |owner: &str, address: [u8; 4]| ResourceRecord::new(name(owner), Class::In, 300, ResourceData::A(address.into()));
+        let mut records = vec![a("b.example", [192, 0, 2, 1]), a("a.example", [192, 0, 2, 10]), a("A.example", [192, 0, 2, 2]), ResourceRecord::new(name("a.example"), Class::In, 300, ResourceData::Ns(name("ns.example"))), a("a.example", [192, 0, 2, 2])];
+        sort_canonical(&mut records);
+        assert_eq!(vec![a("a.example", [192, 0, 2, 2]), a("a.example", [192, 0, 2, 10]), ResourceRecord::new(name("a.example"), Class::In, 300, ResourceData::Ns(name("ns.example"))), a("b.example", [192, 0, 2, 1])], records);
+    }
+}
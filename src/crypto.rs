@@ -0,0 +1,144 @@
+//! Public key signature checks for the DNSSEC algorithms, shared by SIG(0)
+//! and RRSIG validation.
+
+use alloc::vec::Vec;
+
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey, VerificationAlgorithm};
+
+use crate::Rrsig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SignatureError {
+    /// An algorithm number scopa can't check signatures of.
+    UnsupportedAlgorithm(u8),
+    /// A public key that doesn't fit its algorithm.
+    BadKey,
+    /// A signature that doesn't match.
+    BadSignature,
+}
+
+/// Checks `signature` over `data` with a public key in the format of DNSKEY
+/// and KEY records for `algorithm`.
+pub(crate) fn verify_signature(
+    algorithm: u8,
+    public_key: &[u8],
+    data: &[u8],
+    signature: &[u8],
+) -> Result<(), SignatureError> {
+    match algorithm {
+        // RSASHA1 and RSASHA1-NSEC3-SHA1
+        5 | 7 => verify_rsa(
+            &signature::RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY,
+            public_key,
+            data,
+            signature,
+        ),
+        // RSASHA256, the raised minimum size would reject many deployed keys
+        8 => verify_rsa(
+            &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY,
+            public_key,
+            data,
+            signature,
+        ),
+        // RSASHA512
+        10 => verify_rsa(
+            &signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY,
+            public_key,
+            data,
+            signature,
+        ),
+        // ECDSAP256SHA256 and ECDSAP384SHA384 keep the point without the
+        // prefix marking it as uncompressed (RFC 6605)
+        13 => verify_ecdsa(
+            &signature::ECDSA_P256_SHA256_FIXED,
+            64,
+            public_key,
+            data,
+            signature,
+        ),
+        14 => verify_ecdsa(
+            &signature::ECDSA_P384_SHA384_FIXED,
+            96,
+            public_key,
+            data,
+            signature,
+        ),
+        // ED25519
+        15 => verify(&signature::ED25519, public_key, data, signature),
+        _ => Err(SignatureError::UnsupportedAlgorithm(algorithm)),
+    }
+}
+
+/// The RDATA of an RRSIG or SIG record without the signature, which starts
+/// the data signed.
+pub(crate) fn signature_prefix(sig: &Rrsig) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&u16::from(sig.type_covered).to_be_bytes());
+    data.extend_from_slice(&[sig.algorithm, sig.labels]);
+    data.extend_from_slice(&sig.original_ttl.to_be_bytes());
+    data.extend_from_slice(&sig.expiration.to_be_bytes());
+    data.extend_from_slice(&sig.inception.to_be_bytes());
+    data.extend_from_slice(&sig.key_tag.to_be_bytes());
+    data.extend_from_slice(&sig.signer_name.to_lowercase().to_wire());
+
+    data
+}
+
+/// Whether `now`, in seconds since the Unix epoch, lies between inception
+/// and expiration, which use serial number arithmetic to keep working after
+/// 2106.
+pub(crate) fn in_validity_period(sig: &Rrsig, now: u64) -> bool {
+    let now = now as u32;
+    now.wrapping_sub(sig.inception) as i32 >= 0 && sig.expiration.wrapping_sub(now) as i32 >= 0
+}
+
+/// RSA keys are the exponent length in one byte, or in two after a zero
+/// byte, the exponent and the modulus (RFC 3110).
+fn verify_rsa(
+    parameters: &signature::RsaParameters,
+    public_key: &[u8],
+    data: &[u8],
+    signature: &[u8],
+) -> Result<(), SignatureError> {
+    let (exponent_length, rest) = match public_key {
+        [0, high, low, rest @ ..] => (u16::from_be_bytes([*high, *low]) as usize, rest),
+        [length, rest @ ..] => (*length as usize, rest),
+        [] => return Err(SignatureError::BadKey),
+    };
+    if exponent_length == 0 || exponent_length >= rest.len() {
+        return Err(SignatureError::BadKey);
+    }
+
+    let (e, n) = rest.split_at(exponent_length);
+    RsaPublicKeyComponents { n, e }
+        .verify(parameters, data, signature)
+        .map_err(|_| SignatureError::BadSignature)
+}
+
+fn verify_ecdsa(
+    algorithm: &'static dyn VerificationAlgorithm,
+    length: usize,
+    public_key: &[u8],
+    data: &[u8],
+    signature: &[u8],
+) -> Result<(), SignatureError> {
+    if public_key.len() != length {
+        return Err(SignatureError::BadKey);
+    }
+
+    let mut point = [0; 97];
+    point[0] = 4;
+    point[1..=length].copy_from_slice(public_key);
+    verify(algorithm, &point[..=length], data, signature)
+}
+
+fn verify(
+    algorithm: &'static dyn VerificationAlgorithm,
+    public_key: &[u8],
+    data: &[u8],
+    signature: &[u8],
+) -> Result<(), SignatureError> {
+    UnparsedPublicKey::new(algorithm, public_key)
+        .verify(data, signature)
+        .map_err(|_| SignatureError::BadSignature)
+}
@@ -0,0 +1,362 @@
+//! The checks a validating resolver builds on (RFC 4035): signatures over
+//! RRsets, DS digests over keys and NSEC3 hashes for denial of existence.
+//!
+//! Neither looks at the chain of trust, so the caller has to make sure that
+//! the key belongs to the signer of the RRSIG and that the DS record was
+//! itself validated.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use ring::digest;
+
+use crate::{
+    canonical::sort_canonical,
+    crypto::{in_validity_period, signature_prefix, verify_signature, SignatureError},
+    text::base32hex,
+    Dnskey, DomainName, Ds, Nsec3, ResourceRecord, Rrsig,
+};
+
+/// The flag marking DNSKEYs that may sign RRsets.
+const ZONE_KEY: u16 = 0x0100;
+
+/// Checks `rrsig` over `rrset` with `key` at `now`, in seconds since the
+/// Unix epoch.
+///
+/// The records are put into canonical form and order, with the original TTL
+/// from the RRSIG, so they may come straight from a response. Signatures of
+/// wildcard expansions are checked against the wildcard name.
+pub fn verify_rrsig(
+    key: &Dnskey,
+    rrsig: &Rrsig,
+    rrset: &[ResourceRecord],
+    now: u64,
+) -> Result<(), DnssecError> {
+    if key.flags & ZONE_KEY == 0 || key.protocol != 3 {
+        return Err(DnssecError::NotZoneKey);
+    }
+    if key.algorithm != rrsig.algorithm || key.key_tag() != rrsig.key_tag {
+        return Err(DnssecError::KeyMismatch);
+    }
+
+    let first = rrset.first().ok_or(DnssecError::InvalidRrset)?;
+    let owner = signed_owner(first.name(), rrsig.labels)?;
+    if first.record_type() != rrsig.type_covered
+        || rrset.iter().any(|record| {
+            record.name != first.name
+                || record.r#type != first.r#type
+                || record.class != first.class
+        })
+    {
+        return Err(DnssecError::InvalidRrset);
+    }
+
+    let mut records: Vec<_> = rrset
+        .iter()
+        .map(|record| {
+            let mut record = record.clone();
+            record.name = owner.clone();
+            record.ttl = rrsig.original_ttl;
+            record
+        })
+        .collect();
+    sort_canonical(&mut records);
+
+    let mut data = signature_prefix(rrsig);
+    for record in &records {
+        let wire = record
+            .to_canonical_wire()
+            .map_err(|_| DnssecError::InvalidRrset)?;
+        data.extend_from_slice(&wire);
+    }
+
+    verify_signature(rrsig.algorithm, &key.public_key, &data, &rrsig.signature).map_err(
+        |error| match error {
+            SignatureError::UnsupportedAlgorithm(algorithm) => {
+                DnssecError::UnsupportedAlgorithm(algorithm)
+            }
+            SignatureError::BadKey => DnssecError::BadKey,
+            SignatureError::BadSignature => DnssecError::BadSignature,
+        },
+    )?;
+
+    if !in_validity_period(rrsig, now) {
+        return Err(DnssecError::Expired);
+    }
+
+    Ok(())
+}
+
+/// The owner name the signature was made over, which is the wildcard for
+/// records synthesized from one (RFC 4035 5.3.2).
+fn signed_owner(owner: &DomainName, labels: u8) -> Result<DomainName, DnssecError> {
+    let owner_labels: Vec<_> = owner.labels().collect();
+    let count = owner_labels.len() - (owner_labels.first() == Some(&b"*".as_slice())) as usize;
+    let labels = labels as usize;
+
+    if labels > count {
+        Err(DnssecError::InvalidRrset)
+    } else if labels < count {
+        let suffix = &owner_labels[owner_labels.len() - labels..];
+        Ok(DomainName::from_labels(
+            [b"*".as_slice()].iter().chain(suffix),
+        ))
+    } else {
+        Ok(owner.clone())
+    }
+}
+
+/// Whether `ds` refers to `key`, owned by `owner`, comparing key tag and
+/// algorithm and the digest over the owner and key (RFC 4034 5.1.4).
+///
+/// SHA-1, SHA-256 and SHA-384 digests are supported.
+pub fn ds_matches_key(ds: &Ds, owner: &DomainName, key: &Dnskey) -> Result<bool, DnssecError> {
+    let algorithm = match ds.digest_type {
+        1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        2 => &digest::SHA256,
+        4 => &digest::SHA384,
+        digest_type => return Err(DnssecError::UnsupportedDigest(digest_type)),
+    };
+    if ds.key_tag != key.key_tag() || ds.algorithm != key.algorithm {
+        return Ok(false);
+    }
+
+    let mut context = digest::Context::new(algorithm);
+    context.update(&owner.to_lowercase().to_wire());
+    context.update(&key.flags.to_be_bytes());
+    context.update(&[key.protocol, key.algorithm]);
+    context.update(&key.public_key);
+
+    Ok(context.finish().as_ref() == ds.digest.as_slice())
+}
+
+/// The NSEC3 hash of `name` (RFC 5155 5), of which SHA-1 is the only
+/// defined algorithm.
+pub fn nsec3_hash(
+    name: &DomainName,
+    algorithm: u8,
+    iterations: u16,
+    salt: &[u8],
+) -> Result<Vec<u8>, DnssecError> {
+    if algorithm != 1 {
+        return Err(DnssecError::UnsupportedDigest(algorithm));
+    }
+
+    let mut hash = name.to_lowercase().to_wire();
+    for _ in 0..=iterations {
+        let mut context = digest::Context::new(&digest::SHA1_FOR_LEGACY_USE_ONLY);
+        context.update(&hash);
+        context.update(salt);
+        hash = context.finish().as_ref().to_vec();
+    }
+
+    Ok(hash)
+}
+
+/// The owner of the NSEC3 record matching `name`, the base32hex encoded
+/// hash as a label below `zone`.
+pub fn nsec3_owner(
+    name: &DomainName,
+    zone: &DomainName,
+    algorithm: u8,
+    iterations: u16,
+    salt: &[u8],
+) -> Result<DomainName, DnssecError> {
+    let hash = nsec3_hash(name, algorithm, iterations, salt)?;
+
+    let label = base32hex(&hash);
+    Ok(DomainName::from_labels(
+        core::iter::once(label.as_bytes()).chain(zone.labels()),
+    ))
+}
+
+/// Whether the NSEC3 record owned by `owner` is the one for `hash`.
+pub fn nsec3_matches(owner: &DomainName, hash: &[u8]) -> bool {
+    owner_hash(owner).is_some_and(|owner| owner == hash)
+}
+
+/// Whether `hash` falls strictly between the hash in `owner` and the next
+/// hashed owner of `nsec3`, which proves that no such name exists. The last
+/// record of a zone covers the hashes after it and before the first one.
+///
+/// An [`Nsec3::opt_out`] record covering a hash leaves open that an
+/// unsigned delegation exists there.
+pub fn nsec3_covers(owner: &DomainName, nsec3: &Nsec3, hash: &[u8]) -> bool {
+    let Some(owner) = owner_hash(owner) else {
+        return false;
+    };
+    let (owner, next) = (owner.as_slice(), nsec3.next_hashed_owner.as_slice());
+
+    if owner < next {
+        owner < hash && hash < next
+    } else {
+        owner < hash || hash < next
+    }
+}
+
+/// The hash in the first label of an NSEC3 owner name.
+fn owner_hash(owner: &DomainName) -> Option<Vec<u8>> {
+    let label = owner.labels().next()?;
+
+    let mut hash = Vec::new();
+    let mut bits = 0u64;
+    let mut count = 0;
+    for &byte in label {
+        let quintet = match byte.to_ascii_lowercase() {
+            byte @ b'0'..=b'9' => byte - b'0',
+            byte @ b'a'..=b'v' => byte - b'a' + 10,
+            _ => return None,
+        };
+        bits = bits << 5 | quintet as u64;
+        count += 5;
+        if count >= 8 {
+            count -= 8;
+            hash.push((bits >> count) as u8);
+        }
+    }
+
+    Some(hash)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnssecError {
+    /// The DNSKEY doesn't have the zone key flag or protocol 3.
+    NotZoneKey,
+    /// The key tag or algorithm of the RRSIG don't match the key.
+    KeyMismatch,
+    /// A public key that doesn't fit its algorithm.
+    BadKey,
+    /// The records aren't one RRset of the type the RRSIG covers, or have
+    /// fewer labels than it.
+    InvalidRrset,
+    UnsupportedAlgorithm(u8),
+    /// A DS digest type or NSEC3 hash algorithm scopa doesn't support.
+    UnsupportedDigest(u8),
+    /// The signature doesn't match.
+    BadSignature,
+    /// The signature isn't valid at this time.
+    Expired,
+}
+
+impl fmt::Display for DnssecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DnssecError::NotZoneKey => write!(f, "DNSKEY isn't a zone key"),
+            DnssecError::KeyMismatch => write!(f, "RRSIG was made with a different key"),
+            DnssecError::BadKey => write!(f, "invalid DNSKEY for its algorithm"),
+            DnssecError::InvalidRrset => write!(f, "records aren't the RRset the RRSIG covers"),
+            DnssecError::UnsupportedAlgorithm(algorithm) => {
+                write!(f, "unsupported DNSSEC algorithm {algorithm}")
+            }
+            DnssecError::UnsupportedDigest(digest_type) => {
+                write!(f, "unsupported digest type {digest_type}")
+            }
+            DnssecError::BadSignature => write!(f, "RRSIG signature doesn't match"),
+            DnssecError::Expired => write!(f, "RRSIG not valid at this time"),
+        }
+    }
+}
+
+impl core::error::Error for DnssecError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Class, RecordType, ResourceData};
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    fn name(name: &str) -> DomainName {
+        name.parse().unwrap()
+    }
+
+    fn key_pair() -> Ed25519KeyPair {
+        Ed25519KeyPair::from_seed_unchecked(&[3; 32]).unwrap()
+    }
+
+    fn key() -> Dnskey {
+        Dnskey { flags: 257, protocol: 3, algorithm: 15, public_key: key_pair().public_key().as_ref().to_vec() }
+    }
+
+    fn a(owner: &str, ttl: u32, address: [u8; 4]) -> ResourceRecord {
+        ResourceRecord::new(name(owner), Class::In, ttl, ResourceData::A(address.into()))
+    }
+
+    /// Signs the RRset in the order given, which has to be canonical.
+    fn rrsig(rrset: &[ResourceRecord], labels: u8) -> Rrsig {
+        let mut rrsig = Rrsig { type_covered: RecordType::A, algorithm: 15, labels, original_ttl: 3600, expiration: 1_700_086_400, inception: 1_700_000_000, key_tag: key().key_tag(), signer_name: name("example"), signature: vec![] };
+        let mut data = signature_prefix(&rrsig);
+        for record in rrset {
+            let mut record = record.clone();
+            record.ttl = 3600;
+            data.extend_from_slice(&record.to_canonical_wire().unwrap());
+        }
+        rrsig.signature = key_pair().sign(&data).as_ref().to_vec();
+        rrsig
+    }
+
+    #[test]
+    fn test_verify_rrsig() {
+        let rrset = [a("www.example", 3600, [192, 0, 2, 1]), a("www.example", 3600, [192, 0, 2, 2])];
+        let rrsig = rrsig(&rrset, 2);
+
+        // case, order and decremented TTLs don't matter
+        let received = [a("WWW.example", 100, [192, 0, 2, 2]), a("www.Example", 100, [192, 0, 2, 1])];
+        assert_eq!(Ok(()), verify_rrsig(&key(), &rrsig, &received, 1_700_000_100));
+        assert_eq!(Err(DnssecError::Expired), verify_rrsig(&key(), &rrsig, &received, 1_700_086_401));
+        assert_eq!(Err(DnssecError::BadSignature), verify_rrsig(&key(), &rrsig, &received[..1], 1_700_000_100));
+        assert_eq!(Err(DnssecError::InvalidRrset), verify_rrsig(&key(), &rrsig, &[], 1_700_000_100));
+        assert_eq!(Err(DnssecError::InvalidRrset), verify_rrsig(&key(), &rrsig, &[a("www.example", 100, [192, 0, 2, 1]), a("ftp.example", 100, [192, 0, 2, 2])], 1_700_000_100));
+        assert_eq!(Err(DnssecError::KeyMismatch), verify_rrsig(&Dnskey { algorithm: 13, ..key() }, &rrsig, &received, 1_700_000_100));
+        assert_eq!(Err(DnssecError::NotZoneKey), verify_rrsig(&Dnskey { flags: 1, ..key() }, &rrsig, &received, 1_700_000_100));
+    }
+
+    #[test]
+    fn test_verify_wildcard_rrsig() {
+        let rrsig = rrsig(&[a("*.example", 3600, [192, 0, 2, 1])], 1);
+        assert_eq!(Ok(()), verify_rrsig(&key(), &rrsig, &[a("a.b.example", 60, [192, 0, 2, 1])], 1_700_000_100));
+        assert_eq!(Ok(()), verify_rrsig(&key(), &rrsig, &[a("*.example", 60, [192, 0, 2, 1])], 1_700_000_100));
+        assert_eq!(Err(DnssecError::BadSignature), verify_rrsig(&key(), &rrsig, &[a("example", 60, [192, 0, 2, 1])], 1_700_000_100));
+        assert_eq!(Err(DnssecError::InvalidRrset), verify_rrsig(&key(), &Rrsig { labels: 3, ..rrsig }, &[a("b.example", 60, [192, 0, 2, 1])], 1_700_000_100));
+    }
+
+    #[test]
+    fn test_ds_matches_key() {
+        // the example from RFC 4034 5.4
+        let public_key = vec![1, 3, 158, 138, 36, 116, 24, 227, 24, 144, 59, 33, 90, 132, 138, 207, 213, 243, 127, 2, 107, 212, 6, 45, 178, 108, 119, 76, 105, 9, 104, 213, 213, 109, 248, 191, 218, 145, 230, 243, 109, 154, 39, 152, 136, 244, 19, 51, 53, 124, 94, 96, 41, 153, 13, 16, 253, 245, 102, 48, 98, 165, 18, 118, 51, 38, 152, 10, 97, 93, 219, 241, 122, 5, 221, 252, 206, 126, 95, 179, 171, 204, 160, 90, 49, 176, 149, 116, 82, 212, 82, 30, 131, 135, 7, 137, 6, 49, 21, 191, 151, 246, 195, 8, 204, 245, 124, 220, 156, 231, 254, 16, 246, 237, 27, 208, 204, 6, 96, 3, 140, 80, 220, 219, 15, 235, 150, 60, 47, 23];
+        let key = Dnskey { flags: 256, protocol: 3, algorithm: 5, public_key };
+        let ds = Ds { key_tag: 60485, algorithm: 5, digest_type: 1, digest: vec![43, 177, 131, 175, 95, 34, 88, 129, 121, 165, 59, 10, 152, 99, 31, 173, 26, 41, 33, 24] };
+        assert_eq!(Ok(true), ds_matches_key(&ds, &name("DSKEY.example.com"), &key));
+        assert_eq!(Ok(false), ds_matches_key(&ds, &name("other.example.com"), &key));
+        assert_eq!(Ok(false), ds_matches_key(&Ds { key_tag: 1, ..ds.clone() }, &name("dskey.example.com"), &key));
+        assert_eq!(Err(DnssecError::UnsupportedDigest(3)), ds_matches_key(&Ds { digest_type: 3, ..ds }, &name("dskey.example.com"), &key));
+    }
+
+    #[test]
+    fn test_nsec3_hash() {
+        // from RFC 5155 appendix A
+        let salt = [0xaa, 0xbb, 0xcc, 0xdd];
+        assert_eq!(Ok(name("0p9mhaveqvm6t7vbl5lop2u3t2rp3tom.example")), nsec3_owner(&name("example"), &name("example"), 1, 12, &salt));
+        assert_eq!(Ok(name("35mthgpgcu1qg68fab165klnsnk3dpvl.example")), nsec3_owner(&name("A.example"), &name("example"), 1, 12, &salt));
+        assert_eq!(Err(DnssecError::UnsupportedDigest(2)), nsec3_hash(&name("example"), 2, 12, &salt));
+
+        let hash = nsec3_hash(&name("a.example"), 1, 12, &salt).unwrap();
+        assert!(nsec3_matches(&name("35MTHGPGCU1QG68FAB165KLNSNK3DPVL.example"), &hash));
+        assert!(!nsec3_matches(&name("0p9mhaveqvm6t7vbl5lop2u3t2rp3tom.example"), &hash));
+    }
+
+    #[test]
+    fn test_nsec3_covers() {
+        let nsec3 = |next: u8, flags| Nsec3 { hash_algorithm: 1, flags, iterations: 0, salt: vec![], next_hashed_owner: vec![next; 20], types: vec![] };
+        // the label is the hash [0x10; 20]
+        let owner = name(&(base32hex(&[0x10; 20]) + ".example"));
+        assert!(nsec3_covers(&owner, &nsec3(0x20, 0), &[0x18; 20]));
+        assert!(!nsec3_covers(&owner, &nsec3(0x20, 0), &[0x10; 20]));
+        assert!(!nsec3_covers(&owner, &nsec3(0x20, 0), &[0x28; 20]));
+        // the last record wraps around
+        assert!(nsec3_covers(&owner, &nsec3(0x08, 0), &[0x28; 20]));
+        assert!(nsec3_covers(&owner, &nsec3(0x08, 0), &[0x04; 20]));
+        assert!(!nsec3_covers(&owner, &nsec3(0x08, 0), &[0x09; 20]));
+        assert!(!nsec3_covers(&name("not-base32.example"), &nsec3(0x20, 0), &[0x18; 20]));
+        assert!(nsec3(0x20, 1).opt_out() && !nsec3(0x20, 0).opt_out());
+    }
+}
@@ -0,0 +1,692 @@
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+
+use crate::{
+    buffer::{Buffer, BufferMut},
+    Class, DomainName, EncodeError, Header, Message, ParseError, Rcode, ResourceData,
+    ResourceRecord, Violation,
+};
+
+/// The EDNS(0) parameters of a message, carried on the wire by an OPT
+/// pseudo-record in the additional section (RFC 6891).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Edns {
+    /// The largest UDP payload the sender can reassemble.
+    pub udp_payload: u16,
+    /// The upper eight bits of the message's twelve bit rcode. When parsing,
+    /// they are combined into the rcode of the header, and when encoding,
+    /// they are taken from it, so setting this field has no effect.
+    pub ext_rcode: u8,
+    pub version: u8,
+    /// The DO bit, asking for DNSSEC records (RFC 3225).
+    pub dnssec_ok: bool,
+    pub options: Vec<EdnsOption>,
+}
+
+impl Default for Edns {
+    /// Version 0 with a payload size of 1232, which avoids fragmentation on
+    /// nearly all paths as recommended by DNS Flag Day 2020.
+    fn default() -> Self {
+        Edns {
+            udp_payload: 1232,
+            ext_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: Vec::new(),
+        }
+    }
+}
+
+impl Edns {
+    /// The identifier of the server that answered, if it sent one.
+    pub fn nsid(&self) -> Option<&[u8]> {
+        self.options.iter().find_map(|option| match option {
+            EdnsOption::Nsid(nsid) => Some(nsid.as_slice()),
+            _ => None,
+        })
+    }
+
+    pub fn cookie(&self) -> Option<&Cookie> {
+        self.options.iter().find_map(|option| match option {
+            EdnsOption::Cookie(cookie) => Some(cookie),
+            _ => None,
+        })
+    }
+
+    /// Replaces the cookie option, or adds one.
+    pub fn set_cookie(&mut self, cookie: Cookie) {
+        self.options
+            .retain(|option| !matches!(option, EdnsOption::Cookie(_)));
+        self.options.push(EdnsOption::Cookie(cookie));
+    }
+
+    /// The idle timeout a server advertised for the TCP connection.
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        self.options.iter().find_map(|option| match option {
+            EdnsOption::TcpKeepalive(Some(timeout)) => {
+                Some(Duration::from_millis(*timeout as u64 * 100))
+            }
+            _ => None,
+        })
+    }
+
+    /// The extended errors, of which a response may carry several.
+    pub fn extended_errors(&self) -> impl Iterator<Item = &ExtendedError> {
+        self.options.iter().filter_map(|option| match option {
+            EdnsOption::ExtendedError(error) => Some(error),
+            _ => None,
+        })
+    }
+
+    pub fn client_subnet(&self) -> Option<&ClientSubnet> {
+        self.options.iter().find_map(|option| match option {
+            EdnsOption::ClientSubnet(subnet) => Some(subnet),
+            _ => None,
+        })
+    }
+
+    /// The OPT record carrying these parameters for a message with `rcode`.
+    pub(crate) fn to_record(&self, rcode: Rcode) -> ResourceRecord {
+        let ext_rcode = (u16::from(rcode) >> 4) as u8;
+        let ttl = u32::from_be_bytes([ext_rcode, self.version, (self.dnssec_ok as u8) << 7, 0]);
+
+        ResourceRecord::new(
+            DomainName::root(),
+            Class::from(self.udp_payload),
+            ttl,
+            ResourceData::Opt(self.options.clone()),
+        )
+    }
+
+    fn from_record(record: ResourceRecord) -> Option<Self> {
+        let ResourceData::Opt(options) = record.data else {
+            return None;
+        };
+        let [ext_rcode, version, flags, _] = record.ttl.to_be_bytes();
+
+        Some(Edns {
+            udp_payload: record.class.into(),
+            ext_rcode,
+            version,
+            dnssec_ok: flags & 0x80 != 0,
+            options,
+        })
+    }
+}
+
+/// The block size RFC 8467 recommends padding queries to.
+pub const QUERY_BLOCK_SIZE: usize = 128;
+
+/// The block size RFC 8467 recommends padding responses to.
+pub const RESPONSE_BLOCK_SIZE: usize = 468;
+
+impl Message {
+    /// Replaces any padding option with one that makes the encoded message a
+    /// multiple of `block_size` bytes long, following the Block-Length
+    /// Padding strategy of RFC 8467. Messages without EDNS are left alone,
+    /// as they can't carry padding.
+    ///
+    /// Panics if `block_size` is zero.
+    pub fn pad_to_block(&mut self, block_size: usize) -> Result<(), EncodeError> {
+        let Some(edns) = &mut self.edns else {
+            return Ok(());
+        };
+        edns.options
+            .retain(|option| !matches!(option, EdnsOption::Padding(_)));
+
+        // the option itself takes four bytes for its code and length
+        let length = self.to_bytes()?.len() + 4;
+        let padding = (block_size - length % block_size) % block_size;
+        let padding = u16::try_from(padding).map_err(|_| EncodeError::DataTooLong)?;
+        if let Some(edns) = &mut self.edns {
+            edns.options.push(EdnsOption::Padding(padding));
+        }
+
+        Ok(())
+    }
+
+    /// Attaches an extended error, for example to say why an answer was
+    /// blocked. Returns `false` if the message has no EDNS to carry it, as
+    /// RFC 8914 only allows extended errors in replies to EDNS queries.
+    pub fn push_extended_error(&mut self, error: ExtendedError) -> bool {
+        match &mut self.edns {
+            Some(edns) => {
+                edns.options.push(EdnsOption::ExtendedError(error));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Takes the first OPT record out of `additionals`, combining its extended
+/// rcode bits into the header. Any further OPT records are left in place and
+/// reported, since RFC 6891 allows only one.
+pub(crate) fn take_edns(
+    header: &mut Header,
+    additionals: &mut Vec<ResourceRecord>,
+    violations: &mut Vec<Violation>,
+) -> Option<Edns> {
+    let mut opts = additionals
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| matches!(record.data, ResourceData::Opt(_)))
+        .map(|(index, _)| index);
+    let first = opts.next()?;
+    if opts.next().is_some() {
+        violations.push(Violation::MultipleOpt);
+    }
+
+    let edns = Edns::from_record(additionals.remove(first))?;
+    header.rcode = Rcode::from(u16::from(edns.ext_rcode) << 4 | u16::from(header.rcode));
+
+    Some(edns)
+}
+
+/// An option in the RDATA of an OPT record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EdnsOption {
+    /// The name server identifier (RFC 5001), empty in queries asking for it.
+    Nsid(Vec<u8>),
+    ClientSubnet(ClientSubnet),
+    Cookie(Cookie),
+    /// The idle timeout for TCP connections (RFC 7828) in units of 100
+    /// milliseconds, set by servers. Clients send it without a timeout to
+    /// show that they support it.
+    TcpKeepalive(Option<u16>),
+    /// Padding with this many zero bytes (RFC 7830), to hide the length of
+    /// encrypted messages.
+    Padding(u16),
+    ExtendedError(ExtendedError),
+    Unknown {
+        code: u16,
+        data: Vec<u8>,
+    },
+}
+
+impl EdnsOption {
+    pub fn code(&self) -> u16 {
+        match self {
+            EdnsOption::Nsid(_) => 3,
+            EdnsOption::ClientSubnet(_) => 8,
+            EdnsOption::Cookie(_) => 10,
+            EdnsOption::TcpKeepalive(_) => 11,
+            EdnsOption::Padding(_) => 12,
+            EdnsOption::ExtendedError(_) => 15,
+            EdnsOption::Unknown { code, .. } => *code,
+        }
+    }
+
+    fn encode_data(&self, buf: &mut BufferMut) {
+        match self {
+            EdnsOption::Nsid(nsid) => buf.write_n(nsid),
+            EdnsOption::ClientSubnet(subnet) => subnet.encode(buf),
+            EdnsOption::Cookie(cookie) => {
+                buf.write_n(&cookie.client);
+                buf.write_n(cookie.server.as_deref().unwrap_or_default());
+            }
+            EdnsOption::TcpKeepalive(timeout) => {
+                if let Some(timeout) = timeout {
+                    buf.write_u16(*timeout);
+                }
+            }
+            EdnsOption::Padding(length) => buf.write_n(&vec![0; *length as usize]),
+            EdnsOption::ExtendedError(error) => {
+                buf.write_u16(error.info_code);
+                buf.write_n(error.extra_text.as_bytes());
+            }
+            EdnsOption::Unknown { data, .. } => buf.write_n(data),
+        }
+    }
+}
+
+/// The EDNS Client Subnet option (RFC 7871), telling an authoritative server
+/// which network a query comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClientSubnet {
+    /// The address with all bits after `source_prefix` zeroed.
+    address: IpAddr,
+    source_prefix: u8,
+    scope_prefix: u8,
+}
+
+impl ClientSubnet {
+    /// The subnet of `address` with the given prefix length, which is capped
+    /// to the length of the address. The scope is left at zero, as queries
+    /// need it to be.
+    pub fn new(address: IpAddr, source_prefix: u8) -> Self {
+        let source_prefix = source_prefix.min(address_bits(address));
+
+        ClientSubnet {
+            address: truncate_address(address, source_prefix),
+            source_prefix,
+            scope_prefix: 0,
+        }
+    }
+
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    pub fn source_prefix(&self) -> u8 {
+        self.source_prefix
+    }
+
+    /// The prefix length the answer is valid for, set by the server.
+    pub fn scope_prefix(&self) -> u8 {
+        self.scope_prefix
+    }
+
+    pub fn set_scope_prefix(&mut self, scope_prefix: u8) {
+        self.scope_prefix = scope_prefix.min(address_bits(self.address));
+    }
+
+    fn parse(buf: &mut Buffer) -> Result<Self, ParseError> {
+        let invalid = ParseError::InvalidOption(8);
+        let family = buf.next_u16()?;
+        let source_prefix = buf.next_u8()?;
+        let scope_prefix = buf.next_u8()?;
+        let bytes = buf.rest();
+
+        let mut octets = [0; 16];
+        let address = match family {
+            1 if source_prefix <= 32 && bytes.len() <= 4 => {
+                octets[..bytes.len()].copy_from_slice(bytes);
+                IpAddr::V4(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]))
+            }
+            2 if source_prefix <= 128 && bytes.len() <= 16 => {
+                octets[..bytes.len()].copy_from_slice(bytes);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            _ => return Err(invalid),
+        };
+
+        // RFC 7871 requires exactly the bytes covering the prefix, with the
+        // bits after it zeroed
+        let subnet = ClientSubnet::new(address, source_prefix);
+        if bytes.len() != source_prefix.div_ceil(8) as usize || subnet.address != address {
+            return Err(invalid);
+        }
+
+        Ok(ClientSubnet {
+            scope_prefix,
+            ..subnet
+        })
+    }
+
+    fn encode(&self, buf: &mut BufferMut) {
+        let length = self.source_prefix.div_ceil(8) as usize;
+        buf.write_u16(match self.address {
+            IpAddr::V4(_) => 1,
+            IpAddr::V6(_) => 2,
+        });
+        buf.write(self.source_prefix);
+        buf.write(self.scope_prefix);
+        match self.address {
+            IpAddr::V4(address) => buf.write_n(&address.octets()[..length]),
+            IpAddr::V6(address) => buf.write_n(&address.octets()[..length]),
+        }
+    }
+}
+
+/// A DNS cookie (RFC 7873), the client cookie alone in a first query and
+/// with the server cookie once the server has sent one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cookie {
+    client: [u8; 8],
+    /// Between 8 and 32 bytes.
+    server: Option<Vec<u8>>,
+}
+
+impl Cookie {
+    pub fn new(client: [u8; 8]) -> Self {
+        Cookie {
+            client,
+            server: None,
+        }
+    }
+
+    /// A client cookie for talking to `server_ip` from `client_ip`, derived
+    /// from a secret of the client with FNV-64 as in appendix A.1 of
+    /// RFC 7873. The secret should be random, and changed now and then.
+    pub fn for_server(secret: &[u8], client_ip: IpAddr, server_ip: IpAddr) -> Self {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut add = |bytes: &[u8]| {
+            for &byte in bytes {
+                hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+            }
+        };
+        for ip in [client_ip, server_ip] {
+            match ip {
+                IpAddr::V4(ip) => add(&ip.octets()),
+                IpAddr::V6(ip) => add(&ip.octets()),
+            }
+        }
+        add(secret);
+
+        Cookie::new(hash.to_be_bytes())
+    }
+
+    pub fn client(&self) -> [u8; 8] {
+        self.client
+    }
+
+    pub fn server(&self) -> Option<&[u8]> {
+        self.server.as_deref()
+    }
+
+    /// Takes the server cookie of a response to be sent along on retries and
+    /// later queries. Returns `false` and leaves the cookie unchanged if the
+    /// response doesn't echo the client cookie, in which case RFC 7873 asks
+    /// for the response to be dropped.
+    pub fn refresh(&mut self, response: &Cookie) -> bool {
+        if response.client != self.client {
+            return false;
+        }
+        if response.server.is_some() {
+            self.server.clone_from(&response.server);
+        }
+
+        true
+    }
+
+    fn parse(buf: &mut Buffer) -> Result<Self, ParseError> {
+        let bytes = buf.rest();
+        if bytes.len() != 8 && !(16..=40).contains(&bytes.len()) {
+            return Err(ParseError::InvalidOption(10));
+        }
+        let (client, server) = bytes.split_at(8);
+
+        Ok(Cookie {
+            client: client.try_into().unwrap(),
+            server: (!server.is_empty()).then(|| server.to_vec()),
+        })
+    }
+}
+
+/// An Extended DNS Error (RFC 8914), giving the reason for a response in
+/// more detail than the rcode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtendedError {
+    /// One of the codes registered with IANA, like [`ExtendedError::BLOCKED`].
+    pub info_code: u16,
+    /// Text for humans, which may be empty.
+    pub extra_text: String,
+}
+
+impl ExtendedError {
+    pub const OTHER: u16 = 0;
+    pub const UNSUPPORTED_DNSKEY_ALGORITHM: u16 = 1;
+    pub const UNSUPPORTED_DS_DIGEST_TYPE: u16 = 2;
+    pub const STALE_ANSWER: u16 = 3;
+    pub const FORGED_ANSWER: u16 = 4;
+    pub const DNSSEC_INDETERMINATE: u16 = 5;
+    pub const DNSSEC_BOGUS: u16 = 6;
+    pub const SIGNATURE_EXPIRED: u16 = 7;
+    pub const SIGNATURE_NOT_YET_VALID: u16 = 8;
+    pub const DNSKEY_MISSING: u16 = 9;
+    pub const RRSIGS_MISSING: u16 = 10;
+    pub const NO_ZONE_KEY_BIT_SET: u16 = 11;
+    pub const NSEC_MISSING: u16 = 12;
+    pub const CACHED_ERROR: u16 = 13;
+    pub const NOT_READY: u16 = 14;
+    pub const BLOCKED: u16 = 15;
+    pub const CENSORED: u16 = 16;
+    pub const FILTERED: u16 = 17;
+    pub const PROHIBITED: u16 = 18;
+    pub const STALE_NXDOMAIN_ANSWER: u16 = 19;
+    pub const NOT_AUTHORITATIVE: u16 = 20;
+    pub const NOT_SUPPORTED: u16 = 21;
+    pub const NO_REACHABLE_AUTHORITY: u16 = 22;
+    pub const NETWORK_ERROR: u16 = 23;
+    pub const INVALID_DATA: u16 = 24;
+
+    pub fn new(info_code: u16, extra_text: &str) -> Self {
+        ExtendedError {
+            info_code,
+            extra_text: extra_text.to_string(),
+        }
+    }
+}
+
+fn address_bits(address: IpAddr) -> u8 {
+    match address {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn truncate_address(address: IpAddr, prefix: u8) -> IpAddr {
+    match address {
+        IpAddr::V4(address) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(address) & mask))
+        }
+        IpAddr::V6(address) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(address) & mask))
+        }
+    }
+}
+
+pub(crate) fn parse_edns_options(buf: &mut Buffer) -> Result<Vec<EdnsOption>, ParseError> {
+    let mut options = Vec::new();
+
+    while !buf.is_empty() {
+        let code = buf.next_u16()?;
+        let length = buf.next_u16()?;
+        let mut data = buf.split(length as usize)?;
+
+        let option = match code {
+            3 => EdnsOption::Nsid(data.rest().to_vec()),
+            8 => EdnsOption::ClientSubnet(ClientSubnet::parse(&mut data)?),
+            10 => EdnsOption::Cookie(Cookie::parse(&mut data)?),
+            11 => EdnsOption::TcpKeepalive(match data.remaining() {
+                0 => None,
+                2 => Some(data.next_u16()?),
+                _ => return Err(ParseError::InvalidOption(11)),
+            }),
+            // the content is to be ignored, even if it isn't zeros
+            12 => EdnsOption::Padding(data.rest().len() as u16),
+            15 => EdnsOption::ExtendedError(ExtendedError {
+                info_code: data.next_u16()?,
+                extra_text: String::from_utf8_lossy(data.rest()).to_string(),
+            }),
+            _ => EdnsOption::Unknown {
+                code,
+                data: data.rest().to_vec(),
+            },
+        };
+        if !data.is_empty() {
+            return Err(ParseError::InvalidOption(code));
+        }
+        options.push(option);
+    }
+
+    Ok(options)
+}
+
+pub(crate) fn encode_edns_options(
+    options: &[EdnsOption],
+    buf: &mut BufferMut,
+) -> Result<(), EncodeError> {
+    for option in options {
+        buf.write_u16(option.code());
+        buf.with_length(|buf| {
+            option.encode_data(buf);
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_message, Message, Query, RecordType};
+
+    #[test]
+    fn test_parse() {
+        // BADVERS with the DO bit and a cookie option
+        let packet = [0, 1, 0x81, 0x80, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 41, 16, 0, 1, 0, 0x80, 0, 0, 12, 0, 10, 0, 8, 1, 2, 3, 4, 5, 6, 7, 8];
+        let message = parse_message(&packet).unwrap();
+
+        assert!(message.additionals().is_empty());
+        assert_eq!(Rcode::BadVers, message.header().rcode());
+        let options = vec![EdnsOption::Cookie(Cookie::new([1, 2, 3, 4, 5, 6, 7, 8]))];
+        assert_eq!(Some(&Edns { udp_payload: 4096, ext_rcode: 1, version: 0, dnssec_ok: true, options }), message.edns());
+        assert_eq!(Ok(packet.to_vec()), message.to_bytes());
+    }
+
+    #[test]
+    fn test_set_edns() {
+        let mut message = Message::try_from(Query::new("example", RecordType::A)).unwrap();
+        message.set_edns(Some(Edns::default()));
+        assert_eq!(1, message.header().additional_count());
+        assert!(message.validate().is_empty());
+
+        let message = parse_message(&message.to_bytes().unwrap()).unwrap();
+        assert_eq!(Some(&Edns::default()), message.edns());
+
+        let mut reply = Message::reply_to(&message);
+        assert_eq!(Some(&Edns::default()), reply.edns());
+        reply.set_rcode(Rcode::BadCookie);
+        reply.set_edns(None);
+        assert_eq!(0, reply.header().additional_count());
+        assert_eq!(Rcode::from(7), parse_message(&reply.to_bytes().unwrap()).unwrap().header().rcode());
+    }
+
+    #[test]
+    fn test_client_subnet() {
+        let subnet = ClientSubnet::new([192, 0, 2, 77].into(), 24);
+        assert_eq!((IpAddr::from([192, 0, 2, 0]), 24, 0), (subnet.address(), subnet.source_prefix(), subnet.scope_prefix()));
+        assert_eq!((IpAddr::from([192, 0, 2, 77]), 32), { let subnet = ClientSubnet::new([192, 0, 2, 77].into(), 40); (subnet.address(), subnet.source_prefix()) });
+
+        let mut buf = BufferMut::new();
+        encode_edns_options(&[EdnsOption::ClientSubnet(subnet.clone())], &mut buf).unwrap();
+        let bytes = buf.into_vec();
+        assert_eq!(vec![0, 8, 0, 7, 0, 1, 24, 0, 192, 0, 2], bytes);
+        let options = parse_edns_options(&mut Buffer::new(&bytes)).unwrap();
+        assert_eq!(Some(&subnet), Edns { options, ..Edns::default() }.client_subnet());
+
+        let mut subnet = ClientSubnet::new("2001:db8::1".parse().unwrap(), 56);
+        subnet.set_scope_prefix(48);
+        let mut buf = BufferMut::new();
+        encode_edns_options(&[EdnsOption::ClientSubnet(subnet.clone())], &mut buf).unwrap();
+        assert_eq!(Ok(vec![EdnsOption::ClientSubnet(subnet)]), parse_edns_options(&mut Buffer::new(&buf.into_vec())));
+
+        // bits after the prefix, too many address bytes and an unknown family
+        for bytes in [[0, 8, 0, 7, 0, 1, 23, 0, 192, 0, 3], [0, 8, 0, 7, 0, 1, 8, 0, 192, 0, 2], [0, 8, 0, 7, 0, 3, 24, 0, 192, 0, 2]] {
+            assert_eq!(Err(ParseError::InvalidOption(8)), parse_edns_options(&mut Buffer::new(&bytes)));
+        }
+    }
+
+    #[test]
+    fn test_cookie() {
+        let client: IpAddr = [192, 0, 2, 1].into();
+        let server: IpAddr = [198, 51, 100, 53].into();
+        let mut cookie = Cookie::for_server(b"secret", client, server);
+        assert_eq!(cookie, Cookie::for_server(b"secret", client, server));
+        assert_ne!(cookie, Cookie::for_server(b"secret", client, [198, 51, 100, 54].into()));
+        assert_eq!(None, cookie.server());
+
+        let mut edns = Edns::default();
+        edns.set_cookie(cookie.clone());
+        let mut buf = BufferMut::new();
+        encode_edns_options(&edns.options, &mut buf).unwrap();
+        let mut bytes = buf.into_vec();
+        assert_eq!(12, bytes.len());
+
+        // the server answers with its cookie appended
+        bytes[3] = 24;
+        bytes.extend_from_slice(&[7; 16]);
+        let response = Edns { options: parse_edns_options(&mut Buffer::new(&bytes)).unwrap(), ..Edns::default() };
+        assert!(cookie.refresh(response.cookie().unwrap()));
+        assert_eq!(Some(&[7; 16][..]), cookie.server());
+        assert!(!cookie.refresh(&Cookie::new([0; 8])));
+
+        edns.set_cookie(cookie.clone());
+        assert_eq!((1, Some(&cookie)), (edns.options.len(), edns.cookie()));
+
+        for length in [0, 9, 15, 41] {
+            let bytes = [&[0, 10, 0, length as u8][..], &[1; 41][..length]].concat();
+            assert_eq!(Err(ParseError::InvalidOption(10)), parse_edns_options(&mut Buffer::new(&bytes)));
+        }
+    }
+
+    #[test]
+    fn test_padding() {
+        let mut query = Message::try_from(Query::new("example", RecordType::A)).unwrap();
+        query.pad_to_block(QUERY_BLOCK_SIZE).unwrap();
+        assert_eq!(None, query.edns());
+
+        query.set_edns(Some(Edns::default()));
+        query.pad_to_block(QUERY_BLOCK_SIZE).unwrap();
+        assert_eq!(128, query.to_bytes().unwrap().len());
+        query.pad_to_block(RESPONSE_BLOCK_SIZE).unwrap();
+        assert_eq!(468, query.to_bytes().unwrap().len());
+        assert_eq!(1, query.edns().unwrap().options.len());
+
+        let parsed = parse_message(&query.to_bytes().unwrap()).unwrap();
+        assert_eq!(query.edns(), parsed.edns());
+        assert_eq!(Ok(vec![EdnsOption::Padding(2)]), parse_edns_options(&mut Buffer::new(&[0, 12, 0, 2, 1, 2])));
+    }
+
+    #[test]
+    fn test_extended_error() {
+        let query = Message::try_from(Query::new("ads.example", RecordType::A)).unwrap();
+        let mut reply = Message::reply_to(&query);
+        assert!(!reply.push_extended_error(ExtendedError::new(ExtendedError::BLOCKED, "")));
+
+        let query = Message::try_from(Query::new("ads.example", RecordType::A).edns(Edns::default())).unwrap();
+        let mut reply = Message::reply_to(&query);
+        reply.set_rcode(Rcode::NxDomain);
+        assert!(reply.push_extended_error(ExtendedError::new(ExtendedError::BLOCKED, "ad network")));
+
+        let packet = reply.to_bytes().unwrap();
+        assert_eq!(&[0, 15, 0, 12, 0, 15, b'a', b'd', b' ', b'n', b'e', b't', b'w', b'o', b'r', b'k'], &packet[packet.len() - 16..]);
+        let reply = parse_message(&packet).unwrap();
+        let errors: Vec<_> = reply.edns().unwrap().extended_errors().collect();
+        assert_eq!(vec![&ExtendedError::new(15, "ad network")], errors);
+
+        assert_eq!(Err(ParseError::UnexpectedEof), parse_edns_options(&mut Buffer::new(&[0, 15, 0, 1, 0])));
+    }
+
+    #[test]
+    fn test_tcp_keepalive() {
+        let options = parse_edns_options(&mut Buffer::new(&[0, 11, 0, 2, 1, 44])).unwrap();
+        assert_eq!(vec![EdnsOption::TcpKeepalive(Some(300))], options);
+        assert_eq!(Some(Duration::from_secs(30)), Edns { options, ..Edns::default() }.tcp_keepalive());
+
+        let mut buf = BufferMut::new();
+        encode_edns_options(&[EdnsOption::TcpKeepalive(None)], &mut buf).unwrap();
+        let bytes = buf.into_vec();
+        assert_eq!(vec![0, 11, 0, 0], bytes);
+        let options = parse_edns_options(&mut Buffer::new(&bytes)).unwrap();
+        assert_eq!(None, Edns { options, ..Edns::default() }.tcp_keepalive());
+
+        assert_eq!(Err(ParseError::InvalidOption(11)), parse_edns_options(&mut Buffer::new(&[0, 11, 0, 1, 0])));
+    }
+
+    #[test]
+    fn test_multiple_opt() {
+        let opt = [0, 0, 41, 4, 208, 0, 0, 0, 0, 0, 0];
+        let packet = [&[0, 1, 0x81, 0x80, 0, 0, 0, 0, 0, 0, 0, 2][..], &opt, &opt].concat();
+        let message = parse_message(&packet).unwrap();
+        assert_eq!(1, message.additionals().len());
+        assert_eq!(vec![Violation::MultipleOpt], message.validate());
+    }
+}
@@ -0,0 +1,455 @@
+use alloc::vec::Vec;
+
+use crate::{
+    buffer::BufferMut, edns::encode_edns_options, EncodeError, Header, Message, Question,
+    RecordType, ResourceData, ResourceRecord, Section, SvcParam, Svcb,
+};
+
+impl Message {
+    /// Encodes the message into its wire format. The section counts and data
+    /// lengths are derived from the records rather than taken from the parsed
+    /// header and records, and repeated names are compressed.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut buf = BufferMut::new();
+
+        let counts = [
+            section_count(Section::Question, self.questions.len())?,
+            section_count(Section::Answer, self.answers.len())?,
+            section_count(Section::Authority, self.authorities.len())?,
+            section_count(
+                Section::Additional,
+                self.additionals.len() + self.edns.is_some() as usize,
+            )?,
+        ];
+
+        self.header.encode(counts, &mut buf);
+        for question in &self.questions {
+            question.encode(&mut buf)?;
+        }
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.additionals)
+        {
+            record.encode(&mut buf)?;
+        }
+        if let Some(edns) = &self.edns {
+            edns.to_record(self.header.rcode).encode(&mut buf)?;
+        }
+
+        Ok(buf.into_vec())
+    }
+
+    /// A copy of the message that encodes into at most `max_size` bytes, or
+    /// as close to that as dropping records gets. Whole RRsets are dropped
+    /// from the end, additional records first, while EDNS and OPT records
+    /// are kept. As RFC 2181 asks, the TC bit is only set once answer or
+    /// authority records have to go.
+    pub fn truncated_to(&self, max_size: usize) -> Result<Message, EncodeError> {
+        let mut message = self.clone();
+
+        while message.to_bytes()?.len() > max_size {
+            if pop_rrset(&mut message.additionals_mut()) {
+                continue;
+            }
+            if pop_rrset(&mut message.authorities_mut()) || pop_rrset(&mut message.answers_mut()) {
+                message.header.truncated = true;
+                continue;
+            }
+
+            break;
+        }
+
+        Ok(message)
+    }
+}
+
+/// Removes the RRset of the last record other than an OPT record, returning
+/// whether there was one.
+fn pop_rrset(records: &mut Vec<ResourceRecord>) -> bool {
+    let last = match records
+        .iter()
+        .rposition(|record| record.r#type != RecordType::Opt)
+    {
+        Some(last) => last,
+        None => return false,
+    };
+
+    let (name, r#type, class) = {
+        let record = &records[last];
+        (record.name.clone(), record.r#type, record.class)
+    };
+    records
+        .retain(|record| record.r#type != r#type || record.class != class || record.name != name);
+
+    true
+}
+
+impl Header {
+    fn encode(&self, counts: [u16; 4], buf: &mut BufferMut) {
+        let mut flags = [0u8; 2];
+        flags[0] |= (self.response as u8) << 7;
+        flags[0] |= (u8::from(self.opcode) & 0b00001111) << 3;
+        flags[0] |= (self.authoritative as u8) << 2;
+        flags[0] |= (self.truncated as u8) << 1;
+        flags[0] |= self.recursion_desired as u8;
+        flags[1] |= (self.recursion_available as u8) << 7;
+        flags[1] |= (self.z as u8) << 6;
+        flags[1] |= (self.authentic_data as u8) << 5;
+        flags[1] |= (self.checking_disabled as u8) << 4;
+        // only the lower four bits fit into the header
+        flags[1] |= (u16::from(self.rcode) & 0b00001111) as u8;
+
+        buf.write_u16(self.id);
+        buf.write_n(&flags);
+        for count in counts {
+            buf.write_u16(count);
+        }
+    }
+}
+
+impl Question {
+    fn encode(&self, buf: &mut BufferMut) -> Result<(), EncodeError> {
+        buf.write_name(&self.domain_name, true)?;
+        buf.write_u16(self.q_type.into());
+        buf.write_u16(self.q_class.into());
+
+        Ok(())
+    }
+}
+
+impl ResourceRecord {
+    /// Encodes the record on its own, without compression.
+    pub fn to_wire(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut buf = BufferMut::uncompressed();
+        self.encode(&mut buf)?;
+
+        Ok(buf.into_vec())
+    }
+
+    pub(crate) fn encode(&self, buf: &mut BufferMut) -> Result<(), EncodeError> {
+        buf.write_name(&self.name, true)?;
+        buf.write_u16(self.r#type.into());
+        buf.write_u16(self.class.into());
+        buf.write_u32(self.ttl);
+
+        buf.with_length(|buf| self.data.encode(buf))
+    }
+}
+
+impl ResourceData {
+    /// The length of the RDATA when encoded without compression, if it can be
+    /// encoded at all.
+    pub(crate) fn data_length(&self) -> Option<u16> {
+        let mut buf = BufferMut::uncompressed();
+        self.encode(&mut buf).ok()?;
+
+        u16::try_from(buf.len()).ok()
+    }
+
+    /// The RDATA encoded without compression.
+    pub(crate) fn to_wire(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut buf = BufferMut::uncompressed();
+        self.encode(&mut buf)?;
+
+        Ok(buf.into_vec())
+    }
+
+    /// Only the names in the RDATA of the types from RFC 1035 are compressed,
+    /// as required by RFC 3597.
+    fn encode(&self, buf: &mut BufferMut) -> Result<(), EncodeError> {
+        match self {
+            ResourceData::A(addr) => buf.write_n(&addr.octets()),
+            ResourceData::Aaaa(addr) => buf.write_n(&addr.octets()),
+            ResourceData::Ns(name) | ResourceData::Cname(name) | ResourceData::Ptr(name) => {
+                buf.write_name(name, true)?
+            }
+            ResourceData::Soa(soa) => {
+                buf.write_name(&soa.mname, true)?;
+                buf.write_name(&soa.rname, true)?;
+                for value in [soa.serial, soa.refresh, soa.retry, soa.expire, soa.minimum] {
+                    buf.write_u32(value);
+                }
+            }
+            ResourceData::Hinfo { cpu, os } => {
+                buf.write_character_string(cpu)?;
+                buf.write_character_string(os)?;
+            }
+            ResourceData::Mx {
+                preference,
+                exchange,
+            } => {
+                buf.write_u16(*preference);
+                buf.write_name(exchange, true)?;
+            }
+            ResourceData::Txt(strings) => {
+                for string in strings {
+                    buf.write_character_string(string)?;
+                }
+            }
+            ResourceData::Loc(loc) => {
+                buf.write(loc.version);
+                buf.write(loc.size);
+                buf.write(loc.horizontal_precision);
+                buf.write(loc.vertical_precision);
+                buf.write_u32(loc.latitude);
+                buf.write_u32(loc.longitude);
+                buf.write_u32(loc.altitude);
+            }
+            ResourceData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                buf.write_u16(*priority);
+                buf.write_u16(*weight);
+                buf.write_u16(*port);
+                buf.write_name(target, false)?;
+            }
+            ResourceData::Naptr {
+                order,
+                preference,
+                flags,
+                services,
+                regexp,
+                replacement,
+            } => {
+                buf.write_u16(*order);
+                buf.write_u16(*preference);
+                buf.write_character_string(flags)?;
+                buf.write_character_string(services)?;
+                buf.write_character_string(regexp)?;
+                buf.write_name(replacement, false)?;
+            }
+            ResourceData::Dname(name) => buf.write_name(name, false)?,
+            ResourceData::Opt(options) => encode_edns_options(options, buf)?,
+            ResourceData::Ds(ds) => {
+                buf.write_u16(ds.key_tag);
+                buf.write(ds.algorithm);
+                buf.write(ds.digest_type);
+                buf.write_n(&ds.digest);
+            }
+            ResourceData::Sshfp(sshfp) => {
+                buf.write(sshfp.algorithm);
+                buf.write(sshfp.fingerprint_type);
+                buf.write_n(&sshfp.fingerprint);
+            }
+            ResourceData::Rrsig(rrsig) | ResourceData::Sig(rrsig) => {
+                buf.write_u16(rrsig.type_covered.into());
+                buf.write(rrsig.algorithm);
+                buf.write(rrsig.labels);
+                buf.write_u32(rrsig.original_ttl);
+                buf.write_u32(rrsig.expiration);
+                buf.write_u32(rrsig.inception);
+                buf.write_u16(rrsig.key_tag);
+                buf.write_name(&rrsig.signer_name, false)?;
+                buf.write_n(&rrsig.signature);
+            }
+            ResourceData::Nsec(nsec) => {
+                buf.write_name(&nsec.next_domain_name, false)?;
+                encode_type_bitmaps(&nsec.types, buf);
+            }
+            ResourceData::Dnskey(dnskey) => {
+                buf.write_u16(dnskey.flags);
+                buf.write(dnskey.protocol);
+                buf.write(dnskey.algorithm);
+                buf.write_n(&dnskey.public_key);
+            }
+            ResourceData::Nsec3(nsec3) => {
+                buf.write(nsec3.hash_algorithm);
+                buf.write(nsec3.flags);
+                buf.write_u16(nsec3.iterations);
+                buf.write_character_string(&nsec3.salt)?;
+                buf.write_character_string(&nsec3.next_hashed_owner)?;
+                encode_type_bitmaps(&nsec3.types, buf);
+            }
+            ResourceData::Tlsa(tlsa) | ResourceData::Smimea(tlsa) => {
+                buf.write(tlsa.usage);
+                buf.write(tlsa.selector);
+                buf.write(tlsa.matching_type);
+                buf.write_n(&tlsa.data);
+            }
+            ResourceData::Openpgpkey(key) => buf.write_n(&key.0),
+            ResourceData::Svcb(svcb) | ResourceData::Https(svcb) => svcb.encode(buf)?,
+            ResourceData::Uri {
+                priority,
+                weight,
+                target,
+            } => {
+                buf.write_u16(*priority);
+                buf.write_u16(*weight);
+                buf.write_n(target.as_bytes());
+            }
+            ResourceData::Caa { flags, tag, value } => {
+                buf.write(*flags);
+                buf.write_character_string(tag.as_bytes())?;
+                buf.write_n(value);
+            }
+            ResourceData::Tsig(tsig) => {
+                buf.write_name(&tsig.algorithm, false)?;
+                buf.write_u16((tsig.time_signed >> 32) as u16);
+                buf.write_u32(tsig.time_signed as u32);
+                buf.write_u16(tsig.fudge);
+                buf.write_u16(u16::try_from(tsig.mac.len()).map_err(|_| EncodeError::DataTooLong)?);
+                buf.write_n(&tsig.mac);
+                buf.write_u16(tsig.original_id);
+                buf.write_u16(tsig.error.into());
+                buf.write_u16(
+                    u16::try_from(tsig.other.len()).map_err(|_| EncodeError::DataTooLong)?,
+                );
+                buf.write_n(&tsig.other);
+            }
+            ResourceData::Unknown { data, .. } => buf.write_n(&data.0),
+        }
+
+        Ok(())
+    }
+}
+
+impl Svcb {
+    fn encode(&self, buf: &mut BufferMut) -> Result<(), EncodeError> {
+        buf.write_u16(self.priority);
+        buf.write_name(&self.target, false)?;
+
+        for param in &self.params {
+            buf.write_u16(param.key());
+            buf.with_length(|buf| {
+                match param {
+                    SvcParam::Mandatory(keys) => keys.iter().for_each(|&key| buf.write_u16(key)),
+                    SvcParam::Alpn(protocols) => {
+                        for protocol in protocols {
+                            buf.write_character_string(protocol)?;
+                        }
+                    }
+                    SvcParam::NoDefaultAlpn => {}
+                    SvcParam::Port(port) => buf.write_u16(*port),
+                    SvcParam::Ipv4Hint(addrs) => {
+                        addrs.iter().for_each(|addr| buf.write_n(&addr.octets()))
+                    }
+                    SvcParam::Ech(config) => buf.write_n(config),
+                    SvcParam::Ipv6Hint(addrs) => {
+                        addrs.iter().for_each(|addr| buf.write_n(&addr.octets()))
+                    }
+                    SvcParam::Unknown { value, .. } => buf.write_n(value),
+                }
+
+                Ok(())
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+impl SvcParam {
+    fn key(&self) -> u16 {
+        match self {
+            SvcParam::Mandatory(_) => 0,
+            SvcParam::Alpn(_) => 1,
+            SvcParam::NoDefaultAlpn => 2,
+            SvcParam::Port(_) => 3,
+            SvcParam::Ipv4Hint(_) => 4,
+            SvcParam::Ech(_) => 5,
+            SvcParam::Ipv6Hint(_) => 6,
+            SvcParam::Unknown { key, .. } => *key,
+        }
+    }
+}
+
+fn section_count(section: Section, count: usize) -> Result<u16, EncodeError> {
+    u16::try_from(count).map_err(|_| EncodeError::TooManyRecords(section))
+}
+
+/// Encodes the types into the window blocks used by NSEC and NSEC3.
+fn encode_type_bitmaps(types: &[RecordType], buf: &mut BufferMut) {
+    let mut types: Vec<u16> = types.iter().map(|&r#type| r#type.into()).collect();
+    types.sort_unstable();
+    types.dedup();
+
+    for window in types.chunk_by(|a, b| a >> 8 == b >> 8) {
+        let mut bitmap = [0u8; 32];
+        for r#type in window {
+            let bit = (r#type & 0xff) as usize;
+            bitmap[bit / 8] |= 0b10000000 >> (bit % 8);
+        }
+        let length = (window[window.len() - 1] & 0xff) as usize / 8 + 1;
+
+        buf.write((window[0] >> 8) as u8);
+        buf.write(length as u8);
+        buf.write_n(&bitmap[..length]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::net::{Ipv4Addr, Ipv6Addr};
+
+    use crate::{parse_message, Class, DomainName, Edns, Query};
+
+    fn name(name: &str) -> DomainName {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let packet = [18, 52, 129, 128, 0, 1, 0, 6, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1, 0, 0, 14, 16, 0, 4, 93, 184, 216, 34, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 15, 0, 1, 0, 0, 14, 16, 0, 20, 0, 10, 4, 109, 97, 105, 108, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 16, 0, 1, 0, 0, 14, 16, 0, 12, 5, 104, 101, 108, 108, 111, 5, 119, 111, 114, 108, 100, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 6, 0, 1, 0, 0, 14, 16, 0, 55, 2, 110, 115, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 5, 97, 100, 109, 105, 110, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0, 5, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 47, 0, 1, 0, 0, 14, 16, 0, 25, 3, 119, 119, 119, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 6, 98, 0, 0, 0, 0, 3, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 65, 0, 1, 0, 0, 14, 16, 0, 16, 0, 1, 0, 0, 1, 0, 3, 2, 104, 50, 0, 3, 0, 2, 1, 187];
+
+        let message = parse_message(&packet).unwrap();
+        let bytes = message.to_bytes().unwrap();
+        assert!(bytes.len() < packet.len());
+        // data lengths shrink with compression, but the records stay the same
+        let reparsed = parse_message(&bytes).unwrap();
+        assert_eq!(message.answers.len(), reparsed.answers.len());
+        assert!(message.answers.iter().zip(&reparsed.answers).all(|(a, b)| a.data == b.data));
+        assert_eq!(Ok(bytes), reparsed.to_bytes());
+    }
+
+    #[test]
+    fn test_compression() {
+        // example.com CNAME www.example.com, with the owner and the
+        // target's suffix pointing at the question name
+        let packet = [0, 1, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 5, 0, 1, 192, 12, 0, 5, 0, 1, 0, 0, 1, 44, 0, 6, 3, 119, 119, 119, 192, 12];
+
+        let message = parse_message(&packet).unwrap();
+        assert_eq!(Ok(packet.to_vec()), message.to_bytes());
+    }
+
+    #[test]
+    fn test_counts_are_recomputed() {
+        let packet = [0, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1];
+        let mut message = parse_message(&packet).unwrap();
+        message.questions.clear();
+
+        assert_eq!(Ok(vec![0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]), message.to_bytes());
+    }
+
+    #[test]
+    fn test_truncated_to() {
+        let owner = name("example.com");
+        let query = parse_message(&Query::new("example.com", RecordType::A).build().unwrap()).unwrap();
+        let mut reply = Message::reply_to(&query);
+        reply.push_answer(ResourceRecord::new(owner.clone(), Class::In, 300, ResourceData::A(Ipv4Addr::new(192, 0, 2, 1))));
+        reply.push_answer(ResourceRecord::new(owner.clone(), Class::In, 300, ResourceData::A(Ipv4Addr::new(192, 0, 2, 2))));
+        reply.push_additional(ResourceRecord::new(name("ns.example.com"), Class::In, 300, ResourceData::Aaaa(Ipv6Addr::LOCALHOST)));
+        reply.set_edns(Some(Edns::default()));
+        assert_eq!(103, reply.to_bytes().unwrap().len());
+
+        assert_eq!(reply, reply.truncated_to(512).unwrap());
+
+        let truncated = reply.truncated_to(80).unwrap();
+        assert_eq!(72, truncated.to_bytes().unwrap().len());
+        assert_eq!((2, 0, false), (truncated.answers.len(), truncated.additionals.len(), truncated.header.truncated));
+        assert!(truncated.edns.is_some());
+
+        let truncated = reply.truncated_to(50).unwrap();
+        assert_eq!(40, truncated.to_bytes().unwrap().len());
+        assert_eq!((0, 0, true), (truncated.answers.len(), truncated.additionals.len(), truncated.header.truncated));
+        assert_eq!(Some(&Edns::default()), truncated.edns());
+        assert!(truncated.validate().is_empty());
+
+        assert_eq!(40, reply.truncated_to(0).unwrap().to_bytes().unwrap().len());
+    }
+}
@@ -0,0 +1,245 @@
+//! A C interface to the parser, built as a `cdylib` by `cargo build-ffi`
+//! with the `ffi` feature. The declarations are in `include/scopa.h`.
+//!
+//! Parsed messages are handed out as opaque handles that have to be freed
+//! with [`scopa_free`], and strings as NUL-terminated copies that have to be
+//! freed with [`scopa_string_free`].
+
+use core::ffi::c_char;
+use core::{ptr, slice};
+use std::ffi::CString;
+
+use crate::{parse_message, Message, ResourceRecord};
+
+/// A parsed message, opaque to C.
+pub struct ScopaMessage(Message);
+
+/// Section numbers as used by the accessors.
+pub const SCOPA_SECTION_QUESTION: u32 = 0;
+pub const SCOPA_SECTION_ANSWER: u32 = 1;
+pub const SCOPA_SECTION_AUTHORITY: u32 = 2;
+pub const SCOPA_SECTION_ADDITIONAL: u32 = 3;
+
+/// Parses the `len` bytes at `ptr` as a message. Returns null if they don't
+/// hold a valid message.
+///
+/// # Safety
+///
+/// `ptr` must point to `len` readable bytes, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn scopa_parse(ptr: *const u8, len: usize) -> *mut ScopaMessage {
+    if ptr.is_null() {
+        return ptr::null_mut();
+    }
+
+    match parse_message(slice::from_raw_parts(ptr, len)) {
+        Ok(message) => Box::into_raw(Box::new(ScopaMessage(message))),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Frees a message returned by [`scopa_parse`]. Does nothing for null.
+///
+/// # Safety
+///
+/// `message` must come from [`scopa_parse`] and not have been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn scopa_free(message: *mut ScopaMessage) {
+    if !message.is_null() {
+        drop(Box::from_raw(message));
+    }
+}
+
+/// Frees a string returned by one of the other functions. Does nothing for
+/// null.
+///
+/// # Safety
+///
+/// `string` must come from this library and not have been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn scopa_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// # Safety
+///
+/// `message` must be a live handle from [`scopa_parse`].
+#[no_mangle]
+pub unsafe extern "C" fn scopa_id(message: *const ScopaMessage) -> u16 {
+    (*message).0.id()
+}
+
+/// # Safety
+///
+/// `message` must be a live handle from [`scopa_parse`].
+#[no_mangle]
+pub unsafe extern "C" fn scopa_is_response(message: *const ScopaMessage) -> bool {
+    (*message).0.header().is_response()
+}
+
+/// # Safety
+///
+/// `message` must be a live handle from [`scopa_parse`].
+#[no_mangle]
+pub unsafe extern "C" fn scopa_opcode(message: *const ScopaMessage) -> u8 {
+    (*message).0.header().opcode().into()
+}
+
+/// # Safety
+///
+/// `message` must be a live handle from [`scopa_parse`].
+#[no_mangle]
+pub unsafe extern "C" fn scopa_rcode(message: *const ScopaMessage) -> u16 {
+    (*message).0.header().rcode().into()
+}
+
+/// The number of entries in a section, or 0 for an unknown section.
+///
+/// # Safety
+///
+/// `message` must be a live handle from [`scopa_parse`].
+#[no_mangle]
+pub unsafe extern "C" fn scopa_count(message: *const ScopaMessage, section: u32) -> usize {
+    let message = &(*message).0;
+    match section {
+        SCOPA_SECTION_QUESTION => message.questions().len(),
+        _ => records(message, section).map_or(0, <[_]>::len),
+    }
+}
+
+/// The name of an entry in a section, or null if there is no such entry.
+///
+/// # Safety
+///
+/// `message` must be a live handle from [`scopa_parse`].
+#[no_mangle]
+pub unsafe extern "C" fn scopa_name(
+    message: *const ScopaMessage,
+    section: u32,
+    index: usize,
+) -> *mut c_char {
+    let message = &(*message).0;
+    let name = match section {
+        SCOPA_SECTION_QUESTION => message.questions().get(index).map(|q| q.name()),
+        _ => record(message, section, index).map(|r| r.name()),
+    };
+    name.map_or(ptr::null_mut(), |name| to_c_string(name.to_string()))
+}
+
+/// The type of an entry in a section, or 0 if there is no such entry.
+///
+/// # Safety
+///
+/// `message` must be a live handle from [`scopa_parse`].
+#[no_mangle]
+pub unsafe extern "C" fn scopa_type(
+    message: *const ScopaMessage,
+    section: u32,
+    index: usize,
+) -> u16 {
+    let message = &(*message).0;
+    let record_type = match section {
+        SCOPA_SECTION_QUESTION => message.questions().get(index).map(|q| q.record_type()),
+        _ => record(message, section, index).map(|r| r.record_type()),
+    };
+    record_type.map_or(0, u16::from)
+}
+
+/// The class of an entry in a section, or 0 if there is no such entry.
+///
+/// # Safety
+///
+/// `message` must be a live handle from [`scopa_parse`].
+#[no_mangle]
+pub unsafe extern "C" fn scopa_class(
+    message: *const ScopaMessage,
+    section: u32,
+    index: usize,
+) -> u16 {
+    let message = &(*message).0;
+    let class = match section {
+        SCOPA_SECTION_QUESTION => message.questions().get(index).map(|q| q.class()),
+        _ => record(message, section, index).map(|r| r.class()),
+    };
+    class.map_or(0, u16::from)
+}
+
+/// An entry in a section in presentation format, or null if there is no
+/// such entry.
+///
+/// # Safety
+///
+/// `message` must be a live handle from [`scopa_parse`].
+#[no_mangle]
+pub unsafe extern "C" fn scopa_to_string(
+    message: *const ScopaMessage,
+    section: u32,
+    index: usize,
+) -> *mut c_char {
+    let message = &(*message).0;
+    let text = match section {
+        SCOPA_SECTION_QUESTION => message.questions().get(index).map(|q| q.to_string()),
+        _ => record(message, section, index).map(|r| r.to_string()),
+    };
+    text.map_or(ptr::null_mut(), to_c_string)
+}
+
+/// The whole message as JSON, see [`Message::to_json`].
+///
+/// # Safety
+///
+/// `message` must be a live handle from [`scopa_parse`].
+#[no_mangle]
+pub unsafe extern "C" fn scopa_to_json(message: *const ScopaMessage) -> *mut c_char {
+    to_c_string((*message).0.to_json())
+}
+
+fn records(message: &Message, section: u32) -> Option<&[ResourceRecord]> {
+    match section {
+        SCOPA_SECTION_ANSWER => Some(message.answers()),
+        SCOPA_SECTION_AUTHORITY => Some(message.authorities()),
+        SCOPA_SECTION_ADDITIONAL => Some(message.additionals()),
+        _ => None,
+    }
+}
+
+fn record(message: &Message, section: u32, index: usize) -> Option<&ResourceRecord> {
+    records(message, section)?.get(index)
+}
+
+/// Names and text can't hold NUL bytes, as those are escaped in
+/// presentation format.
+fn to_c_string(string: String) -> *mut c_char {
+    CString::new(string).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_ffi() {
+        let packet = [0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 1, 0, 1, 0xc0, 12, 0, 1, 0, 1, 0, 0, 14, 16, 0, 4, 192, 0, 2, 1];
+        unsafe {
+            let message = scopa_parse(packet.as_ptr(), packet.len());
+            assert!(!message.is_null());
+            assert_eq!((0x1234, true, 0, 0), (scopa_id(message), scopa_is_response(message), scopa_opcode(message), scopa_rcode(message)));
+            assert_eq!((1, 1, 0, 0, 0), (scopa_count(message, 0), scopa_count(message, 1), scopa_count(message, 2), scopa_count(message, 3), scopa_count(message, 4)));
+            assert_eq!((1, 1, 0), (scopa_type(message, 1, 0), scopa_class(message, 1, 0), scopa_type(message, 1, 1)));
+
+            let strings = [scopa_name(message, 0, 0), scopa_to_string(message, 1, 0), scopa_to_json(message)];
+            assert_eq!("example.", CStr::from_ptr(strings[0]).to_str().unwrap());
+            assert!(CStr::from_ptr(strings[1]).to_str().unwrap().ends_with("192.0.2.1"));
+            assert!(CStr::from_ptr(strings[2]).to_str().unwrap().starts_with("{"));
+            strings.into_iter().for_each(|string| scopa_string_free(string));
+            assert!(scopa_name(message, 1, 1).is_null());
+            scopa_free(message);
+
+            assert!(scopa_parse(packet.as_ptr(), 20).is_null());
+            assert!(scopa_parse(ptr::null(), 0).is_null());
+        }
+    }
+}
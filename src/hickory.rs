@@ -0,0 +1,109 @@
+//! Conversions from and to the types of hickory-proto, for handing messages
+//! over to it for what scopa doesn't do. Both sides go through the wire
+//! format, so everything either crate can represent carries over.
+
+use core::fmt;
+
+use hickory_proto::{
+    op,
+    rr::Record,
+    serialize::binary::{BinDecodable, BinEncodable, BinEncoder},
+    ProtoError,
+};
+
+use crate::{parse_message, EncodeError, Message, ParseError, ResourceRecord};
+
+impl TryFrom<&Message> for op::Message {
+    type Error = HickoryError;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let bytes = message.to_bytes().map_err(HickoryError::Encode)?;
+        op::Message::from_vec(&bytes).map_err(HickoryError::Proto)
+    }
+}
+
+impl TryFrom<&op::Message> for Message {
+    type Error = HickoryError;
+
+    fn try_from(message: &op::Message) -> Result<Self, Self::Error> {
+        let bytes = message.to_vec().map_err(HickoryError::Proto)?;
+        parse_message(&bytes).map_err(HickoryError::Parse)
+    }
+}
+
+impl TryFrom<&ResourceRecord> for Record {
+    type Error = HickoryError;
+
+    fn try_from(record: &ResourceRecord) -> Result<Self, Self::Error> {
+        let bytes = record.to_wire().map_err(HickoryError::Encode)?;
+        Record::from_bytes(&bytes).map_err(HickoryError::Proto)
+    }
+}
+
+impl TryFrom<&Record> for ResourceRecord {
+    type Error = HickoryError;
+
+    fn try_from(record: &Record) -> Result<Self, Self::Error> {
+        let mut bytes = Vec::new();
+        let mut encoder = BinEncoder::new(&mut bytes);
+        // standalone records have to be uncompressed
+        encoder.set_canonical_names(true);
+        record.emit(&mut encoder).map_err(HickoryError::Proto)?;
+
+        ResourceRecord::from_wire(&bytes).map_err(HickoryError::Parse)
+    }
+}
+
+#[derive(Debug)]
+pub enum HickoryError {
+    /// scopa couldn't encode its side.
+    Encode(EncodeError),
+    /// scopa couldn't parse what hickory-proto encoded.
+    Parse(ParseError),
+    /// hickory-proto couldn't encode or parse its side.
+    Proto(ProtoError),
+}
+
+impl fmt::Display for HickoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HickoryError::Encode(error) => write!(f, "{error}"),
+            HickoryError::Parse(error) => write!(f, "{error}"),
+            HickoryError::Proto(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for HickoryError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Class, DomainName, Query, RecordType, ResourceData};
+    use hickory_proto::rr::{rdata, Name, RData};
+
+    #[test]
+    fn test_message_round_trip() {
+        let query = Message::try_from(Query::new("www.example.com", RecordType::A).id(4321)).unwrap();
+        let mut response = Message::reply_to(&query);
+        response.push_answer(ResourceRecord::new("www.example.com".parse().unwrap(), Class::In, 300, ResourceData::A([192, 0, 2, 1].into())));
+
+        let hickory = op::Message::try_from(&response).unwrap();
+        assert_eq!((4321, 1), (hickory.id(), hickory.answers().len()));
+        assert_eq!(&RData::A(rdata::A::new(192, 0, 2, 1)), hickory.answers()[0].data());
+        assert_eq!(response, Message::try_from(&hickory).unwrap());
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let name = Name::from_ascii("example.com.").unwrap();
+        let exchange = Name::from_ascii("mail.example.com.").unwrap();
+        let hickory = Record::from_rdata(name, 3600, RData::MX(rdata::MX::new(10, exchange)));
+
+        let record = ResourceRecord::try_from(&hickory).unwrap();
+        assert_eq!(&ResourceData::Mx { preference: 10, exchange: "mail.example.com".parse::<DomainName>().unwrap() }, record.rdata());
+        // Record has an inherent try_from of its own
+        let converted: Record = (&record).try_into().unwrap();
+        assert_eq!(hickory, converted);
+    }
+}
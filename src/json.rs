@@ -0,0 +1,571 @@
+use core::fmt;
+
+use serde_json::{json, Value};
+
+use crate::{
+    buffer::Buffer, edns::take_edns, parse_resource_data, Class, DomainName, GenericData, Header,
+    Message, ParseError, Question, RecordType, ResourceData, ResourceRecord, Soa,
+};
+
+/// The sections holding records, with their RFC 8427 member names.
+const SECTIONS: [&str; 3] = ["answerRRs", "authorityRRs", "additionalRRs"];
+
+/// The sections holding records, with their member names in the JSON of the
+/// DNS over HTTPS APIs.
+const DNS_JSON_SECTIONS: [&str; 3] = ["Answer", "Authority", "Additional"];
+
+impl Message {
+    /// Represents the message as a JSON object with the member names of
+    /// RFC 8427.
+    ///
+    /// Records carry their RDATA as `RDATAHEX`, uncompressed, and for known
+    /// types also in presentation format as `rdata` followed by the type
+    /// mnemonic, like `rdataMX`.
+    pub fn to_json(&self) -> String {
+        let header = &self.header;
+        let mut object = json!({
+            "ID": header.id,
+            "QR": header.response,
+            "Opcode": u8::from(header.opcode),
+            "AA": header.authoritative,
+            "TC": header.truncated,
+            "RD": header.recursion_desired,
+            "RA": header.recursion_available,
+            "AD": header.authentic_data,
+            "CD": header.checking_disabled,
+            "RCODE": u16::from(header.rcode),
+            "QDCOUNT": self.questions.len(),
+            "ANCOUNT": self.answers.len(),
+            "NSCOUNT": self.authorities.len(),
+            "ARCOUNT": self.additionals.len() + self.edns.is_some() as usize,
+            "questionRRs": self.questions.iter().map(question_to_json).collect::<Vec<_>>(),
+        });
+
+        let sections = [&self.answers, &self.authorities, &self.additionals];
+        for (member, records) in SECTIONS.into_iter().zip(sections) {
+            object[member] = records.iter().map(record_to_json).collect();
+        }
+        // RFC 8427 has no members of its own for EDNS, so it stays an OPT record
+        if let Some(edns) = &self.edns {
+            let opt = record_to_json(&edns.to_record(header.rcode));
+            object["additionalRRs"].as_array_mut().unwrap().push(opt);
+        }
+
+        object.to_string()
+    }
+
+    /// Reads a message written by [`Message::to_json`] or another RFC 8427
+    /// implementation.
+    ///
+    /// The RDATA is taken from `RDATAHEX`, the presentation format members
+    /// are ignored. Flags may be given as booleans or as 0 and 1, and the
+    /// counts are taken from the sections rather than the count members.
+    pub fn from_json(json: &str) -> Result<Self, JsonError> {
+        let object: Value =
+            serde_json::from_str(json).map_err(|error| JsonError::Syntax(error.to_string()))?;
+
+        let flag = |member| optional(&object, member, as_flag).map(Option::unwrap_or_default);
+        let mut header = Header {
+            id: required(&object, "ID", as_u16)?,
+            response: flag("QR")?,
+            opcode: optional(&object, "Opcode", as_u8)?
+                .unwrap_or_default()
+                .into(),
+            authoritative: flag("AA")?,
+            truncated: flag("TC")?,
+            recursion_desired: flag("RD")?,
+            recursion_available: flag("RA")?,
+            authentic_data: flag("AD")?,
+            checking_disabled: flag("CD")?,
+            rcode: optional(&object, "RCODE", as_u16)?
+                .unwrap_or_default()
+                .into(),
+            ..Header::default()
+        };
+
+        let questions = optional(&object, "questionRRs", as_array)?
+            .unwrap_or_default()
+            .iter()
+            .map(question_from_json)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut sections = SECTIONS.into_iter().map(|member| {
+            optional(&object, member, as_array)?
+                .unwrap_or_default()
+                .iter()
+                .map(record_from_json)
+                .collect::<Result<Vec<_>, _>>()
+        });
+        let answers = sections.next().unwrap()?;
+        let authorities = sections.next().unwrap()?;
+        let mut additionals = sections.next().unwrap()?;
+        let edns = take_edns(&mut header, &mut additionals, &mut Vec::new());
+
+        let count = |len: usize| u16::try_from(len).map_err(|_| JsonError::InvalidMember("RRs"));
+        header.question_count = count(questions.len())?;
+        header.answer_count = count(answers.len())?;
+        header.authority_count = count(authorities.len())?;
+        header.additional_count = count(additionals.len() + edns.is_some() as usize)?;
+
+        Ok(Message {
+            header,
+            questions,
+            answers,
+            authorities,
+            additionals,
+            edns,
+            parse_violations: Vec::new(),
+        })
+    }
+
+    /// Represents a response in the JSON format of the DNS over HTTPS APIs
+    /// of Google and Cloudflare, `application/dns-json`, with the data of
+    /// records in presentation format. The format has no place for the ID,
+    /// the opcode or EDNS.
+    pub fn to_dns_json(&self) -> String {
+        let header = &self.header;
+        let mut object = json!({
+            "Status": u16::from(header.rcode),
+            "TC": header.truncated,
+            "RD": header.recursion_desired,
+            "RA": header.recursion_available,
+            "AD": header.authentic_data,
+            "CD": header.checking_disabled,
+            "Question": self.questions.iter().map(|question| json!({
+                "name": question.domain_name.to_string(),
+                "type": u16::from(question.q_type),
+            })).collect::<Vec<_>>(),
+        });
+
+        let sections = [&self.answers, &self.authorities, &self.additionals];
+        for (member, records) in DNS_JSON_SECTIONS.into_iter().zip(sections) {
+            if !records.is_empty() {
+                object[member] = records
+                    .iter()
+                    .map(|record| {
+                        json!({
+                            "name": record.name.to_string(),
+                            "type": u16::from(record.r#type),
+                            "TTL": record.ttl,
+                            "data": record.data.to_string(),
+                        })
+                    })
+                    .collect();
+            }
+        }
+
+        object.to_string()
+    }
+
+    /// Reads a response in the JSON format of the DNS over HTTPS APIs of
+    /// Google and Cloudflare as the reply to `query`, which gives it its ID
+    /// and opcode, its EDNS, and its questions if the JSON has none.
+    ///
+    /// The data of records is read in the RFC 3597 generic format, and in
+    /// presentation format for A, AAAA, NS, CNAME, PTR, DNAME, MX, TXT,
+    /// HINFO, SRV, SOA and CAA records. All records are of class IN.
+    pub fn from_dns_json(json: &str, query: &Message) -> Result<Self, JsonError> {
+        let object: Value =
+            serde_json::from_str(json).map_err(|error| JsonError::Syntax(error.to_string()))?;
+
+        let mut response = Message::reply_to(query);
+        let flag = |member| optional(&object, member, as_flag).map(Option::unwrap_or_default);
+        let header = &mut response.header;
+        header.rcode = required(&object, "Status", as_u16)?.into();
+        header.truncated = flag("TC")?;
+        header.recursion_desired = flag("RD")?;
+        header.recursion_available = flag("RA")?;
+        header.authentic_data = flag("AD")?;
+        header.checking_disabled = flag("CD")?;
+
+        if let Some(questions) = optional(&object, "Question", as_array)? {
+            response.questions = questions
+                .iter()
+                .map(|question| {
+                    Ok(Question {
+                        domain_name: required(question, "name", as_name)?,
+                        q_type: required(question, "type", as_u16)?.into(),
+                        q_class: Class::In,
+                    })
+                })
+                .collect::<Result<Vec<_>, JsonError>>()?;
+            response.header.question_count = u16::try_from(response.questions.len())
+                .map_err(|_| JsonError::InvalidMember("Question"))?;
+        }
+
+        for (section, member) in DNS_JSON_SECTIONS.into_iter().enumerate() {
+            for record in optional(&object, member, as_array)?.unwrap_or_default() {
+                let record = dns_json_record(record)?;
+                match section {
+                    0 => response.push_answer(record),
+                    1 => response.push_authority(record),
+                    _ => response.push_additional(record),
+                }
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+fn question_to_json(question: &Question) -> Value {
+    json!({
+        "NAME": question.domain_name.to_string(),
+        "TYPE": u16::from(question.q_type),
+        "TYPEname": question.q_type.to_string(),
+        "CLASS": u16::from(question.q_class),
+        "CLASSname": question.q_class.to_string(),
+    })
+}
+
+fn record_to_json(record: &ResourceRecord) -> Value {
+    let mut object = json!({
+        "NAME": record.name.to_string(),
+        "TYPE": u16::from(record.r#type),
+        "TYPEname": record.r#type.to_string(),
+        "CLASS": u16::from(record.class),
+        "CLASSname": record.class.to_string(),
+        "TTL": record.ttl,
+    });
+
+    // records that can't be encoded have no RDATAHEX to show
+    if let Ok(data) = record.data.to_wire() {
+        object["RDLENGTH"] = data.len().into();
+        object["RDATAHEX"] = data
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<String>()
+            .into();
+    }
+    if !matches!(record.data, ResourceData::Unknown { .. }) {
+        object[format!("rdata{}", record.r#type)] = record.data.to_string().into();
+    }
+
+    object
+}
+
+fn question_from_json(object: &Value) -> Result<Question, JsonError> {
+    Ok(Question {
+        domain_name: required(object, "NAME", as_name)?,
+        q_type: required(object, "TYPE", as_u16)?.into(),
+        q_class: optional(object, "CLASS", as_u16)?.map_or(Class::In, Class::from),
+    })
+}
+
+fn record_from_json(object: &Value) -> Result<ResourceRecord, JsonError> {
+    let r#type = RecordType::from(required(object, "TYPE", as_u16)?);
+    let hex = required(object, "RDATAHEX", Value::as_str)?;
+    let bytes = decode_hex(hex).ok_or(JsonError::InvalidMember("RDATAHEX"))?;
+    let data_length =
+        u16::try_from(bytes.len()).map_err(|_| JsonError::InvalidMember("RDATAHEX"))?;
+
+    let mut buf = Buffer::new(&bytes);
+    let data = parse_resource_data(r#type, &mut buf).map_err(JsonError::InvalidRdata)?;
+    if !buf.is_empty() {
+        return Err(JsonError::InvalidMember("RDATAHEX"));
+    }
+
+    Ok(ResourceRecord {
+        name: required(object, "NAME", as_name)?,
+        r#type,
+        class: optional(object, "CLASS", as_u16)?.map_or(Class::In, Class::from),
+        ttl: optional(object, "TTL", as_u32)?.unwrap_or_default(),
+        data_length,
+        data,
+    })
+}
+
+fn dns_json_record(object: &Value) -> Result<ResourceRecord, JsonError> {
+    let r#type = RecordType::from(required(object, "type", as_u16)?);
+    let data = required(object, "data", Value::as_str)?;
+    Ok(ResourceRecord::new(
+        required(object, "name", as_name)?,
+        Class::In,
+        optional(object, "TTL", as_u32)?.unwrap_or_default(),
+        data_from_text(r#type, data).ok_or(JsonError::InvalidData(r#type))?,
+    ))
+}
+
+/// Reads RDATA in the RFC 3597 generic format, or in presentation format
+/// for the types [`Message::from_dns_json`] lists.
+fn data_from_text(r#type: RecordType, text: &str) -> Option<ResourceData> {
+    if let Ok(GenericData(bytes)) = text.parse::<GenericData>() {
+        let mut buf = Buffer::new(&bytes);
+        let data = parse_resource_data(r#type, &mut buf).ok()?;
+        return buf.is_empty().then_some(data);
+    }
+
+    let text = text.trim();
+    let data = match r#type {
+        RecordType::A => ResourceData::A(text.parse().ok()?),
+        RecordType::Aaaa => ResourceData::Aaaa(text.parse().ok()?),
+        RecordType::Ns => ResourceData::Ns(text.parse().ok()?),
+        RecordType::Cname => ResourceData::Cname(text.parse().ok()?),
+        RecordType::Ptr => ResourceData::Ptr(text.parse().ok()?),
+        RecordType::Dname => ResourceData::Dname(text.parse().ok()?),
+        RecordType::Mx => {
+            let [preference, exchange] = fields(text)?;
+            ResourceData::Mx {
+                preference: preference.parse().ok()?,
+                exchange: exchange.parse().ok()?,
+            }
+        }
+        RecordType::Txt => ResourceData::Txt(character_strings(text)?),
+        RecordType::Hinfo => {
+            let [cpu, os] = <[_; 2]>::try_from(character_strings(text)?).ok()?;
+            ResourceData::Hinfo { cpu, os }
+        }
+        RecordType::Srv => {
+            let [priority, weight, port, target] = fields(text)?;
+            ResourceData::Srv {
+                priority: priority.parse().ok()?,
+                weight: weight.parse().ok()?,
+                port: port.parse().ok()?,
+                target: target.parse().ok()?,
+            }
+        }
+        RecordType::Soa => {
+            let [mname, rname, serial, refresh, retry, expire, minimum] = fields(text)?;
+            ResourceData::Soa(Soa {
+                mname: mname.parse().ok()?,
+                rname: rname.parse().ok()?,
+                serial: serial.parse().ok()?,
+                refresh: refresh.parse().ok()?,
+                retry: retry.parse().ok()?,
+                expire: expire.parse().ok()?,
+                minimum: minimum.parse().ok()?,
+            })
+        }
+        RecordType::Caa => {
+            let mut parts = text.splitn(3, char::is_whitespace);
+            let (flags, tag) = (parts.next()?, parts.next()?);
+            let [value] = <[_; 1]>::try_from(character_strings(parts.next()?)?).ok()?;
+            ResourceData::Caa {
+                flags: flags.parse().ok()?,
+                tag: tag.to_string(),
+                value,
+            }
+        }
+        _ => return None,
+    };
+    Some(data)
+}
+
+/// Exactly `N` fields separated by whitespace.
+fn fields<const N: usize>(text: &str) -> Option<[&str; N]> {
+    text.split_whitespace().collect::<Vec<_>>().try_into().ok()
+}
+
+/// The character strings of RFC 1035, separated by whitespace, in quotes
+/// unless they have none, and with backslash escapes.
+fn character_strings(text: &str) -> Option<Vec<Vec<u8>>> {
+    let bytes = text.as_bytes();
+    let mut strings = Vec::new();
+    let mut i = 0;
+    loop {
+        while bytes.get(i).is_some_and(u8::is_ascii_whitespace) {
+            i += 1;
+        }
+        let Some(&first) = bytes.get(i) else {
+            return Some(strings);
+        };
+        let quoted = first == b'"';
+        i += quoted as usize;
+
+        let mut string = Vec::new();
+        loop {
+            match bytes.get(i) {
+                None if quoted => return None,
+                None => break,
+                Some(b'"') if quoted => {
+                    i += 1;
+                    break;
+                }
+                Some(byte) if !quoted && byte.is_ascii_whitespace() => break,
+                Some(b'\\') => match bytes.get(i + 1..i + 4) {
+                    Some(digits) if digits.iter().all(u8::is_ascii_digit) => {
+                        let digits = core::str::from_utf8(digits).ok()?;
+                        string.push(digits.parse().ok()?);
+                        i += 4;
+                    }
+                    _ => {
+                        string.push(*bytes.get(i + 1)?);
+                        i += 2;
+                    }
+                },
+                Some(&byte) => {
+                    string.push(byte);
+                    i += 1;
+                }
+            }
+        }
+        if string.len() > 255 {
+            return None;
+        }
+        strings.push(string);
+    }
+}
+
+fn required<'a, T>(
+    object: &'a Value,
+    member: &'static str,
+    convert: impl Fn(&'a Value) -> Option<T>,
+) -> Result<T, JsonError> {
+    optional(object, member, convert)?.ok_or(JsonError::MissingMember(member))
+}
+
+fn optional<'a, T>(
+    object: &'a Value,
+    member: &'static str,
+    convert: impl Fn(&'a Value) -> Option<T>,
+) -> Result<Option<T>, JsonError> {
+    match object.get(member) {
+        Some(value) => convert(value)
+            .map(Some)
+            .ok_or(JsonError::InvalidMember(member)),
+        None => Ok(None),
+    }
+}
+
+fn as_flag(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(flag) => Some(*flag),
+        Value::Number(number) => match number.as_u64()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn as_u8(value: &Value) -> Option<u8> {
+    value.as_u64()?.try_into().ok()
+}
+
+fn as_u16(value: &Value) -> Option<u16> {
+    value.as_u64()?.try_into().ok()
+}
+
+fn as_u32(value: &Value) -> Option<u32> {
+    value.as_u64()?.try_into().ok()
+}
+
+fn as_array(value: &Value) -> Option<&[Value]> {
+    value.as_array().map(Vec::as_slice)
+}
+
+fn as_name(value: &Value) -> Option<DomainName> {
+    value.as_str()?.parse().ok()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonError {
+    /// The input isn't JSON at all.
+    Syntax(String),
+    /// A member that can't be left out, like the `ID` of a message.
+    MissingMember(&'static str),
+    /// A member with a value of the wrong type or out of range.
+    InvalidMember(&'static str),
+    /// RDATA that doesn't parse as its record type.
+    InvalidRdata(ParseError),
+    /// The `data` of a record in the DNS over HTTPS JSON format that isn't
+    /// in a format known for its type.
+    InvalidData(RecordType),
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::Syntax(error) => write!(f, "invalid JSON: {error}"),
+            JsonError::MissingMember(member) => write!(f, "missing member {member}"),
+            JsonError::InvalidMember(member) => write!(f, "invalid member {member}"),
+            JsonError::InvalidRdata(error) => write!(f, "invalid RDATAHEX: {error}"),
+            JsonError::InvalidData(r#type) => write!(f, "invalid data of a {type} record"),
+        }
+    }
+}
+
+impl core::error::Error for JsonError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_message, Rcode};
+
+    #[test]
+    fn test_to_json() {
+        let packet = [0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 15, 0, 1, 0xc0, 12, 0, 15, 0, 1, 0, 0, 14, 16, 0, 4, 0, 10, 0xc0, 12];
+        let message = parse_message(&packet).unwrap();
+        let json: Value = serde_json::from_str(&message.to_json()).unwrap();
+
+        assert_eq!(json!(4660), json["ID"]);
+        assert_eq!((json!(true), json!(true), json!(false)), (json["QR"].clone(), json["RD"].clone(), json["AA"].clone()));
+        assert_eq!(json!([{ "NAME": "example.", "TYPE": 15, "TYPEname": "MX", "CLASS": 1, "CLASSname": "IN" }]), json["questionRRs"]);
+        assert_eq!(json!({ "NAME": "example.", "TYPE": 15, "TYPEname": "MX", "CLASS": 1, "CLASSname": "IN", "TTL": 3600, "RDLENGTH": 11, "RDATAHEX": "000A076578616D706C6500", "rdataMX": "10 example." }), json["answerRRs"][0]);
+        assert_eq!(json!([]), json["additionalRRs"]);
+    }
+
+    #[test]
+    fn test_from_json() {
+        let packet = [0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 1, 0, 1, 0xc0, 12, 0, 1, 0, 1, 0, 0, 14, 16, 0, 4, 192, 0, 2, 1];
+        let message = parse_message(&packet).unwrap();
+        assert_eq!(Ok(message.clone()), Message::from_json(&message.to_json()));
+
+        let mut message = message;
+        message.set_edns(Some(crate::Edns { dnssec_ok: true, ..crate::Edns::default() }));
+        assert_eq!(Ok(message.clone()), Message::from_json(&message.to_json()));
+
+        let json = r#"{ "ID": 1, "QR": 1, "RD": 0, "answerRRs": [{ "NAME": "a.example", "TYPE": 1, "TTL": 60, "RDATAHEX": "c0000201" }] }"#;
+        let message = Message::from_json(json).unwrap();
+        assert!(message.header().is_response());
+        assert_eq!((1, 0), (message.header().answer_count(), message.header().question_count()));
+        assert_eq!(&ResourceData::A([192, 0, 2, 1].into()), message.answers()[0].rdata());
+    }
+
+    #[test]
+    fn test_dns_json() {
+        let query = parse_message(&crate::Query::new("example.com", RecordType::Txt).id(7).build().unwrap()).unwrap();
+        let json = r#"{"Status": 0, "TC": false, "RD": true, "RA": true, "AD": false, "CD": false, "Question": [{"name": "example.com.", "type": 16}],
+            "Answer": [{"name": "example.com.", "type": 16, "TTL": 300, "data": "\"v=spf1 -all\" \"a \\\"quoted\\\" \\059\""}, {"name": "example.com.", "type": 1, "TTL": 60, "data": "\\# 4 c0000201"}],
+            "Comment": "Response from 192.0.2.53."}"#;
+        let response = Message::from_dns_json(json, &query).unwrap();
+        assert!(response.is_response_to(&query));
+        assert_eq!((Rcode::NoError, true, 2), (response.header().rcode(), response.header().recursion_available(), response.header().answer_count()));
+        assert_eq!(&ResourceData::Txt(vec![b"v=spf1 -all".to_vec(), b"a \"quoted\" ;".to_vec()]), response.answers()[0].rdata());
+        assert_eq!(&ResourceData::A([192, 0, 2, 1].into()), response.answers()[1].rdata());
+
+        let mut response = Message::reply_to(&query);
+        response.set_rcode(Rcode::NxDomain);
+        for (r#type, data) in [(RecordType::Soa, "ns.example.com. hostmaster.example.com. 1 7200 3600 1209600 300"), (RecordType::Mx, "10 mail.example.com."), (RecordType::Srv, "0 5 443 www.example.com."), (RecordType::Caa, "0 issue \"letsencrypt.org\""), (RecordType::Hinfo, "\"x86\" Linux")] {
+            response.push_authority(ResourceRecord::new("example.com".parse().unwrap(), Class::In, 300, data_from_text(r#type, data).unwrap()));
+        }
+        response.push_additional(ResourceRecord::new("example.com".parse().unwrap(), Class::In, 300, ResourceData::Unknown { r#type: RecordType::Unknown(65280), data: GenericData(vec![1, 2]) }));
+        assert_eq!(Ok(response.clone()), Message::from_dns_json(&response.to_dns_json(), &query));
+        let json: Value = serde_json::from_str(&response.to_dns_json()).unwrap();
+        assert_eq!((json!(3), json!([{ "name": "example.com.", "type": 16 }]), Value::Null), (json["Status"].clone(), json["Question"].clone(), json["Answer"].clone()));
+
+        assert_eq!(Err(JsonError::MissingMember("Status")), Message::from_dns_json("{}", &query));
+        assert_eq!(Err(JsonError::InvalidData(RecordType::Https)), Message::from_dns_json(r#"{"Status": 0, "Answer": [{"name": "example.com.", "type": 65, "data": "1 . alpn=h2"}]}"#, &query));
+        assert_eq!(Err(JsonError::InvalidData(RecordType::Txt)), Message::from_dns_json(r#"{"Status": 0, "Answer": [{"name": "example.com.", "type": 16, "data": "\"unterminated"}]}"#, &query));
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        assert!(matches!(Message::from_json("{"), Err(JsonError::Syntax(_))));
+        assert_eq!(Err(JsonError::MissingMember("ID")), Message::from_json("{}"));
+        assert_eq!(Err(JsonError::InvalidMember("QR")), Message::from_json(r#"{ "ID": 1, "QR": 2 }"#));
+        assert_eq!(Err(JsonError::MissingMember("RDATAHEX")), Message::from_json(r#"{ "ID": 1, "answerRRs": [{ "NAME": ".", "TYPE": 1 }] }"#));
+        assert_eq!(Err(JsonError::InvalidRdata(ParseError::UnexpectedEof)), Message::from_json(r#"{ "ID": 1, "answerRRs": [{ "NAME": ".", "TYPE": 1, "RDATAHEX": "c000" }] }"#));
+    }
+}
@@ -1,11 +1,94 @@
-use std::{collections::HashMap, net::Ipv4Addr};
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-#[derive(Debug, PartialEq, Eq)]
-struct Header {
+extern crate alloc;
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    ops::{Deref, DerefMut},
+    str::FromStr,
+    time::Duration,
+};
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary;
+mod buffer;
+mod canonical;
+#[cfg(any(feature = "dnssec", feature = "sig0"))]
+mod crypto;
+#[cfg(feature = "dnssec")]
+pub mod dnssec;
+mod edns;
+mod encode;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "hickory")]
+mod hickory;
+#[cfg(feature = "json")]
+mod json;
+mod message_ref;
+#[cfg(all(feature = "testutil", feature = "std"))]
+mod mock_server;
+mod name;
+mod query;
+#[cfg(feature = "std")]
+mod reader;
+mod rrset;
+#[cfg(feature = "sig0")]
+pub mod sig0;
+#[cfg(feature = "std")]
+mod tcp;
+#[cfg(feature = "testutil")]
+pub mod testutil;
+mod text;
+#[cfg(feature = "tsig")]
+pub mod tsig;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+use edns::{parse_edns_options, take_edns};
+
+pub use buffer::Buffer;
+pub use canonical::sort_canonical;
+pub use edns::{ClientSubnet, Cookie, Edns, EdnsOption, QUERY_BLOCK_SIZE, RESPONSE_BLOCK_SIZE};
+#[cfg(feature = "hickory")]
+pub use hickory::HickoryError;
+#[cfg(feature = "json")]
+pub use json::JsonError;
+pub use message_ref::{
+    Labels, MessageReader, MessageRef, NameRef, QuestionRef, RecordRef, Records,
+};
+pub use name::{DomainName, NameError};
+pub use query::Query;
+#[cfg(feature = "std")]
+pub use reader::{parse_message_from, ReadError};
+pub use rrset::RrSet;
+#[cfg(feature = "std")]
+pub use tcp::{read_tcp_message, write_tcp_message};
+
+const HEADER_LENGTH: usize = 12;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Header {
     id: u16,
-    q_type: u8,
+    response: bool,
+    opcode: Opcode,
+    authoritative: bool,
     truncated: bool,
     recursion_desired: bool,
+    recursion_available: bool,
+    /// The reserved bit, which should always be zero.
+    z: bool,
+    authentic_data: bool,
+    checking_disabled: bool,
+    rcode: Rcode,
 
     question_count: u16,
     answer_count: u16,
@@ -13,211 +96,2990 @@ struct Header {
     additional_count: u16,
 }
 
-#[derive(Debug, PartialEq, Eq)]
-struct Question {
-    domain_name: Vec<String>,
-    q_type: u16,
-    q_class: u16,
+impl Header {
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Whether this is a response (QR) rather than a query.
+    pub fn is_response(&self) -> bool {
+        self.response
+    }
+
+    pub fn opcode(&self) -> Opcode {
+        self.opcode
+    }
+
+    pub fn authoritative(&self) -> bool {
+        self.authoritative
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn recursion_desired(&self) -> bool {
+        self.recursion_desired
+    }
+
+    pub fn recursion_available(&self) -> bool {
+        self.recursion_available
+    }
+
+    pub fn z(&self) -> bool {
+        self.z
+    }
+
+    pub fn authentic_data(&self) -> bool {
+        self.authentic_data
+    }
+
+    pub fn checking_disabled(&self) -> bool {
+        self.checking_disabled
+    }
+
+    pub fn rcode(&self) -> Rcode {
+        self.rcode
+    }
+
+    pub fn question_count(&self) -> u16 {
+        self.question_count
+    }
+
+    pub fn answer_count(&self) -> u16 {
+        self.answer_count
+    }
+
+    pub fn authority_count(&self) -> u16 {
+        self.authority_count
+    }
+
+    pub fn additional_count(&self) -> u16 {
+        self.additional_count
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Opcode {
+    #[default]
+    Query,
+    IQuery,
+    Status,
+    Notify,
+    Update,
+    Unknown(u8),
+}
+
+impl From<u8> for Opcode {
+    fn from(opcode: u8) -> Self {
+        match opcode {
+            0 => Opcode::Query,
+            1 => Opcode::IQuery,
+            2 => Opcode::Status,
+            4 => Opcode::Notify,
+            5 => Opcode::Update,
+            _ => Opcode::Unknown(opcode),
+        }
+    }
+}
+
+impl From<Opcode> for u8 {
+    fn from(opcode: Opcode) -> Self {
+        match opcode {
+            Opcode::Query => 0,
+            Opcode::IQuery => 1,
+            Opcode::Status => 2,
+            Opcode::Notify => 4,
+            Opcode::Update => 5,
+            Opcode::Unknown(opcode) => opcode,
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = match self {
+            Opcode::Query => "QUERY",
+            Opcode::IQuery => "IQUERY",
+            Opcode::Status => "STATUS",
+            Opcode::Notify => "NOTIFY",
+            Opcode::Update => "UPDATE",
+            Opcode::Unknown(opcode) => return write!(f, "OPCODE{opcode}"),
+        };
+        write!(f, "{mnemonic}")
+    }
+}
+
+/// Response codes, including the extended ones that only fit into a message
+/// together with the upper eight bits from an OPT record and those used in
+/// TSIG/TKEY records.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rcode {
+    #[default]
+    NoError,
+    FormErr,
+    ServFail,
+    NxDomain,
+    NotImp,
+    Refused,
+    YxDomain,
+    YxRrSet,
+    NxRrSet,
+    NotAuth,
+    NotZone,
+    DsoTypeNi,
+    /// BADVERS in OPT records, BADSIG in TSIG records.
+    BadVers,
+    BadKey,
+    BadTime,
+    BadMode,
+    BadName,
+    BadAlg,
+    BadTrunc,
+    BadCookie,
+    Unknown(u16),
+}
+
+impl Rcode {
+    /// BADSIG of TSIG records, which has the same value as BADVERS.
+    pub const BAD_SIG: Rcode = Rcode::BadVers;
+}
+
+impl From<u16> for Rcode {
+    fn from(rcode: u16) -> Self {
+        match rcode {
+            0 => Rcode::NoError,
+            1 => Rcode::FormErr,
+            2 => Rcode::ServFail,
+            3 => Rcode::NxDomain,
+            4 => Rcode::NotImp,
+            5 => Rcode::Refused,
+            6 => Rcode::YxDomain,
+            7 => Rcode::YxRrSet,
+            8 => Rcode::NxRrSet,
+            9 => Rcode::NotAuth,
+            10 => Rcode::NotZone,
+            11 => Rcode::DsoTypeNi,
+            16 => Rcode::BadVers,
+            17 => Rcode::BadKey,
+            18 => Rcode::BadTime,
+            19 => Rcode::BadMode,
+            20 => Rcode::BadName,
+            21 => Rcode::BadAlg,
+            22 => Rcode::BadTrunc,
+            23 => Rcode::BadCookie,
+            _ => Rcode::Unknown(rcode),
+        }
+    }
+}
+
+impl From<Rcode> for u16 {
+    fn from(rcode: Rcode) -> Self {
+        match rcode {
+            Rcode::NoError => 0,
+            Rcode::FormErr => 1,
+            Rcode::ServFail => 2,
+            Rcode::NxDomain => 3,
+            Rcode::NotImp => 4,
+            Rcode::Refused => 5,
+            Rcode::YxDomain => 6,
+            Rcode::YxRrSet => 7,
+            Rcode::NxRrSet => 8,
+            Rcode::NotAuth => 9,
+            Rcode::NotZone => 10,
+            Rcode::DsoTypeNi => 11,
+            Rcode::BadVers => 16,
+            Rcode::BadKey => 17,
+            Rcode::BadTime => 18,
+            Rcode::BadMode => 19,
+            Rcode::BadName => 20,
+            Rcode::BadAlg => 21,
+            Rcode::BadTrunc => 22,
+            Rcode::BadCookie => 23,
+            Rcode::Unknown(rcode) => rcode,
+        }
+    }
+}
+
+impl fmt::Display for Rcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = match self {
+            Rcode::NoError => "NOERROR",
+            Rcode::FormErr => "FORMERR",
+            Rcode::ServFail => "SERVFAIL",
+            Rcode::NxDomain => "NXDOMAIN",
+            Rcode::NotImp => "NOTIMP",
+            Rcode::Refused => "REFUSED",
+            Rcode::YxDomain => "YXDOMAIN",
+            Rcode::YxRrSet => "YXRRSET",
+            Rcode::NxRrSet => "NXRRSET",
+            Rcode::NotAuth => "NOTAUTH",
+            Rcode::NotZone => "NOTZONE",
+            Rcode::DsoTypeNi => "DSOTYPENI",
+            Rcode::BadVers => "BADVERS",
+            Rcode::BadKey => "BADKEY",
+            Rcode::BadTime => "BADTIME",
+            Rcode::BadMode => "BADMODE",
+            Rcode::BadName => "BADNAME",
+            Rcode::BadAlg => "BADALG",
+            Rcode::BadTrunc => "BADTRUNC",
+            Rcode::BadCookie => "BADCOOKIE",
+            Rcode::Unknown(rcode) => return write!(f, "RCODE{rcode}"),
+        };
+
+        write!(f, "{mnemonic}")
+    }
+}
+
+/// Resource record types, also used for the question type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RecordType {
+    A,
+    Ns,
+    Cname,
+    Soa,
+    Ptr,
+    Hinfo,
+    Mx,
+    Txt,
+    Sig,
+    Aaaa,
+    Loc,
+    Srv,
+    Naptr,
+    Dname,
+    Opt,
+    Ds,
+    Sshfp,
+    Rrsig,
+    Nsec,
+    Dnskey,
+    Nsec3,
+    Nsec3Param,
+    Tlsa,
+    Smimea,
+    Openpgpkey,
+    Svcb,
+    Https,
+    Tsig,
+    Any,
+    Uri,
+    Caa,
+    Unknown(u16),
+}
+
+impl From<u16> for RecordType {
+    fn from(r#type: u16) -> Self {
+        match r#type {
+            1 => RecordType::A,
+            2 => RecordType::Ns,
+            5 => RecordType::Cname,
+            6 => RecordType::Soa,
+            12 => RecordType::Ptr,
+            13 => RecordType::Hinfo,
+            15 => RecordType::Mx,
+            16 => RecordType::Txt,
+            24 => RecordType::Sig,
+            28 => RecordType::Aaaa,
+            29 => RecordType::Loc,
+            33 => RecordType::Srv,
+            35 => RecordType::Naptr,
+            39 => RecordType::Dname,
+            41 => RecordType::Opt,
+            43 => RecordType::Ds,
+            44 => RecordType::Sshfp,
+            46 => RecordType::Rrsig,
+            47 => RecordType::Nsec,
+            48 => RecordType::Dnskey,
+            50 => RecordType::Nsec3,
+            51 => RecordType::Nsec3Param,
+            52 => RecordType::Tlsa,
+            53 => RecordType::Smimea,
+            61 => RecordType::Openpgpkey,
+            64 => RecordType::Svcb,
+            65 => RecordType::Https,
+            250 => RecordType::Tsig,
+            255 => RecordType::Any,
+            256 => RecordType::Uri,
+            257 => RecordType::Caa,
+            _ => RecordType::Unknown(r#type),
+        }
+    }
+}
+
+impl From<RecordType> for u16 {
+    fn from(r#type: RecordType) -> Self {
+        match r#type {
+            RecordType::A => 1,
+            RecordType::Ns => 2,
+            RecordType::Cname => 5,
+            RecordType::Soa => 6,
+            RecordType::Ptr => 12,
+            RecordType::Hinfo => 13,
+            RecordType::Mx => 15,
+            RecordType::Txt => 16,
+            RecordType::Sig => 24,
+            RecordType::Aaaa => 28,
+            RecordType::Loc => 29,
+            RecordType::Srv => 33,
+            RecordType::Naptr => 35,
+            RecordType::Dname => 39,
+            RecordType::Opt => 41,
+            RecordType::Ds => 43,
+            RecordType::Sshfp => 44,
+            RecordType::Rrsig => 46,
+            RecordType::Nsec => 47,
+            RecordType::Dnskey => 48,
+            RecordType::Nsec3 => 50,
+            RecordType::Nsec3Param => 51,
+            RecordType::Tlsa => 52,
+            RecordType::Smimea => 53,
+            RecordType::Openpgpkey => 61,
+            RecordType::Svcb => 64,
+            RecordType::Https => 65,
+            RecordType::Tsig => 250,
+            RecordType::Any => 255,
+            RecordType::Uri => 256,
+            RecordType::Caa => 257,
+            RecordType::Unknown(r#type) => r#type,
+        }
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = match self {
+            RecordType::A => "A",
+            RecordType::Ns => "NS",
+            RecordType::Cname => "CNAME",
+            RecordType::Soa => "SOA",
+            RecordType::Ptr => "PTR",
+            RecordType::Hinfo => "HINFO",
+            RecordType::Mx => "MX",
+            RecordType::Txt => "TXT",
+            RecordType::Sig => "SIG",
+            RecordType::Aaaa => "AAAA",
+            RecordType::Loc => "LOC",
+            RecordType::Srv => "SRV",
+            RecordType::Naptr => "NAPTR",
+            RecordType::Dname => "DNAME",
+            RecordType::Opt => "OPT",
+            RecordType::Ds => "DS",
+            RecordType::Sshfp => "SSHFP",
+            RecordType::Rrsig => "RRSIG",
+            RecordType::Nsec => "NSEC",
+            RecordType::Dnskey => "DNSKEY",
+            RecordType::Nsec3 => "NSEC3",
+            RecordType::Nsec3Param => "NSEC3PARAM",
+            RecordType::Tlsa => "TLSA",
+            RecordType::Smimea => "SMIMEA",
+            RecordType::Openpgpkey => "OPENPGPKEY",
+            RecordType::Svcb => "SVCB",
+            RecordType::Https => "HTTPS",
+            RecordType::Tsig => "TSIG",
+            RecordType::Any => "ANY",
+            RecordType::Uri => "URI",
+            RecordType::Caa => "CAA",
+            RecordType::Unknown(number) => return write!(f, "TYPE{number}"),
+        };
+
+        write!(f, "{mnemonic}")
+    }
+}
+
+impl FromStr for RecordType {
+    type Err = InvalidRecordType;
+
+    /// Accepts the mnemonics in any case as well as the RFC 3597 `TYPE<n>` form.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let r#type = match s.to_ascii_uppercase().as_str() {
+            "A" => RecordType::A,
+            "NS" => RecordType::Ns,
+            "CNAME" => RecordType::Cname,
+            "SOA" => RecordType::Soa,
+            "PTR" => RecordType::Ptr,
+            "HINFO" => RecordType::Hinfo,
+            "MX" => RecordType::Mx,
+            "TXT" => RecordType::Txt,
+            "SIG" => RecordType::Sig,
+            "AAAA" => RecordType::Aaaa,
+            "LOC" => RecordType::Loc,
+            "SRV" => RecordType::Srv,
+            "NAPTR" => RecordType::Naptr,
+            "DNAME" => RecordType::Dname,
+            "OPT" => RecordType::Opt,
+            "DS" => RecordType::Ds,
+            "SSHFP" => RecordType::Sshfp,
+            "RRSIG" => RecordType::Rrsig,
+            "NSEC" => RecordType::Nsec,
+            "DNSKEY" => RecordType::Dnskey,
+            "NSEC3" => RecordType::Nsec3,
+            "NSEC3PARAM" => RecordType::Nsec3Param,
+            "TLSA" => RecordType::Tlsa,
+            "SMIMEA" => RecordType::Smimea,
+            "OPENPGPKEY" => RecordType::Openpgpkey,
+            "SVCB" => RecordType::Svcb,
+            "HTTPS" => RecordType::Https,
+            "TSIG" => RecordType::Tsig,
+            "ANY" => RecordType::Any,
+            "URI" => RecordType::Uri,
+            "CAA" => RecordType::Caa,
+            other => {
+                let number = other.strip_prefix("TYPE").ok_or(InvalidRecordType)?;
+                number.parse::<u16>().map_err(|_| InvalidRecordType)?.into()
+            }
+        };
+
+        Ok(r#type)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidRecordType;
+
+impl fmt::Display for InvalidRecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown record type")
+    }
+}
+
+impl core::error::Error for InvalidRecordType {}
+
+/// Record classes.
+///
+/// OPT records reuse the class field for the requestor's UDP payload size, so
+/// theirs is kept as is and usually ends up as `Unknown`; convert it back with
+/// `u16::from` to get the size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Class {
+    In,
+    Ch,
+    Hs,
+    None,
+    Any,
+    Unknown(u16),
+}
+
+impl From<u16> for Class {
+    fn from(class: u16) -> Self {
+        match class {
+            1 => Class::In,
+            3 => Class::Ch,
+            4 => Class::Hs,
+            254 => Class::None,
+            255 => Class::Any,
+            _ => Class::Unknown(class),
+        }
+    }
+}
+
+impl From<Class> for u16 {
+    fn from(class: Class) -> Self {
+        match class {
+            Class::In => 1,
+            Class::Ch => 3,
+            Class::Hs => 4,
+            Class::None => 254,
+            Class::Any => 255,
+            Class::Unknown(class) => class,
+        }
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = match self {
+            Class::In => "IN",
+            Class::Ch => "CH",
+            Class::Hs => "HS",
+            Class::None => "NONE",
+            Class::Any => "ANY",
+            Class::Unknown(class) => return write!(f, "CLASS{class}"),
+        };
+
+        write!(f, "{mnemonic}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Question {
+    domain_name: DomainName,
+    q_type: RecordType,
+    q_class: Class,
+}
+
+impl Question {
+    pub fn name(&self) -> &DomainName {
+        &self.domain_name
+    }
+
+    pub fn record_type(&self) -> RecordType {
+        self.q_type
+    }
+
+    pub fn class(&self) -> Class {
+        self.q_class
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-struct ResourceRecord {
-    name: Vec<String>,
-    r#type: u16,
-    class: u16,
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResourceRecord {
+    name: DomainName,
+    r#type: RecordType,
+    /// The UDP payload size for OPT records, see [`Class`].
+    class: Class,
     ttl: u32,
     data_length: u16,
     data: ResourceData,
 }
 
-#[derive(Debug, PartialEq, Eq)]
-enum ResourceData {
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResourceData {
     A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Ns(DomainName),
+    Cname(DomainName),
+    Soa(Soa),
+    Ptr(DomainName),
+    Hinfo {
+        cpu: Vec<u8>,
+        os: Vec<u8>,
+    },
+    Mx {
+        preference: u16,
+        exchange: DomainName,
+    },
+    Txt(Vec<Vec<u8>>),
+    Loc(Loc),
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: DomainName,
+    },
+    Naptr {
+        order: u16,
+        preference: u16,
+        flags: Vec<u8>,
+        services: Vec<u8>,
+        regexp: Vec<u8>,
+        replacement: DomainName,
+    },
+    Dname(DomainName),
+    /// The options of an OPT record. Parsed messages carry their OPT record
+    /// as [`Message::edns`] instead.
+    Opt(Vec<EdnsOption>),
+    Ds(Ds),
+    Sshfp(Sshfp),
+    Rrsig(Rrsig),
+    /// A SIG record, which has the format of RRSIG records and is only still
+    /// used for SIG(0) transaction signatures (RFC 2931).
+    Sig(Rrsig),
+    Nsec(Nsec),
+    Dnskey(Dnskey),
+    Nsec3(Nsec3),
+    Tlsa(Tlsa),
+    Smimea(Tlsa),
+    Openpgpkey(OpenPgpKey),
+    Svcb(Svcb),
+    Https(Svcb),
+    Uri {
+        priority: u16,
+        weight: u16,
+        target: String,
+    },
+    Caa {
+        flags: u8,
+        tag: String,
+        value: Vec<u8>,
+    },
+    Tsig(Tsig),
+    Unknown {
+        r#type: RecordType,
+        data: GenericData,
+    },
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct Message {
-    header: Header,
-    question: Question,
-    answers: Option<Vec<ResourceRecord>>,
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Soa {
+    pub mname: DomainName,
+    pub rname: DomainName,
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    pub minimum: u32,
+}
+
+/// Whether serial `a` is newer than `b` in the serial number arithmetic of
+/// RFC 1982, under which serials wrap around after `u32::MAX`. Serials
+/// exactly 2^31 apart are neither newer nor older than each other.
+pub fn serial_gt(a: u32, b: u32) -> bool {
+    (a < b && b - a > 1 << 31) || (a > b && a - b < 1 << 31)
+}
+
+/// Whether serial `a` is older than `b`, see [`serial_gt`].
+pub fn serial_lt(a: u32, b: u32) -> bool {
+    serial_gt(b, a)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ds {
+    pub key_tag: u16,
+    pub algorithm: u8,
+    pub digest_type: u8,
+    pub digest: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rrsig {
+    pub type_covered: RecordType,
+    pub algorithm: u8,
+    pub labels: u8,
+    pub original_ttl: u32,
+    pub expiration: u32,
+    pub inception: u32,
+    pub key_tag: u16,
+    pub signer_name: DomainName,
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Nsec {
+    pub next_domain_name: DomainName,
+    pub types: Vec<RecordType>,
 }
 
-pub fn parse_message(buf: &[u8]) -> Message {
-    let mut index = 0;
-    let header = parse_header(&buf, &mut index);
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dnskey {
+    pub flags: u16,
+    pub protocol: u8,
+    pub algorithm: u8,
+    pub public_key: Vec<u8>,
+}
 
-    let mut names = HashMap::new();
-    let question = parse_question(&mut names, &buf, &mut index);
-    let answers = parse_resource_records(header.answer_count, &mut names, &buf, &mut index);
+impl Dnskey {
+    /// The tag identifying the key in RRSIG and DS records (RFC 4034
+    /// appendix B).
+    pub fn key_tag(&self) -> u16 {
+        let mut rdata = Vec::with_capacity(4 + self.public_key.len());
+        rdata.extend_from_slice(&self.flags.to_be_bytes());
+        rdata.extend_from_slice(&[self.protocol, self.algorithm]);
+        rdata.extend_from_slice(&self.public_key);
 
-    Message {
-        header,
-        question,
-        answers,
+        let sum = rdata.iter().enumerate().fold(0u32, |sum, (i, &byte)| {
+            sum + if i % 2 == 0 {
+                (byte as u32) << 8
+            } else {
+                byte as u32
+            }
+        });
+        (sum + (sum >> 16)) as u16
     }
 }
 
-fn parse_header(buf: &[u8], index: &mut usize) -> Header {
-    *index = 12;
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Nsec3 {
+    pub hash_algorithm: u8,
+    pub flags: u8,
+    pub iterations: u16,
+    pub salt: Vec<u8>,
+    pub next_hashed_owner: Vec<u8>,
+    pub types: Vec<RecordType>,
+}
 
-    Header {
-        id: u16::from_be_bytes([buf[0], buf[1]]),
-        // Query/response bit,
-        q_type: (buf[2] >> 3) & 0b00001111,
-        // Authorative Answer,
-        truncated: bits_set(&buf[2], 0x02),
-        recursion_desired: bits_set(&buf[2], 0x01),
-        // Recursion available,
-        // Zeros
-        // Response code
-        question_count: u16::from_be_bytes([buf[4], buf[5]]),
-        answer_count: u16::from_be_bytes([buf[6], buf[7]]),
-        authority_count: u16::from_be_bytes([buf[8], buf[9]]),
-        additional_count: u16::from_be_bytes([buf[10], buf[11]]),
+impl Nsec3 {
+    /// Whether the span may contain unsigned delegations (RFC 5155 6).
+    pub fn opt_out(&self) -> bool {
+        self.flags & 1 != 0
     }
 }
 
-fn parse_question(
-    names: &mut HashMap<usize, Vec<String>>,
-    buf: &[u8],
-    index: &mut usize,
-) -> Question {
-    let domain_name = parse_name(names, buf, index);
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sshfp {
+    pub algorithm: u8,
+    pub fingerprint_type: u8,
+    pub fingerprint: Vec<u8>,
+}
+
+impl fmt::Display for Sshfp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ", self.algorithm, self.fingerprint_type)?;
+        write_hex(f, &self.fingerprint)
+    }
+}
 
-    let q_type = u16::from_be_bytes([buf[*index], buf[*index + 1]]);
-    *index += 2;
-    let q_class = u16::from_be_bytes([buf[*index], buf[*index + 1]]);
-    *index += 2;
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tlsa {
+    pub usage: u8,
+    pub selector: u8,
+    pub matching_type: u8,
+    pub data: Vec<u8>,
+}
 
-    Question {
-        domain_name,
-        q_type,
-        q_class,
+impl fmt::Display for Tlsa {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} ",
+            self.usage, self.selector, self.matching_type
+        )?;
+        write_hex(f, &self.data)
     }
 }
 
-fn parse_resource_records(
-    amt: u16,
-    names: &mut HashMap<usize, Vec<String>>,
-    buf: &[u8],
-    index: &mut usize,
-) -> Option<Vec<ResourceRecord>> {
-    if amt == 0 {
-        return None;
+/// Location information as defined in RFC 1876, kept in its wire encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Loc {
+    pub version: u8,
+    pub size: u8,
+    pub horizontal_precision: u8,
+    pub vertical_precision: u8,
+    pub latitude: u32,
+    pub longitude: u32,
+    pub altitude: u32,
+}
+
+impl Loc {
+    /// Degrees north of the equator, negative for the southern hemisphere.
+    pub fn latitude_degrees(&self) -> f64 {
+        (self.latitude as i64 - (1 << 31)) as f64 / 3_600_000.0
+    }
+
+    /// Degrees east of the prime meridian, negative for the western hemisphere.
+    pub fn longitude_degrees(&self) -> f64 {
+        (self.longitude as i64 - (1 << 31)) as f64 / 3_600_000.0
+    }
+
+    /// Meters above the WGS 84 reference spheroid.
+    pub fn altitude_meters(&self) -> f64 {
+        (self.altitude as i64 - 10_000_000) as f64 / 100.0
+    }
+
+    pub fn size_meters(&self) -> f64 {
+        loc_precision_meters(self.size)
+    }
+
+    pub fn horizontal_precision_meters(&self) -> f64 {
+        loc_precision_meters(self.horizontal_precision)
+    }
+
+    pub fn vertical_precision_meters(&self) -> f64 {
+        loc_precision_meters(self.vertical_precision)
+    }
+}
+
+/// Sizes and precisions are centimeters encoded as a mantissa in the high
+/// nibble and a power of ten in the low nibble.
+fn loc_precision_meters(value: u8) -> f64 {
+    (value >> 4) as f64 * 10u64.pow((value & 0x0f) as u32) as f64 / 100.0
+}
+
+/// A transferable OpenPGP public key, displayed base64 encoded like in zone
+/// files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OpenPgpKey(pub Vec<u8>);
+
+impl fmt::Display for OpenPgpKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_base64(f, &self.0)
+    }
+}
+
+/// RDATA of a type scopa doesn't know, presented in the RFC 3597 generic
+/// format `\# <length> <hex>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GenericData(pub Vec<u8>);
+
+impl fmt::Display for GenericData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\\# {}", self.0.len())?;
+        if !self.0.is_empty() {
+            write!(f, " ")?;
+            write_hex(f, &self.0)?;
+        }
+
+        Ok(())
     }
+}
+
+impl FromStr for GenericData {
+    type Err = InvalidGenericData;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        if parts.next() != Some("\\#") {
+            return Err(InvalidGenericData);
+        }
+        let length: usize = parts
+            .next()
+            .and_then(|length| length.parse().ok())
+            .ok_or(InvalidGenericData)?;
+
+        // the hex may be split into any number of whitespace separated groups
+        let hex: String = parts.collect();
+        if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+            return Err(InvalidGenericData);
+        }
+        let data = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| InvalidGenericData))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if data.len() != length {
+            return Err(InvalidGenericData);
+        }
+
+        Ok(GenericData(data))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidGenericData;
+
+impl fmt::Display for InvalidGenericData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid RFC 3597 generic RDATA")
+    }
+}
+
+impl core::error::Error for InvalidGenericData {}
 
-    let mut resource_records = Vec::new();
-
-    for _ in 0..amt {
-        let name = parse_name(names, buf, index);
-        let r#type = u16::from_be_bytes([buf[*index], buf[*index + 1]]);
-        *index += 2;
-        let class = u16::from_be_bytes([buf[*index], buf[*index + 1]]);
-        *index += 2;
-        let ttl = u32::from_be_bytes([
-            buf[*index],
-            buf[*index + 1],
-            buf[*index + 2],
-            buf[*index + 3],
-        ]);
-        *index += 4;
-        let data_length = u16::from_be_bytes([buf[*index], buf[*index + 1]]);
-        *index += 2;
-        let data = match r#type {
-            1 => ResourceData::A(Ipv4Addr::from([
-                buf[*index],
-                buf[*index + 1],
-                buf[*index + 2],
-                buf[*index + 3],
-            ])),
-            _ => ResourceData::A(Ipv4Addr::new(0, 0, 0, 0)),
-        };
-        *index += data_length as usize;
-        resource_records.push(ResourceRecord {
+/// A transaction signature (RFC 8945), owned by the name of the key. The
+/// `tsig` module computes and checks them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tsig {
+    pub algorithm: DomainName,
+    /// Seconds since the Unix epoch, of which only 48 bits are encoded.
+    pub time_signed: u64,
+    /// The seconds `time_signed` may be off by.
+    pub fudge: u16,
+    pub mac: Vec<u8>,
+    /// The ID of the message when it was signed, in case a forwarder
+    /// changed it.
+    pub original_id: u16,
+    pub error: Rcode,
+    pub other: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Svcb {
+    pub priority: u16,
+    pub target: DomainName,
+    pub params: Vec<SvcParam>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SvcParam {
+    Mandatory(Vec<u16>),
+    Alpn(Vec<Vec<u8>>),
+    NoDefaultAlpn,
+    Port(u16),
+    Ipv4Hint(Vec<Ipv4Addr>),
+    Ech(Vec<u8>),
+    Ipv6Hint(Vec<Ipv6Addr>),
+    Unknown { key: u16, value: Vec<u8> },
+}
+
+impl ResourceRecord {
+    /// A record of the type matching `data`.
+    pub fn new(name: DomainName, class: Class, ttl: u32, data: ResourceData) -> Self {
+        ResourceRecord {
             name,
-            r#type,
+            r#type: data.record_type(),
             class,
             ttl,
-            data_length,
+            // data that doesn't fit is rejected when encoding anyway
+            data_length: data.data_length().unwrap_or(u16::MAX),
             data,
-        })
+        }
+    }
+
+    /// Decodes an uncompressed record that makes up all of `bytes`.
+    pub fn from_wire(bytes: &[u8]) -> Result<Self, ParseError> {
+        let mut buf = Buffer::new(bytes);
+        let record = message_ref::parse_record(bytes, &mut buf)?;
+        if !buf.is_empty() {
+            return Err(ParseError::Invalid(Violation::TrailingBytes(
+                buf.remaining(),
+            )));
+        }
+
+        record.to_record()
+    }
+
+    pub fn name(&self) -> &DomainName {
+        &self.name
     }
 
-    Some(resource_records)
+    pub fn record_type(&self) -> RecordType {
+        self.r#type
+    }
+
+    pub fn class(&self) -> Class {
+        self.class
+    }
+
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl.into())
+    }
+
+    /// The length of the RDATA as given in the record, or as it would be
+    /// encoded without compression for records that weren't parsed.
+    pub fn data_length(&self) -> u16 {
+        self.data_length
+    }
+
+    pub fn rdata(&self) -> &ResourceData {
+        &self.data
+    }
+
+    pub fn set_ttl(&mut self, ttl: u32) {
+        self.ttl = ttl;
+    }
+
+    /// Replaces the RDATA, also changing the type to match.
+    pub fn set_data(&mut self, data: ResourceData) {
+        self.r#type = data.record_type();
+        self.data_length = data.data_length().unwrap_or(u16::MAX);
+        self.data = data;
+    }
+
+    /// Whether both records hold the same data, regardless of their owner
+    /// names and TTLs.
+    pub fn same_rdata(&self, other: &ResourceRecord) -> bool {
+        self.r#type == other.r#type && self.data == other.data
+    }
+
+    /// Compares the records like `==` does, except for the TTL and how long
+    /// the RDATA was on the wire, which differ between copies of a record
+    /// from different responses or with different compression.
+    pub fn eq_ignore_ttl(&self, other: &ResourceRecord) -> bool {
+        self.name == other.name && self.class == other.class && self.same_rdata(other)
+    }
+
+    /// When the record stops being valid, given the time it was received.
+    #[cfg(feature = "std")]
+    pub fn expires_at(&self, received: Instant) -> Instant {
+        received + self.ttl()
+    }
+
+    /// The TTL limited to `min..=max`, as caches commonly do to avoid
+    /// requerying too often or holding on to records for too long.
+    ///
+    /// Panics if `min` is greater than `max`.
+    pub fn clamp_ttl(&self, min: Duration, max: Duration) -> Duration {
+        self.ttl().clamp(min, max)
+    }
 }
 
-fn parse_name(
-    names: &mut HashMap<usize, Vec<String>>,
-    buf: &[u8],
-    index: &mut usize,
-) -> Vec<String> {
-    let mut name = Vec::new();
-    let mut length = buf[*index] as usize;
-    let offset = *index;
-    *index += 1;
+impl ResourceData {
+    pub fn record_type(&self) -> RecordType {
+        match self {
+            ResourceData::A(_) => RecordType::A,
+            ResourceData::Aaaa(_) => RecordType::Aaaa,
+            ResourceData::Ns(_) => RecordType::Ns,
+            ResourceData::Cname(_) => RecordType::Cname,
+            ResourceData::Soa(_) => RecordType::Soa,
+            ResourceData::Ptr(_) => RecordType::Ptr,
+            ResourceData::Hinfo { .. } => RecordType::Hinfo,
+            ResourceData::Mx { .. } => RecordType::Mx,
+            ResourceData::Txt(_) => RecordType::Txt,
+            ResourceData::Loc(_) => RecordType::Loc,
+            ResourceData::Srv { .. } => RecordType::Srv,
+            ResourceData::Naptr { .. } => RecordType::Naptr,
+            ResourceData::Dname(_) => RecordType::Dname,
+            ResourceData::Opt(_) => RecordType::Opt,
+            ResourceData::Ds(_) => RecordType::Ds,
+            ResourceData::Sshfp(_) => RecordType::Sshfp,
+            ResourceData::Rrsig(_) => RecordType::Rrsig,
+            ResourceData::Sig(_) => RecordType::Sig,
+            ResourceData::Nsec(_) => RecordType::Nsec,
+            ResourceData::Dnskey(_) => RecordType::Dnskey,
+            ResourceData::Nsec3(_) => RecordType::Nsec3,
+            ResourceData::Tlsa(_) => RecordType::Tlsa,
+            ResourceData::Smimea(_) => RecordType::Smimea,
+            ResourceData::Openpgpkey(_) => RecordType::Openpgpkey,
+            ResourceData::Svcb(_) => RecordType::Svcb,
+            ResourceData::Https(_) => RecordType::Https,
+            ResourceData::Uri { .. } => RecordType::Uri,
+            ResourceData::Caa { .. } => RecordType::Caa,
+            ResourceData::Tsig(_) => RecordType::Tsig,
+            ResourceData::Unknown { r#type, .. } => *r#type,
+        }
+    }
 
-    while length > 0 {
-        // pointer to another name
-        if bits_set(&(length as u8), 0b11000000) {
-            let offset = u16::from_be_bytes([length as u8 & 0b00111111, buf[*index]]) as usize;
-            name.extend(names[&offset].clone());
-            *index += 1;
-            break;
+    /// The character-strings of a TXT record, with invalid UTF-8 replaced.
+    pub fn txt_lossy(&self) -> Option<Vec<String>> {
+        match self {
+            ResourceData::Txt(strings) => Some(
+                strings
+                    .iter()
+                    .map(|string| String::from_utf8_lossy(string).to_string())
+                    .collect(),
+            ),
+            _ => None,
         }
+    }
+}
 
-        let label = String::from_utf8_lossy(&buf[*index..*index + length]).to_string();
-        name.push(label);
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The message ended in the middle of a field.
+    UnexpectedEof,
+    /// A compression pointer that doesn't point at a previously parsed name.
+    BadPointer,
+    /// A label length using one of the reserved prefixes.
+    BadLabel,
+    /// A name that breaks the limits on label and name lengths.
+    InvalidName(NameError),
+    /// An EDNS option with data that doesn't fit its option code.
+    InvalidOption(u16),
+    /// An SvcParam with a value that doesn't fit its key.
+    InvalidSvcParam(u16),
+    /// An inconsistency rejected by [`parse_message_strict`].
+    Invalid(Violation),
+}
 
-        *index += length;
-        length = buf[*index] as usize;
-        *index += 1;
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedEof => write!(f, "message ended unexpectedly"),
+            ParseError::BadPointer => write!(f, "invalid compression pointer"),
+            ParseError::BadLabel => write!(f, "invalid label length"),
+            ParseError::InvalidName(error) => write!(f, "invalid name: {error}"),
+            ParseError::InvalidOption(code) => write!(f, "invalid EDNS option {code}"),
+            ParseError::InvalidSvcParam(key) => write!(f, "invalid SvcParam {key}"),
+            ParseError::Invalid(violation) => write!(f, "{violation}"),
+        }
     }
+}
 
-    if !name.is_empty() {
-        names.insert(offset, name.clone());
-    }
+impl core::error::Error for ParseError {}
 
-    name
+/// A [`ParseError`] together with where in the message it happened, from
+/// [`parse_message_detailed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetailedParseError {
+    pub error: ParseError,
+    /// The offset of the field that couldn't be parsed, from the start of
+    /// the message. For names, that is where the name starts.
+    pub offset: usize,
+    /// The section and index of the question or record being parsed, or
+    /// `None` for the header.
+    pub entry: Option<(Section, usize)>,
 }
 
-fn bits_set(byte: &u8, bit_pos: u8) -> bool {
-    byte & bit_pos == bit_pos
+impl DetailedParseError {
+    /// The bytes of `message` around the offset, sixteen per line, with the
+    /// failing one marked.
+    pub fn hexdump(&self, message: &[u8]) -> String {
+        use fmt::Write;
+
+        let row = self.offset / 16;
+        let rows = row.saturating_sub(1)..=(row + 1).min(message.len().saturating_sub(1) / 16);
+
+        let mut dump = String::new();
+        for start in rows.map(|row| row * 16) {
+            let bytes = &message[start..(start + 16).min(message.len())];
+            let _ = write!(dump, "{start:04x} ");
+            for byte in bytes {
+                let _ = write!(dump, " {byte:02x}");
+            }
+            let padding = 3 * (16 - bytes.len());
+            let ascii: String = bytes
+                .iter()
+                .map(|&byte| match byte {
+                    b' '..=b'~' => byte as char,
+                    _ => '.',
+                })
+                .collect();
+            let _ = writeln!(dump, "{:padding$}  |{ascii}|", "");
+            if start == row * 16 {
+                let _ = writeln!(dump, "{:width$}^^", "", width = 6 + 3 * (self.offset % 16));
+            }
+        }
+        // a message ending right before the offset ends in a full line
+        if self.offset == message.len() && self.offset.is_multiple_of(16) && self.offset > 0 {
+            let _ = writeln!(dump, "{:04x}  ^^", self.offset);
+        }
+        dump
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl fmt::Display for DetailedParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.entry {
+            None => write!(f, "header")?,
+            Some((Section::Question, question)) => write!(f, "question {question}")?,
+            Some((section, record)) => write!(f, "{section} record {record}")?,
+        }
+        write!(f, " at offset {}: {}", self.offset, self.error)
+    }
+}
 
-    #[test]
-    fn test_parse_question() {
-        let buf = [141, 225, 1, 32, 0, 1, 0, 0, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1];
-        let parsed_message = parse_message(&buf);
+impl core::error::Error for DetailedParseError {}
 
-        let expected_message = Message {
-            header: Header { id: 36321, q_type: 0, truncated: false, recursion_desired: true, question_count: 1, answer_count: 0, authority_count: 0, additional_count: 0 },
-            question: Question { domain_name: ["example".to_string(), "com".to_string()].into(), q_type: 1, q_class: 1 },
-            answers: None,
-        };
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    /// A name that breaks the limits on label and name lengths.
+    InvalidName(NameError),
+    /// A character-string longer than 255 bytes.
+    CharacterStringTooLong,
+    /// RDATA or an SvcParam value longer than 65535 bytes.
+    DataTooLong,
+    /// More than 65535 entries in a section.
+    TooManyRecords(Section),
+}
 
-        assert_eq!(expected_message.header, parsed_message.header);
-        assert_eq!(expected_message.question, parsed_message.question);
-        assert_eq!(expected_message.answers, parsed_message.answers);
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::InvalidName(error) => write!(f, "invalid name: {error}"),
+            EncodeError::CharacterStringTooLong => {
+                write!(f, "character-string longer than 255 bytes")
+            }
+            EncodeError::DataTooLong => write!(f, "data longer than 65535 bytes"),
+            EncodeError::TooManyRecords(section) => {
+                write!(f, "more than 65535 entries in the {section} section")
+            }
+        }
     }
+}
 
-    #[test]
-    fn test_parse_response() {
-        let buf = [141, 225, 129, 160, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1, 192, 12, 0, 1, 0, 1, 0, 1, 42, 15, 0, 4, 93, 184, 216, 34];
-        let parsed_message = parse_message(&buf);
+impl core::error::Error for EncodeError {}
 
-        let domain_name = vec!["example".to_string(), "com".to_string()];
-        let expected_message = Message {
-            header: Header { id: 36321, q_type: 0, truncated: false, recursion_desired: true, question_count: 1, answer_count: 1, authority_count: 0, additional_count: 0 },
-            question: Question { domain_name: domain_name.clone(), q_type: 1, q_class: 1 },
-            answers: Some(vec![ResourceRecord { name: domain_name, r#type: 1, class: 1, ttl: 76303, data_length: 4, data: ResourceData::A(Ipv4Addr::from([93, 184, 216, 34])) }]),
-        };
+/// Why [`parse_message_lenient`] left out the `record`th record of a section
+/// (and, if the record couldn't be delimited, the ones after it).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordError {
+    pub section: Section,
+    pub record: usize,
+    pub error: ParseError,
+}
 
-        assert_eq!(expected_message.header, parsed_message.header);
-        assert_eq!(expected_message.question, parsed_message.question);
-        assert_eq!(expected_message.answers, parsed_message.answers);
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} record {}: {}", self.section, self.record, self.error)
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Section {
+    Question,
+    Answer,
+    Authority,
+    Additional,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Section::Question => write!(f, "question"),
+            Section::Answer => write!(f, "answer"),
+            Section::Authority => write!(f, "authority"),
+            Section::Additional => write!(f, "additional"),
+        }
+    }
+}
+
+/// An inconsistency that doesn't keep a message from being parsed, but that
+/// a well-behaved implementation wouldn't produce.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Violation {
+    /// The header count for a section doesn't match its number of entries.
+    SectionCount {
+        section: Section,
+        header: u16,
+        actual: usize,
+    },
+    /// The `record`th record of a section has a `data_length` that differs
+    /// from what its RDATA decoded to.
+    DataLength {
+        section: Section,
+        record: usize,
+        data_length: u16,
+        consumed: usize,
+    },
+    /// Bytes left over after the last record of the message.
+    TrailingBytes(usize),
+    /// More than one OPT record, of which only the first is taken as the
+    /// message's EDNS.
+    MultipleOpt,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::SectionCount {
+                section,
+                header,
+                actual,
+            } => write!(
+                f,
+                "header announces {header} {section} entries, but there are {actual}"
+            ),
+            Violation::DataLength {
+                section,
+                record,
+                data_length,
+                consumed,
+            } => write!(
+                f,
+                "{section} record {record} has a data length of {data_length}, but its RDATA is {consumed} bytes"
+            ),
+            Violation::TrailingBytes(amt) => {
+                write!(f, "{amt} trailing bytes after the last record")
+            }
+            Violation::MultipleOpt => write!(f, "more than one OPT record"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Message {
+    header: Header,
+    questions: Vec<Question>,
+    answers: Vec<ResourceRecord>,
+    authorities: Vec<ResourceRecord>,
+    additionals: Vec<ResourceRecord>,
+    /// Taken out of the additional section, which the header count still
+    /// includes it in.
+    edns: Option<Edns>,
+    /// Problems noticed while parsing that only the wire format shows.
+    parse_violations: Vec<Violation>,
+}
+
+impl Message {
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn id(&self) -> u16 {
+        self.header.id
+    }
+
+    pub fn questions(&self) -> &[Question] {
+        &self.questions
+    }
+
+    pub fn answers(&self) -> &[ResourceRecord] {
+        &self.answers
+    }
+
+    pub fn authorities(&self) -> &[ResourceRecord] {
+        &self.authorities
+    }
+
+    pub fn additionals(&self) -> &[ResourceRecord] {
+        &self.additionals
+    }
+
+    pub fn edns(&self) -> Option<&Edns> {
+        self.edns.as_ref()
+    }
+
+    /// The addresses of the A answers, whatever their owner names.
+    pub fn a_records(&self) -> impl Iterator<Item = Ipv4Addr> + '_ {
+        self.answers.iter().filter_map(|answer| match answer.data {
+            ResourceData::A(addr) => Some(addr),
+            _ => None,
+        })
+    }
+
+    /// The addresses of the AAAA answers, whatever their owner names.
+    pub fn aaaa_records(&self) -> impl Iterator<Item = Ipv6Addr> + '_ {
+        self.answers.iter().filter_map(|answer| match answer.data {
+            ResourceData::Aaaa(addr) => Some(addr),
+            _ => None,
+        })
+    }
+
+    /// The text of each TXT answer, with its character-strings joined as
+    /// RFC 7208 does for SPF and invalid UTF-8 replaced.
+    pub fn txt_strings(&self) -> impl Iterator<Item = String> + '_ {
+        self.answers.iter().filter_map(|answer| match &answer.data {
+            ResourceData::Txt(strings) => {
+                Some(String::from_utf8_lossy(&strings.concat()).to_string())
+            }
+            _ => None,
+        })
+    }
+
+    /// The preference and exchange of each MX answer, in the order of the
+    /// answers rather than by preference.
+    pub fn mx_records(&self) -> impl Iterator<Item = (u16, &DomainName)> {
+        self.answers.iter().filter_map(|answer| match &answer.data {
+            ResourceData::Mx {
+                preference,
+                exchange,
+            } => Some((*preference, exchange)),
+            _ => None,
+        })
+    }
+
+    /// A response skeleton for `query`, with its ID, questions, opcode and
+    /// RD and CD bits echoed and recursion marked as available.
+    pub fn reply_to(query: &Message) -> Message {
+        Message {
+            header: Header {
+                id: query.header.id,
+                response: true,
+                opcode: query.header.opcode,
+                recursion_desired: query.header.recursion_desired,
+                recursion_available: true,
+                checking_disabled: query.header.checking_disabled,
+                question_count: query.header.question_count,
+                additional_count: query.edns.is_some() as u16,
+                ..Header::default()
+            },
+            questions: query.questions.clone(),
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            // RFC 6891 asks for EDNS in replies to EDNS queries, and RFC 3225
+            // for the DO bit to be copied
+            edns: query.edns.as_ref().map(|edns| Edns {
+                dnssec_ok: edns.dnssec_ok,
+                ..Edns::default()
+            }),
+            parse_violations: Vec::new(),
+        }
+    }
+
+    /// An ID from the system's secure random number generator, which makes
+    /// spoofed responses harder to get accepted (RFC 5452).
+    ///
+    /// Panics if the random number generator fails.
+    #[cfg(feature = "std")]
+    pub fn random_id() -> u16 {
+        let mut id = [0; 2];
+        getrandom::fill(&mut id).expect("the system's random number generator failed");
+        u16::from_be_bytes(id)
+    }
+
+    /// Whether this is a response to `query`, with the same ID and the same
+    /// questions, which may differ in case.
+    pub fn is_response_to(&self, query: &Message) -> bool {
+        self.header.response
+            && self.header.id == query.header.id
+            && self.questions == query.questions
+    }
+
+    pub fn answers_mut(&mut self) -> RecordsMut<'_> {
+        RecordsMut {
+            records: &mut self.answers,
+            count: &mut self.header.answer_count,
+            extra: 0,
+        }
+    }
+
+    pub fn authorities_mut(&mut self) -> RecordsMut<'_> {
+        RecordsMut {
+            records: &mut self.authorities,
+            count: &mut self.header.authority_count,
+            extra: 0,
+        }
+    }
+
+    pub fn additionals_mut(&mut self) -> RecordsMut<'_> {
+        RecordsMut {
+            records: &mut self.additionals,
+            count: &mut self.header.additional_count,
+            extra: self.edns.is_some() as usize,
+        }
+    }
+
+    pub fn push_answer(&mut self, record: ResourceRecord) {
+        self.answers_mut().push(record);
+    }
+
+    pub fn push_authority(&mut self, record: ResourceRecord) {
+        self.authorities_mut().push(record);
+    }
+
+    pub fn push_additional(&mut self, record: ResourceRecord) {
+        self.additionals_mut().push(record);
+    }
+
+    pub fn set_id(&mut self, id: u16) {
+        self.header.id = id;
+    }
+
+    /// Replaces the questions, keeping the question count in the header in
+    /// step.
+    pub fn set_questions(&mut self, questions: Vec<Question>) {
+        self.header.question_count = u16::try_from(questions.len()).unwrap_or(u16::MAX);
+        self.questions = questions;
+    }
+
+    /// Extended rcodes need EDNS, without it only the lower four bits are
+    /// encoded.
+    pub fn set_rcode(&mut self, rcode: Rcode) {
+        self.header.rcode = rcode;
+        if let Some(edns) = &mut self.edns {
+            edns.ext_rcode = (u16::from(rcode) >> 4) as u8;
+        }
+    }
+
+    /// Sets or removes the OPT record, keeping the additional count in the
+    /// header in step.
+    pub fn set_edns(&mut self, edns: Option<Edns>) {
+        let others = self
+            .header
+            .additional_count
+            .saturating_sub(self.edns.is_some() as u16);
+        self.header.additional_count = others.saturating_add(edns.is_some() as u16);
+        self.edns = edns.map(|edns| Edns {
+            ext_rcode: (u16::from(self.header.rcode) >> 4) as u8,
+            ..edns
+        });
+    }
+
+    /// Checks the message for inconsistencies such as section counts that
+    /// differ from the header, RDATA that doesn't fill its `data_length` or
+    /// bytes after the last record. An empty list means the message is
+    /// consistent.
+    pub fn validate(&self) -> Vec<Violation> {
+        let sections = [
+            (
+                Section::Question,
+                self.header.question_count,
+                self.questions.len(),
+            ),
+            (
+                Section::Answer,
+                self.header.answer_count,
+                self.answers.len(),
+            ),
+            (
+                Section::Authority,
+                self.header.authority_count,
+                self.authorities.len(),
+            ),
+            (
+                Section::Additional,
+                self.header.additional_count,
+                self.additionals.len() + self.edns.is_some() as usize,
+            ),
+        ];
+
+        let mut violations: Vec<_> = sections
+            .into_iter()
+            .filter(|(_, header, actual)| *header as usize != *actual)
+            .map(|(section, header, actual)| Violation::SectionCount {
+                section,
+                header,
+                actual,
+            })
+            .collect();
+        violations.extend(self.parse_violations.iter().cloned());
+
+        violations
+    }
+
+    /// Rewrites `qname` through the first DNAME answer whose owner it falls
+    /// under, or returns `None` if no DNAME applies. A rewritten name longer
+    /// than 255 bytes is [`NameError::NameTooLong`], which RFC 6672 2.2 has
+    /// answered with YXDOMAIN.
+    pub fn substitute_dname(&self, qname: &DomainName) -> Result<Option<DomainName>, NameError> {
+        self.answers
+            .iter()
+            .find_map(|answer| match &answer.data {
+                ResourceData::Dname(target) => dname_substitute(qname, &answer.name, target),
+                _ => None,
+            })
+            .transpose()
+    }
+
+    /// Follows the CNAME answers starting at the (first) question name and
+    /// returns the addresses of the A/AAAA records the chain ends in.
+    pub fn resolve_chain(&self) -> Vec<IpAddr> {
+        let answers = &self.answers;
+
+        let mut name = match self.questions.first() {
+            Some(question) => &question.domain_name,
+            None => return Vec::new(),
+        };
+        // a chain can have at most one hop per answer, which also stops loops
+        for _ in 0..=answers.len() {
+            let cname = answers.iter().find_map(|answer| match &answer.data {
+                ResourceData::Cname(target) if answer.name == *name => Some(target),
+                _ => None,
+            });
+
+            match cname {
+                Some(target) => name = target,
+                None => break,
+            }
+        }
+
+        answers
+            .iter()
+            .filter(|answer| answer.name == *name)
+            .filter_map(|answer| match answer.data {
+                ResourceData::A(addr) => Some(IpAddr::V4(addr)),
+                ResourceData::Aaaa(addr) => Some(IpAddr::V6(addr)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The records of one section, borrowed for editing. The section count in the
+/// header is updated to match once this is dropped.
+pub struct RecordsMut<'a> {
+    records: &'a mut Vec<ResourceRecord>,
+    count: &'a mut u16,
+    /// Entries counted in the header that aren't among the records, like
+    /// the OPT record.
+    extra: usize,
+}
+
+impl Deref for RecordsMut<'_> {
+    type Target = Vec<ResourceRecord>;
+
+    fn deref(&self) -> &Self::Target {
+        self.records
+    }
+}
+
+impl DerefMut for RecordsMut<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.records
+    }
+}
+
+impl Drop for RecordsMut<'_> {
+    fn drop(&mut self) {
+        // more records can't be encoded anyway
+        *self.count = u16::try_from(self.records.len() + self.extra).unwrap_or(u16::MAX);
+    }
+}
+
+/// Parses a message, tolerating the inconsistencies [`Message::validate`]
+/// reports.
+pub fn parse_message(buf: &[u8]) -> Result<Message, ParseError> {
+    parse_message_detailed(buf).map_err(|error| error.error)
+}
+
+/// Parses a message like [`parse_message`], with errors telling where in
+/// the message parsing failed.
+pub fn parse_message_detailed(buf: &[u8]) -> Result<Message, DetailedParseError> {
+    Parser::new(buf, false).parse().map(|(message, _)| message)
+}
+
+/// Parses a message, treating every inconsistency [`Message::validate`]
+/// would report as an error.
+pub fn parse_message_strict(buf: &[u8]) -> Result<Message, ParseError> {
+    let message = parse_message(buf)?;
+
+    match message.validate().into_iter().next() {
+        Some(violation) => Err(ParseError::Invalid(violation)),
+        None => Ok(message),
+    }
+}
+
+/// Parses as much of a message as possible. Records whose RDATA is
+/// malformed are left out, and a record that can't be delimited ends the
+/// message early, with the reasons returned next to the message. Only a
+/// broken header or question section fails the whole message.
+pub fn parse_message_lenient(buf: &[u8]) -> Result<(Message, Vec<RecordError>), ParseError> {
+    Parser::new(buf, true).parse().map_err(|error| error.error)
+}
+
+/// State shared by the section parsers of one message.
+struct Parser<'a> {
+    buf: Buffer<'a>,
+    lenient: bool,
+    violations: Vec<Violation>,
+    errors: Vec<RecordError>,
+    /// Set in lenient mode once a record couldn't be delimited, after which
+    /// nothing more can be parsed.
+    stopped: bool,
+    /// The question or record being parsed, for errors.
+    entry: Option<(Section, usize)>,
+    /// Where RDATA failed to parse, which `buf` has already moved past.
+    rdata_error: Option<usize>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(buf: &'a [u8], lenient: bool) -> Self {
+        Parser {
+            buf: Buffer::new(buf),
+            lenient,
+            violations: Vec::new(),
+            errors: Vec::new(),
+            stopped: false,
+            entry: None,
+            rdata_error: None,
+        }
+    }
+
+    fn parse(mut self) -> Result<(Message, Vec<RecordError>), DetailedParseError> {
+        let mut sections = || {
+            let header = parse_header(&mut self.buf)?;
+            let questions = self.questions(header.question_count)?;
+            let answers = self.resource_records(Section::Answer, header.answer_count)?;
+            let authorities = self.resource_records(Section::Authority, header.authority_count)?;
+            let additionals =
+                self.resource_records(Section::Additional, header.additional_count)?;
+            Ok((header, questions, answers, authorities, additionals))
+        };
+        let (mut header, questions, answers, authorities, mut additionals) =
+            sections().map_err(|error| DetailedParseError {
+                error,
+                offset: self.rdata_error.unwrap_or(self.buf.position()),
+                entry: self.entry,
+            })?;
+        let edns = take_edns(&mut header, &mut additionals, &mut self.violations);
+
+        let trailing = self.buf.rest().len();
+        if trailing > 0 && !self.stopped {
+            self.violations.push(Violation::TrailingBytes(trailing));
+        }
+
+        let message = Message {
+            header,
+            questions,
+            answers,
+            authorities,
+            additionals,
+            edns,
+            parse_violations: self.violations,
+        };
+        Ok((message, self.errors))
+    }
+
+    fn questions(&mut self, amt: u16) -> Result<Vec<Question>, ParseError> {
+        // a question takes at least 5 bytes, so a bogus count can't make this
+        // allocate much more than the message
+        let mut questions = Vec::with_capacity((amt as usize).min(self.buf.remaining() / 5));
+
+        for question in 0..amt as usize {
+            self.entry = Some((Section::Question, question));
+            let buf = &mut self.buf;
+            let domain_name = parse_name(buf)?;
+            let q_type = RecordType::from(buf.next_u16()?);
+            let q_class = Class::from(buf.next_u16()?);
+
+            questions.push(Question {
+                domain_name,
+                q_type,
+                q_class,
+            })
+        }
+
+        Ok(questions)
+    }
+
+    fn resource_records(
+        &mut self,
+        section: Section,
+        amt: u16,
+    ) -> Result<Vec<ResourceRecord>, ParseError> {
+        // a record takes at least 11 bytes
+        let mut resource_records =
+            Vec::with_capacity((amt as usize).min(self.buf.remaining() / 11));
+
+        for record in 0..amt as usize {
+            if self.stopped {
+                break;
+            }
+
+            self.entry = Some((section, record));
+            match self.resource_record(section, record) {
+                Ok(Some(resource_record)) => resource_records.push(resource_record),
+                Ok(None) => {}
+                Err(error) if self.lenient => {
+                    self.errors.push(RecordError {
+                        section,
+                        record,
+                        error,
+                    });
+                    self.stopped = true;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(resource_records)
+    }
+
+    /// Parses one record, or returns `None` if its RDATA is malformed and
+    /// parsing is lenient.
+    fn resource_record(
+        &mut self,
+        section: Section,
+        record: usize,
+    ) -> Result<Option<ResourceRecord>, ParseError> {
+        let buf = &mut self.buf;
+        let name = parse_name(buf)?;
+        let r#type = RecordType::from(buf.next_u16()?);
+        let class = Class::from(buf.next_u16()?);
+        let ttl = buf.next_u32()?;
+        let data_length = buf.next_u16()?;
+
+        // giving the RDATA its own buffer keeps the record parsers from
+        // reading into the next record
+        let start = buf.position();
+        let mut rdata = buf.split(data_length as usize)?;
+        let data = match parse_resource_data(r#type, &mut rdata) {
+            Ok(data) => data,
+            Err(error) if self.lenient => {
+                self.errors.push(RecordError {
+                    section,
+                    record,
+                    error,
+                });
+                return Ok(None);
+            }
+            Err(error) => {
+                self.rdata_error = Some(rdata.position());
+                return Err(error);
+            }
+        };
+        if !rdata.is_empty() {
+            self.violations.push(Violation::DataLength {
+                section,
+                record,
+                data_length,
+                consumed: rdata.position() - start,
+            });
+        }
+
+        Ok(Some(ResourceRecord {
+            name,
+            r#type,
+            class,
+            ttl,
+            data_length,
+            data,
+        }))
+    }
+}
+
+fn parse_header(buf: &mut Buffer) -> Result<Header, ParseError> {
+    let id = buf.next_u16()?;
+    let flags = buf.next_n(2)?;
+
+    Ok(Header {
+        id,
+        response: bits_set(&flags[0], 0x80),
+        opcode: Opcode::from((flags[0] >> 3) & 0b00001111),
+        authoritative: bits_set(&flags[0], 0x04),
+        truncated: bits_set(&flags[0], 0x02),
+        recursion_desired: bits_set(&flags[0], 0x01),
+        recursion_available: bits_set(&flags[1], 0x80),
+        z: bits_set(&flags[1], 0x40),
+        authentic_data: bits_set(&flags[1], 0x20),
+        checking_disabled: bits_set(&flags[1], 0x10),
+        rcode: Rcode::from((flags[1] & 0b00001111) as u16),
+        question_count: buf.next_u16()?,
+        answer_count: buf.next_u16()?,
+        authority_count: buf.next_u16()?,
+        additional_count: buf.next_u16()?,
+    })
+}
+
+fn parse_resource_data(r#type: RecordType, buf: &mut Buffer) -> Result<ResourceData, ParseError> {
+    let data = match r#type {
+        RecordType::A => {
+            let octets = buf.next_n(4)?;
+            ResourceData::A(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]))
+        }
+        RecordType::Ns => ResourceData::Ns(parse_name(buf)?),
+        RecordType::Cname => ResourceData::Cname(parse_name(buf)?),
+        RecordType::Soa => {
+            let mname = parse_name(buf)?;
+            let rname = parse_name(buf)?;
+
+            ResourceData::Soa(Soa {
+                mname,
+                rname,
+                serial: buf.next_u32()?,
+                refresh: buf.next_u32()?,
+                retry: buf.next_u32()?,
+                expire: buf.next_u32()?,
+                minimum: buf.next_u32()?,
+            })
+        }
+        RecordType::Ptr => ResourceData::Ptr(parse_name(buf)?),
+        RecordType::Hinfo => {
+            let cpu = parse_character_string(buf)?;
+            let os = parse_character_string(buf)?;
+
+            ResourceData::Hinfo { cpu, os }
+        }
+        RecordType::Mx => {
+            let preference = buf.next_u16()?;
+            let exchange = parse_name(buf)?;
+
+            ResourceData::Mx {
+                preference,
+                exchange,
+            }
+        }
+        RecordType::Txt => ResourceData::Txt(parse_character_strings(buf)?),
+        RecordType::Aaaa => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(buf.next_n(16)?);
+            ResourceData::Aaaa(Ipv6Addr::from(octets))
+        }
+        RecordType::Loc => ResourceData::Loc(Loc {
+            version: buf.next_u8()?,
+            size: buf.next_u8()?,
+            horizontal_precision: buf.next_u8()?,
+            vertical_precision: buf.next_u8()?,
+            latitude: buf.next_u32()?,
+            longitude: buf.next_u32()?,
+            altitude: buf.next_u32()?,
+        }),
+        RecordType::Srv => {
+            let priority = buf.next_u16()?;
+            let weight = buf.next_u16()?;
+            let port = buf.next_u16()?;
+            let target = parse_name(buf)?;
+
+            ResourceData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            }
+        }
+        RecordType::Naptr => {
+            let order = buf.next_u16()?;
+            let preference = buf.next_u16()?;
+            let flags = parse_character_string(buf)?;
+            let services = parse_character_string(buf)?;
+            let regexp = parse_character_string(buf)?;
+            let replacement = parse_name(buf)?;
+
+            ResourceData::Naptr {
+                order,
+                preference,
+                flags,
+                services,
+                regexp,
+                replacement,
+            }
+        }
+        RecordType::Dname => ResourceData::Dname(parse_name(buf)?),
+        RecordType::Ds => ResourceData::Ds(Ds {
+            key_tag: buf.next_u16()?,
+            algorithm: buf.next_u8()?,
+            digest_type: buf.next_u8()?,
+            digest: buf.rest().to_vec(),
+        }),
+        RecordType::Sshfp => ResourceData::Sshfp(Sshfp {
+            algorithm: buf.next_u8()?,
+            fingerprint_type: buf.next_u8()?,
+            fingerprint: buf.rest().to_vec(),
+        }),
+        RecordType::Rrsig | RecordType::Sig => {
+            let type_covered = RecordType::from(buf.next_u16()?);
+            let algorithm = buf.next_u8()?;
+            let labels = buf.next_u8()?;
+            let original_ttl = buf.next_u32()?;
+            let expiration = buf.next_u32()?;
+            let inception = buf.next_u32()?;
+            let key_tag = buf.next_u16()?;
+            let signer_name = parse_name(buf)?;
+
+            let rrsig = Rrsig {
+                type_covered,
+                algorithm,
+                labels,
+                original_ttl,
+                expiration,
+                inception,
+                key_tag,
+                signer_name,
+                signature: buf.rest().to_vec(),
+            };
+
+            if r#type == RecordType::Sig {
+                ResourceData::Sig(rrsig)
+            } else {
+                ResourceData::Rrsig(rrsig)
+            }
+        }
+        RecordType::Nsec => {
+            let next_domain_name = parse_name(buf)?;
+
+            ResourceData::Nsec(Nsec {
+                next_domain_name,
+                types: parse_type_bitmaps(buf)?,
+            })
+        }
+        RecordType::Dnskey => ResourceData::Dnskey(Dnskey {
+            flags: buf.next_u16()?,
+            protocol: buf.next_u8()?,
+            algorithm: buf.next_u8()?,
+            public_key: buf.rest().to_vec(),
+        }),
+        RecordType::Nsec3 => {
+            let hash_algorithm = buf.next_u8()?;
+            let flags = buf.next_u8()?;
+            let iterations = buf.next_u16()?;
+            let salt = parse_character_string(buf)?;
+            let next_hashed_owner = parse_character_string(buf)?;
+
+            ResourceData::Nsec3(Nsec3 {
+                hash_algorithm,
+                flags,
+                iterations,
+                salt,
+                next_hashed_owner,
+                types: parse_type_bitmaps(buf)?,
+            })
+        }
+        RecordType::Tlsa | RecordType::Smimea => {
+            let tlsa = Tlsa {
+                usage: buf.next_u8()?,
+                selector: buf.next_u8()?,
+                matching_type: buf.next_u8()?,
+                data: buf.rest().to_vec(),
+            };
+
+            if r#type == RecordType::Tlsa {
+                ResourceData::Tlsa(tlsa)
+            } else {
+                ResourceData::Smimea(tlsa)
+            }
+        }
+        RecordType::Openpgpkey => ResourceData::Openpgpkey(OpenPgpKey(buf.rest().to_vec())),
+        RecordType::Svcb | RecordType::Https => {
+            let priority = buf.next_u16()?;
+            let target = parse_name(buf)?;
+            let svcb = Svcb {
+                priority,
+                target,
+                params: parse_svc_params(buf)?,
+            };
+
+            if r#type == RecordType::Svcb {
+                ResourceData::Svcb(svcb)
+            } else {
+                ResourceData::Https(svcb)
+            }
+        }
+        RecordType::Opt => ResourceData::Opt(parse_edns_options(buf)?),
+        RecordType::Uri => ResourceData::Uri {
+            priority: buf.next_u16()?,
+            weight: buf.next_u16()?,
+            target: String::from_utf8_lossy(buf.rest()).to_string(),
+        },
+        RecordType::Caa => {
+            let flags = buf.next_u8()?;
+            let tag_length = buf.next_u8()? as usize;
+            let tag = String::from_utf8_lossy(buf.next_n(tag_length)?).to_string();
+
+            ResourceData::Caa {
+                flags,
+                tag,
+                value: buf.rest().to_vec(),
+            }
+        }
+        RecordType::Tsig => {
+            let algorithm = parse_name(buf)?;
+            let time_signed = (buf.next_u16()? as u64) << 32 | buf.next_u32()? as u64;
+            let fudge = buf.next_u16()?;
+            let mac_length = buf.next_u16()? as usize;
+            let mac = buf.next_n(mac_length)?.to_vec();
+            let original_id = buf.next_u16()?;
+            let error = Rcode::from(buf.next_u16()?);
+            let other_length = buf.next_u16()? as usize;
+
+            ResourceData::Tsig(Tsig {
+                algorithm,
+                time_signed,
+                fudge,
+                mac,
+                original_id,
+                error,
+                other: buf.next_n(other_length)?.to_vec(),
+            })
+        }
+        _ => ResourceData::Unknown {
+            r#type,
+            data: GenericData(buf.rest().to_vec()),
+        },
+    };
+
+    Ok(data)
+}
+
+fn parse_character_strings(buf: &mut Buffer) -> Result<Vec<Vec<u8>>, ParseError> {
+    let mut strings = Vec::new();
+
+    while !buf.is_empty() {
+        strings.push(parse_character_string(buf)?);
+    }
+
+    Ok(strings)
+}
+
+fn parse_character_string(buf: &mut Buffer) -> Result<Vec<u8>, ParseError> {
+    let length = buf.next_u8()? as usize;
+
+    Ok(buf.next_n(length)?.to_vec())
+}
+
+/// Decodes the window blocks used by NSEC and NSEC3 into the listed types.
+fn parse_type_bitmaps(buf: &mut Buffer) -> Result<Vec<RecordType>, ParseError> {
+    let mut types = Vec::new();
+
+    while !buf.is_empty() {
+        let window = buf.next_u8()? as u16;
+        let length = buf.next_u8()? as usize;
+
+        for (byte_index, byte) in buf.next_n(length)?.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0b10000000 >> bit) != 0 {
+                    types.push(RecordType::from(
+                        window << 8 | (byte_index * 8 + bit) as u16,
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(types)
+}
+
+fn parse_svc_params(buf: &mut Buffer) -> Result<Vec<SvcParam>, ParseError> {
+    let mut params = Vec::new();
+
+    while !buf.is_empty() {
+        let key = buf.next_u16()?;
+        let length = buf.next_u16()? as usize;
+        let mut value = buf.split(length)?;
+        // RFC 9460 has values of the wrong length rejected as malformed
+        let invalid = ParseError::InvalidSvcParam(key);
+
+        let param = match key {
+            0 => SvcParam::Mandatory(
+                svc_param_items(value.rest())
+                    .ok_or(invalid)?
+                    .map(u16::from_be_bytes)
+                    .collect(),
+            ),
+            1 => SvcParam::Alpn(parse_character_strings(&mut value)?),
+            2 if value.is_empty() => SvcParam::NoDefaultAlpn,
+            2 => return Err(invalid),
+            3 => SvcParam::Port(u16::from_be_bytes(
+                value.rest().try_into().map_err(|_| invalid)?,
+            )),
+            4 => SvcParam::Ipv4Hint(
+                svc_param_items(value.rest())
+                    .ok_or(invalid)?
+                    .map(Ipv4Addr::from)
+                    .collect(),
+            ),
+            5 => SvcParam::Ech(value.rest().to_vec()),
+            6 => SvcParam::Ipv6Hint(
+                svc_param_items(value.rest())
+                    .ok_or(invalid)?
+                    .map(Ipv6Addr::from)
+                    .collect(),
+            ),
+            _ => SvcParam::Unknown {
+                key,
+                value: value.rest().to_vec(),
+            },
+        };
+        params.push(param);
+    }
+
+    Ok(params)
+}
+
+/// A list value of an SvcParam split into its items of `N` bytes, or `None`
+/// if it doesn't end with a whole one.
+fn svc_param_items<const N: usize>(value: &[u8]) -> Option<impl Iterator<Item = [u8; N]> + '_> {
+    value.len().is_multiple_of(N).then(|| {
+        value
+            .chunks_exact(N)
+            .map(|item| item.try_into().expect("chunks are N bytes"))
+    })
+}
+
+/// Decodes a possibly compressed name by following compression pointers
+/// through the message.
+fn parse_name(buf: &mut Buffer) -> Result<DomainName, ParseError> {
+    let mut name = DomainName::root();
+    walk_name(buf, |label| name.push_label(label))?;
+
+    Ok(name)
+}
+
+/// The most compression pointers followed for one name. A name has at most
+/// 127 labels, so any more pointers would have to point at pointers, which
+/// only serves to make decoding slow: a long chain of them could otherwise
+/// be followed from every record of a message.
+const MAX_POINTERS: usize = 127;
+
+/// Passes the labels of a possibly compressed name to `label`, leaving `buf`
+/// after the name. Every pointer has to point further back than the label it
+/// replaces, so the jumps can't loop or read ahead into data that hasn't been
+/// validated yet, and at most [`MAX_POINTERS`] are followed.
+fn walk_name<'a>(buf: &mut Buffer<'a>, mut label: impl FnMut(&'a [u8])) -> Result<(), ParseError> {
+    let mut wire_length = 0;
+    let mut pointers = 0;
+    // decoding continues elsewhere after a pointer, while the name itself
+    // ends right after the first one
+    let mut reader = *buf;
+    let mut end = None;
+    let mut limit = buf.position();
+
+    loop {
+        let length = reader.next_u8()?;
+        if length == 0 {
+            break;
+        }
+
+        // pointer to another name
+        if bits_set(&length, 0b11000000) {
+            let target = u16::from_be_bytes([length & 0b00111111, reader.next_u8()?]) as usize;
+            pointers += 1;
+            if target >= limit || target < HEADER_LENGTH || pointers > MAX_POINTERS {
+                return Err(ParseError::BadPointer);
+            }
+
+            end.get_or_insert(reader.position());
+            limit = target;
+            reader.seek(target)?;
+            continue;
+        }
+        // the 0b01 and 0b10 prefixes are reserved
+        if length & 0b11000000 != 0 {
+            return Err(ParseError::BadLabel);
+        }
+
+        let bytes = reader.next_n(length as usize)?;
+        label(bytes);
+        // the limit includes the length bytes and the root label
+        wire_length += bytes.len() + 1;
+        if wire_length + 1 > 255 {
+            return Err(ParseError::InvalidName(NameError::NameTooLong));
+        }
+    }
+
+    buf.seek(end.unwrap_or(reader.position()))
+}
+
+/// Replaces the `owner` suffix of `qname` with `target`. Per RFC 6672 the
+/// owner itself is not redirected, only names strictly below it.
+fn dname_substitute(
+    qname: &DomainName,
+    owner: &DomainName,
+    target: &DomainName,
+) -> Option<Result<DomainName, NameError>> {
+    let prefix_length = qname.labels().len().checked_sub(owner.labels().len())?;
+    let below_owner = qname
+        .labels()
+        .skip(prefix_length)
+        .zip(owner.labels())
+        .all(|(label, owner_label)| label.eq_ignore_ascii_case(owner_label));
+    if prefix_length == 0 || !below_owner {
+        return None;
+    }
+
+    let labels = qname.labels().take(prefix_length).chain(target.labels());
+    let name = DomainName::from_labels(labels);
+    Some(name.check().map(|()| name))
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    bytes.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+}
+
+fn write_base64(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    for chunk in bytes.chunks(3) {
+        let group = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let sextet = (group >> (18 - 6 * i)) & 0b111111;
+                write!(f, "{}", ALPHABET[sextet as usize] as char)?;
+            } else {
+                write!(f, "=")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn bits_set(byte: &u8, bit_pos: u8) -> bool {
+    byte & bit_pos == bit_pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str) -> DomainName {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_question() {
+        let buf = [141, 225, 1, 32, 0, 1, 0, 0, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let expected_message = Message {
+            header: Header { id: 36321, response: false, opcode: Opcode::Query, authoritative: false, truncated: false, recursion_desired: true, recursion_available: false, z: false, authentic_data: true, checking_disabled: false, rcode: Rcode::NoError, question_count: 1, answer_count: 0, authority_count: 0, additional_count: 0 },
+            questions: vec![Question { domain_name: name("example.com"), q_type: RecordType::A, q_class: Class::In }],
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: None,
+            parse_violations: Vec::new(),
+        };
+
+        assert_eq!(expected_message.header, parsed_message.header);
+        assert_eq!(expected_message.questions, parsed_message.questions);
+        assert_eq!(expected_message.answers, parsed_message.answers);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let buf = [141, 225, 129, 160, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1, 192, 12, 0, 1, 0, 1, 0, 1, 42, 15, 0, 4, 93, 184, 216, 34];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let domain_name = name("example.com");
+        let expected_message = Message {
+            header: Header { id: 36321, response: true, opcode: Opcode::Query, authoritative: false, truncated: false, recursion_desired: true, recursion_available: true, z: false, authentic_data: true, checking_disabled: false, rcode: Rcode::NoError, question_count: 1, answer_count: 1, authority_count: 0, additional_count: 0 },
+            questions: vec![Question { domain_name: domain_name.clone(), q_type: RecordType::A, q_class: Class::In }],
+            answers: vec![ResourceRecord { name: domain_name, r#type: RecordType::A, class: Class::In, ttl: 76303, data_length: 4, data: ResourceData::A(Ipv4Addr::from([93, 184, 216, 34])) }],
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: None,
+            parse_violations: Vec::new(),
+        };
+
+        assert_eq!(expected_message.header, parsed_message.header);
+        assert_eq!(expected_message.questions, parsed_message.questions);
+        assert_eq!(expected_message.answers, parsed_message.answers);
+    }
+
+    #[test]
+    fn test_parse_aaaa_response() {
+        let buf = [141, 225, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 28, 0, 1, 192, 12, 0, 28, 0, 1, 0, 0, 14, 16, 0, 16, 38, 6, 40, 0, 2, 32, 0, 1, 2, 72, 24, 147, 37, 200, 25, 70];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let expected_answers = vec![ResourceRecord { name: name("example.com"), r#type: RecordType::Aaaa, class: Class::In, ttl: 3600, data_length: 16, data: ResourceData::Aaaa("2606:2800:220:1:248:1893:25c8:1946".parse().unwrap()) }];
+
+        assert_eq!(expected_answers, parsed_message.answers);
+    }
+
+    #[test]
+    fn test_resolve_cname_chain() {
+        let buf = [0, 7, 129, 128, 0, 1, 0, 2, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1, 192, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 6, 3, 99, 100, 110, 192, 12, 192, 41, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 1];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let cname = &parsed_message.answers[0];
+        assert_eq!(ResourceData::Cname(name("cdn.example.com")), cname.data);
+        assert_eq!(vec![IpAddr::from([192, 0, 2, 1])], parsed_message.resolve_chain());
+    }
+
+    #[test]
+    fn test_parse_mx_response() {
+        let buf = [0, 9, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 15, 0, 1, 192, 12, 0, 15, 0, 1, 0, 0, 1, 44, 0, 9, 0, 10, 4, 109, 97, 105, 108, 192, 12];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let mx = &parsed_message.answers[0];
+        assert_eq!(ResourceData::Mx { preference: 10, exchange: name("mail.example.com") }, mx.data);
+    }
+
+    #[test]
+    fn test_parse_ns_response() {
+        let buf = [0, 2, 129, 128, 0, 1, 0, 2, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 2, 0, 1, 192, 12, 0, 2, 0, 1, 0, 1, 81, 128, 0, 6, 3, 110, 115, 49, 192, 12, 192, 12, 0, 2, 0, 1, 0, 1, 81, 128, 0, 6, 3, 110, 115, 50, 192, 12];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let answers = parsed_message.answers;
+        assert_eq!(ResourceData::Ns(name("ns1.example.com")), answers[0].data);
+        assert_eq!(ResourceData::Ns(name("ns2.example.com")), answers[1].data);
+    }
+
+    #[test]
+    fn test_parse_txt_response() {
+        let buf = [0, 16, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 16, 0, 1, 192, 12, 0, 16, 0, 1, 0, 0, 0, 60, 0, 14, 8, 118, 61, 115, 112, 102, 49, 32, 45, 0, 3, 104, 105, 255];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let txt = &parsed_message.answers[0].data;
+        assert_eq!(&ResourceData::Txt(vec![b"v=spf1 -".to_vec(), vec![], vec![104, 105, 255]]), txt);
+        assert_eq!(Some(vec!["v=spf1 -".to_string(), String::new(), "hi\u{FFFD}".to_string()]), txt.txt_lossy());
+    }
+
+    #[test]
+    fn test_parse_soa_response() {
+        let buf = [0, 6, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 6, 0, 1, 192, 12, 0, 6, 0, 1, 0, 0, 14, 16, 0, 39, 3, 110, 115, 49, 192, 12, 10, 104, 111, 115, 116, 109, 97, 115, 116, 101, 114, 192, 12, 120, 73, 175, 1, 0, 0, 28, 32, 0, 0, 14, 16, 0, 18, 117, 0, 0, 0, 14, 16];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let expected_soa = Soa {
+            mname: name("ns1.example.com"),
+            rname: name("hostmaster.example.com"),
+            serial: 2018094849,
+            refresh: 7200,
+            retry: 3600,
+            expire: 1209600,
+            minimum: 3600,
+        };
+        assert_eq!(ResourceData::Soa(expected_soa), parsed_message.answers[0].data);
+    }
+
+    #[test]
+    fn test_parse_ptr_response() {
+        let buf = [0, 12, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 1, 49, 1, 50, 1, 48, 3, 49, 57, 50, 7, 105, 110, 45, 97, 100, 100, 114, 4, 97, 114, 112, 97, 0, 0, 12, 0, 1, 192, 12, 0, 12, 0, 1, 0, 0, 14, 16, 0, 14, 4, 104, 111, 115, 116, 7, 101, 120, 97, 109, 112, 108, 101, 0];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let ptr = &parsed_message.answers[0];
+        assert_eq!(name("1.2.0.192.in-addr.arpa"), ptr.name);
+        assert_eq!(ResourceData::Ptr(name("host.example")), ptr.data);
+    }
+
+    #[test]
+    fn test_parse_srv_response() {
+        let buf = [0, 33, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 5, 95, 120, 109, 112, 112, 4, 95, 116, 99, 112, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 33, 0, 1, 192, 12, 0, 33, 0, 1, 0, 0, 1, 44, 0, 24, 0, 5, 0, 10, 20, 102, 4, 120, 109, 112, 112, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let srv = &parsed_message.answers[0];
+        assert_eq!(ResourceData::Srv { priority: 5, weight: 10, port: 5222, target: name("xmpp.example.com") }, srv.data);
+    }
+
+    #[test]
+    fn test_parse_caa_response() {
+        let buf = [1, 1, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 1, 1, 0, 1, 192, 12, 1, 1, 0, 1, 0, 0, 14, 16, 0, 22, 128, 5, 105, 115, 115, 117, 101, 108, 101, 116, 115, 101, 110, 99, 114, 121, 112, 116, 46, 111, 114, 103];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let caa = &parsed_message.answers[0];
+        assert_eq!(ResourceData::Caa { flags: 128, tag: "issue".to_string(), value: b"letsencrypt.org".to_vec() }, caa.data);
+    }
+
+    #[test]
+    fn test_parse_https_response() {
+        let buf = [0, 65, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 65, 0, 1, 192, 12, 0, 65, 0, 1, 0, 0, 1, 44, 0, 37, 0, 1, 0, 0, 1, 0, 6, 2, 104, 51, 2, 104, 50, 0, 3, 0, 2, 1, 187, 0, 4, 0, 8, 192, 0, 2, 1, 192, 0, 2, 2, 0, 5, 0, 2, 170, 187];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let expected_https = Svcb {
+            priority: 1,
+            target: DomainName::root(),
+            params: vec![
+                SvcParam::Alpn(vec![b"h3".to_vec(), b"h2".to_vec()]),
+                SvcParam::Port(443),
+                SvcParam::Ipv4Hint(vec![Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(192, 0, 2, 2)]),
+                SvcParam::Ech(vec![170, 187]),
+            ],
+        };
+        assert_eq!(ResourceData::Https(expected_https), parsed_message.answers[0].data);
+    }
+
+    #[test]
+    fn test_parse_naptr_response() {
+        let buf = [0, 35, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 35, 0, 1, 192, 12, 0, 35, 0, 1, 0, 0, 1, 44, 0, 34, 0, 100, 0, 10, 1, 117, 7, 69, 50, 85, 43, 115, 105, 112, 18, 33, 94, 46, 42, 36, 33, 115, 105, 112, 58, 105, 110, 102, 111, 64, 101, 120, 33, 0];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let naptr = &parsed_message.answers[0];
+        assert_eq!(ResourceData::Naptr { order: 100, preference: 10, flags: b"u".to_vec(), services: b"E2U+sip".to_vec(), regexp: b"!^.*$!sip:info@ex!".to_vec(), replacement: DomainName::root() }, naptr.data);
+    }
+
+    #[test]
+    fn test_parse_tlsa_response() {
+        let buf = [0, 52, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 4, 95, 52, 52, 51, 4, 95, 116, 99, 112, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 52, 0, 1, 192, 12, 0, 52, 0, 1, 0, 0, 1, 44, 0, 7, 3, 1, 1, 222, 173, 190, 239];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let tlsa = Tlsa { usage: 3, selector: 1, matching_type: 1, data: vec![222, 173, 190, 239] };
+        assert_eq!("3 1 1 deadbeef", tlsa.to_string());
+        assert_eq!(ResourceData::Tlsa(tlsa), parsed_message.answers[0].data);
+    }
+
+    #[test]
+    fn test_parse_sshfp_response() {
+        let buf = [0, 44, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 4, 104, 111, 115, 116, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 44, 0, 1, 192, 12, 0, 44, 0, 1, 0, 0, 1, 44, 0, 6, 4, 2, 1, 35, 69, 103];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let sshfp = Sshfp { algorithm: 4, fingerprint_type: 2, fingerprint: vec![1, 35, 69, 103] };
+        assert_eq!("4 2 01234567", sshfp.to_string());
+        assert_eq!(ResourceData::Sshfp(sshfp), parsed_message.answers[0].data);
+    }
+
+    #[test]
+    fn test_parse_nsec_response() {
+        let buf = [0, 47, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 47, 0, 1, 192, 12, 0, 47, 0, 1, 0, 0, 14, 16, 0, 18, 3, 119, 119, 119, 192, 12, 0, 6, 98, 0, 0, 0, 0, 3, 1, 2, 129, 64];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let expected_nsec = Nsec { next_domain_name: name("www.example.com"), types: [1, 2, 6, 46, 47, 256, 263, 265].into_iter().map(RecordType::from).collect() };
+        assert_eq!(ResourceData::Nsec(expected_nsec), parsed_message.answers[0].data);
+    }
+
+    #[test]
+    fn test_parse_rrsig_response() {
+        let buf = [0, 46, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1, 192, 12, 0, 46, 0, 1, 0, 0, 14, 16, 0, 24, 0, 1, 13, 2, 0, 0, 14, 16, 101, 0, 0, 0, 100, 0, 0, 0, 48, 57, 192, 12, 1, 2, 3, 4];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let expected_rrsig = Rrsig { type_covered: RecordType::A, algorithm: 13, labels: 2, original_ttl: 3600, expiration: 1694498816, inception: 1677721600, key_tag: 12345, signer_name: name("example.com"), signature: vec![1, 2, 3, 4] };
+        assert_eq!(ResourceData::Rrsig(expected_rrsig), parsed_message.answers[0].data);
+    }
+
+    #[test]
+    fn test_record_wire_format() {
+        let record = ResourceRecord::new(name("example.com"), Class::In, 300, ResourceData::Mx { preference: 10, exchange: name("mail.example.com") });
+        let wire = record.to_wire().unwrap();
+        assert_eq!(13 + 10 + 2 + 18, wire.len());
+        assert_eq!(Ok(record), ResourceRecord::from_wire(&wire));
+        assert_eq!(Err(ParseError::Invalid(Violation::TrailingBytes(1))), ResourceRecord::from_wire(&[&wire[..], &[0]].concat()));
+        assert_eq!(Err(ParseError::UnexpectedEof), ResourceRecord::from_wire(&wire[..20]));
+    }
+
+    #[test]
+    fn test_key_tag() {
+        // the key from RFC 4034 5.4
+        let public_key = vec![1, 3, 158, 138, 36, 116, 24, 227, 24, 144, 59, 33, 90, 132, 138, 207, 213, 243, 127, 2, 107, 212, 6, 45, 178, 108, 119, 76, 105, 9, 104, 213, 213, 109, 248, 191, 218, 145, 230, 243, 109, 154, 39, 152, 136, 244, 19, 51, 53, 124, 94, 96, 41, 153, 13, 16, 253, 245, 102, 48, 98, 165, 18, 118, 51, 38, 152, 10, 97, 93, 219, 241, 122, 5, 221, 252, 206, 126, 95, 179, 171, 204, 160, 90, 49, 176, 149, 116, 82, 212, 82, 30, 131, 135, 7, 137, 6, 49, 21, 191, 151, 246, 195, 8, 204, 245, 124, 220, 156, 231, 254, 16, 246, 237, 27, 208, 204, 6, 96, 3, 140, 80, 220, 219, 15, 235, 150, 60, 47, 23];
+        assert_eq!(60485, Dnskey { flags: 256, protocol: 3, algorithm: 5, public_key }.key_tag());
+    }
+
+    #[test]
+    fn test_dname_substitution() {
+        let buf = [0, 39, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 3, 119, 119, 119, 3, 111, 108, 100, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 1, 0, 1, 3, 111, 108, 100, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 39, 0, 1, 0, 0, 14, 16, 0, 13, 3, 110, 101, 119, 7, 101, 120, 97, 109, 112, 108, 101, 0];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let qname = parsed_message.questions[0].domain_name.clone();
+        assert_eq!(Ok(Some(name("www.new.example"))), parsed_message.substitute_dname(&qname));
+        assert_eq!(Ok(None), parsed_message.substitute_dname(&name("old.example")));
+        assert_eq!(Ok(None), parsed_message.substitute_dname(&name("www.example")));
+
+        // the target leaves room for one short label, not for a long one too
+        let target = vec!["a".repeat(63); 3].join(".");
+        let mut message = parsed_message.clone();
+        message.answers[0].data = ResourceData::Dname(name(&target));
+        assert_eq!(Ok(Some(name(&format!("b.{target}")))), message.substitute_dname(&name("b.old.example")));
+        let qname = name(&format!("{}.b.old.example", "b".repeat(63)));
+        assert_eq!(Err(NameError::NameTooLong), message.substitute_dname(&qname));
+    }
+
+    #[test]
+    fn test_parse_loc_response() {
+        // 52 22 23.000 N 4 53 32.000 E -2.00m 1m 10000m 10m
+        let buf = [0, 29, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 29, 0, 1, 192, 12, 0, 29, 0, 1, 0, 0, 14, 16, 0, 16, 0, 18, 22, 19, 139, 60, 240, 24, 129, 12, 188, 224, 0, 152, 149, 184];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let loc = match &parsed_message.answers[0].data {
+            ResourceData::Loc(loc) => loc,
+            data => panic!("expected LOC, got {data:?}"),
+        };
+        assert!((loc.latitude_degrees() - 52.373055).abs() < 1e-6);
+        assert!((loc.longitude_degrees() - 4.892222).abs() < 1e-6);
+        assert_eq!(-2.0, loc.altitude_meters());
+        assert_eq!(1.0, loc.size_meters());
+        assert_eq!(10000.0, loc.horizontal_precision_meters());
+        assert_eq!(10.0, loc.vertical_precision_meters());
+    }
+
+    #[test]
+    fn test_parse_hinfo_response() {
+        let buf = [0, 13, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 13, 0, 1, 192, 12, 0, 13, 0, 1, 0, 0, 14, 16, 0, 11, 5, 65, 82, 77, 54, 52, 4, 82, 73, 83, 67];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        assert_eq!(ResourceData::Hinfo { cpu: b"ARM64".to_vec(), os: b"RISC".to_vec() }, parsed_message.answers[0].data);
+    }
+
+    #[test]
+    fn test_parse_uri_response() {
+        let buf = [1, 0, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 4, 95, 102, 116, 112, 4, 95, 116, 99, 112, 7, 101, 120, 97, 109, 112, 108, 101, 0, 1, 0, 0, 1, 192, 12, 1, 0, 0, 1, 0, 0, 14, 16, 0, 25, 0, 10, 0, 1, 102, 116, 112, 58, 47, 47, 102, 116, 112, 46, 101, 120, 97, 109, 112, 108, 101, 47, 112, 117, 98];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        assert_eq!(ResourceData::Uri { priority: 10, weight: 1, target: "ftp://ftp.example/pub".to_string() }, parsed_message.answers[0].data);
+    }
+
+    #[test]
+    fn test_parse_openpgpkey_response() {
+        let buf = [0, 61, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 4, 117, 115, 101, 114, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 61, 0, 1, 192, 12, 0, 61, 0, 1, 0, 0, 14, 16, 0, 5, 104, 101, 108, 108, 111];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let key = OpenPgpKey(b"hello".to_vec());
+        assert_eq!("aGVsbG8=", key.to_string());
+        assert_eq!("", OpenPgpKey(vec![]).to_string());
+        assert_eq!("AAEC", OpenPgpKey(vec![0, 1, 2]).to_string());
+        assert_eq!(ResourceData::Openpgpkey(key), parsed_message.answers[0].data);
+    }
+
+    #[test]
+    fn test_parse_unknown_record() {
+        let buf = [0, 99, 129, 128, 0, 1, 0, 2, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 255, 0, 1, 192, 12, 255, 0, 0, 1, 0, 0, 14, 16, 0, 3, 1, 2, 3, 192, 12, 0, 1, 0, 1, 0, 0, 14, 16, 0, 4, 192, 0, 2, 1];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let answers = parsed_message.answers;
+        assert_eq!(ResourceData::Unknown { r#type: RecordType::Unknown(65280), data: GenericData(vec![1, 2, 3]) }, answers[0].data);
+        assert_eq!(ResourceData::A(Ipv4Addr::new(192, 0, 2, 1)), answers[1].data);
+    }
+
+    #[test]
+    fn test_generic_data_presentation() {
+        let data = GenericData(vec![10, 0, 0, 1]);
+        assert_eq!("\\# 4 0a000001", data.to_string());
+        assert_eq!("\\# 0", GenericData(vec![]).to_string());
+
+        assert_eq!(Ok(data), "\\# 4 0A00 0001".parse());
+        assert_eq!(Ok(GenericData(vec![])), "\\# 0".parse());
+        assert_eq!(Err(InvalidGenericData), "\\# 3 0a000001".parse::<GenericData>());
+        assert_eq!(Err(InvalidGenericData), "# 4 0a000001".parse::<GenericData>());
+        assert_eq!(Err(InvalidGenericData), "\\# 1 0g".parse::<GenericData>());
+    }
+
+    #[test]
+    fn test_parse_malformed_messages() {
+        let query = [141, 225, 1, 32, 0, 1, 0, 0, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1];
+        for length in 0..query.len() {
+            assert_eq!(Err(ParseError::UnexpectedEof), parse_message(&query[..length]));
+        }
+
+
+        let mut bad_label = query;
+        bad_label[12] = 0b01000111;
+        assert_eq!(Err(ParseError::BadLabel), parse_message(&bad_label));
+
+        let bad_pointer = [0, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 192, 12, 0, 1, 0, 1];
+        assert_eq!(Err(ParseError::BadPointer), parse_message(&bad_pointer));
+
+        // a record with a chain of pointers to pointers in its RDATA, and one
+        // owned by a pointer to the end of the chain
+        let pointer_chain = |pointers: u16| {
+            let mut message = vec![0, 1, 129, 128, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 10, 0, 1, 0, 0, 0, 0];
+            message.extend((2 * pointers - 1).to_be_bytes());
+            message.push(0);
+            for target in (0..pointers).map(|i| if i == 0 { 23 } else { 24 + 2 * (i - 1) }) {
+                message.extend((0xc000 | target).to_be_bytes());
+            }
+            message.extend([0, 10, 0, 1, 0, 0, 0, 0, 0, 0]);
+            message
+        };
+        assert!(parse_message(&pointer_chain(MAX_POINTERS as u16)).is_ok());
+        assert_eq!(Err(ParseError::BadPointer), parse_message(&pointer_chain(MAX_POINTERS as u16 + 1)));
+
+        // an A record claiming more RDATA than the message holds
+        let short_rdata = [0, 1, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 1, 2];
+        assert_eq!(Err(ParseError::UnexpectedEof), parse_message(&short_rdata));
+        // and one with too little RDATA for an address
+        let truncated_a = [0, 1, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 1, 0, 1, 0, 0, 0, 60, 0, 2, 1, 2];
+        assert_eq!(Err(ParseError::UnexpectedEof), parse_message(&truncated_a));
+
+        // HTTPS records with SvcParam values of the wrong length for their key
+        let https = |params: &[u8]| {
+            let mut message = vec![0, 1, 129, 128, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 65, 0, 1, 0, 0, 0, 60];
+            message.extend((params.len() as u16 + 3).to_be_bytes());
+            message.extend([0, 1, 0]);
+            message.extend(params);
+            message
+        };
+        assert!(parse_message(&https(&[0, 0, 0, 2, 0, 3, 0, 2, 0, 0, 0, 3, 0, 2, 1, 187, 0, 4, 0, 4, 192, 0, 2, 1])).is_ok());
+        for (key, params) in [(0, &[0, 0, 0, 3, 0, 3, 0][..]), (2, &[0, 2, 0, 1, 0]), (3, &[0, 3, 0, 1, 1]), (3, &[0, 3, 0, 3, 1, 187, 0]), (4, &[0, 4, 0, 5, 192, 0, 2, 1, 1]), (6, &[0, 6, 0, 4, 32, 1, 13, 184])] {
+            assert_eq!(Err(ParseError::InvalidSvcParam(key)), parse_message(&https(params)), "{params:?}");
+        }
+    }
+
+    #[test]
+    fn test_detailed_parse_errors() {
+        let bad_pointer = [0, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 192, 12, 0, 1, 0, 1];
+        let error = parse_message_detailed(&bad_pointer).unwrap_err();
+        assert_eq!(DetailedParseError { error: ParseError::BadPointer, offset: 12, entry: Some((Section::Question, 0)) }, error);
+        assert_eq!("question 0 at offset 12: invalid compression pointer", error.to_string());
+
+        assert_eq!("header at offset 4: message ended unexpectedly", parse_message_detailed(&bad_pointer[..5]).unwrap_err().to_string());
+
+        let truncated_a = [0, 1, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 1, 0, 1, 0, 0, 0, 60, 0, 2, b'h', b'i'];
+        let error = parse_message_detailed(&truncated_a).unwrap_err();
+        assert_eq!("answer record 0 at offset 28: message ended unexpectedly", error.to_string());
+        let dump = "\
+0000  00 01 81 80 00 01 00 01 00 00 00 00 00 00 01 00  |................|
+0010  01 00 00 01 00 01 00 00 00 3c 00 02 68 69        |.........<..hi|
+                                          ^^
+";
+        assert_eq!(dump, error.hexdump(&truncated_a));
+    }
+
+    #[test]
+    fn test_reject_bad_pointers() {
+        // the question name points at itself
+        let self_pointer = [0, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 192, 12, 0, 1, 0, 1];
+        assert_eq!(Err(ParseError::BadPointer), parse_message(&self_pointer));
+
+        // a label followed by a pointer back to the start of the same name
+        let label_loop = [0, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 1, 97, 192, 12, 0, 1, 0, 1];
+        assert_eq!(Err(ParseError::BadPointer), parse_message(&label_loop));
+
+        // a pointer to the answer name further on in the message
+        let forward_pointer = [0, 1, 129, 128, 0, 1, 0, 1, 0, 0, 0, 0, 192, 18, 0, 1, 0, 1, 1, 97, 0, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 1];
+        assert_eq!(Err(ParseError::BadPointer), parse_message(&forward_pointer));
+
+        // a pointer into the middle of the header
+        let header_pointer = [0, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 192, 4, 0, 1, 0, 1];
+        assert_eq!(Err(ParseError::BadPointer), parse_message(&header_pointer));
+    }
+
+    #[test]
+    fn test_pointer_into_middle_of_name() {
+        // the CNAME target points at "example.com" inside "www.example.com"
+        let buf = [0, 7, 129, 128, 0, 1, 0, 2, 0, 0, 0, 0, 3, 119, 119, 119, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1, 192, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 6, 3, 99, 100, 110, 192, 16, 192, 45, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 1];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let answers = &parsed_message.answers;
+        let cdn = name("cdn.example.com");
+        assert_eq!(ResourceData::Cname(cdn.clone()), answers[0].data);
+        assert_eq!(cdn, answers[1].name);
+        assert_eq!(vec![IpAddr::from([192, 0, 2, 1])], parsed_message.resolve_chain());
+    }
+
+    #[test]
+    fn test_chained_pointers() {
+        // the first answer name is a bare pointer, the second points at that
+        // pointer and the third at the root label ending the question name
+        let buf = [0, 8, 129, 128, 0, 1, 0, 3, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 1, 0, 1, 192, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 1, 192, 25, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 2, 192, 20, 0, 2, 0, 1, 0, 0, 0, 60, 0, 2, 192, 12];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        let answers = parsed_message.answers;
+        assert_eq!(name("example"), answers[0].name);
+        assert_eq!(name("example"), answers[1].name);
+        assert!(answers[2].name.is_root());
+    }
+
+    #[test]
+    fn test_parse_authority_section() {
+        // an NXDOMAIN response carrying the zone's SOA record
+        let buf = [0, 26, 129, 131, 0, 1, 0, 0, 0, 1, 0, 0, 4, 110, 111, 110, 101, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 1, 0, 1, 192, 17, 0, 6, 0, 1, 0, 0, 14, 16, 0, 26, 2, 110, 115, 192, 17, 0, 0, 0, 0, 1, 0, 0, 28, 32, 0, 0, 14, 16, 0, 18, 117, 0, 0, 0, 14, 16];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        assert!(parsed_message.answers.is_empty());
+        let expected_soa = Soa { mname: name("ns.example"), rname: DomainName::root(), serial: 1, refresh: 7200, retry: 3600, expire: 1209600, minimum: 3600 };
+        assert_eq!(name("example"), parsed_message.authorities[0].name);
+        assert_eq!(ResourceData::Soa(expected_soa), parsed_message.authorities[0].data);
+    }
+
+    #[test]
+    fn test_parse_additional_section() {
+        // a referral with glue for the nameserver and an OPT record
+        let buf = [0, 27, 129, 0, 0, 1, 0, 0, 0, 1, 0, 2, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 1, 0, 1, 192, 12, 0, 2, 0, 1, 0, 2, 163, 0, 0, 5, 2, 110, 115, 192, 12, 192, 37, 0, 1, 0, 1, 0, 2, 163, 0, 0, 4, 192, 0, 2, 53, 0, 0, 41, 4, 208, 0, 0, 0, 0, 0, 0];
+        let parsed_message = parse_message(&buf).unwrap();
+
+        assert_eq!(ResourceData::Ns(name("ns.example")), parsed_message.authorities[0].data);
+        let additionals = &parsed_message.additionals;
+        assert_eq!(1, additionals.len());
+        assert_eq!(name("ns.example"), additionals[0].name);
+        assert_eq!(ResourceData::A(Ipv4Addr::new(192, 0, 2, 53)), additionals[0].data);
+        assert_eq!(Some(&Edns::default()), parsed_message.edns());
+        assert!(parsed_message.validate().is_empty());
+    }
+
+    #[test]
+    fn test_parse_question_counts() {
+        // a NOTIFY without a question, followed by an answer
+        let no_questions = [0, 28, 36, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 1];
+        let parsed_message = parse_message(&no_questions).unwrap();
+        assert!(parsed_message.questions.is_empty());
+        assert_eq!(ResourceData::A(Ipv4Addr::new(192, 0, 2, 1)), parsed_message.answers[0].data);
+
+        let two_questions = [0, 28, 1, 0, 0, 2, 0, 0, 0, 0, 0, 0, 1, 97, 0, 0, 1, 0, 1, 1, 98, 0, 0, 28, 0, 1];
+        let parsed_message = parse_message(&two_questions).unwrap();
+        let names: Vec<_> = parsed_message.questions.iter().map(|question| (question.domain_name.clone(), question.q_type)).collect();
+        assert_eq!(vec![(name("a"), RecordType::A), (name("b"), RecordType::Aaaa)], names);
+    }
+
+    #[test]
+    fn test_validate_message() {
+        let response = [141, 225, 129, 160, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1, 192, 12, 0, 1, 0, 1, 0, 1, 42, 15, 0, 4, 93, 184, 216, 34];
+        let parsed_message = parse_message(&response).unwrap();
+        assert_eq!(Vec::<Violation>::new(), parsed_message.validate());
+
+        let mut padded = response.to_vec();
+        padded.extend([0, 0]);
+        assert_eq!(vec![Violation::TrailingBytes(2)], parse_message(&padded).unwrap().validate());
+
+        // the A record claims 6 bytes of RDATA, the last two of which are
+        // ignored when decoding the address
+        let long_rdata = [0, 1, 129, 128, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 60, 0, 6, 192, 0, 2, 1, 0, 0];
+        let parsed_message = parse_message(&long_rdata).unwrap();
+        assert_eq!(vec![Violation::DataLength { section: Section::Authority, record: 0, data_length: 6, consumed: 4 }], parsed_message.validate());
+
+        let mut edited_message = parse_message(&response).unwrap();
+        edited_message.answers.clear();
+        assert_eq!(vec![Violation::SectionCount { section: Section::Answer, header: 1, actual: 0 }], edited_message.validate());
+    }
+
+    #[test]
+    fn test_strict_and_lenient_parsing() {
+        // the first answer is an AAAA record with a 3 byte address, the
+        // second a valid A record, the additional record is cut short
+        let buf = [0, 30, 129, 128, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 28, 0, 1, 0, 0, 0, 60, 0, 3, 1, 2, 3, 0, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 1, 0, 0, 1, 0, 1, 0, 0];
+        assert_eq!(Err(ParseError::UnexpectedEof), parse_message(&buf));
+        assert_eq!(Err(ParseError::UnexpectedEof), parse_message_strict(&buf));
+
+        let (parsed_message, errors) = parse_message_lenient(&buf).unwrap();
+        assert_eq!(1, parsed_message.answers.len());
+        assert_eq!(ResourceData::A(Ipv4Addr::new(192, 0, 2, 1)), parsed_message.answers[0].data);
+        assert!(parsed_message.additionals.is_empty());
+        assert_eq!(vec![RecordError { section: Section::Answer, record: 0, error: ParseError::UnexpectedEof }, RecordError { section: Section::Additional, record: 0, error: ParseError::UnexpectedEof }], errors);
+        assert_eq!(2, parsed_message.validate().len());
+
+        let padded = [0, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0];
+        assert!(parse_message(&padded).is_ok());
+        assert_eq!(Err(ParseError::Invalid(Violation::TrailingBytes(1))), parse_message_strict(&padded));
+    }
+
+    #[test]
+    fn test_parse_header_flags() {
+        // an authoritative, truncated NXDOMAIN for a NOTIFY with CD set
+        let buf = [0, 31, 166, 19, 0, 0, 0, 0, 0, 0, 0, 0];
+        let header = parse_message(&buf).unwrap().header;
+
+        assert!(header.response);
+        assert_eq!(Opcode::Notify, header.opcode);
+        assert!(header.authoritative);
+        assert!(header.truncated);
+        assert!(!header.recursion_desired);
+        assert!(!header.recursion_available);
+        assert!(!header.z);
+        assert!(!header.authentic_data);
+        assert!(header.checking_disabled);
+        assert_eq!(Rcode::NxDomain, header.rcode);
+    }
+
+    #[test]
+    fn test_opcode_conversion() {
+        for opcode in 0..16 {
+            assert_eq!(opcode, u8::from(Opcode::from(opcode)));
+        }
+        assert_eq!(Opcode::Update, Opcode::from(5));
+        assert_eq!(Opcode::Unknown(3), Opcode::from(3));
+    }
+
+    #[test]
+    fn test_rcode_conversion() {
+        for rcode in 0..4096 {
+            assert_eq!(rcode, u16::from(Rcode::from(rcode)));
+        }
+        assert_eq!(Rcode::BadCookie, Rcode::from(23));
+        assert_eq!("SERVFAIL", Rcode::ServFail.to_string());
+        assert_eq!("RCODE12", Rcode::from(12).to_string());
+    }
+
+    #[test]
+    fn test_record_type_conversion() {
+        for r#type in 0..=u16::MAX {
+            let record_type = RecordType::from(r#type);
+            assert_eq!(r#type, u16::from(record_type));
+            assert_eq!(Ok(record_type), record_type.to_string().parse());
+        }
+        assert_eq!(Ok(RecordType::Aaaa), "aaaa".parse());
+        assert_eq!(Ok(RecordType::Mx), "TYPE15".parse());
+        assert_eq!("TYPE65280", RecordType::Unknown(65280).to_string());
+        assert_eq!(Err(InvalidRecordType), "BOGUS".parse::<RecordType>());
+    }
+
+    #[test]
+    fn test_class_conversion() {
+        for class in 0..=u16::MAX {
+            assert_eq!(class, u16::from(Class::from(class)));
+        }
+        assert_eq!(Class::Ch, Class::from(3));
+        assert_eq!("CLASS2", Class::from(2).to_string());
+        assert_eq!("IN", Class::In.to_string());
+    }
+
+    #[test]
+    fn test_ttl() {
+        let record = ResourceRecord { name: name("example"), r#type: RecordType::A, class: Class::In, ttl: 300, data_length: 4, data: ResourceData::A(Ipv4Addr::LOCALHOST) };
+        assert_eq!(Duration::from_secs(300), record.ttl());
+
+        #[cfg(feature = "std")]
+        {
+            let received = Instant::now();
+            assert_eq!(received + Duration::from_secs(300), record.expires_at(received));
+        }
+
+        assert_eq!(Duration::from_secs(60), record.clamp_ttl(Duration::ZERO, Duration::from_secs(60)));
+        assert_eq!(Duration::from_secs(600), record.clamp_ttl(Duration::from_secs(600), Duration::from_secs(3600)));
+        assert_eq!(Duration::from_secs(300), record.clamp_ttl(Duration::ZERO, Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn test_reply_to() {
+        let query = parse_message(&Query::new("example.com", RecordType::A).id(36321).checking_disabled(true).build().unwrap()).unwrap();
+
+        let mut reply = Message::reply_to(&query);
+        reply.push_answer(ResourceRecord::new(name("example.com"), Class::In, 300, ResourceData::A(Ipv4Addr::new(93, 184, 216, 34))));
+        reply.set_rcode(Rcode::NoError);
+        assert!(reply.validate().is_empty());
+
+        let parsed = parse_message(&reply.to_bytes().unwrap()).unwrap();
+        assert_eq!(Header { id: 36321, response: true, recursion_desired: true, recursion_available: true, checking_disabled: true, question_count: 1, answer_count: 1, ..Header::default() }, parsed.header);
+        assert_eq!(query.questions, parsed.questions);
+        assert_eq!(reply.answers, parsed.answers);
+    }
+
+    #[test]
+    fn test_is_response_to() {
+        let query = Message::try_from(Query::new("Example.COM", RecordType::A).id(1234)).unwrap();
+        let mut response = Message::reply_to(&query);
+        response.questions[0].domain_name = name("example.com");
+        assert!(response.is_response_to(&query));
+        assert!(!query.is_response_to(&query));
+
+        response.set_id(1235);
+        assert!(!response.is_response_to(&query));
+        let other = Message::try_from(Query::new("example.com", RecordType::Aaaa).id(1234)).unwrap();
+        assert!(!Message::reply_to(&other).is_response_to(&query));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_random_id() {
+        let ids: std::collections::HashSet<u16> = (0..8).map(|_| Message::random_id()).collect();
+        assert!(ids.len() > 1);
+    }
+
+    #[test]
+    fn test_editing_keeps_counts() {
+        let owner = name("example.com");
+        let query = parse_message(&Query::new("example.com", RecordType::A).build().unwrap()).unwrap();
+        let mut reply = Message::reply_to(&query);
+        for i in 0..3 {
+            reply.push_answer(ResourceRecord::new(owner.clone(), Class::In, 300 + i, ResourceData::A(Ipv4Addr::new(192, 0, 2, i as u8))));
+        }
+        reply.push_authority(ResourceRecord::new(owner.clone(), Class::In, 300, ResourceData::Ns(name("ns"))));
+        reply.push_additional(ResourceRecord::new(name("ns"), Class::In, 300, ResourceData::Aaaa(Ipv6Addr::LOCALHOST)));
+        assert_eq!((3, 1, 1), (reply.header.answer_count, reply.header.authority_count, reply.header.additional_count));
+
+        reply.answers_mut().retain(|answer| answer.ttl != 301);
+        reply.answers_mut()[0].set_data(ResourceData::Cname(name("www")));
+        for answer in reply.answers_mut().iter_mut() {
+            answer.set_ttl(answer.ttl.min(60));
+        }
+        reply.additionals_mut().clear();
+        assert!(reply.validate().is_empty());
+
+        let parsed = parse_message(&reply.to_bytes().unwrap()).unwrap();
+        assert_eq!((2, 1, 0), (parsed.header.answer_count, parsed.header.authority_count, parsed.header.additional_count));
+        assert_eq!(RecordType::Cname, parsed.answers[0].r#type);
+        assert!(parsed.answers.iter().all(|answer| answer.ttl == 60));
+    }
+
+    #[test]
+    fn test_serial_arithmetic() {
+        assert!(serial_gt(2, 1) && serial_lt(1, 2) && !serial_gt(1, 1) && !serial_lt(1, 1));
+        // wrapping around
+        assert!(serial_gt(0, u32::MAX) && serial_gt(5, u32::MAX - 5) && serial_lt(u32::MAX, 0));
+        assert!(serial_gt(1 << 31, 1) && serial_lt((1 << 31) + 1, 0));
+        // undefined for serials exactly halfway around
+        assert!(!serial_gt(1 << 31, 0) && !serial_lt(1 << 31, 0) && !serial_gt(0, 1 << 31));
+    }
+
+    #[test]
+    fn test_eq_ignore_ttl() {
+        let record = ResourceRecord::new(name("example.com"), Class::In, 300, ResourceData::Ns(name("ns.example.com")));
+        let mut other = record.clone();
+        other.set_ttl(42);
+        other.data_length = 2;
+        assert!(record != other && record.eq_ignore_ttl(&other) && record.same_rdata(&other));
+
+        let elsewhere = ResourceRecord::new(name("example.org"), Class::In, 300, ResourceData::Ns(name("NS.example.com")));
+        assert!(!record.eq_ignore_ttl(&elsewhere) && record.same_rdata(&elsewhere));
+        let chaos = ResourceRecord::new(name("example.com"), Class::Ch, 300, ResourceData::Ns(name("ns.example.com")));
+        assert!(!record.eq_ignore_ttl(&chaos));
+        assert!(!record.same_rdata(&ResourceRecord::new(name("example.com"), Class::In, 300, ResourceData::Cname(name("ns.example.com")))));
+    }
+
+    #[test]
+    fn test_typed_accessors() {
+        let txt = ResourceData::Txt(vec![b"v=spf1 ".to_vec(), b"-all".to_vec()]);
+        let mx = ResourceData::Mx { preference: 10, exchange: name("mail.example") };
+        let mut message = Message::reply_to(&Message::try_from(Query::new("example", RecordType::Any)).unwrap());
+        for data in [ResourceData::A(Ipv4Addr::LOCALHOST), ResourceData::Aaaa(Ipv6Addr::LOCALHOST), txt, mx, ResourceData::A(Ipv4Addr::BROADCAST)] {
+            message.push_answer(ResourceRecord::new(name("example"), Class::In, 60, data));
+        }
+
+        assert_eq!(vec![Ipv4Addr::LOCALHOST, Ipv4Addr::BROADCAST], message.a_records().collect::<Vec<_>>());
+        assert_eq!(vec![Ipv6Addr::LOCALHOST], message.aaaa_records().collect::<Vec<_>>());
+        assert_eq!(vec!["v=spf1 -all"], message.txt_strings().collect::<Vec<_>>());
+        assert_eq!(vec![(10, &name("mail.example"))], message.mx_records().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_accessors() {
+        let packet = [0x8d, 0xe1, 0x81, 0xa0, 0, 1, 0, 1, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1, 192, 12, 0, 1, 0, 1, 0, 1, 41, 143, 0, 4, 93, 184, 216, 34];
+        let message = parse_message(&packet).unwrap();
+
+        let header = message.header();
+        assert_eq!(36321, message.id());
+        assert!(header.is_response() && header.recursion_desired() && header.recursion_available() && header.authentic_data());
+        assert!(!header.authoritative() && !header.truncated() && !header.z() && !header.checking_disabled());
+        assert_eq!((Opcode::Query, Rcode::NoError), (header.opcode(), header.rcode()));
+        assert_eq!((1, 1, 0, 0), (header.question_count(), header.answer_count(), header.authority_count(), header.additional_count()));
+
+        let question = &message.questions()[0];
+        assert_eq!((&name("example.com"), RecordType::A, Class::In), (question.name(), question.record_type(), question.class()));
+
+        let answer = &message.answers()[0];
+        assert_eq!((question.name(), RecordType::A, Class::In, 4), (answer.name(), answer.record_type(), answer.class(), answer.data_length()));
+        assert_eq!(Duration::from_secs(76175), answer.ttl());
+        assert_eq!(&ResourceData::A(Ipv4Addr::new(93, 184, 216, 34)), answer.rdata());
+        assert!(message.authorities().is_empty() && message.additionals().is_empty());
+    }
+
+    #[test]
+    fn test_name_length_limit() {
+        let mut packet = vec![0, 0, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        for length in [63, 63, 63, 61] {
+            packet.push(length);
+            packet.extend(vec![b'a'; length as usize]);
+        }
+        packet.extend([0, 0, 1, 0, 1]);
+        assert!(parse_message(&packet).is_ok());
+
+        // one byte more
+        packet[12 + 64 * 3] = 62;
+        packet.insert(12 + 64 * 3 + 1, b'a');
+        assert_eq!(Err(ParseError::InvalidName(NameError::NameTooLong)), parse_message(&packet));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let packet = [0x8d, 0xe1, 0x81, 0xa0, 0, 1, 0, 2, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1, 192, 12, 0, 5, 0, 1, 0, 0, 1, 44, 0, 6, 3, 119, 119, 119, 192, 12, 192, 41, 0, 1, 0, 1, 0, 0, 1, 44, 0, 4, 93, 184, 216, 34];
+        let message = parse_message(&packet).unwrap();
+
+        let json = serde_json::to_string(&message).unwrap();
+        assert!(json.contains("\"www.example.com.\""));
+        assert_eq!(message, serde_json::from_str(&json).unwrap());
+    }
+}
@@ -0,0 +1,555 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{
+    buffer::Buffer, edns::take_edns, name::write_label, parse_header, parse_resource_data,
+    walk_name, Class, DomainName, Header, Message, ParseError, Question, RecordType, ResourceData,
+    ResourceRecord, Section, Violation,
+};
+
+/// A message borrowing its names and RDATA from the packet it was parsed
+/// from, for inspecting messages without allocating per record.
+///
+/// Parsing checks the names and where each record ends, but leaves the RDATA
+/// undecoded until [`RecordRef::parse_data`] or [`MessageRef::to_message`]
+/// is called.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageRef<'a> {
+    header: Header,
+    questions: Vec<QuestionRef<'a>>,
+    answers: Vec<RecordRef<'a>>,
+    authorities: Vec<RecordRef<'a>>,
+    additionals: Vec<RecordRef<'a>>,
+    trailing: usize,
+}
+
+impl<'a> MessageRef<'a> {
+    pub fn parse(message: &'a [u8]) -> Result<Self, ParseError> {
+        let mut buf = Buffer::new(message);
+        let header = parse_header(&mut buf)?;
+
+        let questions = parse_questions(header.question_count, message, &mut buf)?;
+        let answers = parse_records(header.answer_count, message, &mut buf)?;
+        let authorities = parse_records(header.authority_count, message, &mut buf)?;
+        let additionals = parse_records(header.additional_count, message, &mut buf)?;
+
+        Ok(MessageRef {
+            header,
+            questions,
+            answers,
+            authorities,
+            additionals,
+            trailing: buf.remaining(),
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn questions(&self) -> &[QuestionRef<'a>] {
+        &self.questions
+    }
+
+    pub fn answers(&self) -> &[RecordRef<'a>] {
+        &self.answers
+    }
+
+    pub fn authorities(&self) -> &[RecordRef<'a>] {
+        &self.authorities
+    }
+
+    pub fn additionals(&self) -> &[RecordRef<'a>] {
+        &self.additionals
+    }
+
+    /// Decodes everything into an owned message, the same one
+    /// [`parse_message`](crate::parse_message) returns.
+    pub fn to_message(&self) -> Result<Message, ParseError> {
+        let mut violations = Vec::new();
+        let mut records = |section, records: &[RecordRef<'a>]| {
+            records
+                .iter()
+                .enumerate()
+                .map(|(record, record_ref)| {
+                    let (data, consumed) = record_ref.parse_data_consumed()?;
+                    let data_length = record_ref.data.len() as u16;
+                    if consumed != record_ref.data.len() {
+                        violations.push(Violation::DataLength {
+                            section,
+                            record,
+                            data_length,
+                            consumed,
+                        });
+                    }
+
+                    Ok(ResourceRecord {
+                        name: record_ref.name.to_name(),
+                        r#type: record_ref.r#type,
+                        class: record_ref.class,
+                        ttl: record_ref.ttl,
+                        data_length,
+                        data,
+                    })
+                })
+                .collect::<Result<Vec<_>, ParseError>>()
+        };
+        let answers = records(Section::Answer, &self.answers)?;
+        let authorities = records(Section::Authority, &self.authorities)?;
+        let mut additionals = records(Section::Additional, &self.additionals)?;
+        if self.trailing > 0 {
+            violations.push(Violation::TrailingBytes(self.trailing));
+        }
+        let mut header = self.header.clone();
+        let edns = take_edns(&mut header, &mut additionals, &mut violations);
+
+        Ok(Message {
+            header,
+            questions: self
+                .questions
+                .iter()
+                .map(QuestionRef::to_question)
+                .collect(),
+            answers,
+            authorities,
+            additionals,
+            edns,
+            parse_violations: violations,
+        })
+    }
+}
+
+fn parse_questions<'a>(
+    amt: u16,
+    message: &'a [u8],
+    buf: &mut Buffer<'a>,
+) -> Result<Vec<QuestionRef<'a>>, ParseError> {
+    // a question takes at least 5 bytes, so a count the message can't hold
+    // doesn't get to allocate
+    let mut questions = Vec::with_capacity((amt as usize).min(buf.remaining() / 5));
+
+    for _ in 0..amt {
+        questions.push(QuestionRef {
+            name: NameRef::parse(message, buf)?,
+            r#type: RecordType::from(buf.next_u16()?),
+            class: Class::from(buf.next_u16()?),
+        });
+    }
+
+    Ok(questions)
+}
+
+fn parse_records<'a>(
+    amt: u16,
+    message: &'a [u8],
+    buf: &mut Buffer<'a>,
+) -> Result<Vec<RecordRef<'a>>, ParseError> {
+    (0..amt).map(|_| parse_record(message, buf)).collect()
+}
+
+pub(crate) fn parse_record<'a>(
+    message: &'a [u8],
+    buf: &mut Buffer<'a>,
+) -> Result<RecordRef<'a>, ParseError> {
+    let name = NameRef::parse(message, buf)?;
+    let r#type = RecordType::from(buf.next_u16()?);
+    let class = Class::from(buf.next_u16()?);
+    let ttl = buf.next_u32()?;
+    let data_length = buf.next_u16()?;
+    let data_offset = buf.position();
+    let data = buf.next_n(data_length as usize)?;
+
+    Ok(RecordRef {
+        message,
+        name,
+        r#type,
+        class,
+        ttl,
+        data_offset,
+        data,
+    })
+}
+
+/// Reads a message section by section as it is asked for, so that callers
+/// only interested in the header and question don't pay for the records.
+///
+/// Only the header and questions are parsed up front. Each section iterator
+/// has to step over the records of the sections before it, without decoding
+/// their RDATA.
+#[derive(Debug, Clone)]
+pub struct MessageReader<'a> {
+    message: &'a [u8],
+    header: Header,
+    questions: Vec<QuestionRef<'a>>,
+    /// Where the answer section starts.
+    records_offset: usize,
+}
+
+impl<'a> MessageReader<'a> {
+    pub fn new(message: &'a [u8]) -> Result<Self, ParseError> {
+        let mut buf = Buffer::new(message);
+        let header = parse_header(&mut buf)?;
+        let questions = parse_questions(header.question_count, message, &mut buf)?;
+
+        Ok(MessageReader {
+            message,
+            header,
+            questions,
+            records_offset: buf.position(),
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn questions(&self) -> &[QuestionRef<'a>] {
+        &self.questions
+    }
+
+    pub fn answers(&self) -> Records<'a> {
+        self.records(0, self.header.answer_count)
+    }
+
+    pub fn authorities(&self) -> Records<'a> {
+        self.records(
+            self.header.answer_count as usize,
+            self.header.authority_count,
+        )
+    }
+
+    pub fn additionals(&self) -> Records<'a> {
+        let skip = self.header.answer_count as usize + self.header.authority_count as usize;
+        self.records(skip, self.header.additional_count)
+    }
+
+    fn records(&self, skip: usize, remaining: u16) -> Records<'a> {
+        let mut buf = Buffer::new(self.message);
+        // the offset is where parsing the questions stopped
+        let _ = buf.seek(self.records_offset);
+
+        Records {
+            message: self.message,
+            buf,
+            skip,
+            remaining,
+        }
+    }
+}
+
+/// Iterator decoding the records of one section as they are taken.
+///
+/// After an error the iterator ends, since the following records can't be
+/// found anymore.
+#[derive(Debug, Clone)]
+pub struct Records<'a> {
+    message: &'a [u8],
+    buf: Buffer<'a>,
+    /// Records of earlier sections still to be stepped over.
+    skip: usize,
+    remaining: u16,
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<RecordRef<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        while self.skip > 0 {
+            if let Err(error) = parse_record(self.message, &mut self.buf) {
+                self.remaining = 0;
+                return Some(Err(error));
+            }
+            self.skip -= 1;
+        }
+
+        let record = parse_record(self.message, &mut self.buf);
+        self.remaining = if record.is_ok() {
+            self.remaining - 1
+        } else {
+            0
+        };
+
+        Some(record)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining as usize))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuestionRef<'a> {
+    name: NameRef<'a>,
+    r#type: RecordType,
+    class: Class,
+}
+
+impl<'a> QuestionRef<'a> {
+    pub fn name(&self) -> NameRef<'a> {
+        self.name
+    }
+
+    pub fn record_type(&self) -> RecordType {
+        self.r#type
+    }
+
+    pub fn class(&self) -> Class {
+        self.class
+    }
+
+    pub fn to_question(&self) -> Question {
+        Question {
+            domain_name: self.name.to_name(),
+            q_type: self.r#type,
+            q_class: self.class,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordRef<'a> {
+    /// The whole message, which names in the RDATA may point into.
+    message: &'a [u8],
+    name: NameRef<'a>,
+    r#type: RecordType,
+    class: Class,
+    ttl: u32,
+    data_offset: usize,
+    data: &'a [u8],
+}
+
+impl<'a> RecordRef<'a> {
+    pub fn name(&self) -> NameRef<'a> {
+        self.name
+    }
+
+    pub fn record_type(&self) -> RecordType {
+        self.r#type
+    }
+
+    pub fn class(&self) -> Class {
+        self.class
+    }
+
+    pub fn ttl(&self) -> u32 {
+        self.ttl
+    }
+
+    /// The undecoded RDATA. Names in it may still be compressed.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn parse_data(&self) -> Result<ResourceData, ParseError> {
+        self.parse_data_consumed().map(|(data, _)| data)
+    }
+
+    /// Decodes the record into an owned one.
+    pub fn to_record(&self) -> Result<ResourceRecord, ParseError> {
+        Ok(ResourceRecord {
+            name: self.name.to_name(),
+            r#type: self.r#type,
+            class: self.class,
+            ttl: self.ttl,
+            data_length: self.data.len() as u16,
+            data: self.parse_data()?,
+        })
+    }
+
+    /// The decoded RDATA and how many of its bytes the decoding used.
+    fn parse_data_consumed(&self) -> Result<(ResourceData, usize), ParseError> {
+        let mut buf = Buffer::new(self.message);
+        buf.seek(self.data_offset)?;
+        let mut rdata = buf.split(self.data.len())?;
+        let data = parse_resource_data(self.r#type, &mut rdata)?;
+
+        Ok((data, rdata.position() - self.data_offset))
+    }
+}
+
+/// A possibly compressed name in a message, decoded as its labels are read.
+#[derive(Debug, Clone, Copy)]
+pub struct NameRef<'a> {
+    message: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> NameRef<'a> {
+    /// Checks the name at the position of `buf` and moves past it.
+    fn parse(message: &'a [u8], buf: &mut Buffer<'a>) -> Result<Self, ParseError> {
+        let offset = buf.position();
+        walk_name(buf, |_| {})?;
+
+        Ok(NameRef { message, offset })
+    }
+
+    /// The labels from the leftmost one, without the root label.
+    pub fn labels(&self) -> Labels<'a> {
+        let mut buf = Buffer::new(self.message);
+        // the name was checked when parsing, so this and reading the labels
+        // can't fail
+        let buf = buf.seek(self.offset).ok().map(|()| buf);
+
+        Labels { buf }
+    }
+
+    /// Where the name starts in the message.
+    #[cfg(any(feature = "sig0", feature = "tsig"))]
+    pub(crate) fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.message.get(self.offset) == Some(&0)
+    }
+
+    pub fn to_name(&self) -> DomainName {
+        DomainName::from_labels(self.labels())
+    }
+}
+
+impl PartialEq for NameRef<'_> {
+    /// Compares the names, ignoring ASCII case like [`DomainName`] does.
+    fn eq(&self, other: &Self) -> bool {
+        labels_match(self.labels(), other.labels())
+    }
+}
+
+impl Eq for NameRef<'_> {}
+
+impl PartialEq<DomainName> for NameRef<'_> {
+    fn eq(&self, other: &DomainName) -> bool {
+        labels_match(self.labels(), other.labels())
+    }
+}
+
+fn labels_match<'x, 'y>(
+    mut a: impl Iterator<Item = &'x [u8]>,
+    mut b: impl Iterator<Item = &'y [u8]>,
+) -> bool {
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return true,
+            (Some(a), Some(b)) if a.eq_ignore_ascii_case(b) => {}
+            _ => return false,
+        }
+    }
+}
+
+impl fmt::Display for NameRef<'_> {
+    /// Writes the name like [`DomainName`] does.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_root() {
+            return write!(f, ".");
+        }
+
+        self.labels().try_for_each(|label| write_label(f, label))
+    }
+}
+
+/// Iterator over the labels of a [`NameRef`], following compression
+/// pointers as it goes.
+#[derive(Debug, Clone)]
+pub struct Labels<'a> {
+    /// `None` once the root label has been read.
+    buf: Option<Buffer<'a>>,
+}
+
+impl<'a> Iterator for Labels<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let buf = self.buf.as_mut()?;
+        loop {
+            let length = buf.next_u8().ok()?;
+            if length & 0b11000000 == 0b11000000 {
+                let target = u16::from_be_bytes([length & 0b00111111, buf.next_u8().ok()?]);
+                buf.seek(target as usize).ok()?;
+                continue;
+            }
+            if length == 0 {
+                self.buf = None;
+                return None;
+            }
+
+            return buf.next_n(length as usize).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_message;
+
+    const PACKET: [u8; 62] = [0x12, 0x34, 0x81, 0x80, 0, 1, 0, 2, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 15, 0, 1, 0xc0, 12, 0, 15, 0, 1, 0, 0, 14, 16, 0, 4, 0, 10, 0xc0, 12, 0xc0, 12, 0, 15, 0, 1, 0, 0, 14, 16, 0, 9, 0, 20, 4, 109, 97, 105, 108, 0xc0, 12];
+
+    #[test]
+    fn test_parse() {
+        let message = MessageRef::parse(&PACKET).unwrap();
+        assert_eq!(0x1234, message.header().id());
+        assert_eq!(vec![b"example".as_slice()], message.questions()[0].name().labels().collect::<Vec<_>>());
+        assert_eq!("example.", message.questions()[0].name().to_string());
+
+        let answer = &message.answers()[1];
+        assert_eq!((RecordType::Mx, 3600), (answer.record_type(), answer.ttl()));
+        assert_eq!(&[0, 20, 4, 109, 97, 105, 108, 0xc0, 12], answer.data());
+        assert_eq!(Ok(ResourceData::Mx { preference: 20, exchange: "mail.example".parse().unwrap() }), answer.parse_data());
+        assert!(answer.name() == "EXAMPLE".parse::<DomainName>().unwrap());
+        assert_eq!(message.answers()[0].name(), answer.name());
+    }
+
+    #[test]
+    fn test_to_message() {
+        assert_eq!(parse_message(&PACKET), MessageRef::parse(&PACKET).unwrap().to_message());
+
+        let mut packet = PACKET.to_vec();
+        packet.push(0);
+        assert_eq!(parse_message(&packet), MessageRef::parse(&packet).unwrap().to_message());
+    }
+
+    #[test]
+    fn test_reader() {
+        let reader = MessageReader::new(&PACKET).unwrap();
+        assert!(reader.header().is_response());
+        assert_eq!("example.", reader.questions()[0].name().to_string());
+
+        let answers = reader.answers().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(MessageRef::parse(&PACKET).unwrap().answers(), answers);
+        assert_eq!(0, reader.authorities().count());
+        assert_eq!(0, reader.additionals().count());
+
+        // the second answer is cut off
+        let reader = MessageReader::new(&PACKET[..50]).unwrap();
+        let mut answers = reader.answers();
+        assert!(answers.next().unwrap().is_ok());
+        assert_eq!(Some(Err(ParseError::UnexpectedEof)), answers.next());
+        assert_eq!(None, answers.next());
+
+        // the second record moved into the authority section
+        let mut packet = PACKET.to_vec();
+        packet[7] = 1;
+        packet[9] = 1;
+        let reader = MessageReader::new(&packet).unwrap();
+        assert_eq!(1, reader.answers().count());
+        assert_eq!(Ok(ResourceData::Mx { preference: 20, exchange: "mail.example".parse().unwrap() }), reader.authorities().next().unwrap().unwrap().parse_data());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(Err(ParseError::UnexpectedEof), MessageRef::parse(&PACKET[..50]));
+
+        let mut packet = PACKET.to_vec();
+        packet[26] = 25;
+        assert_eq!(Err(ParseError::BadPointer), MessageRef::parse(&packet));
+
+        let mut packet = vec![0; 17];
+        packet[4..6].copy_from_slice(&u16::MAX.to_be_bytes());
+        assert_eq!(Err(ParseError::UnexpectedEof), MessageRef::parse(&packet));
+        assert_eq!(Err(ParseError::UnexpectedEof), MessageReader::new(&packet[..12]).map(|reader| reader.questions().len()));
+    }
+}
@@ -0,0 +1,278 @@
+//! A DNS server on the loopback interface that answers from a script, to
+//! stand in for upstream resolvers in tests.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::{
+    parse_message, read_tcp_message, write_tcp_message, DomainName, Message, Rcode, RecordType,
+};
+
+/// What the server does with a query.
+#[derive(Debug, Clone)]
+pub enum Reply {
+    /// Sends the message with the ID of the query.
+    Answer(Message),
+    /// Sends nothing, like a lost packet.
+    Drop,
+    /// Sends these bytes as they are.
+    Raw(Vec<u8>),
+}
+
+/// Which transport a query came in on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+struct Entry {
+    name: DomainName,
+    record_type: RecordType,
+    /// The last reply is repeated for all further queries.
+    replies: VecDeque<Reply>,
+}
+
+#[derive(Default)]
+struct State {
+    script: Mutex<Vec<Entry>>,
+    queries: Mutex<Vec<(Transport, Message)>>,
+    stopped: AtomicBool,
+}
+
+impl State {
+    /// The reply to a query, `REFUSED` for questions not in the script.
+    fn reply(&self, transport: Transport, query: Message) -> Option<Vec<u8>> {
+        let reply = {
+            let mut script = self.script.lock().unwrap();
+            let question = query.questions().first();
+            let entry = script.iter_mut().find(|entry| {
+                question.is_some_and(|question| {
+                    *question.name() == entry.name && question.record_type() == entry.record_type
+                })
+            });
+            match entry {
+                Some(entry) if entry.replies.len() > 1 => entry.replies.pop_front(),
+                Some(entry) => entry.replies.front().cloned(),
+                None => {
+                    let mut refused = Message::reply_to(&query);
+                    refused.set_rcode(Rcode::Refused);
+                    Some(Reply::Answer(refused))
+                }
+            }
+        };
+        let id = query.id();
+        self.queries.lock().unwrap().push((transport, query));
+
+        match reply? {
+            Reply::Answer(mut message) => {
+                message.set_id(id);
+                Some(
+                    message
+                        .to_bytes()
+                        .expect("the scripted answer can't be encoded"),
+                )
+            }
+            Reply::Drop => None,
+            Reply::Raw(bytes) => Some(bytes),
+        }
+    }
+}
+
+/// A server answering on an ephemeral port of `127.0.0.1`, over both UDP
+/// and TCP like a real one, which it stops listening on when dropped.
+///
+/// Queries for a name and type are answered with the [replies](Reply)
+/// [scripted](MockServer::script) for them, in turn.
+pub struct MockServer {
+    udp_addr: SocketAddr,
+    tcp_addr: SocketAddr,
+    state: Arc<State>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl MockServer {
+    pub fn start() -> io::Result<MockServer> {
+        let (udp, tcp) = bind()?;
+        let udp_addr = udp.local_addr()?;
+        let tcp_addr = tcp.local_addr()?;
+        let state = Arc::new(State::default());
+
+        let udp_state = Arc::clone(&state);
+        let tcp_state = Arc::clone(&state);
+        let threads = vec![
+            thread::spawn(move || serve_udp(&udp, &udp_state)),
+            thread::spawn(move || serve_tcp(&tcp, &tcp_state)),
+        ];
+
+        Ok(MockServer {
+            udp_addr,
+            tcp_addr,
+            state,
+            threads,
+        })
+    }
+
+    pub fn udp_addr(&self) -> SocketAddr {
+        self.udp_addr
+    }
+
+    pub fn tcp_addr(&self) -> SocketAddr {
+        self.tcp_addr
+    }
+
+    /// Replies to queries for `name` and `record_type` with `replies`, one
+    /// per query, and with the last one once the others are used up. Later
+    /// scripts for the same question replace earlier ones.
+    ///
+    /// Panics if `name` is invalid or `replies` is empty.
+    pub fn script(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        replies: impl IntoIterator<Item = Reply>,
+    ) {
+        let name: DomainName = name.parse().expect("invalid name");
+        let replies: VecDeque<Reply> = replies.into_iter().collect();
+        assert!(!replies.is_empty(), "no replies for {name} {record_type}");
+
+        let mut script = self.state.script.lock().unwrap();
+        script.retain(|entry| entry.name != name || entry.record_type != record_type);
+        script.push(Entry {
+            name,
+            record_type,
+            replies,
+        });
+    }
+
+    /// The queries received so far, in the order they came in.
+    pub fn queries(&self) -> Vec<(Transport, Message)> {
+        self.state.queries.lock().unwrap().clone()
+    }
+}
+
+/// Binds a UDP socket and a TCP listener to the same port, trying a few
+/// ports in case the TCP one is taken.
+fn bind() -> io::Result<(UdpSocket, TcpListener)> {
+    let mut attempts = 0;
+    loop {
+        let udp = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        match TcpListener::bind(udp.local_addr()?) {
+            Ok(tcp) => return Ok((udp, tcp)),
+            Err(error) if error.kind() == io::ErrorKind::AddrInUse && attempts < 10 => {
+                attempts += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.state.stopped.store(true, Ordering::SeqCst);
+        // both threads block until something arrives
+        if let Ok(socket) = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)) {
+            let _ = socket.send_to(&[], self.udp_addr);
+        }
+        let _ = TcpStream::connect(self.tcp_addr);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve_udp(socket: &UdpSocket, state: &State) {
+    let mut buffer = [0; 65535];
+    while let Ok((length, from)) = socket.recv_from(&mut buffer) {
+        if state.stopped.load(Ordering::SeqCst) {
+            break;
+        }
+        let Ok(query) = parse_message(&buffer[..length]) else {
+            continue;
+        };
+        if let Some(reply) = state.reply(Transport::Udp, query) {
+            let _ = socket.send_to(&reply, from);
+        }
+    }
+}
+
+fn serve_tcp(listener: &TcpListener, state: &Arc<State>) {
+    for stream in listener.incoming() {
+        if state.stopped.load(Ordering::SeqCst) {
+            break;
+        }
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        let state = Arc::clone(state);
+        thread::spawn(move || {
+            while let Ok(Some(query)) = read_tcp_message(&mut stream) {
+                let Ok(query) = parse_message(&query) else {
+                    break;
+                };
+                if let Some(reply) = state.reply(Transport::Tcp, query) {
+                    if write_tcp_message(&mut stream, &reply)
+                        .and_then(|()| stream.flush())
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{fake_query, fake_response, QUERY_A, RESPONSE_A};
+    use std::time::Duration;
+
+    fn exchange_udp(server: &MockServer, query: &[u8]) -> Option<Vec<u8>> {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        socket.send_to(query, server.udp_addr()).unwrap();
+        let mut buffer = [0; 512];
+        let length = socket.recv(&mut buffer).ok()?;
+        Some(buffer[..length].to_vec())
+    }
+
+    #[test]
+    fn test_scripted_replies() {
+        let server = MockServer::start().unwrap();
+        server.script("www.example.com", RecordType::A, [Reply::Drop, Reply::Raw(vec![1, 2, 3]), Reply::Answer(fake_response("www.example.com", RecordType::A, "192.0.2.1"))]);
+
+        assert_eq!(None, exchange_udp(&server, QUERY_A));
+        assert_eq!(Some(vec![1, 2, 3]), exchange_udp(&server, QUERY_A));
+        assert_eq!(Some(RESPONSE_A.to_vec()), exchange_udp(&server, QUERY_A));
+        assert_eq!(Some(RESPONSE_A.to_vec()), exchange_udp(&server, QUERY_A));
+
+        let mut query = fake_query("www.example.com", RecordType::Aaaa);
+        query.set_id(7);
+        let refused = parse_message(&exchange_udp(&server, &query.to_bytes().unwrap()).unwrap()).unwrap();
+        assert!(refused.is_response_to(&query));
+        assert_eq!(Rcode::Refused, refused.header().rcode());
+
+        assert_eq!(vec![Transport::Udp; 5], server.queries().iter().map(|(transport, _)| *transport).collect::<Vec<_>>());
+        assert_eq!(query, server.queries()[4].1);
+    }
+
+    #[test]
+    fn test_tcp() {
+        let server = MockServer::start().unwrap();
+        server.script("www.example.com", RecordType::A, [Reply::Answer(fake_response("www.example.com", RecordType::A, "192.0.2.1"))]);
+
+        let mut stream = TcpStream::connect(server.tcp_addr()).unwrap();
+        for _ in 0..2 {
+            write_tcp_message(&mut stream, QUERY_A).unwrap();
+            assert_eq!(Some(RESPONSE_A.to_vec()), read_tcp_message(&mut stream).unwrap());
+        }
+        assert_eq!(server.udp_addr(), server.tcp_addr());
+        assert_eq!(vec![Transport::Tcp; 2], server.queries().iter().map(|(transport, _)| *transport).collect::<Vec<_>>());
+    }
+}
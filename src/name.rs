@@ -0,0 +1,669 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use smallvec::SmallVec;
+
+use crate::{buffer::Buffer, parse_name, ParseError, Violation};
+
+/// Names up to this long in wire format, without the root label, are kept
+/// without allocating, which covers most names seen in practice.
+const INLINE_LENGTH: usize = 32;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// A domain name, kept in uncompressed wire format without the root label.
+///
+/// Names are always absolute, so `"example.com"` and `"example.com."` parse
+/// into the same name, which is displayed as the latter. Comparing and
+/// hashing ignores ASCII case, while the case is kept for display.
+#[derive(Default, Clone)]
+pub struct DomainName(SmallVec<[u8; INLINE_LENGTH]>);
+
+impl fmt::Debug for DomainName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DomainName")
+            .field(&format_args!("{self}"))
+            .finish()
+    }
+}
+
+impl PartialEq for DomainName {
+    fn eq(&self, other: &Self) -> bool {
+        // the length bytes are below the ASCII letters
+        self.0.eq_ignore_ascii_case(&other.0)
+    }
+}
+
+impl Eq for DomainName {}
+
+impl Hash for DomainName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.0.len());
+        for byte in &self.0 {
+            state.write_u8(byte.to_ascii_lowercase());
+        }
+    }
+}
+
+/// Names are ordered canonically as in RFC 4034 section 6.1, comparing the
+/// lowercased labels from the rightmost one.
+impl Ord for DomainName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        fn lowercase(label: &[u8]) -> impl Iterator<Item = u8> + '_ {
+            label.iter().map(|byte| byte.to_ascii_lowercase())
+        }
+
+        let mut labels = self.labels().rev();
+        let mut other_labels = other.labels().rev();
+        loop {
+            match (labels.next(), other_labels.next()) {
+                (Some(label), Some(other_label)) => {
+                    match lowercase(label).cmp(lowercase(other_label)) {
+                        Ordering::Equal => {}
+                        ordering => return ordering,
+                    }
+                }
+                (left, right) => return left.is_some().cmp(&right.is_some()),
+            }
+        }
+    }
+}
+
+impl PartialOrd for DomainName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl DomainName {
+    pub fn root() -> Self {
+        DomainName(SmallVec::new())
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The labels from the leftmost one, without the root label, as the
+    /// octets they are made of on the wire.
+    pub fn labels(&self) -> impl DoubleEndedIterator<Item = &[u8]> + ExactSizeIterator {
+        let (mut count, mut rest) = (0, &self.0[..]);
+        while let Some(&length) = rest.first() {
+            rest = &rest[1 + length as usize..];
+            count += 1;
+        }
+        Labels {
+            wire: &self.0,
+            count,
+        }
+    }
+
+    /// The name with ASCII letters lowercased, as in the canonical form.
+    pub fn to_lowercase(&self) -> Self {
+        let mut name = self.clone();
+        name.0.make_ascii_lowercase();
+        name
+    }
+
+    /// Decodes an uncompressed name that makes up all of `bytes`.
+    pub fn from_wire(bytes: &[u8]) -> Result<Self, ParseError> {
+        let mut buf = Buffer::new(bytes);
+        let name = parse_name(&mut buf)?;
+        if !buf.is_empty() {
+            return Err(ParseError::Invalid(Violation::TrailingBytes(
+                buf.remaining(),
+            )));
+        }
+
+        Ok(name)
+    }
+
+    /// Encodes the name uncompressed.
+    pub fn to_wire(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.0.len() + 1);
+        bytes.extend_from_slice(&self.0);
+        bytes.push(0);
+
+        bytes
+    }
+
+    /// Converts an internationalized name into its ASCII form with `xn--`
+    /// labels, following UTS #46 without transitional processing, which is
+    /// compatible with IDNA2008.
+    #[cfg(feature = "idna")]
+    pub fn from_unicode(name: &str) -> Result<Self, NameError> {
+        idna::domain_to_ascii(name)
+            .map_err(|_| NameError::InvalidIdna)?
+            .parse()
+    }
+
+    /// The name with `xn--` labels decoded, written like `Display` does but
+    /// without escapes. Labels that fail to decode are kept as they are.
+    #[cfg(feature = "idna")]
+    pub fn to_unicode(&self) -> String {
+        if self.is_root() {
+            return String::from(".");
+        }
+
+        let labels: Vec<_> = self.labels().map(String::from_utf8_lossy).collect();
+        let (name, _) = idna::domain_to_unicode(&labels.join("."));
+        name + "."
+    }
+
+    /// The name with its leftmost label removed, or `None` for the root.
+    pub fn parent(&self) -> Option<Self> {
+        self.ancestors().nth(1)
+    }
+
+    /// The name itself followed by every name above it, up to the root.
+    pub fn ancestors(&self) -> impl Iterator<Item = Self> + '_ {
+        self.suffixes()
+            .map(|suffix| DomainName(SmallVec::from_slice(suffix)))
+    }
+
+    /// Whether the name is `other` or below it, ignoring ASCII case. Every
+    /// name is a subdomain of the root.
+    pub fn is_subdomain_of(&self, other: &DomainName) -> bool {
+        self.suffixes()
+            .find(|suffix| suffix.len() <= other.0.len())
+            .is_some_and(|suffix| suffix.eq_ignore_ascii_case(&other.0))
+    }
+
+    /// Whether a wildcard `owner` like `*.example.com` covers the name,
+    /// which is the case for every name strictly below `example.com`. Only
+    /// a leftmost label of just `*` makes a wildcard (RFC 4592 2.1.1).
+    ///
+    /// This looks at the names alone. In a zone, a name that exists between
+    /// the two, like `b.example.com` for `a.b.example.com`, keeps the
+    /// wildcard from applying, which is up to the caller to check.
+    pub fn matches_wildcard(&self, owner: &DomainName) -> bool {
+        if owner.labels().next() != Some(b"*") {
+            return false;
+        }
+
+        let parent = owner.parent().unwrap_or_default();
+        self.labels().len() > parent.labels().len() && self.is_subdomain_of(&parent)
+    }
+
+    /// The closest name that both names are subdomains of.
+    pub fn common_ancestor(&self, other: &DomainName) -> Self {
+        let common = self
+            .labels()
+            .rev()
+            .zip(other.labels().rev())
+            .take_while(|(label, other_label)| label.eq_ignore_ascii_case(other_label))
+            .count();
+        let suffix = self.suffixes().nth(self.labels().len() - common);
+        DomainName(SmallVec::from_slice(suffix.unwrap_or_default()))
+    }
+
+    /// The name to look up PTR records of `address` under, like
+    /// `4.3.2.1.in-addr.arpa.` or a nibble for every 4 bits of an IPv6
+    /// address under `ip6.arpa.` (RFC 3596).
+    pub fn reverse_from(address: IpAddr) -> Self {
+        match address {
+            IpAddr::V4(address) => {
+                let octets = address.octets().map(|octet| octet.to_string());
+                DomainName::from_labels(
+                    octets
+                        .iter()
+                        .rev()
+                        .map(String::as_str)
+                        .chain(["in-addr", "arpa"]),
+                )
+            }
+            IpAddr::V6(address) => {
+                let nibbles = address
+                    .octets()
+                    .into_iter()
+                    .rev()
+                    .flat_map(|octet| [octet & 0xf, octet >> 4]);
+                let labels = nibbles.map(|nibble| &HEX_DIGITS[nibble as usize..][..1]);
+                DomainName::from_labels(labels.chain([&b"ip6"[..], b"arpa"]))
+            }
+        }
+    }
+
+    /// The address a name made by [`reverse_from`] is for, or `None` for
+    /// any other name.
+    ///
+    /// [`reverse_from`]: DomainName::reverse_from
+    pub fn reverse_address(&self) -> Option<IpAddr> {
+        let labels: Vec<_> = self.labels().collect();
+        let is_zone = |zone: [&[u8]; 2]| {
+            labels.len() >= 2
+                && zone
+                    .iter()
+                    .zip(&labels[labels.len() - 2..])
+                    .all(|(a, b)| a.eq_ignore_ascii_case(b))
+        };
+
+        match labels.len() {
+            6 if is_zone([b"in-addr", b"arpa"]) => {
+                let mut octets = [0; 4];
+                for (octet, label) in octets.iter_mut().rev().zip(&labels) {
+                    if label.is_empty() || label.len() > 3 || !label.iter().all(u8::is_ascii_digit)
+                    {
+                        return None;
+                    }
+                    *octet = core::str::from_utf8(label).ok()?.parse().ok()?;
+                }
+                Some(IpAddr::V4(Ipv4Addr::from(octets)))
+            }
+            34 if is_zone([b"ip6", b"arpa"]) => {
+                let mut address = 0u128;
+                for label in labels[..32].iter().rev() {
+                    let &[digit] = *label else {
+                        return None;
+                    };
+                    address = address << 4 | (digit as char).to_digit(16)? as u128;
+                }
+                Some(IpAddr::V6(Ipv6Addr::from(address)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Builds a name from labels that are at most 63 bytes long, without
+    /// checking the other limits.
+    pub(crate) fn from_labels<L: AsRef<[u8]>>(labels: impl IntoIterator<Item = L>) -> Self {
+        let mut name = DomainName::root();
+        for label in labels {
+            name.push_label(label.as_ref());
+        }
+        name
+    }
+
+    /// Appends a label with its octets as they are, leaving the limits to
+    /// [`check`](DomainName::check).
+    pub(crate) fn push_label(&mut self, label: &[u8]) {
+        self.0.push(label.len() as u8);
+        self.0.extend_from_slice(label);
+    }
+
+    /// The wire format of the name and of every name above it, down to the
+    /// empty one of the root.
+    fn suffixes(&self) -> impl Iterator<Item = &[u8]> {
+        let mut suffix = Some(&self.0[..]);
+        core::iter::from_fn(move || {
+            let current = suffix?;
+            suffix = current
+                .first()
+                .map(|&length| &current[1 + length as usize..]);
+            Some(current)
+        })
+    }
+
+    /// The wire format without the root label.
+    pub(crate) fn as_wire(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Checks the limits RFC 1035 puts on labels and names.
+    pub(crate) fn check(&self) -> Result<(), NameError> {
+        if self.labels().any(<[u8]>::is_empty) {
+            return Err(NameError::EmptyLabel);
+        }
+        if self.labels().any(|label| label.len() > 63) {
+            return Err(NameError::LabelTooLong);
+        }
+        if self.0.len() + 1 > 255 {
+            return Err(NameError::NameTooLong);
+        }
+
+        Ok(())
+    }
+}
+
+/// Iterator over the labels of a [`DomainName`], from the leftmost one.
+#[derive(Debug, Clone)]
+struct Labels<'a> {
+    /// The labels not handed out yet, in wire format.
+    wire: &'a [u8],
+    count: usize,
+}
+
+impl<'a> Labels<'a> {
+    fn split_first(wire: &'a [u8]) -> (&'a [u8], &'a [u8]) {
+        let length = wire[0] as usize;
+        wire[1..].split_at(length)
+    }
+}
+
+impl<'a> Iterator for Labels<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.wire.is_empty() {
+            return None;
+        }
+
+        let (label, rest) = Labels::split_first(self.wire);
+        self.wire = rest;
+        self.count -= 1;
+        Some(label)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.count, Some(self.count))
+    }
+}
+
+impl DoubleEndedIterator for Labels<'_> {
+    /// Finding the last label takes a walk over the others, which is cheap
+    /// for names of at most 255 bytes.
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let mut start = 0;
+        loop {
+            let next = start + 1 + *self.wire.get(start)? as usize;
+            if next == self.wire.len() {
+                let (label, _) = Labels::split_first(&self.wire[start..]);
+                self.wire = &self.wire[..start];
+                self.count -= 1;
+                return Some(label);
+            }
+            start = next;
+        }
+    }
+}
+
+impl ExactSizeIterator for Labels<'_> {}
+
+/// Serialized in its presentation format, like `"example.com."`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for DomainName {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DomainName {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = <alloc::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl TryFrom<Vec<String>> for DomainName {
+    type Error = NameError;
+
+    /// Takes the labels as they are, without the root label.
+    fn try_from(labels: Vec<String>) -> Result<Self, Self::Error> {
+        if labels.iter().any(|label| label.len() > 63) {
+            return Err(NameError::LabelTooLong);
+        }
+        let name = DomainName::from_labels(labels);
+        name.check()?;
+
+        Ok(name)
+    }
+}
+
+impl fmt::Display for DomainName {
+    /// Writes the name like in zone files, escaping dots and backslashes in
+    /// labels and anything that isn't printable ASCII as `\DDD`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_root() {
+            return write!(f, ".");
+        }
+
+        for label in self.labels() {
+            write_label(f, label)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes a label followed by its dot, escaped as described for `Display`.
+pub(crate) fn write_label(f: &mut fmt::Formatter<'_>, label: &[u8]) -> fmt::Result {
+    for &byte in label {
+        match byte {
+            b'.' | b'\\' => write!(f, "\\{}", byte as char)?,
+            0x21..=0x7e => write!(f, "{}", byte as char)?,
+            _ => write!(f, "\\{byte:03}")?,
+        }
+    }
+    write!(f, ".")
+}
+
+impl FromStr for DomainName {
+    type Err = NameError;
+
+    /// Parses the zone file presentation format, with the escapes written by
+    /// `Display`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "." {
+            return Ok(DomainName::root());
+        }
+        let s = s.strip_suffix('.').unwrap_or(s);
+
+        let mut name = DomainName::root();
+        let mut label = Vec::new();
+        let mut bytes = s.bytes();
+        while let Some(byte) = bytes.next() {
+            if label.len() > 63 {
+                return Err(NameError::LabelTooLong);
+            }
+            match byte {
+                b'.' => name.push_label(&core::mem::take(&mut label)),
+                b'\\' => match bytes.next().ok_or(NameError::BadEscape)? {
+                    digit @ b'0'..=b'9' => {
+                        let digits = [digit, bytes.next().unwrap_or(0), bytes.next().unwrap_or(0)];
+                        let value = core::str::from_utf8(&digits)
+                            .ok()
+                            .and_then(|digits| digits.parse::<u8>().ok())
+                            .ok_or(NameError::BadEscape)?;
+                        label.push(value);
+                    }
+                    escaped => label.push(escaped),
+                },
+                _ => label.push(byte),
+            }
+        }
+        if label.len() > 63 {
+            return Err(NameError::LabelTooLong);
+        }
+        name.push_label(&label);
+        name.check()?;
+
+        Ok(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameError {
+    /// An empty label other than the root label, as in `"a..b"`.
+    EmptyLabel,
+    /// A label longer than 63 bytes.
+    LabelTooLong,
+    /// A name longer than 255 bytes in wire format.
+    NameTooLong,
+    /// A backslash not followed by a character or three decimal digits up to
+    /// 255.
+    BadEscape,
+    /// A name that can't be converted by IDNA.
+    InvalidIdna,
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameError::EmptyLabel => write!(f, "empty label"),
+            NameError::LabelTooLong => write!(f, "label longer than 63 bytes"),
+            NameError::NameTooLong => write!(f, "name longer than 255 bytes"),
+            NameError::BadEscape => write!(f, "invalid escape sequence"),
+            NameError::InvalidIdna => write!(f, "invalid internationalized name"),
+        }
+    }
+}
+
+impl core::error::Error for NameError {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_display_and_from_str() {
+        let name: DomainName = "www.example.com".parse().unwrap();
+        assert_eq!(vec![b"www".as_slice(), b"example", b"com"], name.labels().collect::<Vec<_>>());
+        assert_eq!("www.example.com.", name.to_string());
+        assert_eq!(Ok(name), "www.example.com.".parse());
+
+        let root: DomainName = ".".parse().unwrap();
+        assert!(root.is_root());
+        assert_eq!(".", root.to_string());
+
+        let escaped: DomainName = "a\\.b\\\\\\032c.example.".parse().unwrap();
+        assert_eq!(vec![b"a.b\\ c".as_slice(), b"example"], escaped.labels().collect::<Vec<_>>());
+        assert_eq!("a\\.b\\\\\\032c.example.", escaped.to_string());
+
+        // octets that aren't UTF-8 are kept as they are
+        let binary: DomainName = "a\\128\\255.example".parse().unwrap();
+        assert_eq!(Some(b"a\x80\xff".as_slice()), binary.labels().next());
+        assert_eq!("a\\128\\255.example.", binary.to_string());
+        assert_eq!(Ok(binary.clone()), binary.to_string().parse());
+        assert_eq!(Ok(binary.clone()), DomainName::from_wire(&binary.to_wire()));
+        assert_eq!(vec![3, 0x61, 0x80, 0xff, 7], binary.to_wire()[..5]);
+        let long: DomainName = vec!["\\255".repeat(63); 3].join(".").parse().unwrap();
+        assert_eq!(Some(63), long.labels().next().map(<[u8]>::len));
+        assert_eq!(Err(NameError::NameTooLong), vec!["\\255".repeat(63); 4].join(".").parse::<DomainName>());
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        assert_eq!(Err(NameError::EmptyLabel), "a..b".parse::<DomainName>());
+        assert_eq!(Err(NameError::EmptyLabel), "".parse::<DomainName>());
+        assert_eq!(Err(NameError::LabelTooLong), "a".repeat(64).parse::<DomainName>());
+        assert_eq!(Err(NameError::NameTooLong), vec!["a".repeat(63); 4].join(".").parse::<DomainName>());
+        assert_eq!(Err(NameError::BadEscape), "a\\256".parse::<DomainName>());
+        assert_eq!(Err(NameError::BadEscape), "a\\".parse::<DomainName>());
+    }
+
+    #[test]
+    fn test_try_from_labels() {
+        assert_eq!(Ok("a.b".parse().unwrap()), DomainName::try_from(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(Ok(DomainName::root()), DomainName::try_from(vec![]));
+        assert_eq!(Err(NameError::EmptyLabel), DomainName::try_from(vec![String::new()]));
+        assert_eq!(Err(NameError::LabelTooLong), DomainName::try_from(vec!["a".repeat(64)]));
+    }
+
+    #[test]
+    fn test_wire_format() {
+        let name: DomainName = "example.com".parse().unwrap();
+        let wire = vec![7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0];
+        assert_eq!(wire, name.to_wire());
+        assert_eq!(Ok(name), DomainName::from_wire(&wire));
+        assert_eq!(Ok(DomainName::root()), DomainName::from_wire(&[0]));
+        assert_eq!(Err(ParseError::Invalid(Violation::TrailingBytes(1))), DomainName::from_wire(&[0, 0]));
+        assert_eq!(Err(ParseError::UnexpectedEof), DomainName::from_wire(&[3, 99]));
+    }
+
+    #[test]
+    fn test_labels() {
+        let name: DomainName = "www.example.com".parse().unwrap();
+        assert_eq!(vec![b"com".as_slice(), b"example", b"www"], name.labels().rev().collect::<Vec<_>>());
+        let mut labels = name.labels();
+        assert_eq!((Some(b"www".as_slice()), Some(b"com".as_slice()), 1), (labels.next(), labels.next_back(), labels.len()));
+        assert_eq!((Some(b"example".as_slice()), None, None), (labels.next_back(), labels.next(), labels.next_back()));
+        assert_eq!(0, DomainName::root().labels().len());
+
+        // longer names than fit inline work the same
+        let long: DomainName = vec!["a".repeat(63); 3].join(".").parse().unwrap();
+        assert_eq!((3, 63), (long.labels().len(), long.labels().next_back().unwrap().len()));
+        assert_eq!(Ok(long.clone()), DomainName::from_wire(&long.to_wire()));
+    }
+
+    #[test]
+    fn test_relations() {
+        let name = |name: &str| name.parse::<DomainName>().unwrap();
+        let www = name("www.Example.com");
+        assert_eq!(Some(name("example.com")), www.parent());
+        assert_eq!(None, DomainName::root().parent());
+        assert_eq!(vec![www.clone(), name("example.com"), name("com"), DomainName::root()], www.ancestors().collect::<Vec<_>>());
+
+        assert!(www.is_subdomain_of(&name("EXAMPLE.com")) && www.is_subdomain_of(&www) && www.is_subdomain_of(&DomainName::root()));
+        assert!(!www.is_subdomain_of(&name("ample.com")) && !www.is_subdomain_of(&name("a.www.example.com")) && !name("com").is_subdomain_of(&name("example.com")));
+
+        assert_eq!(name("example.com"), www.common_ancestor(&name("mail.example.COM")));
+        assert_eq!(www, www.common_ancestor(&name("a.b.www.example.com")));
+        assert_eq!(DomainName::root(), www.common_ancestor(&name("example.org")));
+    }
+
+    #[test]
+    fn test_matches_wildcard() {
+        let name = |name: &str| name.parse::<DomainName>().unwrap();
+        let wildcard = name("*.example.com");
+        for matching in ["a.example.com", "a.b.EXAMPLE.com", "*.example.com"] {
+            assert!(name(matching).matches_wildcard(&wildcard), "{matching}");
+        }
+        for other in ["example.com", "com", "a.example.org"] {
+            assert!(!name(other).matches_wildcard(&wildcard), "{other}");
+        }
+        // only a whole label of `*` makes a wildcard
+        assert!(!name("a.example.com").matches_wildcard(&name("a*.example.com")));
+        assert!(!name("*.example.com").matches_wildcard(&name("example.com")));
+        assert!(name("a.b.example.com").matches_wildcard(&name("*.b.example.com")) && name("com").matches_wildcard(&name("*")));
+    }
+
+    #[test]
+    fn test_reverse() {
+        let v4 = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 10));
+        assert_eq!("10.2.0.192.in-addr.arpa.", DomainName::reverse_from(v4).to_string());
+        let v6: IpAddr = "2001:db8::567:89ab".parse().unwrap();
+        assert_eq!("b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa.", DomainName::reverse_from(v6).to_string());
+
+        for address in [v4, v6, IpAddr::V6(Ipv6Addr::UNSPECIFIED)] {
+            assert_eq!(Some(address), DomainName::reverse_from(address).reverse_address());
+        }
+        assert_eq!(Some(v4), "10.2.0.192.IN-ADDR.ARPA".parse::<DomainName>().unwrap().reverse_address());
+        for name in ["2.0.192.in-addr.arpa", "+1.2.0.192.in-addr.arpa", "256.2.0.192.in-addr.arpa", "10.2.0.192.in-addr.example", "g.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa", "example.com"] {
+            assert_eq!(None, name.parse::<DomainName>().unwrap().reverse_address(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        // the example from RFC 4034 6.1
+        let sorted = ["example", "a.example", "yljkjljk.a.example", "Z.a.example", "zABC.a.EXAMPLE", "z.example", "\\001.z.example", "*.z.example"];
+        let mut names: Vec<DomainName> = sorted.iter().rev().map(|name| name.parse().unwrap()).collect();
+        names.sort();
+        assert_eq!(sorted.iter().map(|name| name.parse().unwrap()).collect::<Vec<DomainName>>(), names);
+        assert_eq!("zabc.a.example.", "zABC.a.EXAMPLE".parse::<DomainName>().unwrap().to_lowercase().to_string());
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let name: DomainName = "Example.COM".parse().unwrap();
+        assert_eq!(name, "example.com".parse().unwrap());
+        assert_ne!(name, "example.org".parse().unwrap());
+        assert_eq!("Example.COM.", name.to_string());
+
+        let names = HashSet::from([name]);
+        assert!(names.contains(&"eXaMpLe.CoM".parse().unwrap()));
+        assert!(!names.contains(&"example.com.org".parse().unwrap()));
+    }
+
+    #[cfg(feature = "idna")]
+    #[test]
+    fn test_idna() {
+        let name = DomainName::from_unicode("Bücher.example").unwrap();
+        assert_eq!("xn--bcher-kva.example.", name.to_string());
+        assert_eq!("bücher.example.", name.to_unicode());
+        assert_eq!(".", DomainName::root().to_unicode());
+        assert_eq!(Err(NameError::InvalidIdna), DomainName::from_unicode("a\u{200d}.example"));
+    }
+}
@@ -0,0 +1,149 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{
+    Class, DomainName, Edns, EdnsOption, EncodeError, Header, Message, NameError, Question,
+    RecordType,
+};
+
+/// Builds a query for a single question, ready to be sent.
+#[derive(Debug, Clone)]
+pub struct Query {
+    /// An invalid name is only reported by `build`, to keep the builder
+    /// chainable.
+    name: Result<DomainName, NameError>,
+    r#type: RecordType,
+    class: Class,
+    id: u16,
+    recursion_desired: bool,
+    checking_disabled: bool,
+    edns: Option<Edns>,
+    nsid: bool,
+}
+
+impl Query {
+    /// A recursive query in class IN with ID 0. A trailing dot on `name` is
+    /// optional.
+    pub fn new(name: &str, r#type: RecordType) -> Self {
+        Query {
+            name: name.parse(),
+            r#type,
+            class: Class::In,
+            id: 0,
+            recursion_desired: true,
+            checking_disabled: false,
+            edns: None,
+            nsid: false,
+        }
+    }
+
+    pub fn id(mut self, id: u16) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn class(mut self, class: Class) -> Self {
+        self.class = class;
+        self
+    }
+
+    pub fn recursion_desired(mut self, recursion_desired: bool) -> Self {
+        self.recursion_desired = recursion_desired;
+        self
+    }
+
+    pub fn checking_disabled(mut self, checking_disabled: bool) -> Self {
+        self.checking_disabled = checking_disabled;
+        self
+    }
+
+    pub fn edns(mut self, edns: Edns) -> Self {
+        self.edns = Some(edns);
+        self
+    }
+
+    /// Asks the server to identify itself with the NSID option, which also
+    /// turns on EDNS.
+    pub fn nsid(mut self, nsid: bool) -> Self {
+        self.nsid = nsid;
+        self
+    }
+
+    pub fn build(self) -> Result<Vec<u8>, EncodeError> {
+        Message::try_from(self)
+            .map_err(EncodeError::InvalidName)?
+            .to_bytes()
+    }
+}
+
+impl TryFrom<Query> for Message {
+    type Error = NameError;
+
+    fn try_from(query: Query) -> Result<Self, Self::Error> {
+        let mut edns = query.edns;
+        if query.nsid {
+            edns.get_or_insert_with(Edns::default)
+                .options
+                .push(EdnsOption::Nsid(Vec::new()));
+        }
+
+        let mut message = Message {
+            header: Header {
+                id: query.id,
+                recursion_desired: query.recursion_desired,
+                checking_disabled: query.checking_disabled,
+                question_count: 1,
+                ..Header::default()
+            },
+            questions: vec![Question {
+                domain_name: query.name?,
+                q_type: query.r#type,
+                q_class: query.class,
+            }],
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: None,
+            parse_violations: Vec::new(),
+        };
+        message.set_edns(edns);
+
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_message;
+
+    #[test]
+    fn test_build() {
+        let packet = Query::new("example.com.", RecordType::Aaaa).id(36321).recursion_desired(false).build().unwrap();
+        assert_eq!(vec![141, 225, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 28, 0, 1], packet);
+
+        let message = parse_message(&packet).unwrap();
+        assert_eq!(Ok(message.clone()), Message::try_from(Query::new("example.com", RecordType::Aaaa).id(36321).recursion_desired(false)));
+        assert!(message.validate().is_empty());
+    }
+
+    #[test]
+    fn test_root() {
+        let packet = Query::new(".", RecordType::Ns).class(Class::Ch).build().unwrap();
+        assert_eq!(vec![0, 0, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 3], packet);
+    }
+
+    #[test]
+    fn test_nsid() {
+        let packet = Query::new(".", RecordType::Ns).nsid(true).build().unwrap();
+        assert_eq!(vec![0, 0, 1, 0, 0, 1, 0, 0, 0, 0, 0, 1, 0, 0, 2, 0, 1, 0, 0, 41, 4, 208, 0, 0, 0, 0, 0, 4, 0, 3, 0, 0], packet);
+
+        let message = Message::try_from(Query::new(".", RecordType::Ns).edns(Edns { dnssec_ok: true, ..Edns::default() }).nsid(true)).unwrap();
+        assert_eq!(Some(&Edns { dnssec_ok: true, options: vec![EdnsOption::Nsid(vec![])], ..Edns::default() }), message.edns());
+        assert_eq!(Some(&[][..]), message.edns().unwrap().nsid());
+    }
+
+    #[test]
+    fn test_invalid_name() {
+        assert_eq!(Err(EncodeError::InvalidName(NameError::EmptyLabel)), Query::new("a..example", RecordType::A).build());
+    }
+}
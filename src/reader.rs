@@ -0,0 +1,150 @@
+//! Parsing messages from a stream without framing, pulling in only the
+//! bytes the header counts and record lengths say belong to the message.
+
+use core::fmt;
+use std::io::{self, Read};
+
+use crate::{parse_message, Message, NameError, ParseError};
+
+/// The most a message can take up, as in the length prefix on TCP.
+const MAX_LENGTH: usize = 65535;
+
+/// Reads exactly one message from `reader` and parses it, leaving whatever
+/// follows it unread.
+///
+/// The message is read in small pieces as its structure is walked, so a
+/// buffered reader helps with unbuffered sources like sockets.
+pub fn parse_message_from(reader: impl Read) -> Result<Message, ReadError> {
+    let mut pull = Pull {
+        reader,
+        buf: Vec::new(),
+    };
+
+    let header = pull.pull(12)?;
+    let count = |i: usize| u16::from_be_bytes([header[i], header[i + 1]]);
+    let questions = count(4);
+    let records = [count(6), count(8), count(10)]
+        .iter()
+        .map(|&count| count as u32)
+        .sum::<u32>();
+
+    for _ in 0..questions {
+        pull.pull_name()?;
+        pull.pull(4)?;
+    }
+    for _ in 0..records {
+        pull.pull_name()?;
+        let fixed = pull.pull(10)?;
+        let data_length = u16::from_be_bytes([fixed[8], fixed[9]]);
+        pull.pull(data_length.into())?;
+    }
+
+    parse_message(&pull.buf).map_err(ReadError::Parse)
+}
+
+struct Pull<R> {
+    reader: R,
+    buf: Vec<u8>,
+}
+
+impl<R: Read> Pull<R> {
+    fn pull(&mut self, n: usize) -> Result<&[u8], ReadError> {
+        let start = self.buf.len();
+        if start + n > MAX_LENGTH {
+            return Err(ReadError::TooLong);
+        }
+
+        self.buf.resize(start + n, 0);
+        self.reader
+            .read_exact(&mut self.buf[start..])
+            .map_err(ReadError::Io)?;
+        Ok(&self.buf[start..])
+    }
+
+    /// Pulls in a name up to its root label or the compression pointer
+    /// ending it.
+    fn pull_name(&mut self) -> Result<(), ReadError> {
+        let start = self.buf.len();
+        loop {
+            let length = self.pull(1)?[0];
+            match length >> 6 {
+                0b00 if length == 0 => return Ok(()),
+                0b00 => {
+                    self.pull(length.into())?;
+                }
+                0b11 => {
+                    self.pull(1)?;
+                    return Ok(());
+                }
+                _ => return Err(ReadError::Parse(ParseError::BadLabel)),
+            }
+
+            if self.buf.len() - start > 255 {
+                return Err(ReadError::Parse(ParseError::InvalidName(
+                    NameError::NameTooLong,
+                )));
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadError {
+    /// Reading failed, or the stream ended in the middle of the message.
+    Io(io::Error),
+    Parse(ParseError),
+    /// The message would be longer than 65535 bytes.
+    TooLong,
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io(error) => write!(f, "{error}"),
+            ReadError::Parse(error) => write!(f, "{error}"),
+            ReadError::TooLong => write!(f, "message longer than 65535 bytes"),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordType;
+    use std::io::{Cursor, ErrorKind};
+
+    #[test]
+    fn test_parse_message_from() {
+        // an MX response with compressed names, then the start of the next
+        let packet = [0x12, 0x34, 0x81, 0x80, 0, 1, 0, 2, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 15, 0, 1, 0xc0, 12, 0, 15, 0, 1, 0, 0, 14, 16, 0, 4, 0, 10, 0xc0, 12, 0xc0, 12, 0, 15, 0, 1, 0, 0, 14, 16, 0, 9, 0, 20, 4, 109, 97, 105, 108, 0xc0, 12];
+        let mut stream = Cursor::new([&packet[..], &packet[..], &[0x56, 0x78]].concat());
+
+        for _ in 0..2 {
+            let message = parse_message_from(&mut stream).unwrap();
+            assert_eq!((0x1234, 2), (message.header().id(), message.answers().len()));
+            assert_eq!(RecordType::Mx, message.answers()[1].record_type());
+        }
+        assert_eq!(2 * packet.len() as u64, stream.position());
+
+        assert!(matches!(parse_message_from(&mut stream), Err(ReadError::Io(error)) if error.kind() == ErrorKind::UnexpectedEof));
+        assert!(matches!(parse_message_from(&packet[..40]), Err(ReadError::Io(_))));
+    }
+
+    #[test]
+    fn test_parse_message_from_errors() {
+        let header = [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        assert!(matches!(parse_message_from(&[&header[..], &[0x40]].concat()[..]), Err(ReadError::Parse(ParseError::BadLabel))));
+
+        let endless: Vec<u8> = header.iter().copied().chain([1, 97].repeat(200)).collect();
+        assert!(matches!(parse_message_from(&endless[..]), Err(ReadError::Parse(ParseError::InvalidName(NameError::NameTooLong)))));
+
+        // a pointer to a name that comes later is caught when parsing
+        assert!(matches!(parse_message_from(&[&header[..], &[0xc0, 20, 0, 1, 0, 1]].concat()[..]), Err(ReadError::Parse(ParseError::BadPointer))));
+
+        let huge = [0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 16, 0, 1, 0, 0, 0, 0, 0xff, 0xff];
+        let stream: Vec<u8> = huge.iter().copied().chain(std::iter::repeat_n(0, 65535)).collect();
+        assert!(matches!(parse_message_from(&stream[..]), Err(ReadError::TooLong)));
+    }
+}
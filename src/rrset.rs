@@ -0,0 +1,133 @@
+use alloc::{vec, vec::Vec};
+use core::time::Duration;
+
+use crate::{Class, DomainName, Message, RecordType, ResourceRecord};
+
+/// The records sharing an owner name, type and class, which caching and
+/// DNSSEC treat as a unit (RFC 2181 5).
+///
+/// An RRset never holds the same data twice, and all its records have the
+/// same TTL, the lowest one of those added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RrSet {
+    /// Never empty.
+    records: Vec<ResourceRecord>,
+}
+
+impl RrSet {
+    pub fn new(record: ResourceRecord) -> Self {
+        RrSet {
+            records: vec![record],
+        }
+    }
+
+    /// Groups records into RRsets, in the order each RRset first appears.
+    pub fn group(records: impl IntoIterator<Item = ResourceRecord>) -> Vec<RrSet> {
+        let mut rrsets: Vec<RrSet> = Vec::new();
+        for record in records {
+            match rrsets.iter_mut().find(|rrset| rrset.belongs(&record)) {
+                Some(rrset) => {
+                    rrset.insert(record);
+                }
+                None => rrsets.push(RrSet::new(record)),
+            }
+        }
+        rrsets
+    }
+
+    pub fn name(&self) -> &DomainName {
+        self.records[0].name()
+    }
+
+    pub fn record_type(&self) -> RecordType {
+        self.records[0].record_type()
+    }
+
+    pub fn class(&self) -> Class {
+        self.records[0].class()
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.records[0].ttl()
+    }
+
+    pub fn set_ttl(&mut self, ttl: u32) {
+        for record in &mut self.records {
+            record.set_ttl(ttl);
+        }
+    }
+
+    /// Whether `record` has the owner name, type and class of the RRset.
+    pub fn belongs(&self, record: &ResourceRecord) -> bool {
+        record.name() == self.name()
+            && record.record_type() == self.record_type()
+            && record.class() == self.class()
+    }
+
+    /// Adds a record, lowering the TTL of the RRset to its TTL if that is
+    /// lower. Returns whether the record was added, which it isn't if the
+    /// RRset already has its data or it [doesn't belong](RrSet::belongs).
+    pub fn insert(&mut self, mut record: ResourceRecord) -> bool {
+        if !self.belongs(&record) {
+            return false;
+        }
+
+        let ttl = self.records[0].ttl.min(record.ttl);
+        self.set_ttl(ttl);
+        if self.records.iter().any(|member| member.same_rdata(&record)) {
+            return false;
+        }
+        record.set_ttl(ttl);
+        self.records.push(record);
+        true
+    }
+
+    /// The records, in the form `dnssec::verify_rrsig` takes them.
+    pub fn records(&self) -> &[ResourceRecord] {
+        &self.records
+    }
+
+    pub fn into_records(self) -> Vec<ResourceRecord> {
+        self.records
+    }
+}
+
+impl Message {
+    /// The answers grouped into RRsets, see [`RrSet::group`].
+    pub fn rrsets(&self) -> Vec<RrSet> {
+        RrSet::group(self.answers.iter().cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_message, Query, ResourceData};
+    use core::net::Ipv4Addr;
+
+    fn a(name: &str, ttl: u32, last_octet: u8) -> ResourceRecord {
+        ResourceRecord::new(name.parse().unwrap(), Class::In, ttl, ResourceData::A(Ipv4Addr::new(192, 0, 2, last_octet)))
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut rrset = RrSet::new(a("example.com", 300, 1));
+        assert!(rrset.insert(a("EXAMPLE.com", 600, 2)));
+        assert!(!rrset.insert(a("example.com", 60, 1)));
+        assert!(!rrset.insert(a("www.example.com", 30, 3)));
+        assert_eq!(2, rrset.records().len());
+        assert!(rrset.records().iter().all(|record| record.ttl() == Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_message_rrsets() {
+        let mut message = Message::reply_to(&parse_message(&Query::new("example.com", RecordType::A).build().unwrap()).unwrap());
+        let cname = ResourceRecord::new("www.example.com".parse().unwrap(), Class::In, 300, ResourceData::Cname("example.com".parse().unwrap()));
+        for record in [a("example.com", 300, 1), cname, a("example.com", 300, 2), a("example.com", 300, 1)] {
+            message.push_answer(record);
+        }
+
+        let rrsets = message.rrsets();
+        assert_eq!(vec![(RecordType::A, 2), (RecordType::Cname, 1)], rrsets.iter().map(|rrset| (rrset.record_type(), rrset.records().len())).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,168 @@
+//! Transaction signatures with public keys (SIG(0), RFC 2931).
+//!
+//! [`verify`] checks the SIG record at the end of a received message with a
+//! key found through a [`KeyLookup`], for example from a zone or a list of
+//! clients allowed to send updates.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{
+    crypto::{in_validity_period, signature_prefix, verify_signature, SignatureError},
+    Dnskey, DomainName, MessageRef, ParseError, RecordType, ResourceData, Rrsig,
+};
+
+/// Finds the public key a message was signed with, which is published in
+/// a KEY record with the same format as DNSKEY records.
+pub trait KeyLookup {
+    fn key(&self, signer: &DomainName, algorithm: u8, key_tag: u16) -> Option<Dnskey>;
+}
+
+impl<F> KeyLookup for F
+where
+    F: Fn(&DomainName, u8, u16) -> Option<Dnskey>,
+{
+    fn key(&self, signer: &DomainName, algorithm: u8, key_tag: u16) -> Option<Dnskey> {
+        self(signer, algorithm, key_tag)
+    }
+}
+
+/// Checks the SIG(0) record at the end of `packet` at `now`, in seconds
+/// since the Unix epoch, returning it on success. Responses are verified
+/// together with the whole signed request.
+pub fn verify(
+    packet: &[u8],
+    keys: &impl KeyLookup,
+    now: u64,
+    request: Option<&[u8]>,
+) -> Result<Rrsig, Sig0Error> {
+    let message = MessageRef::parse(packet).map_err(Sig0Error::Parse)?;
+    let record = message
+        .additionals()
+        .last()
+        .filter(|record| record.record_type() == RecordType::Sig && record.name().is_root())
+        .ok_or(Sig0Error::Unsigned)?;
+    let sig = match record.parse_data().map_err(Sig0Error::Parse)? {
+        ResourceData::Sig(sig) => sig,
+        _ => unreachable!("SIG records always parse into SIG data"),
+    };
+
+    let key = keys
+        .key(&sig.signer_name, sig.algorithm, sig.key_tag)
+        .ok_or(Sig0Error::UnknownKey)?;
+
+    // the message as it was before the record was added
+    let mut unsigned = packet[..record.name().offset()].to_vec();
+    let additional_count = message.header().additional_count() - 1;
+    unsigned[10..12].copy_from_slice(&additional_count.to_be_bytes());
+
+    let data = signed_data(&sig, request, &unsigned);
+    verify_signature(sig.algorithm, &key.public_key, &data, &sig.signature).map_err(|error| {
+        match error {
+            SignatureError::UnsupportedAlgorithm(algorithm) => {
+                Sig0Error::UnsupportedAlgorithm(algorithm)
+            }
+            SignatureError::BadKey => Sig0Error::UnknownKey,
+            SignatureError::BadSignature => Sig0Error::BadSignature,
+        }
+    })?;
+
+    if !in_validity_period(&sig, now) {
+        return Err(Sig0Error::Expired);
+    }
+
+    Ok(sig)
+}
+
+/// What the signature is computed over: the RDATA without the signature,
+/// then the request for responses, then the message without the SIG record.
+pub(crate) fn signed_data(sig: &Rrsig, request: Option<&[u8]>, message: &[u8]) -> Vec<u8> {
+    let mut data = signature_prefix(sig);
+    data.extend_from_slice(request.unwrap_or_default());
+    data.extend_from_slice(message);
+
+    data
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sig0Error {
+    /// The message or its SIG record couldn't be parsed.
+    Parse(ParseError),
+    /// There's no SIG(0) record at the end of the message.
+    Unsigned,
+    /// The lookup had no key for the signer, or not a valid one.
+    UnknownKey,
+    UnsupportedAlgorithm(u8),
+    /// The signature doesn't match.
+    BadSignature,
+    /// The message was signed for a different time.
+    Expired,
+}
+
+impl fmt::Display for Sig0Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sig0Error::Parse(error) => write!(f, "{error}"),
+            Sig0Error::Unsigned => write!(f, "message has no SIG(0) record"),
+            Sig0Error::UnknownKey => write!(f, "no valid key for the SIG(0) signer"),
+            Sig0Error::UnsupportedAlgorithm(algorithm) => {
+                write!(f, "unsupported SIG(0) algorithm {algorithm}")
+            }
+            Sig0Error::BadSignature => write!(f, "SIG(0) signature doesn't match"),
+            Sig0Error::Expired => write!(f, "SIG(0) signature not valid at this time"),
+        }
+    }
+}
+
+impl core::error::Error for Sig0Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer::BufferMut, Class, Message, Query, ResourceRecord};
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    fn key_pair() -> Ed25519KeyPair {
+        Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap()
+    }
+
+    fn key() -> Dnskey {
+        Dnskey { flags: 512, protocol: 3, algorithm: 15, public_key: key_pair().public_key().as_ref().to_vec() }
+    }
+
+    fn signed(query: &Message, request: Option<&[u8]>) -> Vec<u8> {
+        let packet = query.to_bytes().unwrap();
+        let mut sig = Rrsig { type_covered: RecordType::Unknown(0), algorithm: 15, labels: 0, original_ttl: 0, expiration: 1_700_000_300, inception: 1_700_000_000, key_tag: key().key_tag(), signer_name: "client.example".parse().unwrap(), signature: vec![] };
+        sig.signature = key_pair().sign(&signed_data(&sig, request, &packet)).as_ref().to_vec();
+
+        let mut buf = BufferMut::uncompressed();
+        buf.write_n(&packet);
+        ResourceRecord::new(DomainName::root(), Class::Any, 0, ResourceData::Sig(sig)).encode(&mut buf).unwrap();
+        let mut packet = buf.into_vec();
+        packet[11] += 1;
+        packet
+    }
+
+    fn lookup(signer: &DomainName, algorithm: u8, key_tag: u16) -> Option<Dnskey> {
+        let key = key();
+        (*signer == "client.example".parse().unwrap() && algorithm == key.algorithm && key_tag == key.key_tag()).then_some(key)
+    }
+
+    #[test]
+    fn test_verify() {
+        let query = Message::try_from(Query::new("example.com", RecordType::A)).unwrap();
+        let packet = signed(&query, None);
+        assert_eq!(Ok(15), verify(&packet, &lookup, 1_700_000_100, None).map(|sig| sig.algorithm));
+        assert_eq!(Err(Sig0Error::Expired), verify(&packet, &lookup, 1_700_000_301, None));
+        assert_eq!(Err(Sig0Error::Unsigned), verify(&query.to_bytes().unwrap(), &lookup, 1_700_000_100, None));
+        assert_eq!(Err(Sig0Error::UnknownKey), verify(&packet, &|_: &DomainName, _, _| None, 1_700_000_100, None));
+
+        let mut tampered = packet.clone();
+        tampered[2] ^= 1;
+        assert_eq!(Err(Sig0Error::BadSignature), verify(&tampered, &lookup, 1_700_000_100, None));
+
+        let response = signed(&Message::reply_to(&query), Some(&packet));
+        assert!(verify(&response, &lookup, 1_700_000_100, Some(&packet)).is_ok());
+        assert_eq!(Err(Sig0Error::BadSignature), verify(&response, &lookup, 1_700_000_100, None));
+    }
+}
@@ -0,0 +1,87 @@
+//! Framing of messages on stream transports, which prefix every message
+//! with its length in two bytes (RFC 1035 4.2.2, RFC 7766).
+
+use std::io::{self, ErrorKind, Read, Write};
+
+/// Reads the next message from a connection, waiting for all of it if it
+/// arrives in pieces. Returns `None` if the connection was closed before
+/// another message started.
+///
+/// Pipelined messages are read one per call. Unless `reader` is buffered,
+/// every call reads the length and the message separately.
+pub fn read_tcp_message(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0; 2];
+    let mut read = 0;
+    while read < length.len() {
+        match reader.read(&mut length[read..]) {
+            Ok(0) if read == 0 => return Ok(None),
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => read += n,
+            Err(error) if error.kind() == ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    let mut message = vec![0; u16::from_be_bytes(length) as usize];
+    reader.read_exact(&mut message)?;
+
+    Ok(Some(message))
+}
+
+/// Writes a message with its length in front, in a single write so that
+/// both usually go out in the same segment.
+///
+/// Fails with [`ErrorKind::InvalidInput`] for messages longer than 65535
+/// bytes.
+pub fn write_tcp_message(writer: &mut impl Write, message: &[u8]) -> io::Result<()> {
+    let length = u16::try_from(message.len())
+        .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "message longer than 65535 bytes"))?;
+
+    let mut framed = Vec::with_capacity(2 + message.len());
+    framed.extend_from_slice(&length.to_be_bytes());
+    framed.extend_from_slice(message);
+    writer.write_all(&framed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Hands out one byte per read, like a slow connection.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((&first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_pipelined_messages() {
+        let mut stream = Vec::new();
+        write_tcp_message(&mut stream, &[1, 2, 3]).unwrap();
+        write_tcp_message(&mut stream, &[]).unwrap();
+        write_tcp_message(&mut stream, &[4; 300]).unwrap();
+        assert_eq!(&[0, 3, 1, 2, 3, 0, 0, 1, 44], &stream[..9]);
+
+        for mut reader in [Box::new(Cursor::new(stream.clone())) as Box<dyn Read>, Box::new(Trickle(&stream))] {
+            assert_eq!(Some(vec![1, 2, 3]), read_tcp_message(&mut reader).unwrap());
+            assert_eq!(Some(vec![]), read_tcp_message(&mut reader).unwrap());
+            assert_eq!(Some(vec![4; 300]), read_tcp_message(&mut reader).unwrap());
+            assert_eq!(None, read_tcp_message(&mut reader).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_truncated_messages() {
+        assert_eq!(ErrorKind::UnexpectedEof, read_tcp_message(&mut Trickle(&[0])).unwrap_err().kind());
+        assert_eq!(ErrorKind::UnexpectedEof, read_tcp_message(&mut Trickle(&[0, 3, 1, 2])).unwrap_err().kind());
+        assert_eq!(ErrorKind::InvalidInput, write_tcp_message(&mut Vec::new(), &[0; 65536]).unwrap_err().kind());
+    }
+}
@@ -0,0 +1,196 @@
+//! Canned messages and builders for tests of code using scopa.
+//!
+//! The builders panic on input they can't make sense of, as befits tests.
+//! With `std`, there is also a [`MockServer`] to stand in for upstream
+//! resolvers.
+
+use alloc::vec::Vec;
+use core::net::IpAddr;
+
+#[cfg(feature = "std")]
+pub use crate::mock_server::{MockServer, Reply, Transport};
+
+use crate::{
+    parse_message, Class, DomainName, Message, Query, Rcode, RecordType, ResourceData,
+    ResourceRecord, Soa,
+};
+
+/// The ID of all canned and built messages.
+pub const ID: u16 = 0x1234;
+
+/// The TTL of all canned and built records.
+pub const TTL: u32 = 300;
+
+/// A recursive query for the A records of `www.example.com`.
+pub const QUERY_A: &[u8] = &[
+    0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0, 3, b'w', b'w', b'w', 7, b'e', b'x', b'a', b'm',
+    b'p', b'l', b'e', 3, b'c', b'o', b'm', 0, 0, 1, 0, 1,
+];
+
+/// The answer to [`QUERY_A`], `192.0.2.1`.
+pub const RESPONSE_A: &[u8] = &[
+    0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0, 3, b'w', b'w', b'w', 7, b'e', b'x', b'a', b'm',
+    b'p', b'l', b'e', 3, b'c', b'o', b'm', 0, 0, 1, 0, 1, 0xc0, 12, 0, 1, 0, 1, 0, 0, 1, 44, 0, 4,
+    192, 0, 2, 1,
+];
+
+/// The AAAA record of `www.example.com`, `2001:db8::1`.
+pub const RESPONSE_AAAA: &[u8] = &[
+    0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0, 3, b'w', b'w', b'w', 7, b'e', b'x', b'a', b'm',
+    b'p', b'l', b'e', 3, b'c', b'o', b'm', 0, 0, 28, 0, 1, 0xc0, 12, 0, 28, 0, 1, 0, 0, 1, 44, 0,
+    16, 0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+];
+
+/// `www.example.com` as an alias of `example.com`, as returned for an A
+/// query without following the CNAME.
+pub const RESPONSE_CNAME: &[u8] = &[
+    0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0, 3, b'w', b'w', b'w', 7, b'e', b'x', b'a', b'm',
+    b'p', b'l', b'e', 3, b'c', b'o', b'm', 0, 0, 1, 0, 1, 0xc0, 12, 0, 5, 0, 1, 0, 0, 1, 44, 0, 2,
+    0xc0, 16,
+];
+
+/// The mail exchanger of `example.com`, `10 mail.example.com.`.
+pub const RESPONSE_MX: &[u8] = &[
+    0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0, 7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3,
+    b'c', b'o', b'm', 0, 0, 15, 0, 1, 0xc0, 12, 0, 15, 0, 1, 0, 0, 1, 44, 0, 9, 0, 10, 4, b'm',
+    b'a', b'i', b'l', 0xc0, 12,
+];
+
+/// A TXT record of `example.com` holding `v=spf1 -all`.
+pub const RESPONSE_TXT: &[u8] = &[
+    0x12, 0x34, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0, 7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3,
+    b'c', b'o', b'm', 0, 0, 16, 0, 1, 0xc0, 12, 0, 16, 0, 1, 0, 0, 1, 44, 0, 12, 11, b'v', b'=',
+    b's', b'p', b'f', b'1', b' ', b'-', b'a', b'l', b'l',
+];
+
+/// NXDOMAIN for an A query for `nx.example.com`, with the SOA record of
+/// `example.com` in the authority section.
+pub const RESPONSE_NXDOMAIN: &[u8] = &[
+    0x12, 0x34, 0x81, 0x83, 0, 1, 0, 0, 0, 1, 0, 0, 2, b'n', b'x', 7, b'e', b'x', b'a', b'm', b'p',
+    b'l', b'e', 3, b'c', b'o', b'm', 0, 0, 1, 0, 1, 0xc0, 15, 0, 6, 0, 1, 0, 0, 1, 44, 0, 39, 3,
+    b'n', b's', b'1', 0xc0, 15, 10, b'h', b'o', b's', b't', b'm', b'a', b's', b't', b'e', b'r',
+    0xc0, 15, 0, 0, 0, 1, 0, 0, 0x1c, 0x20, 0, 0, 0x0e, 0x10, 0, 0x09, 0x3a, 0x80, 0, 0, 1, 44,
+];
+
+/// A recursive query with ID [`ID`].
+pub fn fake_query(name: &str, record_type: RecordType) -> Message {
+    parse_message(&Query::new(name, record_type).id(ID).build().unwrap()).unwrap()
+}
+
+/// The response to [`fake_query`] with one answer, whose data is written
+/// as in zone files for the types with names or addresses, MX and TXT
+/// records, and in the generic `\# 4 c0000201` form for all others.
+///
+/// `fake_response("example.com", RecordType::A, "192.0.2.1")` answers with
+/// an address, for example.
+pub fn fake_response(name: &str, record_type: RecordType, data: &str) -> Message {
+    let mut response = Message::reply_to(&fake_query(name, record_type));
+    response.push_answer(ResourceRecord::new(
+        domain_name(name),
+        Class::In,
+        TTL,
+        rdata(record_type, data),
+    ));
+    response
+}
+
+/// A negative response to [`fake_query`] with the SOA record of `zone` in
+/// the authority section, like RFC 2308 calls for.
+pub fn fake_negative_response(
+    name: &str,
+    record_type: RecordType,
+    rcode: Rcode,
+    zone: &str,
+) -> Message {
+    let zone = domain_name(zone);
+    let soa = Soa {
+        mname: DomainName::from_labels([b"ns1".as_slice()].into_iter().chain(zone.labels())),
+        rname: DomainName::from_labels([b"hostmaster".as_slice()].into_iter().chain(zone.labels())),
+        serial: 1,
+        refresh: 7200,
+        retry: 3600,
+        expire: 604800,
+        minimum: TTL,
+    };
+
+    let mut response = Message::reply_to(&fake_query(name, record_type));
+    response.set_rcode(rcode);
+    response.push_authority(ResourceRecord::new(
+        zone,
+        Class::In,
+        TTL,
+        ResourceData::Soa(soa),
+    ));
+    response
+}
+
+fn domain_name(name: &str) -> DomainName {
+    name.parse()
+        .unwrap_or_else(|error| panic!("invalid name {name:?}: {error}"))
+}
+
+fn rdata(record_type: RecordType, data: &str) -> ResourceData {
+    let address = || {
+        data.parse::<IpAddr>()
+            .unwrap_or_else(|error| panic!("invalid address {data:?}: {error}"))
+    };
+
+    match record_type {
+        RecordType::A => match address() {
+            IpAddr::V4(address) => ResourceData::A(address),
+            IpAddr::V6(_) => panic!("an IPv6 address for an A record: {data:?}"),
+        },
+        RecordType::Aaaa => match address() {
+            IpAddr::V6(address) => ResourceData::Aaaa(address),
+            IpAddr::V4(_) => panic!("an IPv4 address for an AAAA record: {data:?}"),
+        },
+        RecordType::Ns => ResourceData::Ns(domain_name(data)),
+        RecordType::Cname => ResourceData::Cname(domain_name(data)),
+        RecordType::Ptr => ResourceData::Ptr(domain_name(data)),
+        RecordType::Dname => ResourceData::Dname(domain_name(data)),
+        RecordType::Mx => {
+            let (preference, exchange) = data
+                .split_once(' ')
+                .unwrap_or_else(|| panic!("invalid MX data {data:?}"));
+            ResourceData::Mx {
+                preference: preference
+                    .parse()
+                    .unwrap_or_else(|_| panic!("invalid MX preference {preference:?}")),
+                exchange: domain_name(exchange),
+            }
+        }
+        RecordType::Txt => ResourceData::Txt(data.as_bytes().chunks(255).map(Vec::from).collect()),
+        _ => ResourceData::Unknown {
+            r#type: record_type,
+            data: data
+                .parse()
+                .unwrap_or_else(|_| panic!("invalid generic data {data:?}")),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canned_messages() {
+        assert_eq!(QUERY_A, fake_query("www.example.com", RecordType::A).to_bytes().unwrap());
+        assert_eq!(RESPONSE_A, fake_response("www.example.com", RecordType::A, "192.0.2.1").to_bytes().unwrap());
+        assert_eq!(RESPONSE_AAAA, fake_response("www.example.com", RecordType::Aaaa, "2001:db8::1").to_bytes().unwrap());
+        assert_eq!(RESPONSE_MX, fake_response("example.com", RecordType::Mx, "10 mail.example.com").to_bytes().unwrap());
+        assert_eq!(RESPONSE_TXT, fake_response("example.com", RecordType::Txt, "v=spf1 -all").to_bytes().unwrap());
+        assert_eq!(RESPONSE_NXDOMAIN, fake_negative_response("nx.example.com", RecordType::A, Rcode::NxDomain, "example.com").to_bytes().unwrap());
+
+        let mut cname = fake_response("www.example.com", RecordType::A, "192.0.2.1");
+        cname.answers_mut()[0].set_data(ResourceData::Cname(domain_name("example.com")));
+        assert_eq!(RESPONSE_CNAME, cname.to_bytes().unwrap());
+    }
+
+    #[test]
+    fn test_fake_response() {
+        let response = fake_response("example.com", RecordType::Unknown(65280), "\\# 3 010203");
+        assert_eq!(ResourceData::Unknown { r#type: RecordType::Unknown(65280), data: crate::GenericData(vec![1, 2, 3]) }, *response.answers()[0].rdata());
+        assert!(response.is_response_to(&fake_query("example.com", RecordType::Unknown(65280))));
+    }
+}
@@ -0,0 +1,460 @@
+use alloc::string::String;
+use core::fmt;
+
+use crate::{
+    write_base64, write_hex, GenericData, Loc, Message, Question, RecordType, ResourceData,
+    ResourceRecord, SvcParam, Svcb,
+};
+
+impl fmt::Display for Message {
+    /// Writes the message laid out like dig does, with the header and section
+    /// titles as comments and the records in zone file format.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = &self.header;
+        writeln!(
+            f,
+            ";; ->>HEADER<<- opcode: {}, status: {}, id: {}",
+            header.opcode, header.rcode, header.id
+        )?;
+
+        write!(f, ";; flags:")?;
+        let flags = [
+            (header.response, "qr"),
+            (header.authoritative, "aa"),
+            (header.truncated, "tc"),
+            (header.recursion_desired, "rd"),
+            (header.recursion_available, "ra"),
+            (header.authentic_data, "ad"),
+            (header.checking_disabled, "cd"),
+        ];
+        for (_, flag) in flags.iter().filter(|(set, _)| *set) {
+            write!(f, " {flag}")?;
+        }
+        writeln!(
+            f,
+            "; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.additionals.len() + self.edns.is_some() as usize
+        )?;
+
+        if let Some(edns) = &self.edns {
+            writeln!(f, "\n;; OPT PSEUDOSECTION:")?;
+            write!(f, "; EDNS: version: {}, flags:", edns.version)?;
+            if edns.dnssec_ok {
+                write!(f, " do")?;
+            }
+            writeln!(f, "; udp: {}", edns.udp_payload)?;
+        }
+
+        if !self.questions.is_empty() {
+            writeln!(f, "\n;; QUESTION SECTION:")?;
+            for question in &self.questions {
+                writeln!(f, "{question}")?;
+            }
+        }
+
+        let sections = [
+            ("ANSWER", &self.answers),
+            ("AUTHORITY", &self.authorities),
+            ("ADDITIONAL", &self.additionals),
+        ];
+        for (title, records) in sections {
+            if !records.is_empty() {
+                writeln!(f, "\n;; {title} SECTION:")?;
+                for record in records {
+                    writeln!(f, "{record}")?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Question {
+    /// Writes the question commented out, the way dig shows it.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            ";{}\t\t{}\t{}",
+            self.domain_name, self.q_class, self.q_type
+        )
+    }
+}
+
+impl fmt::Display for ResourceRecord {
+    /// Writes the record as a line of a zone file.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            self.name, self.ttl, self.class, self.r#type, self.data
+        )
+    }
+}
+
+impl fmt::Display for ResourceData {
+    /// Writes the RDATA in the presentation format of zone files.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceData::A(addr) => write!(f, "{addr}"),
+            ResourceData::Aaaa(addr) => write!(f, "{addr}"),
+            ResourceData::Ns(name)
+            | ResourceData::Cname(name)
+            | ResourceData::Ptr(name)
+            | ResourceData::Dname(name) => write!(f, "{name}"),
+            ResourceData::Soa(soa) => write!(
+                f,
+                "{} {} {} {} {} {} {}",
+                soa.mname, soa.rname, soa.serial, soa.refresh, soa.retry, soa.expire, soa.minimum
+            ),
+            ResourceData::Hinfo { cpu, os } => {
+                write_character_string(f, cpu)?;
+                write!(f, " ")?;
+                write_character_string(f, os)
+            }
+            ResourceData::Mx {
+                preference,
+                exchange,
+            } => write!(f, "{preference} {exchange}"),
+            ResourceData::Txt(strings) => {
+                for (i, string) in strings.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write_character_string(f, string)?;
+                }
+
+                Ok(())
+            }
+            ResourceData::Loc(loc) => write_loc(f, loc),
+            ResourceData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => write!(f, "{priority} {weight} {port} {target}"),
+            ResourceData::Naptr {
+                order,
+                preference,
+                flags,
+                services,
+                regexp,
+                replacement,
+            } => {
+                write!(f, "{order} {preference} ")?;
+                for string in [flags, services, regexp] {
+                    write_character_string(f, string)?;
+                    write!(f, " ")?;
+                }
+                write!(f, "{replacement}")
+            }
+            ResourceData::Ds(ds) => {
+                write!(f, "{} {} {} ", ds.key_tag, ds.algorithm, ds.digest_type)?;
+                write_hex(f, &ds.digest)
+            }
+            ResourceData::Sshfp(sshfp) => write!(f, "{sshfp}"),
+            // RFC 4034 allows the timestamps as plain seconds since the epoch
+            ResourceData::Rrsig(rrsig) | ResourceData::Sig(rrsig) => {
+                write!(
+                    f,
+                    "{} {} {} {} {} {} {} {} ",
+                    rrsig.type_covered,
+                    rrsig.algorithm,
+                    rrsig.labels,
+                    rrsig.original_ttl,
+                    rrsig.expiration,
+                    rrsig.inception,
+                    rrsig.key_tag,
+                    rrsig.signer_name
+                )?;
+                write_base64(f, &rrsig.signature)
+            }
+            ResourceData::Nsec(nsec) => {
+                write!(f, "{}", nsec.next_domain_name)?;
+                write_types(f, &nsec.types)
+            }
+            ResourceData::Dnskey(dnskey) => {
+                write!(
+                    f,
+                    "{} {} {} ",
+                    dnskey.flags, dnskey.protocol, dnskey.algorithm
+                )?;
+                write_base64(f, &dnskey.public_key)
+            }
+            ResourceData::Nsec3(nsec3) => {
+                write!(
+                    f,
+                    "{} {} {} ",
+                    nsec3.hash_algorithm, nsec3.flags, nsec3.iterations
+                )?;
+                if nsec3.salt.is_empty() {
+                    write!(f, "-")?;
+                } else {
+                    write_hex(f, &nsec3.salt)?;
+                }
+                write!(f, " ")?;
+                f.write_str(&base32hex(&nsec3.next_hashed_owner))?;
+                write_types(f, &nsec3.types)
+            }
+            ResourceData::Tlsa(tlsa) | ResourceData::Smimea(tlsa) => write!(f, "{tlsa}"),
+            ResourceData::Openpgpkey(key) => write!(f, "{key}"),
+            ResourceData::Svcb(svcb) | ResourceData::Https(svcb) => write_svcb(f, svcb),
+            ResourceData::Uri {
+                priority,
+                weight,
+                target,
+            } => {
+                write!(f, "{priority} {weight} ")?;
+                write_character_string(f, target.as_bytes())
+            }
+            ResourceData::Caa { flags, tag, value } => {
+                write!(f, "{flags} {tag} ")?;
+                write_character_string(f, value)
+            }
+            ResourceData::Tsig(tsig) => {
+                write!(
+                    f,
+                    "{} {} {} {} ",
+                    tsig.algorithm,
+                    tsig.time_signed,
+                    tsig.fudge,
+                    tsig.mac.len()
+                )?;
+                write_base64(f, &tsig.mac)?;
+                write!(
+                    f,
+                    " {} {} {}",
+                    tsig.original_id,
+                    tsig.error,
+                    tsig.other.len()
+                )?;
+                if !tsig.other.is_empty() {
+                    write!(f, " ")?;
+                    write_base64(f, &tsig.other)?;
+                }
+
+                Ok(())
+            }
+            // there is no presentation format for OPT, as it isn't a real record
+            ResourceData::Opt(_) => {
+                let data = self.to_wire().map_err(|_| fmt::Error)?;
+                write!(f, "{}", GenericData(data))
+            }
+            ResourceData::Unknown { data, .. } => write!(f, "{data}"),
+        }
+    }
+}
+
+/// Writes a quoted character-string, escaping quotes and backslashes and
+/// anything that isn't printable ASCII as `\DDD`.
+fn write_character_string(f: &mut fmt::Formatter<'_>, string: &[u8]) -> fmt::Result {
+    write!(f, "\"")?;
+    for &byte in string {
+        match byte {
+            b'"' | b'\\' => write!(f, "\\{}", byte as char)?,
+            0x20..=0x7e => write!(f, "{}", byte as char)?,
+            _ => write!(f, "\\{byte:03}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+fn write_types(f: &mut fmt::Formatter<'_>, types: &[RecordType]) -> fmt::Result {
+    types.iter().try_for_each(|r#type| write!(f, " {type}"))
+}
+
+/// The coordinates as degrees, minutes and seconds and the sizes in meters,
+/// as described in RFC 1876.
+fn write_loc(f: &mut fmt::Formatter<'_>, loc: &Loc) -> fmt::Result {
+    let coordinates = [(loc.latitude, 'N', 'S'), (loc.longitude, 'E', 'W')];
+    for (value, positive, negative) in coordinates {
+        // thousandths of an arc second, offset by 2^31
+        let value = value as i64 - (1 << 31);
+        let hemisphere = if value < 0 { negative } else { positive };
+        let value = value.unsigned_abs();
+
+        write!(
+            f,
+            "{} {} {}.{:03} {hemisphere} ",
+            value / 3_600_000,
+            value / 60_000 % 60,
+            value / 1000 % 60,
+            value % 1000
+        )?;
+    }
+
+    write!(
+        f,
+        "{:.2}m {:.2}m {:.2}m {:.2}m",
+        loc.altitude_meters(),
+        loc.size_meters(),
+        loc.horizontal_precision_meters(),
+        loc.vertical_precision_meters()
+    )
+}
+
+fn write_svcb(f: &mut fmt::Formatter<'_>, svcb: &Svcb) -> fmt::Result {
+    write!(f, "{} {}", svcb.priority, svcb.target)?;
+
+    for param in &svcb.params {
+        write!(f, " ")?;
+        match param {
+            SvcParam::Mandatory(keys) => {
+                write!(f, "mandatory=")?;
+                for (i, key) in keys.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_svc_param_key(f, *key)?;
+                }
+            }
+            SvcParam::Alpn(protocols) => {
+                write!(f, "alpn=\"")?;
+                for (i, protocol) in protocols.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    // commas inside a protocol are escaped once for the list
+                    // and once more for the character-string
+                    for &byte in protocol {
+                        match byte {
+                            b',' => write!(f, "\\\\,")?,
+                            b'\\' => write!(f, "\\\\\\\\")?,
+                            b'"' => write!(f, "\\\"")?,
+                            0x20..=0x7e => write!(f, "{}", byte as char)?,
+                            _ => write!(f, "\\{byte:03}")?,
+                        }
+                    }
+                }
+                write!(f, "\"")?;
+            }
+            SvcParam::NoDefaultAlpn => write!(f, "no-default-alpn")?,
+            SvcParam::Port(port) => write!(f, "port={port}")?,
+            SvcParam::Ipv4Hint(addrs) => {
+                write!(f, "ipv4hint=")?;
+                write_list(f, addrs)?;
+            }
+            SvcParam::Ech(config) => {
+                write!(f, "ech=")?;
+                write_base64(f, config)?;
+            }
+            SvcParam::Ipv6Hint(addrs) => {
+                write!(f, "ipv6hint=")?;
+                write_list(f, addrs)?;
+            }
+            SvcParam::Unknown { key, value } => {
+                write!(f, "key{key}=")?;
+                write_character_string(f, value)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn write_svc_param_key(f: &mut fmt::Formatter<'_>, key: u16) -> fmt::Result {
+    match key {
+        0 => write!(f, "mandatory"),
+        1 => write!(f, "alpn"),
+        2 => write!(f, "no-default-alpn"),
+        3 => write!(f, "port"),
+        4 => write!(f, "ipv4hint"),
+        5 => write!(f, "ech"),
+        6 => write!(f, "ipv6hint"),
+        _ => write!(f, "key{key}"),
+    }
+}
+
+fn write_list<T: fmt::Display>(f: &mut fmt::Formatter<'_>, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ",")?;
+        }
+        write!(f, "{item}")?;
+    }
+
+    Ok(())
+}
+
+/// Base 32 with the extended hex alphabet and without padding, as used for
+/// NSEC3 hashes (RFC 4648, RFC 5155).
+pub(crate) fn base32hex(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"0123456789abcdefghijklmnopqrstuv";
+
+    let mut string = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut group = [0u8; 5];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let group = group
+            .iter()
+            .fold(0u64, |group, &byte| group << 8 | byte as u64);
+
+        // every started byte needs the quintets covering its bits
+        let quintets = (chunk.len() * 8).div_ceil(5);
+        for i in 0..quintets {
+            let quintet = (group >> (35 - 5 * i)) & 0b11111;
+            string.push(ALPHABET[quintet as usize] as char);
+        }
+    }
+
+    string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_message, DomainName, Dnskey, Edns, Nsec3, Soa};
+
+    fn name(name: &str) -> DomainName {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn test_display_message() {
+        let packet = [0x12, 0x34, 0x81, 0x80, 0, 1, 0, 2, 0, 0, 0, 0, 7, 101, 120, 97, 109, 112, 108, 101, 0, 0, 15, 0, 1, 0xc0, 12, 0, 15, 0, 1, 0, 0, 14, 16, 0, 4, 0, 10, 0xc0, 12, 0xc0, 12, 0, 15, 0, 1, 0, 0, 14, 16, 0, 9, 0, 20, 4, 109, 97, 105, 108, 0xc0, 12];
+        let message = parse_message(&packet).unwrap();
+        let expected = ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 4660\n;; flags: qr rd ra; QUERY: 1, ANSWER: 2, AUTHORITY: 0, ADDITIONAL: 0\n\n;; QUESTION SECTION:\n;example.\t\tIN\tMX\n\n;; ANSWER SECTION:\nexample.\t3600\tIN\tMX\t10 example.\nexample.\t3600\tIN\tMX\t20 mail.example.\n";
+        assert_eq!(expected, message.to_string());
+
+        let mut query = Message::try_from(crate::Query::new("example", RecordType::A)).unwrap();
+        query.set_edns(Some(Edns { dnssec_ok: true, ..Edns::default() }));
+        let expected = ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 0\n;; flags: rd; QUERY: 1, ANSWER: 0, AUTHORITY: 0, ADDITIONAL: 1\n\n;; OPT PSEUDOSECTION:\n; EDNS: version: 0, flags: do; udp: 1232\n\n;; QUESTION SECTION:\n;example.\t\tIN\tA\n";
+        assert_eq!(expected, query.to_string());
+    }
+
+    #[test]
+    fn test_display_rdata() {
+        assert_eq!("93.184.216.34", ResourceData::A([93, 184, 216, 34].into()).to_string());
+        assert_eq!("www.example.com.", ResourceData::Cname(name("www.example.com")).to_string());
+        assert_eq!("10 mail.example.com.", ResourceData::Mx { preference: 10, exchange: name("mail.example.com") }.to_string());
+        assert_eq!("\"v=spf1 -all\" \"say \\\"hi\\\"\\010\"", ResourceData::Txt(vec![b"v=spf1 -all".to_vec(), b"say \"hi\"\n".to_vec()]).to_string());
+        assert_eq!("ns.example. admin.example. 1 7200 3600 1209600 3600", ResourceData::Soa(Soa { mname: name("ns.example"), rname: name("admin.example"), serial: 1, refresh: 7200, retry: 3600, expire: 1209600, minimum: 3600 }).to_string());
+        assert_eq!("257 3 13 AQID", ResourceData::Dnskey(Dnskey { flags: 257, protocol: 3, algorithm: 13, public_key: vec![1, 2, 3] }).to_string());
+        assert_eq!("0 issue \"letsencrypt.org\"", ResourceData::Caa { flags: 0, tag: "issue".to_string(), value: b"letsencrypt.org".to_vec() }.to_string());
+        assert_eq!("\\# 2 0102", ResourceData::Unknown { r#type: RecordType::Unknown(65280), data: GenericData(vec![1, 2]) }.to_string());
+    }
+
+    #[test]
+    fn test_display_nsec3() {
+        let nsec3 = Nsec3 { hash_algorithm: 1, flags: 0, iterations: 0, salt: vec![], next_hashed_owner: vec![0xff; 20], types: vec![RecordType::A, RecordType::Rrsig] };
+        assert_eq!("1 0 0 - vvvvvvvvvvvvvvvvvvvvvvvvvvvvvvvv A RRSIG", ResourceData::Nsec3(nsec3).to_string());
+    }
+
+    #[test]
+    fn test_display_loc() {
+        let loc = Loc { version: 0, size: 0x12, horizontal_precision: 0x16, vertical_precision: 0x13, latitude: 2336026648, longitude: 2165095648, altitude: 9999800 };
+        assert_eq!("52 22 23.000 N 4 53 32.000 E -2.00m 1.00m 10000.00m 10.00m", ResourceData::Loc(loc).to_string());
+        let loc = Loc { version: 0, size: 0x12, horizontal_precision: 0x16, vertical_precision: 0x13, latitude: (1 << 31) - 1500, longitude: (1 << 31) - 3_600_000, altitude: 10_000_000 };
+        assert_eq!("0 0 1.500 S 1 0 0.000 W 0.00m 1.00m 10000.00m 10.00m", ResourceData::Loc(loc).to_string());
+    }
+
+    #[test]
+    fn test_display_svcb() {
+        let params = vec![SvcParam::Mandatory(vec![1, 3]), SvcParam::Alpn(vec![b"h2".to_vec(), b"h3".to_vec()]), SvcParam::Port(443), SvcParam::Ipv4Hint(vec![[192, 0, 2, 1].into()]), SvcParam::Unknown { key: 667, value: b"hi".to_vec() }];
+        let svcb = Svcb { priority: 1, target: DomainName::root(), params };
+        assert_eq!("1 . mandatory=alpn,port alpn=\"h2,h3\" port=443 ipv4hint=192.0.2.1 key667=\"hi\"", ResourceData::Https(svcb).to_string());
+    }
+}
@@ -0,0 +1,350 @@
+//! Transaction signatures with shared secrets (RFC 8945).
+//!
+//! [`sign`] appends a TSIG record to an encoded message and [`verify`]
+//! checks the one at the end of a received message. Times are seconds since
+//! the Unix epoch, passed in by the caller so that this also works without
+//! `std`.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use ring::hmac;
+
+use crate::{
+    buffer::BufferMut, Class, DomainName, EncodeError, Message, MessageRef, ParseError, Rcode,
+    RecordType, ResourceData, ResourceRecord, Section, Tsig,
+};
+
+/// The fudge put into signatures, as recommended by RFC 8945.
+pub const FUDGE: u16 = 300;
+
+/// The MAC algorithms that can be used for TSIG.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    HmacSha256,
+    HmacSha384,
+    HmacSha512,
+    /// Only for talking to servers that don't support anything better.
+    HmacSha1,
+}
+
+impl Algorithm {
+    /// The name identifying the algorithm in TSIG records.
+    pub fn name(&self) -> DomainName {
+        let name = match self {
+            Algorithm::HmacSha256 => "hmac-sha256",
+            Algorithm::HmacSha384 => "hmac-sha384",
+            Algorithm::HmacSha512 => "hmac-sha512",
+            Algorithm::HmacSha1 => "hmac-sha1",
+        };
+
+        // the names are valid
+        name.parse().unwrap()
+    }
+
+    /// The algorithm with the given name, if it's supported.
+    pub fn from_name(name: &DomainName) -> Option<Self> {
+        [
+            Algorithm::HmacSha256,
+            Algorithm::HmacSha384,
+            Algorithm::HmacSha512,
+            Algorithm::HmacSha1,
+        ]
+        .into_iter()
+        .find(|algorithm| algorithm.name() == *name)
+    }
+
+    fn hmac(&self) -> hmac::Algorithm {
+        match self {
+            Algorithm::HmacSha256 => hmac::HMAC_SHA256,
+            Algorithm::HmacSha384 => hmac::HMAC_SHA384,
+            Algorithm::HmacSha512 => hmac::HMAC_SHA512,
+            Algorithm::HmacSha1 => hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+        }
+    }
+}
+
+/// A shared secret, known to both sides by its name.
+#[derive(Clone)]
+pub struct Key {
+    name: DomainName,
+    algorithm: Algorithm,
+    key: hmac::Key,
+}
+
+impl Key {
+    pub fn new(name: DomainName, algorithm: Algorithm, secret: &[u8]) -> Self {
+        Key {
+            name,
+            algorithm,
+            key: hmac::Key::new(algorithm.hmac(), secret),
+        }
+    }
+
+    pub fn name(&self) -> &DomainName {
+        &self.name
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// The MAC over the digest of a message, as described in RFC 8945 4.3.
+    fn mac(&self, request_mac: Option<&[u8]>, message: &[u8], tsig: &Tsig) -> hmac::Tag {
+        let mut context = hmac::Context::with_key(&self.key);
+        if let Some(request_mac) = request_mac {
+            context.update(&(request_mac.len() as u16).to_be_bytes());
+            context.update(request_mac);
+        }
+        context.update(message);
+
+        // the names in canonical form
+        context.update(&self.name.to_lowercase().to_wire());
+        context.update(&u16::from(Class::Any).to_be_bytes());
+        context.update(&0u32.to_be_bytes());
+        context.update(&tsig.algorithm.to_lowercase().to_wire());
+        context.update(&tsig.time_signed.to_be_bytes()[2..]);
+        context.update(&tsig.fudge.to_be_bytes());
+        context.update(&u16::from(tsig.error).to_be_bytes());
+        context.update(&(tsig.other.len() as u16).to_be_bytes());
+        context.update(&tsig.other);
+
+        context.sign()
+    }
+}
+
+impl fmt::Debug for Key {
+    /// Leaves out the secret.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Key")
+            .field("name", &self.name)
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A message with a TSIG record appended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signed {
+    pub packet: Vec<u8>,
+    /// The MAC of the signature, which the response to a signed request
+    /// has to be signed with too.
+    pub mac: Vec<u8>,
+}
+
+/// Encodes `message` and signs it at `now`. Responses to a signed request
+/// have to include the MAC of the request.
+pub fn sign(
+    message: &Message,
+    key: &Key,
+    now: u64,
+    request_mac: Option<&[u8]>,
+) -> Result<Signed, EncodeError> {
+    let packet = message.to_bytes()?;
+
+    let mut tsig = Tsig {
+        algorithm: key.algorithm.name(),
+        time_signed: now,
+        fudge: FUDGE,
+        mac: Vec::new(),
+        original_id: message.header().id(),
+        error: Rcode::NoError,
+        other: Vec::new(),
+    };
+    tsig.mac = key.mac(request_mac, &packet, &tsig).as_ref().to_vec();
+    let mac = tsig.mac.clone();
+
+    let record = ResourceRecord::new(key.name.clone(), Class::Any, 0, ResourceData::Tsig(tsig));
+
+    let mut buf = BufferMut::uncompressed();
+    buf.write_n(&packet);
+    record.encode(&mut buf)?;
+    let mut packet = buf.into_vec();
+
+    let additional_count = u16::from_be_bytes([packet[10], packet[11]])
+        .checked_add(1)
+        .ok_or(EncodeError::TooManyRecords(Section::Additional))?;
+    packet[10..12].copy_from_slice(&additional_count.to_be_bytes());
+
+    Ok(Signed { packet, mac })
+}
+
+/// Checks the TSIG record at the end of `packet` against `key` at `now`,
+/// returning it on success. Requests are verified without a request MAC and
+/// responses with the MAC of the signed request.
+pub fn verify(
+    packet: &[u8],
+    key: &Key,
+    now: u64,
+    request_mac: Option<&[u8]>,
+) -> Result<Tsig, TsigError> {
+    let message = MessageRef::parse(packet).map_err(TsigError::Parse)?;
+    let record = message
+        .additionals()
+        .last()
+        .filter(|record| record.record_type() == RecordType::Tsig)
+        .ok_or(TsigError::Unsigned)?;
+    let tsig = match record.parse_data().map_err(TsigError::Parse)? {
+        ResourceData::Tsig(tsig) => tsig,
+        _ => unreachable!("TSIG records always parse into TSIG data"),
+    };
+
+    if record.name() != key.name || tsig.algorithm != key.algorithm.name() {
+        return Err(TsigError::BadKey);
+    }
+
+    // RFC 8945 5.2.2.1 has MACs truncated too far rejected before the MAC is
+    // computed
+    let full_length = key.algorithm.hmac().digest_algorithm().output_len();
+    if tsig.mac.len() > full_length
+        || tsig.mac.len() < full_length && tsig.mac.len() < 10.max(full_length / 2)
+    {
+        return Err(TsigError::Malformed);
+    }
+
+    // the digest covers the message as it was before the record was added
+    let mut unsigned = packet[..record.name().offset()].to_vec();
+    unsigned[..2].copy_from_slice(&tsig.original_id.to_be_bytes());
+    let additional_count = message.header().additional_count() - 1;
+    unsigned[10..12].copy_from_slice(&additional_count.to_be_bytes());
+
+    let mac = key.mac(request_mac, &unsigned, &tsig);
+    if !constant_time_eq(&mac.as_ref()[..tsig.mac.len()], &tsig.mac) {
+        return Err(TsigError::BadSig);
+    }
+    if now.abs_diff(tsig.time_signed) > tsig.fudge.into() {
+        return Err(TsigError::BadTime);
+    }
+
+    Ok(tsig)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TsigError {
+    /// The message or its TSIG record couldn't be parsed.
+    Parse(ParseError),
+    /// There's no TSIG record at the end of the message.
+    Unsigned,
+    /// The MAC is longer than the algorithm's output, or shorter than 10
+    /// bytes or half of it.
+    Malformed,
+    /// The key or algorithm isn't the expected one.
+    BadKey,
+    /// The MAC doesn't match.
+    BadSig,
+    /// The MAC was truncated to fewer bytes than a local policy allows.
+    /// [`verify`] takes every truncation RFC 8945 does, this is for callers
+    /// asking for more.
+    BadTrunc,
+    /// The message was signed longer ago than the fudge allows.
+    BadTime,
+}
+
+impl TsigError {
+    /// The rcode to answer with, put into the TSIG record of the response.
+    pub fn rcode(&self) -> Rcode {
+        match self {
+            TsigError::Parse(_) | TsigError::Malformed => Rcode::FormErr,
+            TsigError::Unsigned => Rcode::NotAuth,
+            TsigError::BadKey => Rcode::BadKey,
+            TsigError::BadSig => Rcode::BAD_SIG,
+            TsigError::BadTrunc => Rcode::BadTrunc,
+            TsigError::BadTime => Rcode::BadTime,
+        }
+    }
+}
+
+impl fmt::Display for TsigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TsigError::Parse(error) => write!(f, "{error}"),
+            TsigError::Unsigned => write!(f, "message has no TSIG record"),
+            TsigError::Malformed => write!(f, "TSIG MAC of an invalid length"),
+            TsigError::BadKey => write!(f, "unknown TSIG key or algorithm"),
+            TsigError::BadSig => write!(f, "TSIG MAC doesn't match"),
+            TsigError::BadTrunc => write!(f, "TSIG MAC truncated too far"),
+            TsigError::BadTime => write!(f, "TSIG signed outside of the fudge"),
+        }
+    }
+}
+
+impl core::error::Error for TsigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_message, Query};
+
+    fn key() -> Key {
+        Key::new("key.example".parse().unwrap(), Algorithm::HmacSha256, b"secret")
+    }
+
+    fn query() -> Message {
+        Query::new("example.com", RecordType::A).id(1234).try_into().unwrap()
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let signed = sign(&query(), &key(), 1_700_000_000, None).unwrap();
+        let tsig = verify(&signed.packet, &key(), 1_700_000_100, None).unwrap();
+        assert_eq!((signed.mac.len(), 32), (tsig.mac.len(), signed.mac.len()));
+        assert_eq!((1234, FUDGE, Rcode::NoError), (tsig.original_id, tsig.fudge, tsig.error));
+
+        let message = parse_message(&signed.packet).unwrap();
+        assert_eq!(1, message.header().additional_count());
+        assert!(matches!(message.additionals()[0].rdata(), ResourceData::Tsig(tsig) if tsig.algorithm == Algorithm::HmacSha256.name()));
+
+        // a forwarder changing the ID doesn't break the signature
+        let mut packet = signed.packet.clone();
+        packet[..2].copy_from_slice(&[0, 1]);
+        assert!(verify(&packet, &key(), 1_700_000_000, None).is_ok());
+    }
+
+    #[test]
+    fn test_verify_errors() {
+        let signed = sign(&query(), &key(), 1_700_000_000, None).unwrap();
+        assert_eq!(Err(TsigError::Unsigned), verify(&query().to_bytes().unwrap(), &key(), 1_700_000_000, None));
+        assert_eq!(Err(TsigError::BadTime), verify(&signed.packet, &key(), 1_700_000_301, None));
+
+        let other = Key::new("other.example".parse().unwrap(), Algorithm::HmacSha256, b"secret");
+        assert_eq!(Err(TsigError::BadKey), verify(&signed.packet, &other, 1_700_000_000, None));
+        let other = Key::new("key.example".parse().unwrap(), Algorithm::HmacSha256, b"wrong");
+        assert_eq!(Err(TsigError::BadSig), verify(&signed.packet, &other, 1_700_000_000, None));
+
+        let mut packet = signed.packet.clone();
+        packet[2] ^= 1;
+        assert_eq!(Err(TsigError::BadSig), verify(&packet, &key(), 1_700_000_000, None));
+        assert_eq!((Rcode::BAD_SIG, 16), (TsigError::BadSig.rcode(), u16::from(TsigError::BadSig.rcode())));
+
+        // MACs may be truncated to half of their length, but no further
+        let truncated = |length: usize| {
+            let mut message = parse_message(&signed.packet).unwrap();
+            for record in message.additionals_mut().iter_mut() {
+                if let ResourceData::Tsig(mut tsig) = record.rdata().clone() {
+                    tsig.mac.truncate(length);
+                    record.set_data(ResourceData::Tsig(tsig));
+                }
+            }
+            message.to_bytes().unwrap()
+        };
+        assert!(verify(&truncated(16), &key(), 1_700_000_000, None).is_ok());
+        for length in [0, 9, 15] {
+            assert_eq!(Err(TsigError::Malformed), verify(&truncated(length), &key(), 1_700_000_000, None), "{length}");
+        }
+        assert_eq!(Rcode::FormErr, TsigError::Malformed.rcode());
+    }
+
+    #[test]
+    fn test_signed_response() {
+        let signed = sign(&query(), &key(), 1_700_000_000, None).unwrap();
+        let response = Message::reply_to(&query());
+        let response = sign(&response, &key(), 1_700_000_001, Some(&signed.mac)).unwrap();
+
+        assert!(verify(&response.packet, &key(), 1_700_000_001, Some(&signed.mac)).is_ok());
+        assert_eq!(Err(TsigError::BadSig), verify(&response.packet, &key(), 1_700_000_001, None));
+    }
+}
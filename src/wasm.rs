@@ -0,0 +1,43 @@
+//! Bindings for JavaScript, built for `wasm32-unknown-unknown` by
+//! `cargo build-wasm` with the `wasm` feature and then run through
+//! `wasm-bindgen`.
+
+use alloc::{string::String, vec::Vec};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{parse_message, Query, RecordType};
+
+/// Decodes a message into JSON with the member names of RFC 8427, see
+/// [`Message::to_json`](crate::Message::to_json).
+#[wasm_bindgen]
+pub fn decode(bytes: &[u8]) -> Result<String, JsError> {
+    Ok(parse_message(bytes)?.to_json())
+}
+
+/// Decodes a message into the text `dig` would show.
+#[wasm_bindgen(js_name = decodeText)]
+pub fn decode_text(bytes: &[u8]) -> Result<String, JsError> {
+    Ok(parse_message(bytes)?.to_string())
+}
+
+/// Encodes a recursive query for `name`, with a type mnemonic like `AAAA`.
+#[wasm_bindgen(js_name = encodeQuery)]
+pub fn encode_query(name: &str, record_type: &str, id: u16) -> Result<Vec<u8>, JsError> {
+    let record_type: RecordType = record_type.parse()?;
+    Ok(Query::new(name, record_type).id(id).build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // errors can only be tested on wasm, as JsError calls into JavaScript
+    #[test]
+    fn test_wasm_bindings() {
+        let query = encode_query("example.com", "aaaa", 0x1234).unwrap();
+        assert_eq!(&[0x12, 0x34, 1, 0, 0, 1], &query[..6]);
+        assert!(decode(&query).unwrap().contains("\"NAME\":\"example.com.\""));
+        assert!(decode_text(&query).unwrap().contains("example.com."));
+    }
+}